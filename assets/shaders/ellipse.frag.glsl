@@ -4,16 +4,16 @@
 precision highp float;
 #endif
 
-uniform vec4 u_color;
 uniform sampler2D u_tex;
 
 varying vec2 v_pos;
 varying vec2 v_texcoord;
+varying vec4 v_color;
 
 void main() {
   if (dot(v_pos, v_pos) > 1.0) {
     discard;
   }
-  gl_FragColor = u_color * texture2D(u_tex, v_texcoord);
+  gl_FragColor = v_color * texture2D(u_tex, v_texcoord);
   // if (gl_FragColor.a == 0.0) discard;
 }
@@ -4,12 +4,12 @@
 precision highp float;
 #endif
 
-uniform vec4 u_color;
 uniform sampler2D u_tex;
 
 varying vec2 v_texcoord;
+varying vec4 v_color;
 
 void main() {
-  gl_FragColor = u_color * texture2D(u_tex, v_texcoord);
+  gl_FragColor = v_color * texture2D(u_tex, v_texcoord);
   // if (gl_FragColor.a == 0.0) discard;
 }
@@ -5,30 +5,21 @@ precision highp float;
 #endif
 
 uniform vec2 u_window_size;
-uniform vec2 u_pos;
-uniform vec2 u_size;
-uniform float u_rotation;
-uniform vec2 u_tex_clipping_offset;
-uniform vec2 u_tex_clipping_size;
 
+// relative to the center of the window, in pixels
 attribute vec2 a_pos;
+// the position inside the shape, in the range [-1, 1] on both axes
+attribute vec2 a_local_pos;
+attribute vec2 a_texcoord;
+attribute vec4 a_color;
 
 varying vec2 v_pos;
 varying vec2 v_texcoord;
-
-mat2 rotate(float angle) {
-  float s = sin(angle);
-  float c = cos(angle);
-  return mat2(c, -s, s, c);
-}
+varying vec4 v_color;
 
 void main() {
-  gl_Position = vec4(
-    (u_pos + (a_pos * 0.5) * u_size * rotate(u_rotation)) / (u_window_size * 0.5),
-    0.0, 1.0
-  );
-  v_pos = a_pos;
-  v_texcoord = vec2(0.5 + 0.5 * a_pos.x, 0.5 - 0.5 * a_pos.y);
-  v_texcoord *= u_tex_clipping_size;
-  v_texcoord += u_tex_clipping_offset;
+  gl_Position = vec4(a_pos / (u_window_size * 0.5), 0.0, 1.0);
+  v_pos = a_local_pos;
+  v_texcoord = a_texcoord;
+  v_color = a_color;
 }
@@ -0,0 +1,13 @@
+#version 100
+
+#ifdef GL_ES
+precision highp float;
+#endif
+
+uniform sampler2D u_tex;
+
+varying vec2 v_texcoord;
+
+void main() {
+  gl_FragColor = texture2D(u_tex, v_texcoord);
+}
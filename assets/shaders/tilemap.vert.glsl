@@ -0,0 +1,22 @@
+#version 100
+
+#ifdef GL_ES
+precision highp float;
+#endif
+
+uniform vec2 u_window_size;
+// the point of the map in the center of the window, in pixels
+uniform vec2 u_camera_pos;
+uniform float u_camera_zoom;
+
+// in the map pixels, Y axis points down
+attribute vec2 a_pos;
+attribute vec2 a_texcoord;
+
+varying vec2 v_texcoord;
+
+void main() {
+  vec2 view_pos = (a_pos - u_camera_pos) * u_camera_zoom / (u_window_size * 0.5);
+  gl_Position = vec4(view_pos.x, -view_pos.y, 0.0, 1.0);
+  v_texcoord = a_texcoord;
+}
@@ -0,0 +1,87 @@
+use prelude_plus::*;
+
+/// Loop markers in frames (i.e. samples per channel) from the start of a clip.
+/// Once the playback reaches `end` it continues from `start`, which allows
+/// music tracks to have an intro which is played only once.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct LoopPoints {
+  pub start: u64,
+  /// Exclusive, `None` means the end of the clip.
+  pub end: Option<u64>,
+}
+
+impl LoopPoints {
+  pub const WHOLE: Self = Self { start: 0, end: None };
+}
+
+#[derive(Debug)]
+pub enum DecodeError {
+  Io(io::Error),
+  Vorbis(lewton::VorbisError),
+  InvalidData(String),
+  Unsupported(String),
+}
+
+impl fmt::Display for DecodeError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Io(e) => write!(f, "I/O error: {}", e),
+      Self::Vorbis(e) => write!(f, "Vorbis error: {}", e),
+      Self::InvalidData(msg) => write!(f, "invalid data: {}", msg),
+      Self::Unsupported(msg) => write!(f, "unsupported: {}", msg),
+    }
+  }
+}
+
+impl std::error::Error for DecodeError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      Self::Io(e) => Some(e),
+      Self::Vorbis(e) => Some(e),
+      _ => None,
+    }
+  }
+}
+
+impl From<io::Error> for DecodeError {
+  #[inline(always)]
+  fn from(e: io::Error) -> Self { Self::Io(e) }
+}
+
+impl From<lewton::VorbisError> for DecodeError {
+  #[inline(always)]
+  fn from(e: lewton::VorbisError) -> Self { Self::Vorbis(e) }
+}
+
+/// A format-specific reader of sample data. Decoders are meant to be wrapped
+/// either into a [`StreamingSource`](crate::StreamingSource) or decoded in
+/// full with [`SoundBuffer::decode`](crate::SoundBuffer::decode).
+pub trait Decoder: Send {
+  fn channels(&self) -> u16;
+  fn sample_rate(&self) -> u32;
+
+  /// Loop markers stored in the file itself, if any.
+  fn loop_points(&self) -> Option<LoopPoints>;
+
+  /// Same contract as [`SoundSource::read`](crate::SoundSource::read), except
+  /// that fewer samples than requested may be returned before the end of the
+  /// stream.
+  fn read(&mut self, out: &mut [f32]) -> Result<usize, DecodeError>;
+
+  /// Seeks to the exact frame, seeking past the end is not an error, all
+  /// subsequent reads will just return zero.
+  fn seek(&mut self, frame: u64) -> Result<(), DecodeError>;
+}
+
+impl<D: Decoder + ?Sized> Decoder for Box<D> {
+  #[inline(always)]
+  fn channels(&self) -> u16 { (**self).channels() }
+  #[inline(always)]
+  fn sample_rate(&self) -> u32 { (**self).sample_rate() }
+  #[inline(always)]
+  fn loop_points(&self) -> Option<LoopPoints> { (**self).loop_points() }
+  #[inline(always)]
+  fn read(&mut self, out: &mut [f32]) -> Result<usize, DecodeError> { (**self).read(out) }
+  #[inline(always)]
+  fn seek(&mut self, frame: u64) -> Result<(), DecodeError> { (**self).seek(frame) }
+}
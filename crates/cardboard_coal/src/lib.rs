@@ -1 +1,21 @@
+#![deny(missing_debug_implementations)]
 
+pub mod decoder;
+pub mod mixer;
+#[cfg(feature = "sdl2")]
+pub mod sdl_sink;
+pub mod sink;
+pub mod source;
+#[cfg(any(test, feature = "test_utils"))]
+pub mod test_utils;
+pub mod vorbis;
+pub mod wav;
+
+pub use decoder::*;
+pub use mixer::*;
+#[cfg(feature = "sdl2")]
+pub use sdl_sink::*;
+pub use sink::*;
+pub use source::*;
+pub use vorbis::*;
+pub use wav::*;
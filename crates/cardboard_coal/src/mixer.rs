@@ -0,0 +1,273 @@
+use crate::source::SoundSource;
+use prelude_plus::*;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct OutputFormat {
+  pub sample_rate: u32,
+  /// Only mono and stereo layouts are mixed properly, any additional channels
+  /// are filled with silence.
+  pub channels: u16,
+}
+
+/// Sample types which the mixer can write into an interleaved output buffer.
+pub trait Sample: Copy + Send + 'static {
+  fn from_f32(value: f32) -> Self;
+}
+
+impl Sample for f32 {
+  #[inline(always)]
+  fn from_f32(value: f32) -> Self { value }
+}
+
+impl Sample for i16 {
+  #[inline(always)]
+  fn from_f32(value: f32) -> Self { (value * i16::MAX as f32) as i16 }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct VoiceId(u64);
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct VoiceParams {
+  pub volume: f32,
+  /// `-1.0` plays only in the left channel, `0.0` in the center and `1.0` only
+  /// in the right one.
+  pub pan: f32,
+  pub paused: bool,
+}
+
+impl Default for VoiceParams {
+  fn default() -> Self { Self { volume: 1.0, pan: 0.0, paused: false } }
+}
+
+pub const DEFAULT_MAX_VOICES: usize = 64;
+
+const SOURCE_READ_BUFFER_FRAMES: usize = 256;
+
+pub struct Mixer {
+  format: OutputFormat,
+  master_volume: f32,
+  max_voices: usize,
+  voices: Vec<Voice>,
+  next_voice_id: u64,
+  mix_buffer: Vec<f32>,
+}
+
+impl fmt::Debug for Mixer {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("Mixer")
+      .field("format", &self.format)
+      .field("master_volume", &self.master_volume)
+      .field("max_voices", &self.max_voices)
+      .field("voices", &self.voices.len())
+      .finish()
+  }
+}
+
+impl Mixer {
+  pub fn new(format: OutputFormat) -> Self {
+    assert!(format.sample_rate > 0);
+    assert!(format.channels > 0);
+    Self {
+      format,
+      master_volume: 1.0,
+      max_voices: DEFAULT_MAX_VOICES,
+      voices: Vec::new(),
+      next_voice_id: 0,
+      mix_buffer: Vec::new(),
+    }
+  }
+
+  #[inline(always)]
+  pub fn format(&self) -> OutputFormat { self.format }
+
+  #[inline(always)]
+  pub fn master_volume(&self) -> f32 { self.master_volume }
+  #[inline(always)]
+  pub fn set_master_volume(&mut self, volume: f32) { self.master_volume = volume; }
+
+  #[inline(always)]
+  pub fn max_voices(&self) -> usize { self.max_voices }
+  pub fn set_max_voices(&mut self, max_voices: usize) {
+    assert!(max_voices > 0);
+    self.max_voices = max_voices;
+    self.steal_voices(0);
+  }
+
+  #[inline(always)]
+  pub fn voice_count(&self) -> usize { self.voices.len() }
+
+  /// Starts playing a source. If all voices are busy, the oldest one is
+  /// stopped to make room for the new one.
+  pub fn play(&mut self, source: impl SoundSource + 'static, params: VoiceParams) -> VoiceId {
+    let source: Box<dyn SoundSource> = Box::new(source);
+    assert!(source.channels() > 0);
+    assert!(source.sample_rate() > 0);
+
+    let id = VoiceId(self.next_voice_id);
+    self.next_voice_id += 1;
+
+    self.steal_voices(1);
+    let step = source.sample_rate() as f64 / self.format.sample_rate as f64;
+    self.voices.push(Voice {
+      id,
+      params,
+      source,
+      step,
+      phase: 0.0,
+      current_frame: None,
+      next_frame: None,
+      read_buffer: Vec::new(),
+      read_buffer_pos: 0,
+      source_ended: false,
+      finished: false,
+    });
+    let voice = self.voices.last_mut().unwrap();
+    // Prime the interpolation window, the first output frame will be exactly
+    // the first frame of the source.
+    voice.advance();
+    voice.advance();
+    id
+  }
+
+  fn steal_voices(&mut self, reserve: usize) {
+    let max_len = self.max_voices - reserve.min(self.max_voices);
+    if self.voices.len() > max_len {
+      // Voices are stored in the order of creation.
+      let excess = self.voices.len() - max_len;
+      self.voices.drain(..excess);
+    }
+  }
+
+  #[inline]
+  pub fn is_playing(&self, id: VoiceId) -> bool { self.voices.iter().any(|v| v.id == id) }
+
+  #[inline]
+  pub fn voice_params(&self, id: VoiceId) -> Option<&VoiceParams> {
+    self.voices.iter().find(|v| v.id == id).map(|v| &v.params)
+  }
+
+  #[inline]
+  pub fn voice_params_mut(&mut self, id: VoiceId) -> Option<&mut VoiceParams> {
+    self.voices.iter_mut().find(|v| v.id == id).map(|v| &mut v.params)
+  }
+
+  pub fn stop(&mut self, id: VoiceId) -> bool {
+    let len_before = self.voices.len();
+    self.voices.retain(|v| v.id != id);
+    self.voices.len() != len_before
+  }
+
+  pub fn stop_all(&mut self) { self.voices.clear(); }
+
+  /// Mixes all playing voices into an interleaved buffer in the output format.
+  /// The length of `out` must be a multiple of the channel count.
+  pub fn mix<S: Sample>(&mut self, out: &mut [S]) {
+    let out_channels = self.format.channels as usize;
+    assert!(out.len() % out_channels == 0);
+    let frames = out.len() / out_channels;
+
+    let mut mix_buffer = mem::take(&mut self.mix_buffer);
+    mix_buffer.clear();
+    mix_buffer.resize(frames * 2, 0.0);
+    for voice in &mut self.voices {
+      if !voice.params.paused {
+        voice.mix_into(&mut mix_buffer);
+      }
+    }
+    self.voices.retain(|v| !v.finished);
+
+    let master_volume = self.master_volume;
+    for (out_frame, mix_frame) in
+      out.chunks_exact_mut(out_channels).zip(mix_buffer.chunks_exact(2))
+    {
+      let l = (mix_frame[0] * master_volume).clamp(-1.0, 1.0);
+      let r = (mix_frame[1] * master_volume).clamp(-1.0, 1.0);
+      if out_channels == 1 {
+        out_frame[0] = S::from_f32((l + r) * 0.5);
+      } else {
+        out_frame[0] = S::from_f32(l);
+        out_frame[1] = S::from_f32(r);
+        for sample in &mut out_frame[2..] {
+          *sample = S::from_f32(0.0);
+        }
+      }
+    }
+    self.mix_buffer = mix_buffer;
+  }
+}
+
+struct Voice {
+  id: VoiceId,
+  params: VoiceParams,
+  source: Box<dyn SoundSource>,
+
+  // Resampling is done with linear interpolation between the two source frames
+  // surrounding the current playback position, which is good enough for sound
+  // effects and doesn't require any lookahead.
+  step: f64,
+  phase: f64,
+  current_frame: Option<[f32; 2]>,
+  next_frame: Option<[f32; 2]>,
+
+  read_buffer: Vec<f32>,
+  read_buffer_pos: usize,
+  source_ended: bool,
+  finished: bool,
+}
+
+impl Voice {
+  fn advance(&mut self) {
+    self.current_frame = self.next_frame;
+    self.next_frame = self.read_frame();
+  }
+
+  fn read_frame(&mut self) -> Option<[f32; 2]> {
+    let channels = self.source.channels() as usize;
+    if self.read_buffer_pos >= self.read_buffer.len() {
+      if self.source_ended {
+        return None;
+      }
+      self.read_buffer.resize(SOURCE_READ_BUFFER_FRAMES * channels, 0.0);
+      let len = self.source.read(&mut self.read_buffer);
+      self.read_buffer.truncate(len);
+      self.read_buffer_pos = 0;
+      if len == 0 {
+        self.source_ended = true;
+        return None;
+      }
+    }
+
+    let frame = &self.read_buffer[self.read_buffer_pos..self.read_buffer_pos + channels];
+    self.read_buffer_pos += channels;
+    Some(if channels == 1 { [frame[0], frame[0]] } else { [frame[0], frame[1]] })
+  }
+
+  fn mix_into(&mut self, out: &mut [f32]) {
+    let VoiceParams { volume, pan, .. } = self.params;
+    let pan = pan.clamp(-1.0, 1.0);
+    let gain_l = volume * (1.0 - pan).min(1.0);
+    let gain_r = volume * (1.0 + pan).min(1.0);
+
+    for out_frame in out.chunks_exact_mut(2) {
+      let current_frame = match self.current_frame {
+        Some(frame) => frame,
+        None => {
+          self.finished = true;
+          return;
+        }
+      };
+      let next_frame = self.next_frame.unwrap_or([0.0; 2]);
+
+      let t = self.phase as f32;
+      out_frame[0] += (current_frame[0] + (next_frame[0] - current_frame[0]) * t) * gain_l;
+      out_frame[1] += (current_frame[1] + (next_frame[1] - current_frame[1]) * t) * gain_r;
+
+      self.phase += self.step;
+      while self.phase >= 1.0 {
+        self.phase -= 1.0;
+        self.advance();
+      }
+    }
+  }
+}
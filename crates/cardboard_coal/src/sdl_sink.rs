@@ -0,0 +1,93 @@
+use crate::mixer::{Mixer, OutputFormat};
+use crate::sink::{MixerGuard, OutputSink};
+use prelude_plus::*;
+use sdl2::audio::{AudioCallback, AudioDevice, AudioDeviceLockGuard, AudioSpecDesired};
+use sdl2::AudioSubsystem;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct SdlSinkConfig {
+  pub sample_rate: Option<u32>,
+  pub channels: Option<u16>,
+  /// The size of the device buffer in frames, must be a power of two. Smaller
+  /// buffers lower the latency at the cost of more frequent callbacks.
+  pub buffer_size: Option<u16>,
+}
+
+impl Default for SdlSinkConfig {
+  fn default() -> Self {
+    Self { sample_rate: Some(48000), channels: Some(2), buffer_size: Some(1024) }
+  }
+}
+
+/// Plays the output of a [`Mixer`] through an SDL2 audio device. The mixing
+/// happens in SDL's audio callback thread.
+pub struct SdlSink {
+  device: AudioDevice<MixerCallback>,
+  format: OutputFormat,
+}
+
+impl fmt::Debug for SdlSink {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("SdlSink")
+      .field("format", &self.format)
+      .field("status", &self.device.status())
+      .finish()
+  }
+}
+
+struct MixerCallback(Mixer);
+
+impl AudioCallback for MixerCallback {
+  type Channel = f32;
+  #[inline]
+  fn callback(&mut self, out: &mut [f32]) { self.0.mix(out); }
+}
+
+impl SdlSink {
+  /// Opens the default playback device and immediately starts playing. The
+  /// obtained output format may differ from the requested one.
+  pub fn open(audio_subsystem: &AudioSubsystem, config: SdlSinkConfig) -> Result<Self, String> {
+    let desired_spec = AudioSpecDesired {
+      freq: config.sample_rate.map(|n| n as i32),
+      channels: config.channels.map(|n| n as u8),
+      samples: config.buffer_size,
+    };
+
+    let device = audio_subsystem.open_playback(None, &desired_spec, |spec| {
+      MixerCallback(Mixer::new(OutputFormat {
+        sample_rate: spec.freq as u32,
+        channels: spec.channels as u16,
+      }))
+    })?;
+    let spec = device.spec();
+    let format = OutputFormat { sample_rate: spec.freq as u32, channels: spec.channels as u16 };
+    device.resume();
+
+    Ok(Self { device, format })
+  }
+}
+
+impl OutputSink for SdlSink {
+  #[inline(always)]
+  fn format(&self) -> OutputFormat { self.format }
+
+  fn lock_mixer(&mut self) -> MixerGuard<'_> { Box::new(SdlMixerGuard(self.device.lock())) }
+
+  #[inline]
+  fn pause(&mut self) { self.device.pause(); }
+  #[inline]
+  fn resume(&mut self) { self.device.resume(); }
+}
+
+struct SdlMixerGuard<'a>(AudioDeviceLockGuard<'a, MixerCallback>);
+
+impl<'a> Deref for SdlMixerGuard<'a> {
+  type Target = Mixer;
+  #[inline(always)]
+  fn deref(&self) -> &Self::Target { &self.0 .0 }
+}
+
+impl<'a> DerefMut for SdlMixerGuard<'a> {
+  #[inline(always)]
+  fn deref_mut(&mut self) -> &mut Self::Target { &mut self.0 .0 }
+}
@@ -0,0 +1,156 @@
+use crate::mixer::{Mixer, OutputFormat};
+use prelude_plus::*;
+
+pub type MixerGuard<'a> = Box<dyn DerefMut<Target = Mixer> + 'a>;
+
+/// A destination for the output of a [`Mixer`], which owns the mixer because
+/// depending on the sink it may be driven from a separate audio thread.
+pub trait OutputSink: fmt::Debug {
+  fn format(&self) -> OutputFormat;
+
+  /// Gives access to the mixer for playing sounds and changing voice
+  /// parameters. Sinks which run on an audio thread are blocked until the
+  /// guard is dropped, so don't hold it for long.
+  fn lock_mixer(&mut self) -> MixerGuard<'_>;
+
+  fn pause(&mut self);
+  fn resume(&mut self);
+}
+
+/// A sink which renders into memory on demand, not bound to any real time.
+/// Mostly useful for tests and exporting audio.
+#[derive(Debug)]
+pub struct OfflineSink {
+  mixer: Mixer,
+  output: Vec<f32>,
+  paused: bool,
+}
+
+impl OfflineSink {
+  pub fn new(format: OutputFormat) -> Self {
+    Self { mixer: Mixer::new(format), output: Vec::new(), paused: false }
+  }
+
+  #[inline(always)]
+  pub fn mixer(&mut self) -> &mut Mixer { &mut self.mixer }
+
+  /// Mixes the given number of frames and appends them to the output buffer.
+  /// A paused sink renders silence.
+  pub fn render(&mut self, frames: usize) {
+    let channels = self.mixer.format().channels as usize;
+    let start = self.output.len();
+    self.output.resize(start + frames * channels, 0.0);
+    if !self.paused {
+      self.mixer.mix(&mut self.output[start..]);
+    }
+  }
+
+  #[inline(always)]
+  pub fn output(&self) -> &[f32] { &self.output }
+  #[inline]
+  pub fn take_output(&mut self) -> Vec<f32> { mem::take(&mut self.output) }
+}
+
+impl OutputSink for OfflineSink {
+  #[inline(always)]
+  fn format(&self) -> OutputFormat { self.mixer.format() }
+  #[inline]
+  fn lock_mixer(&mut self) -> MixerGuard<'_> { Box::new(&mut self.mixer) }
+
+  #[inline(always)]
+  fn pause(&mut self) { self.paused = true; }
+  #[inline(always)]
+  fn resume(&mut self) { self.paused = false; }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::mixer::VoiceParams;
+  use crate::source::SoundBuffer;
+
+  const FORMAT: OutputFormat = OutputFormat { sample_rate: 8000, channels: 2 };
+  const FRAMES: usize = 16;
+
+  fn constant_sound(value: f32) -> SoundBuffer {
+    SoundBuffer::new(1, FORMAT.sample_rate, vec![value; FRAMES])
+  }
+
+  fn render(voices: &[(f32, VoiceParams)]) -> Vec<f32> {
+    let mut sink = OfflineSink::new(FORMAT);
+    for &(value, params) in voices {
+      sink.mixer().play(constant_sound(value).play(), params);
+    }
+    sink.render(FRAMES);
+    sink.take_output()
+  }
+
+  fn assert_frames(output: &[f32], expected: [f32; 2]) {
+    assert_eq!(output.len(), FRAMES * 2);
+    for frame in output.chunks_exact(2) {
+      assert!(
+        (frame[0] - expected[0]).abs() < 1e-6 && (frame[1] - expected[1]).abs() < 1e-6,
+        "got {:?}, expected {:?}",
+        frame,
+        expected,
+      );
+    }
+  }
+
+  fn params(volume: f32, pan: f32) -> VoiceParams { VoiceParams { volume, pan, paused: false } }
+
+  #[test]
+  fn voice_volume() {
+    assert_frames(&render(&[(0.5, params(1.0, 0.0))]), [0.5, 0.5]);
+    assert_frames(&render(&[(0.5, params(0.5, 0.0))]), [0.25, 0.25]);
+    assert_frames(&render(&[(0.5, params(0.0, 0.0))]), [0.0, 0.0]);
+  }
+
+  #[test]
+  fn linear_pan() {
+    assert_frames(&render(&[(0.5, params(1.0, -1.0))]), [0.5, 0.0]);
+    assert_frames(&render(&[(0.5, params(1.0, 1.0))]), [0.0, 0.5]);
+    assert_frames(&render(&[(0.5, params(1.0, 0.5))]), [0.25, 0.5]);
+    assert_frames(&render(&[(0.5, params(1.0, -0.5))]), [0.5, 0.25]);
+    // Out of range values are clamped.
+    assert_frames(&render(&[(0.5, params(1.0, -3.0))]), [0.5, 0.0]);
+  }
+
+  #[test]
+  fn voices_are_summed() {
+    let output =
+      render(&[(0.25, params(1.0, 0.0)), (0.5, params(0.5, -1.0)), (-0.125, params(1.0, 1.0))]);
+    assert_frames(&output, [0.25 + 0.25, 0.25 - 0.125]);
+  }
+
+  #[test]
+  fn output_is_clamped() {
+    let loud = [(0.75, params(1.0, 0.0)), (0.75, params(1.0, 0.0))];
+    assert_frames(&render(&loud), [1.0, 1.0]);
+
+    let mut mixer = Mixer::new(FORMAT);
+    mixer.play(constant_sound(0.75).play(), params(1.0, -1.0));
+    mixer.play(constant_sound(0.75).play(), params(1.0, -1.0));
+    mixer.play(constant_sound(-0.75).play(), params(2.0, 1.0));
+    let mut output = [0i16; FRAMES * 2];
+    mixer.mix(&mut output);
+    for frame in output.chunks_exact(2) {
+      assert_eq!(frame, [i16::MAX, -i16::MAX]);
+    }
+  }
+
+  #[test]
+  fn finished_voices_and_pausing() {
+    let mut sink = OfflineSink::new(FORMAT);
+    sink.mixer().play(constant_sound(0.5).play(), VoiceParams::default());
+    sink.pause();
+    sink.render(FRAMES);
+    assert!(sink.output().iter().all(|&sample| sample == 0.0));
+    // The voice doesn't advance while the sink is paused.
+    sink.resume();
+    sink.render(FRAMES * 2);
+    assert_frames(&sink.output()[FRAMES * 2..FRAMES * 4], [0.5, 0.5]);
+    assert!(sink.output()[FRAMES * 4..].iter().all(|&sample| sample == 0.0));
+    assert_eq!(sink.mixer().voice_count(), 0);
+  }
+}
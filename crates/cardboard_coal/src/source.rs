@@ -0,0 +1,326 @@
+use crate::decoder::{DecodeError, Decoder, LoopPoints};
+use prelude_plus::*;
+
+/// Anything which can produce interleaved PCM samples for the [`Mixer`](crate::Mixer).
+/// Sources are moved to the audio thread when played, hence the `Send` bound.
+pub trait SoundSource: Send {
+  fn channels(&self) -> u16;
+  fn sample_rate(&self) -> u32;
+
+  /// Fills `out` with interleaved samples in the range `-1.0..=1.0` and
+  /// returns the number of samples written, which is always a multiple of
+  /// [`Self::channels`]. Returning zero means that the source has ended.
+  fn read(&mut self, out: &mut [f32]) -> usize;
+}
+
+impl<S: SoundSource + ?Sized> SoundSource for Box<S> {
+  #[inline(always)]
+  fn channels(&self) -> u16 { (**self).channels() }
+  #[inline(always)]
+  fn sample_rate(&self) -> u32 { (**self).sample_rate() }
+  #[inline(always)]
+  fn read(&mut self, out: &mut [f32]) -> usize { (**self).read(out) }
+}
+
+/// A fully decoded sound clip. Cloning is cheap since the samples are shared.
+#[derive(Clone)]
+pub struct SoundBuffer {
+  channels: u16,
+  sample_rate: u32,
+  samples: Arc<[f32]>,
+  loop_points: Option<LoopPoints>,
+}
+
+impl fmt::Debug for SoundBuffer {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("SoundBuffer")
+      .field("channels", &self.channels)
+      .field("sample_rate", &self.sample_rate)
+      .field("frames", &self.frames())
+      .field("loop_points", &self.loop_points)
+      .finish()
+  }
+}
+
+impl SoundBuffer {
+  pub fn new(channels: u16, sample_rate: u32, samples: Vec<f32>) -> Self {
+    assert!(channels > 0);
+    assert!(sample_rate > 0);
+    assert!(samples.len() % channels as usize == 0);
+    Self { channels, sample_rate, samples: samples.into(), loop_points: None }
+  }
+
+  /// Decodes the whole stream into memory, keeping the loop markers.
+  pub fn decode(mut decoder: impl Decoder) -> Result<Self, DecodeError> {
+    let mut samples = Vec::new();
+    let mut chunk = vec![0.0; 4096 * decoder.channels() as usize];
+    loop {
+      let len = decoder.read(&mut chunk)?;
+      if len == 0 {
+        break;
+      }
+      samples.extend_from_slice(&chunk[..len]);
+    }
+    let buffer = Self::new(decoder.channels(), decoder.sample_rate(), samples);
+    Ok(buffer.with_loop_points(decoder.loop_points()))
+  }
+
+  /// Generates a mono clip of the given length sample-by-sample, the closure
+  /// receives the time of each sample in seconds.
+  pub fn from_fn(sample_rate: u32, duration: f64, mut f: impl FnMut(f64) -> f32) -> Self {
+    let frames = (duration * sample_rate as f64).round() as usize;
+    let samples = (0..frames).map(|i| f(i as f64 / sample_rate as f64)).collect();
+    Self::new(1, sample_rate, samples)
+  }
+
+  #[inline(always)]
+  pub fn channels(&self) -> u16 { self.channels }
+  #[inline(always)]
+  pub fn sample_rate(&self) -> u32 { self.sample_rate }
+  #[inline(always)]
+  pub fn samples(&self) -> &[f32] { &self.samples }
+  #[inline(always)]
+  pub fn frames(&self) -> usize { self.samples.len() / self.channels as usize }
+  #[inline]
+  pub fn duration(&self) -> f64 { self.frames() as f64 / self.sample_rate as f64 }
+
+  #[inline(always)]
+  pub fn loop_points(&self) -> Option<LoopPoints> { self.loop_points }
+
+  /// Sets the loop markers, which are clamped to the length of the clip. Pass
+  /// [`LoopPoints::WHOLE`] to loop the clip entirely.
+  pub fn with_loop_points(mut self, loop_points: Option<LoopPoints>) -> Self {
+    let frames = self.frames() as u64;
+    self.loop_points = loop_points.and_then(|LoopPoints { start, end }| {
+      let end = end.map_or(frames, |end| end.min(frames));
+      if start < end {
+        Some(LoopPoints { start, end: Some(end) })
+      } else {
+        None
+      }
+    });
+    self
+  }
+
+  #[inline]
+  pub fn play(&self) -> SoundBufferSource { SoundBufferSource::new(self.clone()) }
+}
+
+#[derive(Debug, Clone)]
+pub struct SoundBufferSource {
+  buffer: SoundBuffer,
+  position: usize,
+}
+
+impl SoundBufferSource {
+  #[inline]
+  pub fn new(buffer: SoundBuffer) -> Self { Self { buffer, position: 0 } }
+
+  #[inline(always)]
+  pub fn buffer(&self) -> &SoundBuffer { &self.buffer }
+}
+
+impl SoundSource for SoundBufferSource {
+  #[inline(always)]
+  fn channels(&self) -> u16 { self.buffer.channels }
+  #[inline(always)]
+  fn sample_rate(&self) -> u32 { self.buffer.sample_rate }
+
+  fn read(&mut self, out: &mut [f32]) -> usize {
+    let channels = self.buffer.channels as usize;
+    let out_len = out.len() - out.len() % channels;
+    let mut written = 0;
+    while written < out_len {
+      let (end, loop_start) = match self.buffer.loop_points {
+        Some(LoopPoints { start, end: Some(end) }) => {
+          (end as usize * channels, Some(start as usize * channels))
+        }
+        _ => (self.buffer.samples.len(), None),
+      };
+      if self.position >= end {
+        match loop_start {
+          Some(loop_start) => self.position = loop_start,
+          None => break,
+        }
+      }
+
+      let remaining = &self.buffer.samples[self.position..end];
+      let len = remaining.len().min(out_len - written);
+      out[written..written + len].copy_from_slice(&remaining[..len]);
+      self.position += len;
+      written += len;
+    }
+    written
+  }
+}
+
+/// Decodes the sound on the fly, in chunks requested by the mixer, which is
+/// the preferred way of playing long music tracks.
+#[derive(Debug)]
+pub struct StreamingSource<D: Decoder> {
+  decoder: D,
+  loop_points: Option<LoopPoints>,
+  position: u64,
+  ended: bool,
+}
+
+impl<D: Decoder> StreamingSource<D> {
+  /// Uses the loop markers from the decoder, see [`Self::set_loop_points`] for
+  /// overriding them.
+  pub fn new(decoder: D) -> Self {
+    let loop_points = decoder.loop_points();
+    Self { decoder, loop_points, position: 0, ended: false }
+  }
+
+  #[inline(always)]
+  pub fn decoder(&self) -> &D { &self.decoder }
+
+  #[inline(always)]
+  pub fn loop_points(&self) -> Option<LoopPoints> { self.loop_points }
+  #[inline(always)]
+  pub fn set_loop_points(&mut self, loop_points: Option<LoopPoints>) {
+    self.loop_points = loop_points;
+  }
+
+  #[inline(always)]
+  pub fn position(&self) -> u64 { self.position }
+
+  fn seek_to_loop_start(&mut self, loop_start: u64) -> Result<(), DecodeError> {
+    self.decoder.seek(loop_start)?;
+    self.position = loop_start;
+    Ok(())
+  }
+
+  fn try_read(&mut self, out: &mut [f32]) -> Result<usize, DecodeError> {
+    let channels = self.decoder.channels() as usize;
+    let out_len = out.len() - out.len() % channels;
+    let mut written = 0;
+    // Guards against looping endlessly when the loop start is past the end of
+    // the stream.
+    let mut just_looped = false;
+    while written < out_len && !self.ended {
+      let mut limit = out_len - written;
+      if let Some(LoopPoints { start, end: Some(end) }) = self.loop_points {
+        if self.position >= end {
+          self.seek_to_loop_start(start)?;
+          just_looped = true;
+          continue;
+        }
+        limit = limit.min(((end - self.position) as usize).saturating_mul(channels));
+      }
+
+      let len = self.decoder.read(&mut out[written..written + limit])?;
+      if len == 0 {
+        match self.loop_points {
+          Some(LoopPoints { start, .. }) if !just_looped => {
+            self.seek_to_loop_start(start)?;
+            just_looped = true;
+          }
+          _ => self.ended = true,
+        }
+        continue;
+      }
+
+      just_looped = false;
+      written += len;
+      self.position += (len / channels) as u64;
+    }
+    Ok(written)
+  }
+}
+
+impl<D: Decoder> SoundSource for StreamingSource<D> {
+  #[inline(always)]
+  fn channels(&self) -> u16 { self.decoder.channels() }
+  #[inline(always)]
+  fn sample_rate(&self) -> u32 { self.decoder.sample_rate() }
+
+  fn read(&mut self, out: &mut [f32]) -> usize {
+    match self.try_read(out) {
+      Ok(len) => len,
+      Err(e) => {
+        // The errors can't be propagated from the audio thread, so the stream
+        // is just stopped.
+        error!("Failed to decode an audio stream: {}", e);
+        self.ended = true;
+        0
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_utils::{pcm16_wav, smpl_chunk, SAMPLE_RATE};
+  use crate::wav::WavDecoder;
+
+  /// The sample `i` of the clips in these tests has the value `i / 16`.
+  fn ramp(frames: i16) -> Vec<i16> { (0..frames).map(|i| i * 2048).collect() }
+
+  fn to_indices(samples: &[f32]) -> Vec<i32> {
+    samples.iter().map(|&sample| (sample * 16.0).round() as i32).collect()
+  }
+
+  fn streaming_source(wav: Vec<u8>) -> StreamingSource<WavDecoder<io::Cursor<Vec<u8>>>> {
+    StreamingSource::new(WavDecoder::new(io::Cursor::new(wav)).unwrap())
+  }
+
+  /// Reads in chunks of varying numbers of frames, so that the loop end falls
+  /// both inside of the chunks and on their boundaries.
+  fn read_samples(source: &mut impl SoundSource, len: usize) -> Vec<f32> {
+    let mut samples = Vec::new();
+    for chunk_len in [1, 2, 3, 5, 7].iter().cycle() {
+      if samples.len() >= len {
+        break;
+      }
+      let chunk_len = chunk_len * source.channels() as usize;
+      let mut chunk = vec![0.0; chunk_len.min(len - samples.len())];
+      let read = source.read(&mut chunk);
+      if read == 0 {
+        break;
+      }
+      samples.extend_from_slice(&chunk[..read]);
+    }
+    samples
+  }
+
+  #[test]
+  fn streaming_source_wraps_at_the_loop_end() {
+    let mut source = streaming_source(pcm16_wav(1, &ramp(8), Some(smpl_chunk(2, 4))));
+    assert_eq!(source.loop_points(), Some(LoopPoints { start: 2, end: Some(5) }));
+    let expected = [0, 1, 2, 3, 4, 2, 3, 4, 2, 3, 4, 2, 3, 4, 2, 3, 4, 2, 3, 4];
+    assert_eq!(to_indices(&read_samples(&mut source, expected.len())), expected);
+  }
+
+  #[test]
+  fn streaming_source_wraps_at_the_end_of_the_stream() {
+    let mut source = streaming_source(pcm16_wav(2, &ramp(6), None));
+    assert_eq!(to_indices(&read_samples(&mut source, 100)), [0, 1, 2, 3, 4, 5]);
+
+    let mut source = streaming_source(pcm16_wav(2, &ramp(6), None));
+    source.set_loop_points(Some(LoopPoints { start: 1, end: None }));
+    let expected = [0, 1, 2, 3, 4, 5, 2, 3, 4, 5, 2, 3, 4, 5];
+    assert_eq!(to_indices(&read_samples(&mut source, expected.len())), expected);
+  }
+
+  #[test]
+  fn streaming_source_stops_on_an_invalid_loop() {
+    let mut source = streaming_source(pcm16_wav(1, &ramp(4), None));
+    source.set_loop_points(Some(LoopPoints { start: 10, end: None }));
+    assert_eq!(to_indices(&read_samples(&mut source, 100)), [0, 1, 2, 3]);
+  }
+
+  #[test]
+  fn buffer_source_wraps_at_the_loop_end() {
+    let decoder = WavDecoder::new(io::Cursor::new(pcm16_wav(1, &ramp(8), Some(smpl_chunk(2, 4)))));
+    let buffer = SoundBuffer::decode(decoder.unwrap()).unwrap();
+    assert_eq!(buffer.sample_rate(), SAMPLE_RATE);
+    assert_eq!(buffer.loop_points(), Some(LoopPoints { start: 2, end: Some(5) }));
+    let expected = [0, 1, 2, 3, 4, 2, 3, 4, 2, 3, 4, 2];
+    assert_eq!(to_indices(&read_samples(&mut buffer.play(), expected.len())), expected);
+
+    let buffer = buffer.with_loop_points(None);
+    assert_eq!(to_indices(&read_samples(&mut buffer.play(), 100)), [0, 1, 2, 3, 4, 5, 6, 7]);
+  }
+}
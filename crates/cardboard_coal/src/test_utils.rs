@@ -0,0 +1,76 @@
+//! Builders of the test files and other helpers shared by the tests of this
+//! crate and of its users, enabled with the `test_utils` feature.
+
+use crate::decoder::Decoder;
+use crate::wav::WAVE_FORMAT_PCM;
+
+/// The sample rate of the files built here.
+pub const SAMPLE_RATE: u32 = 8000;
+
+pub fn fmt_chunk(format_tag: u16, channels: u16, bits_per_sample: u16) -> Vec<u8> {
+  let block_align = channels * bits_per_sample / 8;
+  let mut bytes = Vec::new();
+  bytes.extend_from_slice(&format_tag.to_le_bytes());
+  bytes.extend_from_slice(&channels.to_le_bytes());
+  bytes.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+  bytes.extend_from_slice(&(SAMPLE_RATE * block_align as u32).to_le_bytes());
+  bytes.extend_from_slice(&block_align.to_le_bytes());
+  bytes.extend_from_slice(&bits_per_sample.to_le_bytes());
+  bytes
+}
+
+/// With a single loop, `end` points to the last frame of it.
+pub fn smpl_chunk(start: u32, end: u32) -> Vec<u8> {
+  let mut bytes = vec![0; 36];
+  bytes[28..32].copy_from_slice(&1u32.to_le_bytes());
+  let mut sample_loop = [0; 24];
+  sample_loop[8..12].copy_from_slice(&start.to_le_bytes());
+  sample_loop[12..16].copy_from_slice(&end.to_le_bytes());
+  bytes.extend_from_slice(&sample_loop);
+  bytes
+}
+
+pub fn pcm16(samples: &[i16]) -> Vec<u8> {
+  samples.iter().flat_map(|sample| sample.to_le_bytes()).collect()
+}
+
+pub fn build_wav(chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+  let mut bytes = Vec::new();
+  bytes.extend_from_slice(b"RIFF");
+  bytes.extend_from_slice(&0u32.to_le_bytes());
+  bytes.extend_from_slice(b"WAVE");
+  for (id, data) in chunks {
+    bytes.extend_from_slice(*id);
+    bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(data);
+    if data.len() % 2 != 0 {
+      bytes.push(0);
+    }
+  }
+  let riff_size = bytes.len() as u32 - 8;
+  bytes[4..8].copy_from_slice(&riff_size.to_le_bytes());
+  bytes
+}
+
+/// A complete 16-bit PCM file, optionally with loop markers.
+pub fn pcm16_wav(channels: u16, samples: &[i16], smpl: Option<Vec<u8>>) -> Vec<u8> {
+  let fmt = fmt_chunk(WAVE_FORMAT_PCM, channels, 16);
+  let data = pcm16(samples);
+  match smpl {
+    Some(smpl) => build_wav(&[(b"fmt ", &fmt), (b"smpl", &smpl), (b"data", &data)]),
+    None => build_wav(&[(b"fmt ", &fmt), (b"data", &data)]),
+  }
+}
+
+/// Reads in small chunks, so that the partial reads get exercised too.
+pub fn read_all(decoder: &mut impl Decoder) -> Vec<f32> {
+  let mut samples = Vec::new();
+  let mut chunk = [0.0; 5];
+  loop {
+    let len = decoder.read(&mut chunk).unwrap();
+    if len == 0 {
+      return samples;
+    }
+    samples.extend_from_slice(&chunk[..len]);
+  }
+}
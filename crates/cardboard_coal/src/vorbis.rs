@@ -0,0 +1,249 @@
+use crate::decoder::{DecodeError, Decoder, LoopPoints};
+use lewton::audio::AudioReadError;
+use lewton::inside_ogg::OggStreamReader;
+use lewton::samples::InterleavedSamples;
+use lewton::VorbisError;
+use prelude_plus::*;
+
+/// Seeking in Ogg files is only possible with a page granularity, so the
+/// decoder seeks a bit before the requested position and then discards the
+/// excess samples. This must be larger than the biggest Vorbis block size,
+/// because the first packet after a seek doesn't produce any samples.
+const SEEK_PREROLL_FRAMES: u64 = 8192;
+
+pub struct VorbisDecoder<R: Read + Seek> {
+  // This is an Option only so that the reader can be moved out of the stream
+  // reader when rewinding.
+  stream: Option<OggStreamReader<R>>,
+  channels: u16,
+  sample_rate: u32,
+  loop_points: Option<LoopPoints>,
+
+  packet: Vec<f32>,
+  packet_pos: usize,
+  /// The number of frames which have to be dropped from the following packets
+  /// to finish a seek.
+  frames_to_skip: u64,
+}
+
+impl<R: Read + Seek> fmt::Debug for VorbisDecoder<R> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("VorbisDecoder")
+      .field("channels", &self.channels)
+      .field("sample_rate", &self.sample_rate)
+      .field("loop_points", &self.loop_points)
+      .finish()
+  }
+}
+
+impl<R: Read + Seek> VorbisDecoder<R> {
+  pub fn new(reader: R) -> Result<Self, DecodeError> {
+    let stream = OggStreamReader::new(reader)?;
+    let channels = stream.ident_hdr.audio_channels as u16;
+    let sample_rate = stream.ident_hdr.audio_sample_rate;
+    let loop_points = parse_loop_comments(&stream.comment_hdr.comment_list);
+    Ok(Self {
+      stream: Some(stream),
+      channels,
+      sample_rate,
+      loop_points,
+      packet: Vec::new(),
+      packet_pos: 0,
+      frames_to_skip: 0,
+    })
+  }
+
+  #[inline(always)]
+  pub fn comments(&self) -> &[(String, String)] { &self.stream().comment_hdr.comment_list }
+
+  #[inline(always)]
+  fn stream(&self) -> &OggStreamReader<R> { self.stream.as_ref().unwrap() }
+  #[inline(always)]
+  fn stream_mut(&mut self) -> &mut OggStreamReader<R> { self.stream.as_mut().unwrap() }
+
+  fn read_packet(&mut self) -> Result<Option<Vec<f32>>, DecodeError> {
+    let packet: Option<InterleavedSamples<f32>> = self.stream_mut().read_dec_packet_generic()?;
+    Ok(packet.map(|p| p.samples))
+  }
+
+  fn rewind(&mut self) -> Result<(), DecodeError> {
+    let mut reader = self.stream.take().unwrap().into_inner().into_inner();
+    reader.seek(io::SeekFrom::Start(0))?;
+    self.stream = Some(OggStreamReader::new(reader)?);
+    Ok(())
+  }
+}
+
+/// Reads the loop markers in the format used by RPG Maker and a lot of other
+/// engines: `LOOPSTART` plus either `LOOPLENGTH` or `LOOPEND`, all in samples.
+fn parse_loop_comments(comments: &[(String, String)]) -> Option<LoopPoints> {
+  let find = |key: &str| -> Option<u64> {
+    comments
+      .iter()
+      .find(|(k, _)| k.eq_ignore_ascii_case(key))
+      .and_then(|(_, v)| v.trim().parse().ok())
+  };
+  let start = find("LOOPSTART")?;
+  let end = match (find("LOOPLENGTH"), find("LOOPEND")) {
+    (Some(length), _) => Some(start + length),
+    (None, Some(end)) => Some(end),
+    (None, None) => None,
+  };
+  match end {
+    Some(end) if end <= start => None,
+    _ => Some(LoopPoints { start, end }),
+  }
+}
+
+impl<R: Read + Seek + Send> Decoder for VorbisDecoder<R> {
+  #[inline(always)]
+  fn channels(&self) -> u16 { self.channels }
+  #[inline(always)]
+  fn sample_rate(&self) -> u32 { self.sample_rate }
+  #[inline(always)]
+  fn loop_points(&self) -> Option<LoopPoints> { self.loop_points }
+
+  fn read(&mut self, out: &mut [f32]) -> Result<usize, DecodeError> {
+    let channels = self.channels as usize;
+    while self.packet_pos >= self.packet.len() {
+      let packet = match self.read_packet()? {
+        Some(packet) => packet,
+        None => return Ok(0),
+      };
+      // Chained streams with a different layout are not supported.
+      if self.stream().ident_hdr.audio_channels as u16 != self.channels {
+        return Err(DecodeError::Unsupported("change of the channel count".to_owned()));
+      }
+      let skipped_frames = (packet.len() / channels).min(self.frames_to_skip as usize);
+      self.frames_to_skip -= skipped_frames as u64;
+      self.packet = packet;
+      self.packet_pos = skipped_frames * channels;
+    }
+
+    let remaining = &self.packet[self.packet_pos..];
+    let len = remaining.len().min(out.len() - out.len() % channels);
+    out[..len].copy_from_slice(&remaining[..len]);
+    self.packet_pos += len;
+    Ok(len)
+  }
+
+  fn seek(&mut self, frame: u64) -> Result<(), DecodeError> {
+    self.packet.clear();
+    self.packet_pos = 0;
+
+    if frame < SEEK_PREROLL_FRAMES {
+      self.rewind()?;
+      self.frames_to_skip = frame;
+      return Ok(());
+    }
+
+    self.stream_mut().seek_absgp_pg(frame - SEEK_PREROLL_FRAMES)?;
+    // The position of the decoded samples becomes known only after the end of
+    // a page is reached, so the packets until then are buffered.
+    let mut buffered_packets: Vec<Vec<f32>> = Vec::new();
+    let end_pos = loop {
+      match self.read_packet() {
+        Ok(Some(packet)) => buffered_packets.push(packet),
+        // The Ogg seek lands on the header pages when the requested position is
+        // inside the first audio page, their packets are simply skipped.
+        Err(DecodeError::Vorbis(VorbisError::BadAudio(AudioReadError::AudioIsHeader))) => {}
+        Err(e) => return Err(e),
+        Ok(None) => {
+          // The seek has landed past the end of the stream.
+          self.frames_to_skip = 0;
+          return Ok(());
+        }
+      }
+      if let Some(absgp) = self.stream().get_last_absgp() {
+        break absgp;
+      }
+    };
+
+    let channels = self.channels as usize;
+    let buffered_frames: u64 = buffered_packets.iter().map(|p| (p.len() / channels) as u64).sum();
+    let start_pos = end_pos.saturating_sub(buffered_frames);
+    if start_pos > frame {
+      // Shouldn't happen with a sane preroll, but just in case fall back to the
+      // slow path.
+      self.rewind()?;
+      self.frames_to_skip = frame;
+      return Ok(());
+    }
+
+    self.packet = buffered_packets.concat();
+    let skipped_frames = (frame - start_pos).min(buffered_frames);
+    self.packet_pos = skipped_frames as usize * channels;
+    self.frames_to_skip = frame - start_pos - skipped_frames;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_utils::read_all;
+
+  /// Generated by `tests/make_loop_ogg.py`: 38400 frames of stereo noise, with
+  /// a loop from 1000 to 21000.
+  const LOOP_OGG: &[u8] = include_bytes!("../tests/loop.ogg");
+  const LOOP_OGG_FRAMES: usize = 38400;
+
+  fn open_fixture() -> VorbisDecoder<io::Cursor<&'static [u8]>> {
+    VorbisDecoder::new(io::Cursor::new(LOOP_OGG)).unwrap()
+  }
+
+  fn comments(list: &[(&str, &str)]) -> Vec<(String, String)> {
+    list.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+  }
+
+  #[test]
+  fn parses_loop_comments() {
+    let parse = |list: &[(&str, &str)]| parse_loop_comments(&comments(list));
+    assert_eq!(
+      parse(&[("LOOPSTART", "100"), ("LOOPLENGTH", "50")]),
+      Some(LoopPoints { start: 100, end: Some(150) }),
+    );
+    assert_eq!(
+      parse(&[("loopend", "300"), ("TITLE", "x"), ("LoopStart", " 100 ")]),
+      Some(LoopPoints { start: 100, end: Some(300) }),
+    );
+    assert_eq!(
+      parse(&[("LOOPSTART", "100"), ("LOOPLENGTH", "50"), ("LOOPEND", "300")]),
+      Some(LoopPoints { start: 100, end: Some(150) }),
+    );
+    assert_eq!(parse(&[("LOOPSTART", "100")]), Some(LoopPoints { start: 100, end: None }));
+    assert_eq!(parse(&[("LOOPLENGTH", "50")]), None);
+    assert_eq!(parse(&[("LOOPSTART", "-1"), ("LOOPLENGTH", "50")]), None);
+    assert_eq!(parse(&[("LOOPSTART", "100"), ("LOOPLENGTH", "0")]), None);
+    assert_eq!(parse(&[("LOOPSTART", "100"), ("LOOPEND", "99")]), None);
+    assert_eq!(parse(&[]), None);
+  }
+
+  #[test]
+  fn decodes_the_fixture() {
+    let mut decoder = open_fixture();
+    assert_eq!(decoder.channels(), 2);
+    assert_eq!(decoder.sample_rate(), 8000);
+    assert_eq!(decoder.loop_points(), Some(LoopPoints { start: 1000, end: Some(21000) }));
+    assert!(decoder
+      .comments()
+      .contains(&("TITLE".to_owned(), "cardboard_coal fixture".to_owned())));
+
+    let samples = read_all(&mut decoder);
+    assert_eq!(samples.len(), LOOP_OGG_FRAMES * 2);
+    assert!(samples.iter().any(|&sample| sample != 0.0));
+  }
+
+  #[test]
+  fn seeks_to_exact_frames() {
+    let samples = read_all(&mut open_fixture());
+    let mut decoder = open_fixture();
+    // Covers the rewinding path, seeking into the middle of a page, onto a
+    // page boundary, backwards and to the very end.
+    for &frame in &[0, 5, 8191, 8192, 20000, 159 * 128, 1000, 38399, 38400, 40000] {
+      decoder.seek(frame as u64).unwrap();
+      let expected = &samples[(frame * 2).min(samples.len())..];
+      assert!(read_all(&mut decoder) == expected, "frame {}", frame);
+    }
+  }
+}
@@ -0,0 +1,359 @@
+//! A minimal RIFF WAVE reader. Supports integer PCM with 8, 16, 24 and 32 bits
+//! per sample, 32 and 64-bit floats and loop markers from the `smpl` chunk.
+//! <http://soundfile.sapp.org/doc/WaveFormat/>
+//! <https://www.recordingblogs.com/wiki/sample-chunk-of-a-wave-file>
+
+use crate::decoder::{DecodeError, Decoder, LoopPoints};
+use prelude_plus::*;
+
+pub(crate) const WAVE_FORMAT_PCM: u16 = 0x0001;
+pub(crate) const WAVE_FORMAT_IEEE_FLOAT: u16 = 0x0003;
+pub(crate) const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+const READ_BUFFER_SIZE: usize = 4096;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum SampleFormat {
+  Int,
+  Float,
+}
+
+#[derive(Debug)]
+pub struct WavDecoder<R: Read + Seek> {
+  reader: R,
+  channels: u16,
+  sample_rate: u32,
+  sample_format: SampleFormat,
+  bytes_per_sample: u16,
+  block_align: u16,
+  loop_points: Option<LoopPoints>,
+
+  data_offset: u64,
+  total_frames: u64,
+  position: u64,
+  read_buffer: Vec<u8>,
+}
+
+fn invalid_data(msg: &str) -> DecodeError { DecodeError::InvalidData(msg.to_owned()) }
+
+fn read_u16_le(bytes: &[u8]) -> u16 { u16::from_le_bytes([bytes[0], bytes[1]]) }
+fn read_u32_le(bytes: &[u8]) -> u32 {
+  u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+impl<R: Read + Seek> WavDecoder<R> {
+  pub fn new(mut reader: R) -> Result<Self, DecodeError> {
+    let mut header = [0u8; 12];
+    reader.read_exact(&mut header)?;
+    if &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+      return Err(invalid_data("not a RIFF WAVE file"));
+    }
+
+    let mut fmt_chunk: Option<Vec<u8>> = None;
+    let mut smpl_chunk: Option<Vec<u8>> = None;
+    let mut data_chunk: Option<(u64, u64)> = None;
+
+    loop {
+      let mut chunk_header = [0u8; 8];
+      match reader.read_exact(&mut chunk_header) {
+        Ok(()) => {}
+        // Some encoders write a bogus RIFF size, so the chunks are simply read
+        // until the end of the file.
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+        Err(e) => return Err(e.into()),
+      }
+      let chunk_id = &chunk_header[0..4];
+      let chunk_size = read_u32_le(&chunk_header[4..8]) as u64;
+      // Chunks are padded to an even number of bytes.
+      let padded_chunk_size = chunk_size + (chunk_size & 1);
+
+      match chunk_id {
+        b"fmt " | b"smpl" => {
+          let mut bytes = vec![0; chunk_size as usize];
+          reader.read_exact(&mut bytes)?;
+          reader.seek(io::SeekFrom::Current((padded_chunk_size - chunk_size) as i64))?;
+          if chunk_id == b"fmt " {
+            fmt_chunk = Some(bytes);
+          } else {
+            smpl_chunk = Some(bytes);
+          }
+        }
+        b"data" => {
+          let offset = reader.stream_position()?;
+          data_chunk = Some((offset, chunk_size));
+          reader.seek(io::SeekFrom::Current(padded_chunk_size as i64))?;
+        }
+        _ => {
+          reader.seek(io::SeekFrom::Current(padded_chunk_size as i64))?;
+        }
+      }
+    }
+
+    let fmt_chunk = fmt_chunk.ok_or_else(|| invalid_data("the fmt chunk is missing"))?;
+    let (data_offset, data_size) =
+      data_chunk.ok_or_else(|| invalid_data("the data chunk is missing"))?;
+    if fmt_chunk.len() < 16 {
+      return Err(invalid_data("the fmt chunk is too short"));
+    }
+
+    let mut format_tag = read_u16_le(&fmt_chunk[0..2]);
+    let channels = read_u16_le(&fmt_chunk[2..4]);
+    let sample_rate = read_u32_le(&fmt_chunk[4..8]);
+    let block_align = read_u16_le(&fmt_chunk[12..14]);
+    let bits_per_sample = read_u16_le(&fmt_chunk[14..16]);
+    if format_tag == WAVE_FORMAT_EXTENSIBLE {
+      if fmt_chunk.len() < 26 {
+        return Err(invalid_data("the extensible fmt chunk is too short"));
+      }
+      // The first two bytes of the sub-format GUID are the actual format tag.
+      format_tag = read_u16_le(&fmt_chunk[24..26]);
+    }
+
+    let sample_format = match (format_tag, bits_per_sample) {
+      (WAVE_FORMAT_PCM, 8 | 16 | 24 | 32) => SampleFormat::Int,
+      (WAVE_FORMAT_IEEE_FLOAT, 32 | 64) => SampleFormat::Float,
+      _ => {
+        return Err(DecodeError::Unsupported(format!(
+          "format tag 0x{:04x} with {} bits per sample",
+          format_tag, bits_per_sample
+        )))
+      }
+    };
+    let bytes_per_sample = bits_per_sample / 8;
+    if channels == 0
+      || sample_rate == 0
+      || block_align as u32 != bytes_per_sample as u32 * channels as u32
+    {
+      return Err(invalid_data("inconsistent fmt chunk"));
+    }
+    // NOTE: The reads are done in chunks of whole frames, so at least one has
+    // to fit into the buffer.
+    if block_align as usize > READ_BUFFER_SIZE {
+      return Err(DecodeError::InvalidData(format!("too many channels: {}", channels)));
+    }
+    let total_frames = data_size / block_align as u64;
+
+    let loop_points = smpl_chunk.as_deref().and_then(|smpl| {
+      // The fixed part of the chunk is 36 bytes long, the first loop follows.
+      if smpl.len() < 36 + 24 || read_u32_le(&smpl[28..32]) == 0 {
+        return None;
+      }
+      let sample_loop = &smpl[36..36 + 24];
+      let start = read_u32_le(&sample_loop[8..12]) as u64;
+      // The end marker points to the last sample of the loop.
+      let end = read_u32_le(&sample_loop[12..16]) as u64 + 1;
+      if start < end && end <= total_frames {
+        Some(LoopPoints { start, end: Some(end) })
+      } else {
+        None
+      }
+    });
+
+    reader.seek(io::SeekFrom::Start(data_offset))?;
+    Ok(Self {
+      reader,
+      channels,
+      sample_rate,
+      sample_format,
+      bytes_per_sample,
+      block_align,
+      loop_points,
+      data_offset,
+      total_frames,
+      position: 0,
+      read_buffer: Vec::new(),
+    })
+  }
+
+  #[inline(always)]
+  pub fn total_frames(&self) -> u64 { self.total_frames }
+
+  #[inline(always)]
+  pub fn into_inner(self) -> R { self.reader }
+}
+
+impl<R: Read + Seek + Send> Decoder for WavDecoder<R> {
+  #[inline(always)]
+  fn channels(&self) -> u16 { self.channels }
+  #[inline(always)]
+  fn sample_rate(&self) -> u32 { self.sample_rate }
+  #[inline(always)]
+  fn loop_points(&self) -> Option<LoopPoints> { self.loop_points }
+
+  fn read(&mut self, out: &mut [f32]) -> Result<usize, DecodeError> {
+    let channels = self.channels as usize;
+    let block_align = self.block_align as usize;
+    let remaining_frames = self.total_frames.saturating_sub(self.position);
+    let frames = (out.len() / channels)
+      .min(READ_BUFFER_SIZE / block_align)
+      .min(remaining_frames.try_into().unwrap_or(usize::MAX));
+    if frames == 0 {
+      return Ok(0);
+    }
+
+    self.read_buffer.resize(frames * block_align, 0);
+    self.reader.read_exact(&mut self.read_buffer)?;
+
+    let bytes_per_sample = self.bytes_per_sample as usize;
+    let sample_format = self.sample_format;
+    for (sample, bytes) in out.iter_mut().zip(self.read_buffer.chunks_exact(bytes_per_sample)) {
+      *sample = match (sample_format, bytes_per_sample) {
+        (SampleFormat::Int, 1) => (bytes[0] as f32 - 128.0) / 128.0,
+        (SampleFormat::Int, 2) => i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 32768.0,
+        (SampleFormat::Int, 3) => {
+          // Shifting into the upper bytes of an i32 sign-extends the value.
+          (i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]) >> 8) as f32 / 8388608.0
+        }
+        (SampleFormat::Int, 4) => {
+          i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f32 / 2147483648.0
+        }
+        (SampleFormat::Float, 4) => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+        (SampleFormat::Float, 8) => f64::from_le_bytes(bytes.try_into().unwrap()) as f32,
+        _ => unreachable!(),
+      };
+    }
+
+    self.position += frames as u64;
+    Ok(frames * channels)
+  }
+
+  fn seek(&mut self, frame: u64) -> Result<(), DecodeError> {
+    let frame = frame.min(self.total_frames);
+    self.reader.seek(io::SeekFrom::Start(self.data_offset + frame * self.block_align as u64))?;
+    self.position = frame;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_utils::*;
+
+  fn open(bytes: Vec<u8>) -> Result<WavDecoder<io::Cursor<Vec<u8>>>, DecodeError> {
+    WavDecoder::new(io::Cursor::new(bytes))
+  }
+
+  #[test]
+  fn decodes_pcm16() {
+    let mut decoder = open(pcm16_wav(2, &[0, 16384, -32768, 32767, -16384, 0], None)).unwrap();
+    assert_eq!(decoder.channels(), 2);
+    assert_eq!(decoder.sample_rate(), SAMPLE_RATE);
+    assert_eq!(decoder.total_frames(), 3);
+    assert_eq!(decoder.loop_points(), None);
+    assert_eq!(read_all(&mut decoder), [0.0, 0.5, -1.0, 32767.0 / 32768.0, -0.5, 0.0]);
+  }
+
+  #[test]
+  fn decodes_other_sample_formats() {
+    let wav = |format_tag: u16, bits_per_sample: u16, data: &[u8]| {
+      let fmt = fmt_chunk(format_tag, 1, bits_per_sample);
+      read_all(&mut open(build_wav(&[(b"fmt ", &fmt), (b"data", data)])).unwrap())
+    };
+    assert_eq!(wav(WAVE_FORMAT_PCM, 8, &[0, 128, 192]), [-1.0, 0.0, 0.5]);
+    assert_eq!(wav(WAVE_FORMAT_PCM, 24, &[0, 0, 0x80, 0, 0, 0x40, 0, 0, 0]), [-1.0, 0.5, 0.0]);
+    assert_eq!(wav(WAVE_FORMAT_PCM, 32, &(-1i32 << 30).to_le_bytes()), [-0.5]);
+    assert_eq!(wav(WAVE_FORMAT_IEEE_FLOAT, 32, &0.25f32.to_le_bytes()), [0.25]);
+    assert_eq!(wav(WAVE_FORMAT_IEEE_FLOAT, 64, &(-0.75f64).to_le_bytes()), [-0.75]);
+  }
+
+  #[test]
+  fn reads_the_extensible_format() {
+    let mut fmt = fmt_chunk(WAVE_FORMAT_EXTENSIBLE, 1, 16);
+    fmt.extend_from_slice(&22u16.to_le_bytes());
+    fmt.extend_from_slice(&16u16.to_le_bytes());
+    fmt.extend_from_slice(&0u32.to_le_bytes());
+    let mut sub_format = [0; 16];
+    sub_format[0..2].copy_from_slice(&WAVE_FORMAT_PCM.to_le_bytes());
+    fmt.extend_from_slice(&sub_format);
+    let data = pcm16(&[16384]);
+    let mut decoder = open(build_wav(&[(b"fmt ", &fmt), (b"data", &data)])).unwrap();
+    assert_eq!(read_all(&mut decoder), [0.5]);
+  }
+
+  #[test]
+  fn skips_unknown_and_padded_chunks() {
+    let fmt = fmt_chunk(WAVE_FORMAT_PCM, 1, 16);
+    let data = pcm16(&[16384, -16384]);
+    let wav =
+      build_wav(&[(b"LIST", b"odd"), (b"fmt ", &fmt), (b"junk", &[1; 5]), (b"data", &data)]);
+    assert_eq!(read_all(&mut open(wav).unwrap()), [0.5, -0.5]);
+  }
+
+  #[test]
+  fn reads_loop_markers() {
+    let decoder = open(pcm16_wav(1, &[0; 8], Some(smpl_chunk(2, 4)))).unwrap();
+    assert_eq!(decoder.loop_points(), Some(LoopPoints { start: 2, end: Some(5) }));
+
+    let decoder = open(pcm16_wav(1, &[0; 8], Some(smpl_chunk(0, 7)))).unwrap();
+    assert_eq!(decoder.loop_points(), Some(LoopPoints { start: 0, end: Some(8) }));
+  }
+
+  #[test]
+  fn ignores_invalid_loop_markers() {
+    // Past the end of the data.
+    let decoder = open(pcm16_wav(1, &[0; 8], Some(smpl_chunk(2, 8)))).unwrap();
+    assert_eq!(decoder.loop_points(), None);
+    // Backwards.
+    let decoder = open(pcm16_wav(1, &[0; 8], Some(smpl_chunk(5, 2)))).unwrap();
+    assert_eq!(decoder.loop_points(), None);
+    // No loops in the chunk.
+    let mut smpl = smpl_chunk(2, 4);
+    smpl[28..32].copy_from_slice(&0u32.to_le_bytes());
+    let decoder = open(pcm16_wav(1, &[0; 8], Some(smpl))).unwrap();
+    assert_eq!(decoder.loop_points(), None);
+    // Truncated.
+    let decoder = open(pcm16_wav(1, &[0; 8], Some(smpl_chunk(2, 4)[..40].to_vec()))).unwrap();
+    assert_eq!(decoder.loop_points(), None);
+  }
+
+  #[test]
+  fn seeks() {
+    let mut decoder = open(pcm16_wav(1, &[0, 8192, 16384, -16384], None)).unwrap();
+    decoder.seek(2).unwrap();
+    assert_eq!(read_all(&mut decoder), [0.5, -0.5]);
+    decoder.seek(1).unwrap();
+    assert_eq!(read_all(&mut decoder), [0.25, 0.5, -0.5]);
+    decoder.seek(100).unwrap();
+    assert_eq!(read_all(&mut decoder), []);
+  }
+
+  #[test]
+  fn rejects_invalid_files() {
+    let fmt = fmt_chunk(WAVE_FORMAT_PCM, 1, 16);
+    let data = pcm16(&[0]);
+
+    let mut not_wave = build_wav(&[(b"fmt ", &fmt), (b"data", &data)]);
+    not_wave[8..12].copy_from_slice(b"AVI ");
+    assert!(matches!(open(not_wave), Err(DecodeError::InvalidData(_))));
+    assert!(matches!(open(b"RIFF".to_vec()), Err(DecodeError::Io(_))));
+
+    let no_data = build_wav(&[(b"fmt ", &fmt)]);
+    assert!(matches!(open(no_data), Err(DecodeError::InvalidData(_))));
+    let no_fmt = build_wav(&[(b"data", &data)]);
+    assert!(matches!(open(no_fmt), Err(DecodeError::InvalidData(_))));
+    let short_fmt = build_wav(&[(b"fmt ", &fmt[..14]), (b"data", &data)]);
+    assert!(matches!(open(short_fmt), Err(DecodeError::InvalidData(_))));
+
+    let mut bad_block_align = fmt;
+    bad_block_align[12..14].copy_from_slice(&3u16.to_le_bytes());
+    let wav = build_wav(&[(b"fmt ", &bad_block_align), (b"data", &data)]);
+    assert!(matches!(open(wav), Err(DecodeError::InvalidData(_))));
+    // The expected block alignment overflows a u16 and wraps around to zero.
+    let mut overflowing_block_align = fmt_chunk(WAVE_FORMAT_PCM, 1, 16);
+    overflowing_block_align[2..4].copy_from_slice(&0x8000u16.to_le_bytes());
+    overflowing_block_align[12..14].copy_from_slice(&0u16.to_le_bytes());
+    let wav = build_wav(&[(b"fmt ", &overflowing_block_align), (b"data", &data)]);
+    assert!(matches!(open(wav), Err(DecodeError::InvalidData(_))));
+    let too_many_channels = fmt_chunk(WAVE_FORMAT_PCM, 2000, 24);
+    let wav = build_wav(&[(b"fmt ", &too_many_channels), (b"data", &data)]);
+    assert!(matches!(open(wav), Err(DecodeError::InvalidData(_))));
+
+    let fmt12 = fmt_chunk(WAVE_FORMAT_PCM, 1, 12);
+    let wav = build_wav(&[(b"fmt ", &fmt12), (b"data", &data)]);
+    assert!(matches!(open(wav), Err(DecodeError::Unsupported(_))));
+    let alaw = fmt_chunk(0x0006, 1, 8);
+    let wav = build_wav(&[(b"fmt ", &alaw), (b"data", &data)]);
+    assert!(matches!(open(wav), Err(DecodeError::Unsupported(_))));
+  }
+}
@@ -0,0 +1,206 @@
+#!/usr/bin/env python3
+
+# Generates loop.ogg, the Vorbis fixture used by the decoder tests. No encoder
+# is needed: the stream uses the simplest setup the format allows (a single
+# short block size, a two-point floor and one-bit residue codebooks), and the
+# audio packets are filled with pseudo-random codewords, so the decoded signal
+# is noise which is nevertheless fully deterministic.
+
+import random
+import struct
+import sys
+from typing import List
+
+CHANNELS = 2
+SAMPLE_RATE = 8000
+BLOCKSIZE_EXP = 8
+BLOCK_HALF = (1 << BLOCKSIZE_EXP) // 2
+PARTITION_SIZE = 16
+AUDIO_PACKETS = 301
+PACKETS_PER_PAGE = 16
+COMMENTS = ["TITLE=cardboard_coal fixture", "LOOPSTART=1000", "LOOPLENGTH=20000"]
+
+
+class BitWriter:
+
+  def __init__(self) -> None:
+    self.bytes = bytearray()
+    self.bit_pos = 0
+
+  def write(self, value: int, bits: int) -> None:
+    for i in range(bits):
+      if self.bit_pos == 0:
+        self.bytes.append(0)
+      self.bytes[-1] |= ((value >> i) & 1) << self.bit_pos
+      self.bit_pos = (self.bit_pos + 1) % 8
+
+  def write_bytes(self, data: bytes) -> None:
+    for byte in data:
+      self.write(byte, 8)
+
+
+def vorbis_float(value: float) -> int:
+  # Only powers of two are needed here.
+  sign = 0x80000000 if value < 0 else 0
+  exponent = abs(value).as_integer_ratio()
+  assert exponent[0] == 1 or exponent[1] == 1
+  power = exponent[0].bit_length() - exponent[1].bit_length()
+  return sign | ((788 - 20 + power) << 21) | (1 << 20)
+
+
+def ident_header() -> bytes:
+  w = BitWriter()
+  w.write(1, 8)
+  w.write_bytes(b"vorbis")
+  w.write(0, 32)
+  w.write(CHANNELS, 8)
+  w.write(SAMPLE_RATE, 32)
+  w.write(0, 32)
+  w.write(0, 32)
+  w.write(0, 32)
+  w.write(BLOCKSIZE_EXP, 4)
+  w.write(BLOCKSIZE_EXP, 4)
+  w.write(1, 1)
+  return bytes(w.bytes)
+
+
+def comment_header() -> bytes:
+  w = BitWriter()
+  w.write(3, 8)
+  w.write_bytes(b"vorbis")
+  vendor = b"make_loop_ogg.py"
+  w.write(len(vendor), 32)
+  w.write_bytes(vendor)
+  w.write(len(COMMENTS), 32)
+  for comment in COMMENTS:
+    data = comment.encode()
+    w.write(len(data), 32)
+    w.write_bytes(data)
+  w.write(1, 1)
+  return bytes(w.bytes)
+
+
+def write_codebook(w: BitWriter, vq: bool) -> None:
+  w.write(0x564342, 24)
+  w.write(1, 16)  # dimensions
+  w.write(2, 24)  # entries
+  w.write(0, 1)  # ordered
+  w.write(0, 1)  # sparse
+  for _ in range(2):
+    w.write(0, 5)  # length - 1
+  if vq:
+    w.write(1, 4)  # lookup type
+    w.write(vorbis_float(-1.0), 32)  # minimum value
+    w.write(vorbis_float(2.0), 32)  # delta value
+    w.write(0, 4)  # value bits - 1
+    w.write(0, 1)  # sequence_p
+    w.write(0, 1)
+    w.write(1, 1)
+  else:
+    w.write(0, 4)
+
+
+def setup_header() -> bytes:
+  w = BitWriter()
+  w.write(5, 8)
+  w.write_bytes(b"vorbis")
+
+  w.write(2 - 1, 8)
+  write_codebook(w, vq=False)
+  write_codebook(w, vq=True)
+
+  w.write(1 - 1, 6)  # time domain transforms
+  w.write(0, 16)
+
+  w.write(1 - 1, 6)  # floors
+  w.write(1, 16)
+  w.write(0, 5)  # partitions
+  w.write(1 - 1, 2)  # multiplier
+  w.write(BLOCKSIZE_EXP - 1, 4)  # rangebits
+
+  w.write(1 - 1, 6)  # residues
+  w.write(1, 16)
+  w.write(0, 24)  # begin
+  w.write(BLOCK_HALF, 24)  # end
+  w.write(PARTITION_SIZE - 1, 24)
+  w.write(1 - 1, 6)  # classifications
+  w.write(0, 8)  # classbook
+  w.write(1, 3)  # cascade, low bits
+  w.write(0, 1)
+  w.write(1, 8)  # book of the first pass
+
+  w.write(1 - 1, 6)  # mappings
+  w.write(0, 16)
+  w.write(0, 1)  # submaps
+  w.write(0, 1)  # coupling
+  w.write(0, 2)
+  w.write(0, 8)
+  w.write(0, 8)  # floor
+  w.write(0, 8)  # residue
+
+  w.write(1 - 1, 6)  # modes
+  w.write(0, 1)  # blockflag
+  w.write(0, 16)
+  w.write(0, 16)
+  w.write(0, 8)
+
+  w.write(1, 1)
+  return bytes(w.bytes)
+
+
+def audio_packet(rng: random.Random) -> bytes:
+  w = BitWriter()
+  w.write(0, 1)
+  for _ in range(CHANNELS):
+    w.write(1, 1)  # nonzero
+    w.write(rng.randrange(160, 256), 8)
+    w.write(rng.randrange(160, 256), 8)
+  for _ in range(BLOCK_HALF // PARTITION_SIZE):
+    for _ in range(CHANNELS):
+      w.write(0, 1)  # classification
+    for _ in range(CHANNELS):
+      for _ in range(PARTITION_SIZE):
+        w.write(rng.randrange(2), 1)
+  return bytes(w.bytes)
+
+
+def crc32(data: bytes) -> int:
+  crc = 0
+  for byte in data:
+    crc ^= byte << 24
+    for _ in range(8):
+      crc = ((crc << 1) ^ 0x04C11DB7 if crc & 0x80000000 else crc << 1) & 0xFFFFFFFF
+  return crc
+
+
+def ogg_page(packets: List[bytes], granule: int, sequence: int, flags: int) -> bytes:
+  lacing = bytearray()
+  for packet in packets:
+    lacing += b"\xff" * (len(packet) // 255) + bytes([len(packet) % 255])
+  assert len(lacing) < 256
+  header = b"OggS" + struct.pack("<BBqIIIB", 0, flags, granule, 1, sequence, 0, len(lacing))
+  page = bytearray(header + lacing + b"".join(packets))
+  page[22:26] = struct.pack("<I", crc32(page))
+  return bytes(page)
+
+
+def main() -> None:
+  rng = random.Random(0x5EED)
+  pages = [
+    ogg_page([ident_header()], 0, 0, 0x02),
+    ogg_page([comment_header(), setup_header()], 0, 1, 0),
+  ]
+  packets = [audio_packet(rng) for _ in range(AUDIO_PACKETS)]
+  for start in range(0, AUDIO_PACKETS, PACKETS_PER_PAGE):
+    end = min(start + PACKETS_PER_PAGE, AUDIO_PACKETS)
+    # The first packet doesn't produce any samples, every following one
+    # produces half a block.
+    granule = (end - 1) * BLOCK_HALF
+    flags = 0x04 if end == AUDIO_PACKETS else 0
+    pages.append(ogg_page(packets[start:end], granule, len(pages), flags))
+  with open(sys.argv[1] if len(sys.argv) > 1 else "loop.ogg", "wb") as file:
+    file.write(b"".join(pages))
+
+
+if __name__ == "__main__":
+  main()
@@ -19,6 +19,7 @@ pub struct ExampleConfig {
   pub multisampling: bool,
 }
 
+#[allow(dead_code)]
 pub fn prepare_example_gl_context(
   config: ExampleConfig,
 ) -> (
@@ -58,6 +59,7 @@ pub fn prepare_example_gl_context(
   (sdl_context, video_subsystem, sdl_gl_ctx, window, event_pump, gl)
 }
 
+#[allow(dead_code)]
 pub fn reset_gl_viewport(gl: &Context, window: &Window) {
   let (w, h) = window.drawable_size();
   gl.set_viewport(vec2(0, 0), vec2(w as i32, h as i32));
@@ -0,0 +1,90 @@
+//! Renders the triangle from the `triangle` example without a window and saves
+//! the result to a PNG file. Can be run on a machine without a display:
+//! `cargo run --example headless --features headless -- triangle.png`
+
+use std::fs::File;
+use std::io::BufWriter;
+
+use cardboard_math::*;
+use cardboard_oogl::*;
+
+mod common;
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+struct Vertex {
+  pos: Vec2f,
+  color: Colorf,
+}
+
+#[rustfmt::skip]
+const VERTEX_DATA: &[Vertex] = &[
+  Vertex { pos: vec2( 0.0,  0.5), color: color(1.0, 0.0, 0.0, 1.0) },
+  Vertex { pos: vec2( 0.5, -0.5), color: color(0.0, 1.0, 0.0, 1.0) },
+  Vertex { pos: vec2(-0.5, -0.5), color: color(0.0, 0.0, 1.0, 1.0) },
+];
+
+static VS_SRC: &str = r#"#version 100
+  attribute vec2 a_pos;
+  attribute vec4 a_color;
+  varying   vec4 v_color;
+  void main() {
+    gl_Position = vec4(a_pos, 0.0, 1.0);
+    v_color = a_color;
+  }
+"#;
+
+static FS_SRC: &str = r#"#version 100
+  varying highp vec4 v_color;
+  void main() {
+    gl_FragColor = v_color;
+  }
+"#;
+
+const IMAGE_SIZE: Vec2u32 = vec2(400, 300);
+
+fn main() {
+  let out_path = std::env::args_os().nth(1).unwrap_or_else(|| "headless.png".into());
+
+  let headless_ctx =
+    HeadlessContext::new(HeadlessContextConfig { size: IMAGE_SIZE, debug: true }).unwrap();
+  let gl = headless_ctx.load_gl();
+  println!("{:?}", gl.capabilities());
+
+  let vs = common::compile_shader(gl.share(), VS_SRC, ShaderType::Vertex);
+  let fs = common::compile_shader(gl.share(), FS_SRC, ShaderType::Fragment);
+
+  let mut program = common::link_program(gl.share(), &vs, &fs);
+  let attr_pos = program.get_attrib::<Vec2f>("a_pos");
+  let attr_color = program.get_attrib::<Colorf>("a_color");
+  let bound_program = program.bind();
+
+  let mut vbo = VertexBuffer::<Vertex>::new(
+    gl.share(),
+    BufferUsageHint::StaticDraw,
+    vec![attr_pos.to_pointer_simple(), attr_color.to_pointer_simple()],
+  );
+
+  let bound_vbo = vbo.bind();
+  bound_vbo.enable_attribs();
+  bound_vbo.configure_attribs();
+  bound_vbo.alloc_and_set(VERTEX_DATA);
+
+  gl.set_clear_color(color(0.0, 0.0, 0.0, 1.0));
+  gl.set_viewport(vec2(0, 0), IMAGE_SIZE.cast_into());
+  gl.clear(ClearFlags::COLOR);
+  bound_vbo.draw(&bound_program, DrawPrimitive::Triangles);
+
+  let mut pixels = vec![0; IMAGE_SIZE.x as usize * IMAGE_SIZE.y as usize * 4];
+  gl.read_pixels_rgba(IMAGE_SIZE, &mut pixels);
+  // Rows are read from the bottom, PNG stores them from the top.
+  let pixels: Vec<u8> =
+    pixels.chunks(IMAGE_SIZE.x as usize * 4).rev().flatten().copied().collect();
+
+  let file = BufWriter::new(File::create(&out_path).unwrap());
+  let mut encoder = png::Encoder::new(file, IMAGE_SIZE.x, IMAGE_SIZE.y);
+  encoder.set_color(png::ColorType::Rgba);
+  encoder.set_depth(png::BitDepth::Eight);
+  encoder.write_header().unwrap().write_image_data(&pixels).unwrap();
+  println!("Saved to '{}'", std::path::Path::new(&out_path).display());
+}
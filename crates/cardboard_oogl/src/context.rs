@@ -18,6 +18,7 @@ pub struct Context {
   pub(crate) bound_element_buffer: BindingTarget<BufferBindingTarget>,
   pub(crate) bound_texture_2d: BindingTarget<TextureBindingTarget>,
   pub(crate) bound_framebuffer: BindingTarget<FramebufferBindingTarget>,
+  pub(crate) bound_renderbuffer: BindingTarget<RenderbufferBindingTarget>,
 
   active_texture_unit: Cell<TextureUnit>,
 }
@@ -51,6 +52,7 @@ impl Context {
       bound_element_buffer: BindingTarget::new(crate::BindBufferTarget::Element.as_raw()),
       bound_texture_2d: BindingTarget::new(crate::BindTextureTarget::Texture2D.as_raw()),
       bound_framebuffer: BindingTarget::new(crate::BindFramebufferTarget::Default.as_raw()),
+      bound_renderbuffer: BindingTarget::new(crate::BindRenderbufferTarget::Default.as_raw()),
 
       active_texture_unit: Cell::new(0),
     }
@@ -64,6 +66,18 @@ impl Context {
     self.active_texture_unit.set(unit);
   }
 
+  /// Switches the rendering back to the window (or the surface of a headless
+  /// context). Must not be called while a [`FramebufferBinding`](crate::FramebufferBinding)
+  /// is alive.
+  pub fn bind_default_framebuffer(&self) {
+    assert!(
+      !self.bound_framebuffer.is_binding_alive(),
+      "attempt to bind the default framebuffer while the binding of object #{} is still alive",
+      self.bound_framebuffer.bound_addr(),
+    );
+    self.bound_framebuffer.bind_if_needed(&self.raw_gl, 0);
+  }
+
   #[inline(always)]
   pub fn set_clear_color(&self, color: Colorf) {
     unsafe { self.raw_gl.ClearColor(color.r, color.g, color.b, color.a) };
@@ -262,6 +276,7 @@ impl_binding_target_state!(ProgramBindingTarget, UseProgram());
 impl_binding_target_state!(BufferBindingTarget, BindBuffer(target));
 impl_binding_target_state!(TextureBindingTarget, BindTexture(target));
 impl_binding_target_state!(FramebufferBindingTarget, BindFramebuffer(target));
+impl_binding_target_state!(RenderbufferBindingTarget, BindRenderbuffer(target));
 
 #[derive(Debug, Eq, PartialEq, Clone, Hash, Default)]
 pub struct ContextCapabilities {
@@ -274,6 +289,7 @@ pub struct ContextCapabilities {
   pub max_texture_units: TextureUnit,
   pub max_texture_size: u32,
   pub max_vertex_attribs: u32,
+  pub max_renderbuffer_size: u32,
 
   pub max_debug_object_label_len: i32,
 }
@@ -355,6 +371,7 @@ impl ContextCapabilities {
         .min(TextureUnit::MAX as u32) as TextureUnit,
       max_texture_size: get_u32_1(gl, gl::MAX_TEXTURE_SIZE),
       max_vertex_attribs: get_u32_1(gl, gl::MAX_VERTEX_ATTRIBS),
+      max_renderbuffer_size: get_u32_1(gl, gl::MAX_RENDERBUFFER_SIZE),
 
       max_debug_object_label_len: if gl.ObjectLabel.is_loaded() {
         get_i32_1(gl, gl::MAX_LABEL_LENGTH)
@@ -493,3 +510,59 @@ bitflags! {
     const ALPHA = 1 << 4;
   }
 }
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+  use crate::mock::{MockGl, MockGlConfig, MockValue};
+
+  #[test]
+  fn bind_if_needed_skips_redundant_binds() {
+    let mock = MockGl::new(MockGlConfig::default());
+    let ctx = mock.load_context();
+    mock.clear_calls();
+
+    let target = &ctx.bound_vertex_buffer;
+    target.bind_if_needed(ctx.raw_gl(), 1);
+    target.bind_if_needed(ctx.raw_gl(), 1);
+    assert_eq!(target.bound_addr(), 1);
+    let calls = mock.take_calls();
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0].name, "BindBuffer");
+    assert_eq!(calls[0].args, [MockValue::Int(gl::ARRAY_BUFFER as i64), MockValue::Int(1)]);
+
+    target.bind_if_needed(ctx.raw_gl(), 2);
+    target.unbind_unconditionally(ctx.raw_gl());
+    target.bind_if_needed(ctx.raw_gl(), 0);
+    assert_eq!(target.bound_addr(), 0);
+    assert_eq!(mock.call_names(), ["BindBuffer", "BindBuffer"]);
+  }
+
+  #[test]
+  fn bookkeeping_does_not_call_gl() {
+    let mock = MockGl::new(MockGlConfig::default());
+    let ctx = mock.load_context();
+    mock.clear_calls();
+
+    let target = &ctx.bound_texture_2d;
+    target.on_binding_created(1);
+    assert!(target.is_binding_alive());
+    target.on_binding_dropped();
+    assert!(!target.is_binding_alive());
+    target.on_binding_created(2);
+    assert!(mock.calls().is_empty());
+  }
+
+  #[test]
+  #[should_panic(
+    expected = "attempt to bind object #2 while the binding of object #1 is still alive"
+  )]
+  fn binding_twice_panics() {
+    let mock = MockGl::new(MockGlConfig::default());
+    let ctx = mock.load_context();
+
+    let target = &ctx.bound_program;
+    target.bind_if_needed(ctx.raw_gl(), 1);
+    target.on_binding_created(1);
+    target.on_binding_created(2);
+  }
+}
@@ -1,4 +1,5 @@
 use crate::impl_prelude::*;
+use crate::{Renderbuffer, Texture2D, TextureDataType};
 use prelude_plus::*;
 
 gl_enum!({
@@ -74,8 +75,62 @@ impl<'obj> FramebufferBinding<'obj> {
       self.raw_gl().CheckFramebufferStatus(Self::BIND_TARGET.as_raw())
     })
   }
+
+  // NOTE: GLES2 allows rendering only into the level 0 of a texture, and the
+  // attached objects are not kept alive by the framebuffer. A deleted object
+  // is automatically detached only from the currently bound framebuffer.
+
+  pub fn attach_texture_2d<T: TextureDataType>(
+    &self,
+    attachment: FramebufferAttachment,
+    texture: &Texture2D<T>,
+  ) {
+    unsafe {
+      self.raw_gl().FramebufferTexture2D(
+        Self::BIND_TARGET.as_raw(),
+        attachment.as_raw(),
+        Texture2D::<T>::BIND_TARGET.as_raw(),
+        texture.addr(),
+        0,
+      );
+    }
+  }
+
+  pub fn attach_renderbuffer(
+    &self,
+    attachment: FramebufferAttachment,
+    renderbuffer: &Renderbuffer,
+  ) {
+    unsafe {
+      self.raw_gl().FramebufferRenderbuffer(
+        Self::BIND_TARGET.as_raw(),
+        attachment.as_raw(),
+        Renderbuffer::BIND_TARGET.as_raw(),
+        renderbuffer.addr(),
+      );
+    }
+  }
+
+  pub fn detach(&self, attachment: FramebufferAttachment) {
+    unsafe {
+      self.raw_gl().FramebufferRenderbuffer(
+        Self::BIND_TARGET.as_raw(),
+        attachment.as_raw(),
+        Renderbuffer::BIND_TARGET.as_raw(),
+        0,
+      );
+    }
+  }
 }
 
+gl_enum!({
+  pub enum FramebufferAttachment {
+    Color0 = COLOR_ATTACHMENT0,
+    Depth = DEPTH_ATTACHMENT,
+    Stencil = STENCIL_ATTACHMENT,
+  }
+});
+
 gl_enum!({
   pub enum FramebufferStatus {
     Complete = FRAMEBUFFER_COMPLETE,
@@ -85,3 +140,133 @@ gl_enum!({
     IncompleteUnsupported = FRAMEBUFFER_UNSUPPORTED,
   }
 });
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+  use super::*;
+  use crate::mock::{GlCall, MockGl, MockGlConfig, MockValue};
+  use crate::{RenderbufferInternalFormat, TextureInputFormat};
+  use cardboard_math::vec2;
+
+  fn int(value: u32) -> MockValue { MockValue::Int(value as i64) }
+
+  #[test]
+  fn attaches_a_color_texture_and_depth_stencil_renderbuffers() {
+    let mock = MockGl::new(MockGlConfig::default());
+    let ctx = mock.load_context();
+
+    let mut color = Texture2D::<u8>::new(ctx.share(), None, TextureInputFormat::RGBA, None);
+    {
+      let bound_color = color.bind(None);
+      bound_color.set_size(vec2(64, 32));
+      bound_color.alloc(0);
+    }
+    let mut depth = Renderbuffer::new(ctx.share());
+    depth.bind().alloc(RenderbufferInternalFormat::Depth16, vec2(64, 32));
+    let mut stencil = Renderbuffer::new(ctx.share());
+    stencil.bind().alloc(RenderbufferInternalFormat::Stencil8, vec2(64, 32));
+    mock.clear_calls();
+
+    let mut framebuffer = Framebuffer::new(ctx.share());
+    assert_eq!(ctx.bound_framebuffer.bound_addr(), framebuffer.addr());
+    assert!(!ctx.bound_framebuffer.is_binding_alive());
+    let calls = mock.take_calls();
+    assert_eq!(calls[0].name, "GenFramebuffers");
+    assert_eq!(
+      calls[1..],
+      [GlCall {
+        name: "BindFramebuffer",
+        args: vec![int(gl::FRAMEBUFFER), int(framebuffer.addr())],
+      }]
+    );
+
+    let bound_framebuffer = framebuffer.bind();
+    assert!(ctx.bound_framebuffer.is_binding_alive());
+    bound_framebuffer.attach_texture_2d(FramebufferAttachment::Color0, &color);
+    bound_framebuffer.attach_renderbuffer(FramebufferAttachment::Depth, &depth);
+    bound_framebuffer.attach_renderbuffer(FramebufferAttachment::Stencil, &stencil);
+    assert_eq!(bound_framebuffer.status(), FramebufferStatus::Complete);
+    bound_framebuffer.detach(FramebufferAttachment::Stencil);
+    drop(bound_framebuffer);
+    assert!(!ctx.bound_framebuffer.is_binding_alive());
+
+    // The framebuffer was already bound, so no BindFramebuffer is expected.
+    assert_eq!(
+      mock.take_calls(),
+      [
+        GlCall {
+          name: "FramebufferTexture2D",
+          args: vec![
+            int(gl::FRAMEBUFFER),
+            int(gl::COLOR_ATTACHMENT0),
+            int(gl::TEXTURE_2D),
+            int(color.addr()),
+            int(0),
+          ],
+        },
+        GlCall {
+          name: "FramebufferRenderbuffer",
+          args: vec![
+            int(gl::FRAMEBUFFER),
+            int(gl::DEPTH_ATTACHMENT),
+            int(gl::RENDERBUFFER),
+            int(depth.addr()),
+          ],
+        },
+        GlCall {
+          name: "FramebufferRenderbuffer",
+          args: vec![
+            int(gl::FRAMEBUFFER),
+            int(gl::STENCIL_ATTACHMENT),
+            int(gl::RENDERBUFFER),
+            int(stencil.addr()),
+          ],
+        },
+        GlCall { name: "CheckFramebufferStatus", args: vec![int(gl::FRAMEBUFFER)] },
+        GlCall {
+          name: "FramebufferRenderbuffer",
+          args: vec![
+            int(gl::FRAMEBUFFER),
+            int(gl::STENCIL_ATTACHMENT),
+            int(gl::RENDERBUFFER),
+            int(0),
+          ],
+        },
+      ]
+    );
+
+    mock.config_mut().framebuffer_status = gl::FRAMEBUFFER_INCOMPLETE_MISSING_ATTACHMENT;
+    assert_eq!(framebuffer.bind().status(), FramebufferStatus::IncompleteMissingAttachment);
+  }
+
+  #[test]
+  fn switches_back_to_the_default_framebuffer() {
+    let mock = MockGl::new(MockGlConfig::default());
+    let ctx = mock.load_context();
+    let mut framebuffer = Framebuffer::new(ctx.share());
+    mock.clear_calls();
+
+    ctx.bind_default_framebuffer();
+    ctx.bind_default_framebuffer();
+    assert_eq!(ctx.bound_framebuffer.bound_addr(), 0);
+    assert_eq!(
+      mock.take_calls(),
+      [GlCall { name: "BindFramebuffer", args: vec![int(gl::FRAMEBUFFER), int(0)] }]
+    );
+
+    drop(framebuffer.bind());
+    ctx.bind_default_framebuffer();
+    assert_eq!(mock.call_names(), ["BindFramebuffer", "BindFramebuffer"]);
+  }
+
+  #[test]
+  #[should_panic(expected = "attempt to bind the default framebuffer while the binding")]
+  fn switching_to_the_default_framebuffer_while_bound_panics() {
+    let mock = MockGl::new(MockGlConfig::default());
+    let ctx = mock.load_context();
+    let mut framebuffer = Framebuffer::new(ctx.share());
+
+    let _bound_framebuffer = framebuffer.bind();
+    ctx.bind_default_framebuffer();
+  }
+}
@@ -0,0 +1,193 @@
+//! Creation of GL contexts without a window, for running the renderer on
+//! machines with no display (CI, offline rendering and such). Uses EGL with
+//! pbuffer surfaces, on Mesa this works even with the software rasterizer and
+//! without a GPU thanks to `EGL_MESA_platform_surfaceless`.
+
+use crate::context::{Context, SharedContext};
+use cardboard_math::*;
+use prelude_plus::*;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct EglError {
+  pub function: &'static str,
+  pub code: u32,
+}
+
+impl EglError {
+  fn last(function: &'static str) -> Self {
+    Self { function, code: unsafe { egl::GetError() } as u32 }
+  }
+
+  pub fn code_name(&self) -> &'static str {
+    match self.code {
+      egl::SUCCESS => "EGL_SUCCESS",
+      egl::NOT_INITIALIZED => "EGL_NOT_INITIALIZED",
+      egl::BAD_ACCESS => "EGL_BAD_ACCESS",
+      egl::BAD_ALLOC => "EGL_BAD_ALLOC",
+      egl::BAD_ATTRIBUTE => "EGL_BAD_ATTRIBUTE",
+      egl::BAD_CONFIG => "EGL_BAD_CONFIG",
+      egl::BAD_CONTEXT => "EGL_BAD_CONTEXT",
+      egl::BAD_CURRENT_SURFACE => "EGL_BAD_CURRENT_SURFACE",
+      egl::BAD_DISPLAY => "EGL_BAD_DISPLAY",
+      egl::BAD_MATCH => "EGL_BAD_MATCH",
+      egl::BAD_NATIVE_PIXMAP => "EGL_BAD_NATIVE_PIXMAP",
+      egl::BAD_NATIVE_WINDOW => "EGL_BAD_NATIVE_WINDOW",
+      egl::BAD_PARAMETER => "EGL_BAD_PARAMETER",
+      egl::BAD_SURFACE => "EGL_BAD_SURFACE",
+      egl::CONTEXT_LOST => "EGL_CONTEXT_LOST",
+      _ => "unknown error",
+    }
+  }
+}
+
+impl fmt::Display for EglError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "egl{} failed with {} (0x{:04x})", self.function, self.code_name(), self.code)
+  }
+}
+
+impl std::error::Error for EglError {}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct HeadlessContextConfig {
+  pub size: Vec2u32,
+  pub debug: bool,
+}
+
+/// An EGL display, context and an offscreen surface of a fixed size, the
+/// context is made current on creation. All GL objects must be dropped before
+/// this, as usual.
+#[derive(Debug)]
+pub struct HeadlessContext {
+  display: egl::types::EGLDisplay,
+  context: egl::types::EGLContext,
+  surface: egl::types::EGLSurface,
+  size: Vec2u32,
+}
+
+impl !Send for HeadlessContext {}
+impl !Sync for HeadlessContext {}
+
+fn egl_client_extensions() -> String {
+  let ptr = unsafe { egl::QueryString(egl::NO_DISPLAY, egl::EXTENSIONS as i32) };
+  if ptr.is_null() {
+    // Client extensions are not supported at all, the error must be cleared.
+    unsafe { egl::GetError() };
+    return String::new();
+  }
+  unsafe { CStr::from_ptr(ptr) }.to_string_lossy().into_owned()
+}
+
+impl HeadlessContext {
+  pub fn new(config: HeadlessContextConfig) -> Result<Self, EglError> {
+    unsafe {
+      let has_surfaceless_platform =
+        egl_client_extensions().split(' ').any(|ext| ext == "EGL_MESA_platform_surfaceless");
+      let display = if has_surfaceless_platform {
+        egl::GetPlatformDisplay(egl::PLATFORM_SURFACELESS_MESA, ptr::null_mut(), ptr::null())
+      } else {
+        egl::GetDisplay(egl::DEFAULT_DISPLAY)
+      };
+      if display == egl::NO_DISPLAY {
+        return Err(EglError::last("GetDisplay"));
+      }
+
+      let (mut major, mut minor) = (0, 0);
+      if egl::Initialize(display, &mut major, &mut minor) == egl::FALSE {
+        return Err(EglError::last("Initialize"));
+      }
+      debug!("EGL v{}.{}", major, minor);
+
+      if egl::BindAPI(egl::OPENGL_ES_API) == egl::FALSE {
+        return Err(EglError::last("BindAPI"));
+      }
+
+      #[rustfmt::skip]
+      let config_attribs = [
+        egl::SURFACE_TYPE as i32, egl::PBUFFER_BIT as i32,
+        egl::RENDERABLE_TYPE as i32, egl::OPENGL_ES2_BIT as i32,
+        egl::RED_SIZE as i32, 8,
+        egl::GREEN_SIZE as i32, 8,
+        egl::BLUE_SIZE as i32, 8,
+        egl::ALPHA_SIZE as i32, 8,
+        egl::NONE as i32,
+      ];
+      let mut egl_config = ptr::null();
+      let mut configs_count = 0;
+      if egl::ChooseConfig(
+        display,
+        config_attribs.as_ptr(),
+        &mut egl_config,
+        1,
+        &mut configs_count,
+      ) == egl::FALSE
+      {
+        return Err(EglError::last("ChooseConfig"));
+      }
+      if configs_count == 0 {
+        return Err(EglError { function: "ChooseConfig", code: egl::BAD_CONFIG });
+      }
+
+      #[rustfmt::skip]
+      let context_attribs = [
+        egl::CONTEXT_MAJOR_VERSION as i32, 2,
+        egl::CONTEXT_OPENGL_DEBUG as i32, config.debug as i32,
+        egl::NONE as i32,
+      ];
+      let context =
+        egl::CreateContext(display, egl_config, egl::NO_CONTEXT, context_attribs.as_ptr());
+      if context == egl::NO_CONTEXT {
+        return Err(EglError::last("CreateContext"));
+      }
+
+      #[rustfmt::skip]
+      let surface_attribs = [
+        egl::WIDTH as i32, config.size.x as i32,
+        egl::HEIGHT as i32, config.size.y as i32,
+        egl::NONE as i32,
+      ];
+      let surface = egl::CreatePbufferSurface(display, egl_config, surface_attribs.as_ptr());
+      if surface == egl::NO_SURFACE {
+        let error = EglError::last("CreatePbufferSurface");
+        egl::DestroyContext(display, context);
+        return Err(error);
+      }
+
+      let myself = Self { display, context, surface, size: config.size };
+      myself.make_current()?;
+      Ok(myself)
+    }
+  }
+
+  pub fn make_current(&self) -> Result<(), EglError> {
+    if unsafe { egl::MakeCurrent(self.display, self.surface, self.surface, self.context) }
+      == egl::FALSE
+    {
+      return Err(EglError::last("MakeCurrent"));
+    }
+    Ok(())
+  }
+
+  #[inline(always)]
+  pub fn size(&self) -> Vec2u32 { self.size }
+
+  /// Loads the GL functions of this context, which must be current.
+  pub fn load_gl(&self) -> SharedContext {
+    Rc::new(Context::load_with(|name| {
+      let name = CString::new(name).unwrap();
+      unsafe { egl::GetProcAddress(name.as_ptr()) as *const c_void }
+    }))
+  }
+}
+
+impl Drop for HeadlessContext {
+  fn drop(&mut self) {
+    unsafe {
+      egl::MakeCurrent(self.display, egl::NO_SURFACE, egl::NO_SURFACE, egl::NO_CONTEXT);
+      egl::DestroySurface(self.display, self.surface);
+      egl::DestroyContext(self.display, self.context);
+      // NOTE: The display is not terminated because EGL displays are shared
+      // between all users in the process.
+    }
+  }
+}
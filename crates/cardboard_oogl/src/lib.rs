@@ -60,6 +60,11 @@ pub mod buffer;
 pub mod context;
 pub mod debug;
 pub mod framebuffer;
+#[cfg(feature = "headless")]
+pub mod headless;
+#[cfg(feature = "mock")]
+pub mod mock;
+pub mod renderbuffer;
 pub mod shader;
 pub mod texture;
 pub mod traits;
@@ -68,6 +73,11 @@ pub use buffer::*;
 pub use context::*;
 pub use debug::*;
 pub use framebuffer::*;
+#[cfg(feature = "headless")]
+pub use headless::*;
+#[cfg(feature = "mock")]
+pub use mock::*;
+pub use renderbuffer::*;
 pub use shader::*;
 pub use texture::*;
 pub use traits::*;
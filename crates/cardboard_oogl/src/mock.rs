@@ -0,0 +1,341 @@
+//! A fake GL backend for testing the bookkeeping of the wrappers (binding
+//! tracking, texture units and such) without a driver. Every call made through
+//! a mock context is recorded into an inspectable log, and the commands which
+//! query something return configurable fake results.
+//!
+//! ```ignore
+//! let mock = MockGl::new(MockGlConfig::default());
+//! let ctx = mock.load_context();
+//! mock.clear_calls();
+//! let mut buffer = VertexBuffer::<f32>::new(ctx.share(), BufferUsageHint::StaticDraw, vec![..]);
+//! assert_eq!(mock.call_names(), ["GenBuffers", "BindBuffer"]);
+//! ```
+
+use crate::impl_prelude::*;
+use prelude_plus::*;
+
+pub use gl::mock::MockValue;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlCall {
+  /// The name of the command without the `gl` prefix, e.g. `BindBuffer`.
+  pub name: &'static str,
+  pub args: Vec<MockValue>,
+}
+
+impl fmt::Display for GlCall {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "gl{}(", self.name)?;
+    for (i, arg) in self.args.iter().enumerate() {
+      if i > 0 {
+        write!(f, ", ")?;
+      }
+      match arg {
+        MockValue::Void => write!(f, "void")?,
+        MockValue::Int(value) => write!(f, "{}", value)?,
+        MockValue::Float(value) => write!(f, "{:?}", value)?,
+        MockValue::Ptr(addr) => write!(f, "0x{:x}", addr)?,
+      }
+    }
+    write!(f, ")")
+  }
+}
+
+/// An active uniform or attribute reported by every program in the mock
+/// context, the location of a variable is its index in the list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MockVariable {
+  pub name: String,
+  pub data_type: u32,
+  pub array_len: i32,
+}
+
+#[derive(Debug, Clone)]
+pub struct MockGlConfig {
+  pub renderer: String,
+  pub vendor: String,
+  pub gl_version: String,
+  pub glsl_version: String,
+  pub extensions: Vec<String>,
+  /// Answers to `GetIntegerv`, `GetBooleanv` and `GetFloatv`, unknown
+  /// parameters are zero.
+  pub integers: HashMap<u32, i32>,
+
+  pub shader_compile_status: bool,
+  pub program_link_status: bool,
+  /// Returned for both shaders and programs.
+  pub info_log: String,
+  pub active_uniforms: Vec<MockVariable>,
+  pub active_attribs: Vec<MockVariable>,
+
+  pub framebuffer_status: u32,
+}
+
+impl Default for MockGlConfig {
+  fn default() -> Self {
+    let mut integers = HashMap::new();
+    integers.insert(gl::SHADER_COMPILER, gl::TRUE as i32);
+    integers.insert(gl::MAX_COMBINED_TEXTURE_IMAGE_UNITS, 16);
+    integers.insert(gl::MAX_TEXTURE_SIZE, 4096);
+    integers.insert(gl::MAX_VERTEX_ATTRIBS, 16);
+    integers.insert(gl::MAX_RENDERBUFFER_SIZE, 4096);
+    integers.insert(gl::MAX_LABEL_LENGTH, 256);
+
+    Self {
+      renderer: "mock".to_owned(),
+      vendor: "cardboard".to_owned(),
+      gl_version: "OpenGL ES 2.0 mock".to_owned(),
+      glsl_version: "OpenGL ES GLSL ES 1.00 mock".to_owned(),
+      extensions: vec!["GL_KHR_debug".to_owned(), "GL_OES_texture_npot".to_owned()],
+      integers,
+
+      shader_compile_status: true,
+      program_link_status: true,
+      info_log: String::new(),
+      active_uniforms: Vec::new(),
+      active_attribs: Vec::new(),
+
+      framebuffer_status: gl::FRAMEBUFFER_COMPLETE,
+    }
+  }
+}
+
+type MockOverride = Box<dyn FnMut(&[MockValue]) -> MockValue>;
+
+struct MockState {
+  config: MockGlConfig,
+  calls: Vec<GlCall>,
+  next_object_addr: u32,
+  strings: HashMap<u32, CString>,
+  overrides: HashMap<&'static str, MockOverride>,
+}
+
+/// The mock backend of the current thread. The fake functions are routed to
+/// it until it is dropped, so it must outlive the context and all objects.
+pub struct MockGl {
+  state: Rc<RefCell<MockState>>,
+}
+
+impl fmt::Debug for MockGl {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let state = self.state();
+    f.debug_struct("MockGl")
+      .field("config", &state.config)
+      .field("calls", &state.calls.len())
+      .finish()
+  }
+}
+
+impl !Send for MockGl {}
+impl !Sync for MockGl {}
+
+impl MockGl {
+  pub fn new(config: MockGlConfig) -> Self {
+    let state = Rc::new(RefCell::new(MockState {
+      config,
+      calls: Vec::new(),
+      next_object_addr: 1,
+      strings: HashMap::new(),
+      overrides: HashMap::new(),
+    }));
+
+    let handler_state = Rc::clone(&state);
+    let prev_handler = gl::mock::set_handler(Some(Box::new(move |name, args| {
+      RefCell::borrow_mut(&handler_state).handle_call(name, args)
+    })));
+    assert!(prev_handler.is_none(), "a mock GL backend already exists on this thread");
+
+    Self { state }
+  }
+
+  #[inline(always)]
+  fn state(&self) -> Ref<'_, MockState> { RefCell::borrow(&self.state) }
+  #[inline(always)]
+  fn state_mut(&self) -> RefMut<'_, MockState> { RefCell::borrow_mut(&self.state) }
+
+  pub fn load_context(&self) -> SharedContext {
+    Rc::new(Context::load_with(gl::mock::get_proc_address))
+  }
+
+  pub fn config_mut(&self) -> RefMut<'_, MockGlConfig> {
+    RefMut::map(self.state_mut(), |state| &mut state.config)
+  }
+
+  /// Replaces the default behavior of a command. The closure is called while
+  /// the backend is locked, so it must not call into GL itself.
+  pub fn set_override(
+    &self,
+    name: &'static str,
+    func: impl FnMut(&[MockValue]) -> MockValue + 'static,
+  ) {
+    self.state_mut().overrides.insert(name, Box::new(func));
+  }
+
+  pub fn remove_override(&self, name: &'static str) { self.state_mut().overrides.remove(name); }
+
+  pub fn calls(&self) -> Vec<GlCall> { self.state().calls.clone() }
+  pub fn take_calls(&self) -> Vec<GlCall> { mem::take(&mut self.state_mut().calls) }
+  pub fn clear_calls(&self) { self.state_mut().calls.clear(); }
+
+  pub fn call_names(&self) -> Vec<&'static str> {
+    self.state().calls.iter().map(|call| call.name).collect()
+  }
+}
+
+impl Drop for MockGl {
+  fn drop(&mut self) { gl::mock::set_handler(None); }
+}
+
+unsafe fn write_out<T>(ptr: &MockValue, value: T) {
+  let ptr = ptr.as_ptr::<T>();
+  if !ptr.is_null() {
+    ptr.write(value);
+  }
+}
+
+/// Mimics the `Get*InfoLog`/`GetActive*` style of returning strings: at most
+/// `buf_size - 1` bytes are copied, followed by a NUL terminator.
+unsafe fn write_out_string(buf_size: &MockValue, len: &MockValue, buf: &MockValue, text: &str) {
+  let buf_size = buf_size.as_int().max(0) as usize;
+  let copied_len = text.len().min(buf_size.saturating_sub(1));
+  let buf = buf.as_ptr::<u8>();
+  if !buf.is_null() && buf_size > 0 {
+    ptr::copy_nonoverlapping(text.as_ptr(), buf, copied_len);
+    buf.add(copied_len).write(0);
+  }
+  write_out(len, copied_len as i32);
+}
+
+fn variables_max_name_len(variables: &[MockVariable]) -> i32 {
+  variables.iter().map(|var| var.name.len() as i32 + 1).max().unwrap_or(0)
+}
+
+fn info_log_len(info_log: &str) -> i32 {
+  if info_log.is_empty() {
+    0
+  } else {
+    info_log.len() as i32 + 1
+  }
+}
+
+impl MockState {
+  fn handle_call(&mut self, name: &'static str, args: &[MockValue]) -> MockValue {
+    self.calls.push(GlCall { name, args: args.to_vec() });
+    if let Some(func) = self.overrides.get_mut(name) {
+      return func(args);
+    }
+    unsafe { self.default_result(name, args) }
+  }
+
+  fn gen_object_addr(&mut self) -> u32 {
+    let addr = self.next_object_addr;
+    self.next_object_addr += 1;
+    addr
+  }
+
+  fn get_integer(&self, pname: &MockValue) -> i32 {
+    self.config.integers.get(&(pname.as_int() as u32)).copied().unwrap_or(0)
+  }
+
+  unsafe fn default_result(&mut self, name: &'static str, args: &[MockValue]) -> MockValue {
+    let config = &self.config;
+    match name {
+      "GetError" => MockValue::Int(gl::NO_ERROR as i64),
+
+      "GetString" => {
+        let pname = args[0].as_int() as u32;
+        let string = match pname {
+          gl::RENDERER => config.renderer.clone(),
+          gl::VENDOR => config.vendor.clone(),
+          gl::VERSION => config.gl_version.clone(),
+          gl::SHADING_LANGUAGE_VERSION => config.glsl_version.clone(),
+          gl::EXTENSIONS => config.extensions.join(" "),
+          _ => return MockValue::Ptr(0),
+        };
+        // The strings must stay alive after the call, so they are cached.
+        self.strings.insert(pname, CString::new(string).unwrap());
+        MockValue::Ptr(self.strings[&pname].as_ptr() as usize)
+      }
+
+      "GetIntegerv" => {
+        write_out(&args[1], self.get_integer(&args[0]));
+        MockValue::Void
+      }
+      "GetBooleanv" => {
+        write_out(&args[1], (self.get_integer(&args[0]) != 0) as u8);
+        MockValue::Void
+      }
+      "GetFloatv" => {
+        write_out(&args[1], self.get_integer(&args[0]) as f32);
+        MockValue::Void
+      }
+
+      "GenBuffers" | "GenTextures" | "GenFramebuffers" | "GenRenderbuffers" => {
+        let out = args[1].as_ptr::<u32>();
+        for i in 0..args[0].as_int().max(0) as usize {
+          out.add(i).write(self.gen_object_addr());
+        }
+        MockValue::Void
+      }
+      "CreateShader" | "CreateProgram" => MockValue::Int(self.gen_object_addr() as i64),
+
+      "GetShaderiv" => {
+        let value = match args[1].as_int() as u32 {
+          gl::COMPILE_STATUS => config.shader_compile_status as i32,
+          gl::INFO_LOG_LENGTH => info_log_len(&config.info_log),
+          _ => 0,
+        };
+        write_out(&args[2], value);
+        MockValue::Void
+      }
+      "GetProgramiv" => {
+        let value = match args[1].as_int() as u32 {
+          gl::LINK_STATUS | gl::VALIDATE_STATUS => config.program_link_status as i32,
+          gl::INFO_LOG_LENGTH => info_log_len(&config.info_log),
+          gl::ACTIVE_UNIFORMS => config.active_uniforms.len() as i32,
+          gl::ACTIVE_UNIFORM_MAX_LENGTH => variables_max_name_len(&config.active_uniforms),
+          gl::ACTIVE_ATTRIBUTES => config.active_attribs.len() as i32,
+          gl::ACTIVE_ATTRIBUTE_MAX_LENGTH => variables_max_name_len(&config.active_attribs),
+          _ => 0,
+        };
+        write_out(&args[2], value);
+        MockValue::Void
+      }
+      "GetShaderInfoLog" | "GetProgramInfoLog" => {
+        write_out_string(&args[1], &args[2], &args[3], &config.info_log);
+        MockValue::Void
+      }
+
+      "GetActiveUniform" | "GetActiveAttrib" => {
+        let variables = if name == "GetActiveUniform" {
+          &config.active_uniforms
+        } else {
+          &config.active_attribs
+        };
+        if let Some(var) = variables.get(args[1].as_int() as usize) {
+          write_out(&args[4], var.array_len);
+          write_out(&args[5], var.data_type);
+          write_out_string(&args[2], &args[3], &args[6], &var.name);
+        }
+        MockValue::Void
+      }
+      "GetUniformLocation" | "GetAttribLocation" => {
+        let variables = if name == "GetUniformLocation" {
+          &config.active_uniforms
+        } else {
+          &config.active_attribs
+        };
+        let var_name = CStr::from_ptr(args[1].as_ptr::<c_char>());
+        let location = variables
+          .iter()
+          .position(|var| var.name.as_bytes() == var_name.to_bytes())
+          .map_or(-1, |index| index as i64);
+        MockValue::Int(location)
+      }
+
+      "CheckFramebufferStatus" => MockValue::Int(config.framebuffer_status as i64),
+
+      _ => MockValue::Void,
+    }
+  }
+}
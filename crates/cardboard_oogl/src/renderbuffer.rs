@@ -0,0 +1,156 @@
+use crate::impl_prelude::*;
+use cardboard_math::*;
+use prelude_plus::*;
+
+gl_enum!({
+  pub enum BindRenderbufferTarget {
+    Default = RENDERBUFFER,
+  }
+});
+
+#[derive(Debug)]
+pub struct Renderbuffer {
+  ctx: SharedContext,
+  addr: u32,
+  size: Cell<Vec2u32>,
+  internal_format: Cell<Option<RenderbufferInternalFormat>>,
+}
+
+impl !Send for Renderbuffer {}
+impl !Sync for Renderbuffer {}
+
+unsafe impl Object for Renderbuffer {
+  const DEBUG_TYPE_ID: u32 = gl::RENDERBUFFER;
+
+  #[inline(always)]
+  fn ctx(&self) -> &SharedContext { &self.ctx }
+  #[inline(always)]
+  fn addr(&self) -> u32 { self.addr }
+}
+
+impl Renderbuffer {
+  pub const BIND_TARGET: BindRenderbufferTarget = BindRenderbufferTarget::Default;
+
+  pub fn new(ctx: SharedContext) -> Self {
+    let mut addr = 0;
+    unsafe { ctx.raw_gl().GenRenderbuffers(1, &mut addr) };
+    let mut myself =
+      Self { ctx, addr, size: Cell::new(vec2n(0)), internal_format: Cell::new(None) };
+    drop(myself.bind());
+    myself
+  }
+
+  #[inline(always)]
+  pub fn size(&self) -> Vec2u32 { self.size.get() }
+  /// Returns [`None`] if the storage hasn't been allocated yet.
+  #[inline(always)]
+  pub fn internal_format(&self) -> Option<RenderbufferInternalFormat> {
+    self.internal_format.get()
+  }
+
+  pub fn bind(&mut self) -> RenderbufferBinding<'_> {
+    let binding_target = &self.ctx.bound_renderbuffer;
+    binding_target.on_binding_created(self.addr);
+    binding_target.bind_if_needed(self.raw_gl(), self.addr);
+    RenderbufferBinding { renderbuffer: self }
+  }
+}
+
+impl Drop for Renderbuffer {
+  fn drop(&mut self) { unsafe { self.raw_gl().DeleteRenderbuffers(1, &self.addr) }; }
+}
+
+#[derive(Debug)]
+pub struct RenderbufferBinding<'obj> {
+  renderbuffer: &'obj mut Renderbuffer,
+}
+
+unsafe impl<'obj> ObjectBinding<'obj, Renderbuffer> for RenderbufferBinding<'obj> {
+  #[inline(always)]
+  fn object(&self) -> &Renderbuffer { self.renderbuffer }
+
+  fn unbind_completely(self) {
+    self.ctx().bound_renderbuffer.unbind_unconditionally(self.raw_gl());
+  }
+}
+
+impl<'obj> Drop for RenderbufferBinding<'obj> {
+  fn drop(&mut self) { self.ctx().bound_renderbuffer.on_binding_dropped(); }
+}
+
+impl<'obj> RenderbufferBinding<'obj> {
+  pub const BIND_TARGET: BindRenderbufferTarget = Renderbuffer::BIND_TARGET;
+
+  /// (Re)allocates the storage, the previous contents are discarded.
+  pub fn alloc(&self, internal_format: RenderbufferInternalFormat, size: Vec2u32) {
+    let max_size = self.ctx().capabilities().max_renderbuffer_size;
+    assert!(size.x > 0);
+    assert!(size.y > 0);
+    assert!(size.x <= max_size);
+    assert!(size.y <= max_size);
+    unsafe {
+      self.raw_gl().RenderbufferStorage(
+        Self::BIND_TARGET.as_raw(),
+        internal_format.as_raw(),
+        size.x as i32,
+        size.y as i32,
+      );
+    }
+    self.renderbuffer.size.set(size);
+    self.renderbuffer.internal_format.set(Some(internal_format));
+  }
+}
+
+gl_enum!({
+  pub enum RenderbufferInternalFormat {
+    RGBA4 = RGBA4,
+    RGB5A1 = RGB5_A1,
+    RGB565 = RGB565,
+    Depth16 = DEPTH_COMPONENT16,
+    Stencil8 = STENCIL_INDEX8,
+  }
+});
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+  use super::*;
+  use crate::mock::{GlCall, MockGl, MockGlConfig, MockValue};
+
+  #[test]
+  fn alloc_records_the_storage() {
+    let mock = MockGl::new(MockGlConfig::default());
+    let ctx = mock.load_context();
+    mock.clear_calls();
+
+    let mut renderbuffer = Renderbuffer::new(ctx.share());
+    assert_eq!(mock.call_names(), ["GenRenderbuffers", "BindRenderbuffer"]);
+    assert_eq!(renderbuffer.internal_format(), None);
+    assert_eq!(renderbuffer.size(), vec2n(0));
+    mock.clear_calls();
+
+    renderbuffer.bind().alloc(RenderbufferInternalFormat::Depth16, vec2(64, 32));
+    assert_eq!(renderbuffer.internal_format(), Some(RenderbufferInternalFormat::Depth16));
+    assert_eq!(renderbuffer.size(), vec2(64, 32));
+    assert_eq!(
+      mock.take_calls(),
+      [GlCall {
+        name: "RenderbufferStorage",
+        args: vec![
+          MockValue::Int(gl::RENDERBUFFER as i64),
+          MockValue::Int(gl::DEPTH_COMPONENT16 as i64),
+          MockValue::Int(64),
+          MockValue::Int(32),
+        ],
+      }]
+    );
+  }
+
+  #[test]
+  #[should_panic]
+  fn alloc_rejects_sizes_over_the_limit() {
+    let mock = MockGl::new(MockGlConfig::default());
+    let ctx = mock.load_context();
+    let mut renderbuffer = Renderbuffer::new(ctx.share());
+    renderbuffer.bind().alloc(RenderbufferInternalFormat::Stencil8, vec2(8192, 1));
+  }
+}
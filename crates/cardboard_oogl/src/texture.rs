@@ -423,3 +423,52 @@ where
 {
   const BIND_TARGET: BindTextureTarget = BindTextureTarget::Texture2D;
 }
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+  use super::*;
+  use crate::mock::{MockGl, MockGlConfig, MockValue};
+
+  #[test]
+  fn bind_selects_the_texture_unit_only_when_it_changes() {
+    let mock = MockGl::new(MockGlConfig::default());
+    let ctx = mock.load_context();
+    mock.clear_calls();
+
+    let mut texture = Texture2D::<u8>::new(ctx.share(), Some(0), TextureInputFormat::RGBA, None);
+    // Unit 0 is active from the start, only the texture has to be bound.
+    assert_eq!(mock.call_names(), ["GenTextures", "BindTexture"]);
+    mock.clear_calls();
+
+    drop(texture.bind(None));
+    drop(texture.bind(Some(0)));
+    assert!(mock.calls().is_empty());
+
+    let binding = texture.bind(Some(3));
+    assert_eq!(binding.unit(), 3);
+    drop(binding);
+    let calls = mock.take_calls();
+    assert_eq!(
+      calls.iter().map(|call| call.name).collect::<Vec<_>>(),
+      ["ActiveTexture", "BindTexture"]
+    );
+    assert_eq!(calls[0].args, [MockValue::Int(gl::TEXTURE3 as i64)]);
+    assert_eq!(ctx.active_texture_unit(), 3);
+
+    // The same texture on the now active unit requires nothing.
+    drop(texture.bind(None));
+    assert!(mock.calls().is_empty());
+
+    let mut other_texture =
+      Texture2D::<u8>::new(ctx.share(), None, TextureInputFormat::RGBA, None);
+    assert_eq!(mock.call_names(), ["GenTextures", "BindTexture"]);
+    mock.clear_calls();
+
+    drop(texture.bind(Some(3)));
+    assert_eq!(mock.call_names(), ["BindTexture"]);
+    mock.clear_calls();
+
+    drop(other_texture.bind(Some(0)));
+    assert_eq!(mock.call_names(), ["ActiveTexture", "BindTexture"]);
+  }
+}
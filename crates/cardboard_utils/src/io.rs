@@ -0,0 +1,8 @@
+use prelude_plus::*;
+
+/// Reads exactly `N` bytes, handy together with `from_le_bytes` and friends.
+pub fn read_array<R: Read + ?Sized, const N: usize>(reader: &mut R) -> io::Result<[u8; N]> {
+  let mut bytes = [0; N];
+  reader.read_exact(&mut bytes)?;
+  Ok(bytes)
+}
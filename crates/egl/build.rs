@@ -0,0 +1,19 @@
+use gl_generator::{Api, Fallbacks, Profile, Registry, StaticGenerator};
+use std::env;
+use std::fs::File;
+use std::path::Path;
+
+fn main() {
+  let dest = env::var("OUT_DIR").unwrap();
+  let mut file = File::create(&Path::new(&dest).join("bindings.rs")).unwrap();
+
+  let registry = Registry::new(
+    Api::Egl,
+    (1, 5),
+    Profile::Core,
+    Fallbacks::All,
+    ["EGL_MESA_platform_surfaceless", "EGL_KHR_surfaceless_context"],
+  );
+
+  registry.write_bindings(StaticGenerator, &mut file).unwrap();
+}
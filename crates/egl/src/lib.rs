@@ -0,0 +1,21 @@
+#![allow(clippy::all, non_camel_case_types)]
+
+use std::os::raw::{c_long, c_void};
+
+// Platform-specific types which the generated bindings expect to be defined
+// by the user, see gl_generator's templates/types/egl.rs.
+pub type khronos_utime_nanoseconds_t = khronos_uint64_t;
+pub type khronos_uint64_t = u64;
+pub type khronos_ssize_t = c_long;
+pub type EGLint = i32;
+pub type EGLNativeDisplayType = *const c_void;
+pub type EGLNativePixmapType = *const c_void;
+pub type EGLNativeWindowType = *const c_void;
+pub type NativeDisplayType = EGLNativeDisplayType;
+pub type NativePixmapType = EGLNativePixmapType;
+pub type NativeWindowType = EGLNativeWindowType;
+
+include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
+
+#[link(name = "EGL")]
+extern "system" {}
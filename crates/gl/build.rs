@@ -1,6 +1,7 @@
 use gl_generator::{Api, DebugStructGenerator, Fallbacks, Profile, Registry, StructGenerator};
 use std::env;
 use std::fs::File;
+use std::io::{self, Write};
 use std::path::Path;
 
 fn main() {
@@ -16,4 +17,49 @@ fn main() {
     registry.write_bindings(StructGenerator, &mut file)
   }
   .unwrap();
+
+  if cfg!(feature = "mock") {
+    let mut file = File::create(&Path::new(&dest).join("mock_bindings.rs")).unwrap();
+    write_mock_bindings(&registry, &mut file).unwrap();
+  }
+}
+
+/// Generates a fake implementation of every command, which forwards the call
+/// to the handler installed with `mock::set_handler`, plus a loader function
+/// for those, see `src/mock.rs`.
+fn write_mock_bindings(registry: &Registry, dest: &mut impl Write) -> io::Result<()> {
+  for cmd in &registry.cmds {
+    let params: Vec<String> =
+      cmd.params.iter().map(|param| format!("{}: {}", param.ident, param.ty)).collect();
+    let args: Vec<String> = cmd
+      .params
+      .iter()
+      .map(|param| format!("IntoMockValue::into_mock_value({})", param.ident))
+      .collect();
+    writeln!(
+      dest,
+      "#[allow(non_snake_case)] pub extern \"system\" fn {name}({params}) -> {ret} {{ \
+         FromMockValue::from_mock_value(call(\"{name}\", &[{args}])) \
+       }}",
+      name = cmd.proto.ident,
+      params = params.join(", "),
+      ret = cmd.proto.ty,
+      args = args.join(", "),
+    )?;
+  }
+
+  writeln!(dest, "pub fn get_proc_address(symbol: &str) -> *const __gl_imports::raw::c_void {{")?;
+  writeln!(dest, "  match symbol {{")?;
+  for cmd in &registry.cmds {
+    writeln!(
+      dest,
+      "    \"gl{name}\" => {name} as *const __gl_imports::raw::c_void,",
+      name = cmd.proto.ident
+    )?;
+  }
+  writeln!(dest, "    _ => std::ptr::null(),")?;
+  writeln!(dest, "  }}")?;
+  writeln!(dest, "}}")?;
+
+  Ok(())
 }
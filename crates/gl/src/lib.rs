@@ -1,2 +1,5 @@
 #![allow(clippy::all)]
 include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
+
+#[cfg(feature = "mock")]
+pub mod mock;
@@ -0,0 +1,122 @@
+//! Fake implementations of all GL commands which don't touch any real driver
+//! and instead forward every call to a handler function installed for the
+//! current thread. Pass [`get_proc_address`] to `Gles2::load_with` to get a
+//! "context" backed by them.
+
+use super::__gl_imports;
+use super::types;
+use std::cell::RefCell;
+
+/// A GL argument or return value with the type information erased. Pointers
+/// are stored as plain addresses, handlers which need to write output values
+/// may cast them back.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MockValue {
+  Void,
+  Int(i64),
+  Float(f32),
+  Ptr(usize),
+}
+
+impl MockValue {
+  pub fn as_int(&self) -> i64 {
+    match *self {
+      Self::Int(value) => value,
+      Self::Ptr(addr) => addr as i64,
+      _ => panic!("expected an integer, got {:?}", self),
+    }
+  }
+
+  pub fn as_ptr<T>(&self) -> *mut T {
+    match *self {
+      Self::Ptr(addr) => addr as *mut T,
+      _ => panic!("expected a pointer, got {:?}", self),
+    }
+  }
+}
+
+pub type MockHandler = Box<dyn FnMut(&'static str, &[MockValue]) -> MockValue>;
+
+thread_local! {
+  static HANDLER: RefCell<Option<MockHandler>> = RefCell::new(None);
+}
+
+/// Installs the handler for the current thread, the previous one is returned.
+/// It receives the command names without the `gl` prefix, i.e. the same names
+/// as the fields of `Gles2`.
+pub fn set_handler(handler: Option<MockHandler>) -> Option<MockHandler> {
+  HANDLER.with(|cell| std::mem::replace(&mut *cell.borrow_mut(), handler))
+}
+
+fn call(name: &'static str, args: &[MockValue]) -> MockValue {
+  HANDLER.with(|cell| match &mut *cell.borrow_mut() {
+    Some(handler) => handler(name, args),
+    None => panic!("a mock GL function was called without a handler: gl{}", name),
+  })
+}
+
+pub trait IntoMockValue {
+  fn into_mock_value(self) -> MockValue;
+}
+
+pub trait FromMockValue {
+  fn from_mock_value(value: MockValue) -> Self;
+}
+
+macro_rules! impl_mock_value_for_ints {
+  ($($ty:ty),+) => {$(
+    impl IntoMockValue for $ty {
+      #[inline(always)]
+      fn into_mock_value(self) -> MockValue { MockValue::Int(self as i64) }
+    }
+
+    impl FromMockValue for $ty {
+      #[inline]
+      fn from_mock_value(value: MockValue) -> Self {
+        match value {
+          MockValue::Void => 0,
+          value => value.as_int() as $ty,
+        }
+      }
+    }
+  )+};
+}
+
+impl_mock_value_for_ints!(i8, u8, i16, u16, i32, u32, i64, u64, isize, usize);
+
+impl IntoMockValue for f32 {
+  #[inline(always)]
+  fn into_mock_value(self) -> MockValue { MockValue::Float(self) }
+}
+
+impl<T> IntoMockValue for *const T {
+  #[inline(always)]
+  fn into_mock_value(self) -> MockValue { MockValue::Ptr(self as usize) }
+}
+
+impl<T> IntoMockValue for *mut T {
+  #[inline(always)]
+  fn into_mock_value(self) -> MockValue { MockValue::Ptr(self as usize) }
+}
+
+impl<T> FromMockValue for *const T {
+  #[inline]
+  fn from_mock_value(value: MockValue) -> Self {
+    match value {
+      MockValue::Void => std::ptr::null(),
+      value => value.as_ptr::<T>() as *const T,
+    }
+  }
+}
+
+impl IntoMockValue for types::GLDEBUGPROC {
+  #[inline(always)]
+  fn into_mock_value(self) -> MockValue { MockValue::Ptr(self.map_or(0, |f| f as usize)) }
+}
+
+impl FromMockValue for () {
+  #[inline(always)]
+  fn from_mock_value(_value: MockValue) -> Self {}
+}
+
+include!(concat!(env!("OUT_DIR"), "/mock_bindings.rs"));
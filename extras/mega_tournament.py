@@ -24,7 +24,7 @@ import csv
 import multiprocessing
 import random
 from datetime import datetime
-from typing import Callable, Generator, Iterable, List, Optional, Tuple, TypeVar
+from typing import Callable, Dict, Generator, Iterable, List, Optional, Tuple, TypeVar
 
 try:
   import matplotlib.pyplot as plt  # type: ignore
@@ -65,31 +65,38 @@ def tqdm_wrapper(iterable: Iterable[_T],
 
 
 def main(argv: List[str]) -> int:
-  champion_wins_per_player_number: List[int] = [0] * (
-    MAX_PLAYERS_NUMBER - MIN_PLAYERS_NUMBER + 1  #
-  )
+  # The numbers of players and of the iterations are counted from the results,
+  # so that the tables written by `openkrosskod tournament` with different
+  # options can be read too.
+  champion_wins_per_player_number: Dict[int, int] = {}
+  iterations_per_player_number: Dict[int, int] = {}
 
+  table_file_name = argv[1] if len(argv) > 1 else None
   for total_players, _, best_player in simulate_or_read_results(
-    argv[1] if len(argv) > 1 else None,
-    len(champion_wins_per_player_number) * ITERATIONS_PER_PLAYER_NUMBER,
+    table_file_name,
+    (MAX_PLAYERS_NUMBER - MIN_PLAYERS_NUMBER + 1) * ITERATIONS_PER_PLAYER_NUMBER
+    if table_file_name is None else None,
   ):
+    iterations_per_player_number[total_players] = (
+      iterations_per_player_number.get(total_players, 0) + 1  #
+    )
+    champion_wins_per_player_number.setdefault(total_players, 0)
     if best_player == 0:
-      champion_wins_per_player_number[total_players - MIN_PLAYERS_NUMBER] += 1
+      champion_wins_per_player_number[total_players] += 1
 
-  champion_win_probabilities = [0.0] * len(champion_wins_per_player_number)
-  for total_players, champion_wins in enumerate(champion_wins_per_player_number):
-    total_players += MIN_PLAYERS_NUMBER
-    champion_win_probabilities[total_players - MIN_PLAYERS_NUMBER] = (
-      champion_wins / ITERATIONS_PER_PLAYER_NUMBER  #
-    )
+  player_numbers = sorted(iterations_per_player_number)
+  champion_win_probabilities = [
+    champion_wins_per_player_number[total_players] / iterations_per_player_number[total_players]
+    for total_players in player_numbers
+  ]
 
   if plt is not None:
-    plt.plot(range(MIN_PLAYERS_NUMBER, MAX_PLAYERS_NUMBER + 1), champion_win_probabilities)
+    plt.plot(player_numbers, champion_win_probabilities)
     plt.xlabel("Total number of players")
     plt.ylabel("Win probability of the champion")
     plt.show()
   else:
-    for x, y in zip(range(MIN_PLAYERS_NUMBER, MAX_PLAYERS_NUMBER + 1), champion_win_probabilities):
+    for x, y in zip(player_numbers, champion_win_probabilities):
       print(" {:4}  {}".format(x, y))
 
   return 0
@@ -97,7 +104,7 @@ def main(argv: List[str]) -> int:
 
 def simulate_or_read_results(
   table_file_name: Optional[str],
-  total: int,
+  total: Optional[int],
 ) -> Generator[Tuple[int, int, int], None, None]:
 
   if table_file_name is None:
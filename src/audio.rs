@@ -0,0 +1,71 @@
+use cardboard_coal as coal;
+use prelude_plus::*;
+
+use crate::game_fs::GameFs;
+
+pub type AssetDecoder = Box<dyn coal::Decoder>;
+
+pub fn open_sound_decoder(game_fs: &GameFs, path: &str) -> AnyResult<AssetDecoder> {
+  let file = game_fs.open_file(&path)?;
+  let reader = BufReader::new(file);
+
+  let extension = Path::new(path).extension().and_then(OsStr::to_str).unwrap_or("");
+  let decoder: AssetDecoder = if extension.eq_ignore_ascii_case("wav") {
+    Box::new(
+      coal::WavDecoder::new(reader).with_context(|| format!("Failed to decode '{}'", path))?,
+    )
+  } else if extension.eq_ignore_ascii_case("ogg") {
+    Box::new(
+      coal::VorbisDecoder::new(reader).with_context(|| format!("Failed to decode '{}'", path))?,
+    )
+  } else {
+    bail!("Unsupported sound file format: '{}'", path);
+  };
+  Ok(decoder)
+}
+
+/// Decodes the whole file into memory, meant for short sound effects.
+pub fn load_sound_asset(game_fs: &GameFs, path: &str) -> AnyResult<coal::SoundBuffer> {
+  let decoder = open_sound_decoder(game_fs, path)?;
+  coal::SoundBuffer::decode(decoder).with_context(|| format!("Failed to decode '{}'", path))
+}
+
+/// Opens a file for streaming, meant for music tracks.
+pub fn open_music_asset(
+  game_fs: &GameFs,
+  path: &str,
+) -> AnyResult<coal::StreamingSource<AssetDecoder>> {
+  let decoder = open_sound_decoder(game_fs, path)?;
+  Ok(coal::StreamingSource::new(decoder))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_utils::TempGameFs;
+  use cardboard_coal::test_utils::{pcm16_wav, SAMPLE_RATE};
+  use cardboard_coal::SoundSource;
+
+  #[test]
+  fn loads_sounds_through_game_fs() {
+    let game_fs = TempGameFs::new("audio");
+    fs::create_dir(game_fs.assets_dir.join("sounds")).unwrap();
+    let wav = pcm16_wav(1, &[0, 8192, 16384, -16384], None);
+    fs::write(game_fs.assets_dir.join("sounds/beep.wav"), wav).unwrap();
+    fs::write(game_fs.assets_dir.join("sounds/beep.mp3"), b"").unwrap();
+
+    let buffer = load_sound_asset(&game_fs, "sounds/beep.wav").unwrap();
+    assert_eq!((buffer.channels(), buffer.sample_rate(), buffer.frames()), (1, SAMPLE_RATE, 4));
+    assert_eq!(buffer.samples(), [0.0, 0.25, 0.5, -0.5]);
+
+    let mut music = open_music_asset(&game_fs, "sounds/beep.wav").unwrap();
+    let mut out = [0.0; 8];
+    assert_eq!(music.read(&mut out), 4);
+    assert_eq!(out[..4], [0.0, 0.25, 0.5, -0.5]);
+    assert_eq!(music.read(&mut out), 0);
+
+    let error = load_sound_asset(&game_fs, "sounds/beep.mp3").unwrap_err();
+    assert!(error.to_string().contains("Unsupported sound file format"), "{}", error);
+    assert!(load_sound_asset(&game_fs, "sounds/missing.wav").is_err());
+  }
+}
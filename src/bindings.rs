@@ -0,0 +1,531 @@
+//! Named input actions and axes, so that the gameplay code doesn't have to
+//! care which physical keys are pressed. Every action or axis has a built-in
+//! set of bindings which can be overridden in the `bindings` section of the
+//! settings file, e.g.:
+//!
+//! ```json
+//! "bindings": {
+//!   "pause": ["Escape", "P", "Gamepad.Start"],
+//!   "right_racket": { "negative": ["Down", "Gamepad2.LeftY-"], "positive": ["Up", "Gamepad2.LeftY+"] }
+//! }
+//! ```
+//!
+//! The gamepad inputs are written as `Gamepad.<button>`, or `Gamepad.<axis>+`
+//! and `Gamepad.<axis>-` for the two directions of an axis. These match any
+//! connected gamepad, put the player number after `Gamepad` (`Gamepad2.A`) to
+//! match only a specific one.
+//!
+//! NOTE: The replays record the physical keys, so they must be played back
+//! with the same bindings they were recorded with, which is checked with
+//! [`InputBindings::checksum`].
+
+use prelude_plus::*;
+use serde::Deserialize;
+
+use crate::input::{GamepadAxis, GamepadButton, GamepadState, InputState, Key};
+use crate::netplay::Checksum;
+
+pub mod actions {
+  pub const QUIT: &str = "quit";
+  pub const DEBUG_BREAKPOINT: &str = "debug_breakpoint";
+  pub const RENDERER_STATS: &str = "renderer_stats";
+  pub const SCREENSHOT: &str = "screenshot";
+  pub const MENU: &str = "menu";
+  pub const CONFIRM: &str = "confirm";
+  pub const BACK: &str = "back";
+  /// Both pauses and unpauses the game.
+  pub const PAUSE: &str = "pause";
+  pub const RESET_VIEW: &str = "reset_view";
+  pub const RESET_SIMULATION: &str = "reset_simulation";
+  pub const CAMERA_DRAG: &str = "camera_drag";
+  pub const PICK_STARTING_POINT: &str = "pick_starting_point";
+  pub const TOGGLE_JULIA_MODE: &str = "toggle_julia_mode";
+  pub const SWITCH_INTEGRATOR: &str = "switch_integrator";
+  pub const SWITCH_BOT_DIFFICULTY: &str = "switch_bot_difficulty";
+}
+
+pub mod axes {
+  pub const LEFT_RACKET: &str = "left_racket";
+  pub const RIGHT_RACKET: &str = "right_racket";
+  pub const ZOOM: &str = "zoom";
+  pub const CAMERA_X: &str = "camera_x";
+  pub const CAMERA_Y: &str = "camera_y";
+  pub const ISOVALUE: &str = "isovalue";
+}
+
+/// The analog inputs bound to actions count as pressed past this value.
+pub const ANALOG_PRESS_THRESHOLD: f32 = 0.5;
+
+/// A single physical input which can trigger an action. Both of the left and
+/// right modifier keys can simply be bound to the same action when it doesn't
+/// matter which one is held.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Binding {
+  Key(Key),
+  /// `gamepad` is the slot index in [`InputState::gamepads`], `None` matches
+  /// all of them.
+  GamepadButton {
+    gamepad: Option<u8>,
+    button: GamepadButton,
+  },
+  /// A single direction of an axis.
+  GamepadAxis {
+    gamepad: Option<u8>,
+    axis: GamepadAxis,
+    positive: bool,
+  },
+}
+
+impl Binding {
+  pub fn parse(name: &str) -> AnyResult<Self> {
+    if let Some(rest) = name.strip_prefix("Gamepad") {
+      let (number, input) =
+        rest.split_once('.').ok_or_else(|| format_err!("Invalid gamepad input '{}'", name))?;
+      let gamepad = match number {
+        "" => None,
+        _ => match number.parse::<u8>() {
+          Ok(number) if number > 0 => Some(number - 1),
+          _ => bail!("Invalid gamepad number in '{}'", name),
+        },
+      };
+
+      let axis_direction = match input.as_bytes().last() {
+        Some(b'+') => Some(true),
+        Some(b'-') => Some(false),
+        _ => None,
+      };
+      return match axis_direction {
+        Some(positive) => GamepadAxis::from_name(&input[..input.len() - 1])
+          .map(|axis| Self::GamepadAxis { gamepad, axis, positive })
+          .ok_or_else(|| format_err!("Unknown gamepad axis in '{}'", name)),
+        None => GamepadButton::from_name(input)
+          .map(|button| Self::GamepadButton { gamepad, button })
+          .ok_or_else(|| format_err!("Unknown gamepad button in '{}'", name)),
+      };
+    }
+
+    Key::from_name(name).map(Self::Key).ok_or_else(|| format_err!("Unknown key '{}'", name))
+  }
+
+  /// How far the input is pushed, from 0 to 1, the digital ones return only
+  /// these two. Checks the state on the previous game loop tick if `previous`
+  /// is set.
+  pub fn value(self, input: &InputState, previous: bool) -> f32 {
+    fn gamepads(input: &InputState, index: Option<u8>) -> impl Iterator<Item = &GamepadState> {
+      input
+        .connected_gamepads()
+        .filter(move |&(i, _)| index.map_or(true, |index| i == index as usize))
+        .map(|(_, gamepad)| gamepad)
+    }
+
+    match self {
+      Self::Key(key) => {
+        let table =
+          if previous { &input.prev_keyboard_state_table } else { &input.keyboard_state_table };
+        table[key as usize] as u8 as f32
+      }
+      Self::GamepadButton { gamepad, button } => gamepads(input, gamepad)
+        .map(|gamepad| gamepad.button_state(button, previous) as u8 as f32)
+        .fold(0.0, f32::max),
+      Self::GamepadAxis { gamepad, axis, positive } => gamepads(input, gamepad)
+        .map(|gamepad| {
+          let value = gamepad.axis_state(axis, previous);
+          if positive { value } else { -value }.max(0.0)
+        })
+        .fold(0.0, f32::max),
+    }
+  }
+
+  #[inline]
+  pub fn is_down(self, input: &InputState, previous: bool) -> bool {
+    self.value(input, previous) >= ANALOG_PRESS_THRESHOLD
+  }
+}
+
+impl fmt::Display for Binding {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    fn write_gamepad(f: &mut fmt::Formatter, gamepad: Option<u8>) -> fmt::Result {
+      match gamepad {
+        Some(index) => write!(f, "Gamepad{}.", index as u16 + 1),
+        None => f.write_str("Gamepad."),
+      }
+    }
+
+    match self {
+      Self::Key(key) => f.write_str(key.name()),
+      Self::GamepadButton { gamepad, button } => {
+        write_gamepad(f, *gamepad)?;
+        f.write_str(button.name())
+      }
+      Self::GamepadAxis { gamepad, axis, positive } => {
+        write_gamepad(f, *gamepad)?;
+        write!(f, "{}{}", axis.name(), if *positive { '+' } else { '-' })
+      }
+    }
+  }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AxisBindings {
+  pub negative: Vec<Binding>,
+  pub positive: Vec<Binding>,
+}
+
+/// The `bindings` section of the settings file, with the bindings written as
+/// the key names.
+pub type BindingsConfig = BTreeMap<String, BindingConfig>;
+
+/// NOTE: `deny_unknown_fields` applies to the `Axis` variant, so that a typo
+/// in `negative` or `positive` isn't silently ignored.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+pub enum BindingConfig {
+  Action(Vec<String>),
+  Axis {
+    #[serde(default)]
+    negative: Vec<String>,
+    #[serde(default)]
+    positive: Vec<String>,
+  },
+}
+
+#[derive(Debug, Clone)]
+pub struct InputBindings {
+  actions: HashMap<String, Vec<Binding>>,
+  axes: HashMap<String, AxisBindings>,
+}
+
+impl Default for InputBindings {
+  fn default() -> Self {
+    use GamepadAxis::*;
+    use GamepadButton::{Back, DPadDown, DPadUp, LeftShoulder, RightShoulder, Start};
+    use Key::*;
+    let mut myself = Self { actions: HashMap::new(), axes: HashMap::new() };
+
+    const fn key(key: Key) -> Binding { Binding::Key(key) }
+    const fn button(button: GamepadButton) -> Binding {
+      Binding::GamepadButton { gamepad: None, button }
+    }
+    const fn stick(axis: GamepadAxis, positive: bool) -> Binding {
+      Binding::GamepadAxis { gamepad: None, axis, positive }
+    }
+
+    let mut action = |name: &str, bindings: &[Binding]| {
+      myself.set_action(name, bindings.to_vec());
+    };
+    action(actions::QUIT, &[key(Q)]);
+    action(actions::DEBUG_BREAKPOINT, &[key(B)]);
+    action(actions::RENDERER_STATS, &[key(F3)]);
+    action(actions::SCREENSHOT, &[key(F8)]);
+    action(actions::MENU, &[key(Tab), button(Back)]);
+    action(actions::CONFIRM, &[key(Return), key(Space), button(GamepadButton::A)]);
+    action(actions::BACK, &[key(Escape), button(GamepadButton::B)]);
+    action(actions::PAUSE, &[key(Escape), key(Pause), button(Start)]);
+    action(actions::RESET_VIEW, &[key(R), button(GamepadButton::Y)]);
+    action(actions::RESET_SIMULATION, &[key(Space), button(GamepadButton::X)]);
+    action(actions::CAMERA_DRAG, &[key(MouseLeft)]);
+    action(actions::PICK_STARTING_POINT, &[key(MouseRight)]);
+    action(actions::TOGGLE_JULIA_MODE, &[key(J), button(GamepadButton::A)]);
+    action(actions::SWITCH_INTEGRATOR, &[key(I), button(GamepadButton::A)]);
+    action(actions::SWITCH_BOT_DIFFICULTY, &[key(F2), button(GamepadButton::Y)]);
+
+    let mut axis = |name: &str, negative: &[Binding], positive: &[Binding]| {
+      myself
+        .set_axis(name, AxisBindings { negative: negative.to_vec(), positive: positive.to_vec() });
+    };
+    axis(axes::LEFT_RACKET, &[key(S)], &[key(W)]);
+    axis(
+      axes::RIGHT_RACKET,
+      &[key(Down), button(DPadDown), stick(LeftY, false)],
+      &[key(Up), button(DPadUp), stick(LeftY, true)],
+    );
+    axis(
+      axes::ZOOM,
+      &[key(Minus), key(KpMinus), button(LeftShoulder)],
+      &[key(Equals), key(KpPlus), button(RightShoulder)],
+    );
+    axis(axes::CAMERA_X, &[key(A), stick(LeftX, false)], &[key(D), stick(LeftX, true)]);
+    axis(axes::CAMERA_Y, &[key(S), stick(LeftY, false)], &[key(W), stick(LeftY, true)]);
+    axis(
+      axes::ISOVALUE,
+      &[key(Minus), key(KpMinus), button(LeftShoulder)],
+      &[key(Equals), key(KpPlus), button(RightShoulder)],
+    );
+
+    myself
+  }
+}
+
+impl InputBindings {
+  /// Returns the built-in bindings.
+  pub fn new() -> Self { Self::default() }
+
+  #[inline]
+  pub fn action(&self, name: &str) -> Option<&[Binding]> {
+    self.actions.get(name).map(Vec::as_slice)
+  }
+
+  #[inline]
+  pub fn axis(&self, name: &str) -> Option<&AxisBindings> { self.axes.get(name) }
+
+  /// Replaces all of the bindings of the action, an empty list unbinds it.
+  pub fn set_action(&mut self, name: &str, bindings: Vec<Binding>) {
+    self.actions.insert(name.to_owned(), bindings);
+  }
+
+  pub fn set_axis(&mut self, name: &str, bindings: AxisBindings) {
+    self.axes.insert(name.to_owned(), bindings);
+  }
+
+  /// Covers all of the actions and axes along with the order of their
+  /// bindings, and is the same on every run and every platform.
+  pub fn checksum(&self) -> u64 {
+    fn write_bindings(checksum: &mut Checksum, bindings: &[Binding]) {
+      checksum.write_u64(bindings.len() as u64);
+      for binding in bindings {
+        checksum.write_str(&binding.to_string());
+      }
+    }
+
+    let mut checksum = Checksum::new();
+    let actions: BTreeMap<&String, &Vec<Binding>> = self.actions.iter().collect();
+    for (name, bindings) in actions {
+      checksum.write_str(name);
+      write_bindings(&mut checksum, bindings);
+    }
+    let axes: BTreeMap<&String, &AxisBindings> = self.axes.iter().collect();
+    for (name, bindings) in axes {
+      checksum.write_str(name);
+      write_bindings(&mut checksum, &bindings.negative);
+      write_bindings(&mut checksum, &bindings.positive);
+    }
+    checksum.finish()
+  }
+
+  /// Adds one more binding to the action, unless it is already bound.
+  pub fn bind_action(&mut self, name: &str, binding: Binding) {
+    let bindings = self.actions.entry(name.to_owned()).or_default();
+    if !bindings.contains(&binding) {
+      bindings.push(binding);
+    }
+  }
+
+  /// The bindings of the actions and axes mentioned in the config replace the
+  /// built-in ones, the rest are left as is. Only the names which are already
+  /// known can be rebound, to catch the typos.
+  pub fn apply_config(&mut self, config: &BindingsConfig) -> AnyResult<()> {
+    fn parse_all(names: &[String]) -> AnyResult<Vec<Binding>> {
+      names.iter().map(|name| Binding::parse(name)).collect()
+    }
+
+    for (name, entry) in config {
+      match entry {
+        BindingConfig::Action(keys) => {
+          if self.axes.contains_key(name) {
+            bail!("'{}' is an axis, expected an object with the negative and positive keys", name);
+          }
+          ensure!(self.actions.contains_key(name), "Unknown action '{}'", name);
+          let bindings =
+            parse_all(keys).with_context(|| format!("Invalid bindings for '{}'", name))?;
+          self.set_action(name, bindings);
+        }
+        BindingConfig::Axis { negative, positive } => {
+          if self.actions.contains_key(name) {
+            bail!("'{}' is an action, expected a list of keys", name);
+          }
+          ensure!(self.axes.contains_key(name), "Unknown axis '{}'", name);
+          let context = || format!("Invalid bindings for '{}'", name);
+          let bindings = AxisBindings {
+            negative: parse_all(negative).with_context(context)?,
+            positive: parse_all(positive).with_context(context)?,
+          };
+          self.set_axis(name, bindings);
+        }
+      }
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::input::GamepadInputs;
+
+  fn config(json: &str) -> BindingsConfig { serde_json::from_str(json).unwrap() }
+
+  fn connect_gamepad(input: &mut InputState, slot: usize, inputs: GamepadInputs) {
+    input.gamepads.resize_with(slot + 1, || None);
+    let mut gamepad = GamepadState::new(slot as u32, "Test gamepad".to_owned());
+    gamepad.current = inputs;
+    input.gamepads[slot] = Some(gamepad);
+  }
+
+  #[test]
+  fn parses_keys_and_gamepad_inputs() {
+    let valid = [
+      ("Escape", Binding::Key(Key::Escape)),
+      ("Num1", Binding::Key(Key::Num1)),
+      ("Gamepad.Start", Binding::GamepadButton { gamepad: None, button: GamepadButton::Start }),
+      ("Gamepad2.A", Binding::GamepadButton { gamepad: Some(1), button: GamepadButton::A }),
+      (
+        "Gamepad.LeftY-",
+        Binding::GamepadAxis { gamepad: None, axis: GamepadAxis::LeftY, positive: false },
+      ),
+      (
+        "Gamepad4.TriggerRight+",
+        Binding::GamepadAxis { gamepad: Some(3), axis: GamepadAxis::TriggerRight, positive: true },
+      ),
+    ];
+    for (name, binding) in valid {
+      assert_eq!(Binding::parse(name).unwrap(), binding, "{}", name);
+      assert_eq!(binding.to_string(), name);
+    }
+
+    let invalid = [
+      ("", "Unknown key ''"),
+      ("escape", "Unknown key 'escape'"),
+      ("Gamepad", "Invalid gamepad input 'Gamepad'"),
+      ("GamepadA", "Invalid gamepad input 'GamepadA'"),
+      ("Gamepad0.A", "Invalid gamepad number in 'Gamepad0.A'"),
+      ("Gamepad256.A", "Invalid gamepad number in 'Gamepad256.A'"),
+      ("Gamepad.Z", "Unknown gamepad button in 'Gamepad.Z'"),
+      ("Gamepad.A+", "Unknown gamepad axis in 'Gamepad.A+'"),
+      ("Gamepad.LeftY", "Unknown gamepad button in 'Gamepad.LeftY'"),
+    ];
+    for (name, error) in invalid {
+      assert_eq!(Binding::parse(name).unwrap_err().to_string(), error);
+    }
+  }
+
+  #[test]
+  fn config_overrides_the_defaults() {
+    let mut bindings = InputBindings::new();
+    bindings
+      .apply_config(&config(
+        r#"{
+          "pause": ["P", "Gamepad.Start"],
+          "left_racket": { "positive": ["Gamepad1.LeftY+"] }
+        }"#,
+      ))
+      .unwrap();
+
+    assert_eq!(
+      bindings.action(actions::PAUSE).unwrap(),
+      [
+        Binding::Key(Key::P),
+        Binding::GamepadButton { gamepad: None, button: GamepadButton::Start },
+      ],
+    );
+    let left_racket = bindings.axis(axes::LEFT_RACKET).unwrap();
+    assert_eq!(left_racket.negative, []);
+    assert_eq!(
+      left_racket.positive,
+      [Binding::GamepadAxis { gamepad: Some(0), axis: GamepadAxis::LeftY, positive: true }],
+    );
+    // The rest are left alone.
+    let defaults = InputBindings::new();
+    assert_eq!(bindings.action(actions::BACK), defaults.action(actions::BACK));
+    assert_eq!(bindings.axis(axes::RIGHT_RACKET), defaults.axis(axes::RIGHT_RACKET));
+  }
+
+  #[test]
+  fn config_errors() {
+    let error = |json: &str| {
+      let mut bindings = InputBindings::new();
+      format!("{:#}", bindings.apply_config(&config(json)).unwrap_err())
+    };
+    assert_eq!(error(r#"{ "jump": ["Space"] }"#), "Unknown action 'jump'");
+    assert_eq!(error(r#"{ "strafe": { "positive": ["D"] } }"#), "Unknown axis 'strafe'");
+    assert_eq!(
+      error(r#"{ "zoom": ["Z"] }"#),
+      "'zoom' is an axis, expected an object with the negative and positive keys",
+    );
+    assert_eq!(
+      error(r#"{ "pause": { "positive": ["P"] } }"#),
+      "'pause' is an action, expected a list of keys",
+    );
+    assert_eq!(
+      error(r#"{ "pause": ["Pouse"] }"#),
+      "Invalid bindings for 'pause': Unknown key 'Pouse'"
+    );
+
+    let result = serde_json::from_str::<BindingsConfig>(r#"{ "zoom": { "postive": ["Z"] } }"#);
+    assert!(result.is_err());
+  }
+
+  #[test]
+  fn action_is_down_while_any_binding_is() {
+    let mut input = InputState::new();
+    input.bindings.get_mut().set_action(
+      actions::CONFIRM,
+      vec![
+        Binding::Key(Key::Return),
+        Binding::GamepadButton { gamepad: Some(1), button: GamepadButton::A },
+        Binding::GamepadAxis { gamepad: None, axis: GamepadAxis::TriggerRight, positive: true },
+      ],
+    );
+    assert!(!input.is_action_down(actions::CONFIRM));
+
+    input.set_key_down(Key::Return, true);
+    assert!(input.is_action_pressed(actions::CONFIRM));
+    input.prev_keyboard_state_table = input.keyboard_state_table;
+    input.set_key_down(Key::Return, false);
+    assert!(input.is_action_unpressed(actions::CONFIRM));
+
+    // Only the second gamepad is bound to the button.
+    let mut inputs = GamepadInputs::default();
+    inputs.buttons[GamepadButton::A as usize] = true;
+    connect_gamepad(&mut input, 0, inputs.clone());
+    assert!(!input.is_action_down(actions::CONFIRM));
+    connect_gamepad(&mut input, 1, inputs);
+    assert!(input.is_action_down(actions::CONFIRM));
+
+    // The analog inputs count past the threshold.
+    let mut inputs = GamepadInputs::default();
+    inputs.axes[GamepadAxis::TriggerRight as usize] = ANALOG_PRESS_THRESHOLD - 0.1;
+    connect_gamepad(&mut input, 1, inputs.clone());
+    assert!(!input.is_action_down(actions::CONFIRM));
+    inputs.axes[GamepadAxis::TriggerRight as usize] = ANALOG_PRESS_THRESHOLD;
+    connect_gamepad(&mut input, 1, inputs);
+    assert!(input.is_action_down(actions::CONFIRM));
+  }
+
+  #[test]
+  fn axis_values_combine() {
+    let mut input = InputState::new();
+    input.bindings.get_mut().set_axis(
+      axes::ZOOM,
+      AxisBindings {
+        negative: vec![
+          Binding::Key(Key::Minus),
+          Binding::GamepadAxis { gamepad: None, axis: GamepadAxis::LeftY, positive: false },
+        ],
+        positive: vec![
+          Binding::Key(Key::Equals),
+          Binding::GamepadAxis { gamepad: None, axis: GamepadAxis::LeftY, positive: true },
+        ],
+      },
+    );
+    assert_eq!(input.action_axis_value(axes::ZOOM), 0.0);
+
+    let mut inputs = GamepadInputs::default();
+    inputs.axes[GamepadAxis::LeftY as usize] = 0.25;
+    connect_gamepad(&mut input, 0, inputs);
+    assert_eq!(input.action_axis_value(axes::ZOOM), 0.25);
+    // Below the press threshold for the digital reading.
+    assert_eq!(input.action_axis(axes::ZOOM), 0);
+
+    // The strongest input in each direction wins, and the opposite
+    // directions cancel out.
+    input.set_key_down(Key::Equals, true);
+    assert_eq!(input.action_axis_value(axes::ZOOM), 1.0);
+    assert_eq!(input.action_axis(axes::ZOOM), 1);
+    input.set_key_down(Key::Minus, true);
+    assert_eq!(input.action_axis_value(axes::ZOOM), 0.0);
+    assert_eq!(input.action_axis(axes::ZOOM), 0);
+    input.set_key_down(Key::Equals, false);
+    assert_eq!(input.action_axis_value(axes::ZOOM), -0.75);
+    assert_eq!(input.action_axis(axes::ZOOM), -1);
+  }
+}
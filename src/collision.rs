@@ -0,0 +1,430 @@
+//! Collision tests between the circles and the axis-aligned boxes. The static
+//! tests return the [`Contact`] of two overlapping bodies, and the swept ones
+//! find the moment when a moving circle first touches another body, so that a
+//! fast circle can't tunnel through a thin box within a single fixed update.
+//!
+//! Two bodies which only touch each other are not considered to be colliding.
+
+use cardboard_math::*;
+use prelude_plus::*;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Shape {
+  Aabb { half_extents: Vec2f },
+  Circle { radius: f32 },
+}
+
+impl Default for Shape {
+  /// A single point.
+  fn default() -> Self { Self::Aabb { half_extents: Vec2f::ZERO } }
+}
+
+impl Shape {
+  #[inline]
+  pub fn aabb(size: Vec2f) -> Self { Self::Aabb { half_extents: size / 2.0 } }
+  #[inline]
+  pub fn circle(radius: f32) -> Self { Self::Circle { radius } }
+
+  /// The size of the bounding box.
+  pub fn size(&self) -> Vec2f {
+    match *self {
+      Self::Aabb { half_extents } => half_extents * 2.0,
+      Self::Circle { radius } => vec2n(radius * 2.0),
+    }
+  }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Body {
+  pub pos: Vec2f,
+  pub shape: Shape,
+}
+
+/// Describes the overlap of two bodies `a` and `b`, as seen from `a`: moving
+/// `a` by `normal * depth` separates them.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Contact {
+  pub normal: Vec2f,
+  pub depth: f32,
+  /// The point on the surface of `b` where `a` has gone the deepest.
+  pub point: Vec2f,
+}
+
+impl Contact {
+  /// The same contact as seen from `b`.
+  pub fn flipped(self) -> Self {
+    Self { normal: -self.normal, depth: self.depth, point: self.point - self.normal * self.depth }
+  }
+}
+
+pub fn test(a: &Body, b: &Body) -> Option<Contact> {
+  match (a.shape, b.shape) {
+    (Shape::Aabb { half_extents: a_half }, Shape::Aabb { half_extents: b_half }) => {
+      aabb_vs_aabb(a.pos, a_half, b.pos, b_half)
+    }
+    (Shape::Circle { radius: a_radius }, Shape::Circle { radius: b_radius }) => {
+      circle_vs_circle(a.pos, a_radius, b.pos, b_radius)
+    }
+    (Shape::Circle { radius }, Shape::Aabb { half_extents }) => {
+      circle_vs_aabb(a.pos, radius, b.pos, half_extents)
+    }
+    (Shape::Aabb { half_extents }, Shape::Circle { radius }) => {
+      circle_vs_aabb(b.pos, radius, a.pos, half_extents).map(Contact::flipped)
+    }
+  }
+}
+
+/// [`f32::signum`], but treats both zeroes as positive, so that the bodies at
+/// exactly the same position still get pushed apart in some direction.
+#[inline]
+fn sign(x: f32) -> f32 {
+  if x < 0.0 {
+    -1.0
+  } else {
+    1.0
+  }
+}
+
+pub fn aabb_vs_aabb(a_pos: Vec2f, a_half: Vec2f, b_pos: Vec2f, b_half: Vec2f) -> Option<Contact> {
+  let delta = a_pos - b_pos;
+  let overlap = a_half + b_half - delta.abs();
+  if overlap.x <= 0.0 || overlap.y <= 0.0 {
+    return None;
+  }
+
+  // The bodies are separated along the axis of the least penetration.
+  Some(if overlap.x < overlap.y {
+    let side = sign(delta.x);
+    Contact {
+      normal: vec2(side, 0.0),
+      depth: overlap.x,
+      point: b_pos + vec2(side * b_half.x, delta.y.clamp2_abs(b_half.y)),
+    }
+  } else {
+    let side = sign(delta.y);
+    Contact {
+      normal: vec2(0.0, side),
+      depth: overlap.y,
+      point: b_pos + vec2(delta.x.clamp2_abs(b_half.x), side * b_half.y),
+    }
+  })
+}
+
+pub fn circle_vs_circle(
+  a_pos: Vec2f,
+  a_radius: f32,
+  b_pos: Vec2f,
+  b_radius: f32,
+) -> Option<Contact> {
+  let delta = a_pos - b_pos;
+  let radii = a_radius + b_radius;
+  let sqr_dist = delta.sqr_magnitude();
+  if sqr_dist >= radii * radii {
+    return None;
+  }
+
+  let dist = sqr_dist.sqrt();
+  let normal = if dist > 0.0 { delta / dist } else { Vec2f::RIGHT };
+  Some(Contact { normal, depth: radii - dist, point: b_pos + normal * b_radius })
+}
+
+pub fn circle_vs_aabb(
+  circle_pos: Vec2f,
+  radius: f32,
+  box_pos: Vec2f,
+  half_extents: Vec2f,
+) -> Option<Contact> {
+  let local = circle_pos - box_pos;
+  let closest = local.clamp2_abs(half_extents);
+
+  if closest != local {
+    let delta = local - closest;
+    let sqr_dist = delta.sqr_magnitude();
+    if sqr_dist >= radius * radius {
+      return None;
+    }
+    let dist = sqr_dist.sqrt();
+    return Some(Contact { normal: delta / dist, depth: radius - dist, point: box_pos + closest });
+  }
+
+  // The center is inside of the box or exactly on its edge, the circle gets
+  // pushed out through the nearest one.
+  let face_dist = half_extents - local.abs();
+  Some(if face_dist.x <= face_dist.y {
+    let side = sign(local.x);
+    Contact {
+      normal: vec2(side, 0.0),
+      depth: radius + face_dist.x,
+      point: box_pos + vec2(side * half_extents.x, local.y),
+    }
+  } else {
+    let side = sign(local.y);
+    Contact {
+      normal: vec2(0.0, side),
+      depth: radius + face_dist.y,
+      point: box_pos + vec2(local.x, side * half_extents.y),
+    }
+  })
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SweepHit {
+  /// The fraction of the motion, from 0 to 1, after which the bodies touch.
+  pub time: f32,
+  /// Oriented the same way as [`Contact::normal`].
+  pub normal: Vec2f,
+  /// Is non-zero only if the bodies overlap already at the start of the
+  /// motion, in which case `time` is 0.
+  pub depth: f32,
+}
+
+impl SweepHit {
+  fn from_contact(contact: Contact) -> Self {
+    Self { time: 0.0, normal: contact.normal, depth: contact.depth }
+  }
+}
+
+/// Returns the fraction of `dir` after which the ray enters the circle.
+fn ray_vs_circle(origin: Vec2f, dir: Vec2f, center: Vec2f, radius: f32) -> Option<f32> {
+  // <https://gamedev.stackexchange.com/a/96469>
+  let m = origin - center;
+  let a = dir.sqr_magnitude();
+  let b = m.dot(dir);
+  let c = m.sqr_magnitude() - radius * radius;
+  if a == 0.0 || (c > 0.0 && b > 0.0) {
+    return None;
+  }
+  let discriminant = b * b - a * c;
+  if discriminant < 0.0 {
+    return None;
+  }
+  let time = (-b - discriminant.sqrt()) / a;
+  if time > 1.0 {
+    return None;
+  }
+  Some(time.max(0.0))
+}
+
+/// The slab test, returns the fraction of `dir` after which the ray enters the
+/// box centered at the origin, and the normal of the entered face (zero if the
+/// ray starts inside).
+fn ray_vs_aabb(origin: Vec2f, dir: Vec2f, half_extents: Vec2f) -> Option<(f32, Vec2f)> {
+  let mut time_enter = f32::NEG_INFINITY;
+  let mut time_exit = f32::INFINITY;
+  let mut normal = Vec2f::ZERO;
+  for axis in 0..2 {
+    if dir[axis] == 0.0 {
+      if origin[axis].abs() >= half_extents[axis] {
+        return None;
+      }
+      continue;
+    }
+    let inv_dir = 1.0 / dir[axis];
+    let t1 = (-half_extents[axis] - origin[axis]) * inv_dir;
+    let t2 = (half_extents[axis] - origin[axis]) * inv_dir;
+    let (near, far) = if t1 <= t2 { (t1, t2) } else { (t2, t1) };
+    if near > time_enter {
+      time_enter = near;
+      normal = Vec2f::ZERO;
+      normal[axis] = -sign(dir[axis]);
+    }
+    time_exit = time_exit.min(far);
+  }
+  if time_enter >= time_exit || time_exit <= 0.0 || time_enter > 1.0 {
+    return None;
+  }
+  if time_enter < 0.0 {
+    return Some((0.0, Vec2f::ZERO));
+  }
+  Some((time_enter, normal))
+}
+
+pub fn sweep_circle_vs_circle(
+  start: Vec2f,
+  radius: f32,
+  motion: Vec2f,
+  other_pos: Vec2f,
+  other_radius: f32,
+) -> Option<SweepHit> {
+  if let Some(contact) = circle_vs_circle(start, radius, other_pos, other_radius) {
+    return Some(SweepHit::from_contact(contact));
+  }
+  let time = ray_vs_circle(start, motion, other_pos, radius + other_radius)?;
+  let normal = (start + motion * time - other_pos).normalized();
+  Some(SweepHit { time, normal, depth: 0.0 })
+}
+
+/// `motion` must be relative to the box if it is moving too.
+pub fn sweep_circle_vs_aabb(
+  start: Vec2f,
+  radius: f32,
+  motion: Vec2f,
+  box_pos: Vec2f,
+  half_extents: Vec2f,
+) -> Option<SweepHit> {
+  if let Some(contact) = circle_vs_aabb(start, radius, box_pos, half_extents) {
+    return Some(SweepHit::from_contact(contact));
+  }
+
+  // The path of the center is tested against the box grown by the radius of
+  // the circle, which is a rectangle with rounded corners: first against the
+  // bounding box of that, and then against the rounded corner if the ray hits
+  // one of the corner squares.
+  let local = start - box_pos;
+  let (time, normal) = ray_vs_aabb(local, motion, half_extents + vec2n(radius))?;
+  let hit = local + motion * time;
+  if hit.x.abs() > half_extents.x && hit.y.abs() > half_extents.y {
+    let corner = vec2(sign(hit.x) * half_extents.x, sign(hit.y) * half_extents.y);
+    let time = ray_vs_circle(local, motion, corner, radius)?;
+    let normal = (local + motion * time - corner).normalized();
+    return Some(SweepHit { time, normal, depth: 0.0 });
+  }
+  debug_assert!(!normal.is_zero());
+  Some(SweepHit { time, normal, depth: 0.0 })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const HALF_EXTENTS: Vec2f = vec2(10.0, 50.0);
+  const RADIUS: f32 = 20.0;
+
+  #[track_caller]
+  fn assert_approx_eq(a: Vec2f, b: Vec2f) {
+    assert!((a - b).sqr_magnitude() < 1e-6, "{:?} != {:?}", a, b);
+  }
+
+  fn sweep(start: Vec2f, motion: Vec2f) -> Option<SweepHit> {
+    sweep_circle_vs_aabb(start, RADIUS, motion, Vec2f::ZERO, HALF_EXTENTS)
+  }
+
+  #[test]
+  fn touching_is_not_colliding() {
+    assert_eq!(circle_vs_aabb(vec2(30.0, 0.0), RADIUS, Vec2f::ZERO, HALF_EXTENTS), None);
+    assert_eq!(circle_vs_circle(vec2(40.0, 0.0), RADIUS, Vec2f::ZERO, RADIUS), None);
+    assert_eq!(aabb_vs_aabb(vec2(20.0, 0.0), HALF_EXTENTS, Vec2f::ZERO, HALF_EXTENTS), None);
+    assert_eq!(circle_vs_aabb(vec2(100.0, 100.0), RADIUS, Vec2f::ZERO, HALF_EXTENTS), None);
+  }
+
+  #[test]
+  fn contact_normals_and_depths() {
+    // Outside of the box, near the corner.
+    let contact = circle_vs_aabb(vec2(16.0, 58.0), RADIUS, Vec2f::ZERO, HALF_EXTENTS).unwrap();
+    assert_approx_eq(contact.normal, vec2(0.6, 0.8));
+    assert!((contact.depth - 10.0).abs() < 1e-6);
+    assert_eq!(contact.point, vec2(10.0, 50.0));
+
+    // The center inside of the box, closer to the top face.
+    let contact = circle_vs_aabb(vec2(-2.0, 45.0), RADIUS, Vec2f::ZERO, HALF_EXTENTS).unwrap();
+    assert_approx_eq(contact.normal, Vec2f::UP);
+    assert!((contact.depth - (RADIUS + 5.0)).abs() < 1e-6);
+
+    let contact = circle_vs_circle(vec2(0.0, -30.0), RADIUS, Vec2f::ZERO, RADIUS).unwrap();
+    assert_approx_eq(contact.normal, Vec2f::DOWN);
+    assert_eq!(contact.depth, 10.0);
+    assert_approx_eq(contact.point, vec2(0.0, -20.0));
+
+    // Coincident circles still get pushed apart.
+    let contact = circle_vs_circle(Vec2f::ZERO, RADIUS, Vec2f::ZERO, RADIUS).unwrap();
+    assert_approx_eq(contact.normal, Vec2f::RIGHT);
+    assert_eq!(contact.depth, RADIUS * 2.0);
+  }
+
+  #[test]
+  fn contacts_are_symmetric() {
+    // The boxes are separated the same way from both sides, though the contact
+    // points differ since the overlapping faces have no single deepest point.
+    let a = Body { pos: vec2(15.0, 10.0), shape: Shape::aabb(vec2n(20.0)) };
+    let b = Body { pos: Vec2f::ZERO, shape: Shape::aabb(HALF_EXTENTS * 2.0) };
+    let (ab, ba) = (test(&a, &b).unwrap(), test(&b, &a).unwrap());
+    assert_approx_eq(ba.normal, -ab.normal);
+    assert_eq!(ba.depth, ab.depth);
+    assert_approx_eq(ab.normal, Vec2f::RIGHT);
+    assert_eq!(ab.depth, 5.0);
+
+    let circle = Body { pos: vec2(25.0, 10.0), shape: Shape::circle(RADIUS) };
+    let (ab, ba) = (test(&circle, &b).unwrap(), test(&b, &circle).unwrap());
+    assert_approx_eq(ba.normal, -ab.normal);
+    assert_eq!(ba.depth, ab.depth);
+    assert_approx_eq(ba.point, ab.point - ab.normal * ab.depth);
+  }
+
+  #[test]
+  fn sweeps_find_the_time_of_impact() {
+    // Way too fast for the box, would have jumped over it without a sweep.
+    let hit = sweep(vec2(-200.0, 0.0), vec2(400.0, 0.0)).unwrap();
+    assert!((hit.time - 0.425).abs() < 1e-6);
+    assert_approx_eq(hit.normal, Vec2f::LEFT);
+    assert_eq!(hit.depth, 0.0);
+
+    // Stops short of the box.
+    assert_eq!(sweep(vec2(-200.0, 0.0), vec2(160.0, 0.0)), None);
+    // Moves away from the box.
+    assert_eq!(sweep(vec2(-200.0, 0.0), vec2(-400.0, 0.0)), None);
+
+    let hit =
+      sweep_circle_vs_circle(vec2(-100.0, 0.0), RADIUS, vec2(200.0, 0.0), Vec2f::ZERO, 10.0)
+        .unwrap();
+    assert!((hit.time - 0.35).abs() < 1e-6);
+    assert_approx_eq(hit.normal, Vec2f::LEFT);
+  }
+
+  #[test]
+  fn sweeps_against_the_rounded_corners() {
+    let (start, motion) = (vec2(-60.0, 100.0), vec2(100.0, -100.0));
+    let hit = sweep(start, motion).unwrap();
+    let corner_dir = (start + motion * hit.time - vec2(-10.0, 50.0)).normalized();
+    assert_approx_eq(hit.normal, corner_dir);
+    assert!(hit.normal.x < 0.0 && hit.normal.y > 0.0);
+
+    // Misses the corner, even though it goes through the corner of the
+    // bounding box of the grown box.
+    assert_eq!(sweep(vec2(-49.0, 49.0), vec2(40.0, 40.0)), None);
+  }
+
+  #[test]
+  fn sweeps_starting_overlapped_or_without_motion() {
+    let hit = sweep(vec2(25.0, 0.0), vec2(100.0, 0.0)).unwrap();
+    assert_eq!(hit.time, 0.0);
+    assert_approx_eq(hit.normal, Vec2f::RIGHT);
+    assert_eq!(hit.depth, 5.0);
+
+    assert_eq!(sweep(vec2(-100.0, 0.0), Vec2f::ZERO), None);
+    let hit = sweep(vec2(0.0, 45.0), Vec2f::ZERO).unwrap();
+    assert_eq!(hit.time, 0.0);
+    assert_approx_eq(hit.normal, Vec2f::UP);
+  }
+
+  /// The old Pong collision code hit a `todo!()` when the center of the ball
+  /// ended up exactly on the edge of a racket.
+  #[test]
+  fn ball_center_on_a_racket_edge() {
+    let racket = Body { pos: vec2(-300.0, 20.0), shape: Shape::aabb(HALF_EXTENTS * 2.0) };
+    let edges = [
+      (vec2(10.0, 20.0), Vec2f::RIGHT),
+      (vec2(-10.0, -20.0), Vec2f::LEFT),
+      (vec2(0.0, 50.0), Vec2f::UP),
+      (vec2(5.0, -50.0), Vec2f::DOWN),
+      // Both faces are equally close at the corners, the horizontal one wins.
+      (vec2(10.0, 50.0), Vec2f::RIGHT),
+      (vec2(-10.0, -50.0), Vec2f::LEFT),
+    ];
+    for (offset, expected_normal) in edges {
+      let ball = Body { pos: racket.pos + offset, shape: Shape::circle(RADIUS) };
+      let contact = test(&ball, &racket).unwrap();
+      assert_approx_eq(contact.normal, expected_normal);
+      assert!((contact.depth - RADIUS).abs() < 1e-6);
+      assert_approx_eq(contact.point, ball.pos);
+      assert_approx_eq(test(&racket, &ball).unwrap().normal, -expected_normal);
+
+      for motion in [-expected_normal * 50.0, expected_normal * 50.0, Vec2f::ZERO] {
+        let hit =
+          sweep_circle_vs_aabb(ball.pos, RADIUS, motion, racket.pos, HALF_EXTENTS).unwrap();
+        assert_eq!(hit.time, 0.0);
+        assert_approx_eq(hit.normal, expected_normal);
+        assert!((hit.depth - RADIUS).abs() < 1e-6);
+      }
+    }
+  }
+}
@@ -0,0 +1,162 @@
+//! A port of `extras/collision_simulation.py` onto the physics world: a bunch
+//! of heavy balls given a random kick, bouncing off each other and the walls of
+//! the window. With the perfectly elastic collisions the total kinetic energy
+//! should stay the same, which makes the drift of an integrator easy to see.
+
+use cardboard_math::*;
+use prelude_plus::*;
+
+use crate::bindings::actions;
+use crate::collision::Shape as CollisionShape;
+use crate::globals::{Random, SharedGlobals};
+use crate::physics::{Integrator, PhysicsWorld, RigidBody};
+use crate::renderer::*;
+use crate::scene::{Scene, SceneCommand, SceneKind};
+
+const BODIES_COUNT: usize = 20;
+const BODY_MASS: f32 = 50.0;
+const BODY_MIN_RADIUS: f32 = 20.0;
+const BODY_MAX_RADIUS: f32 = 30.0;
+const BODY_SPAWN_AREA: Vec2f = vec2n(300.0);
+const BODY_MAX_INITIAL_SPEED: f32 = 100.0;
+const BODY_COLOR: Colorf = color(1.0, 0.0, 0.0, 1.0);
+const VELOCITY_LINE_LENGTH: f32 = 100.0;
+const VELOCITY_LINE_COLOR: Colorf = color(0.0, 1.0, 0.0, 1.0);
+
+#[derive(Debug)]
+pub struct CollisionSimulation {
+  globals: SharedGlobals,
+  random: Random,
+  world: PhysicsWorld,
+  /// The value of `fixed_time` on the last fixed update received by the scene.
+  last_fixed_time: f64,
+  initial_kinetic_energy: f32,
+  font: Font,
+}
+
+impl Scene for CollisionSimulation {
+  fn init(globals: SharedGlobals) -> AnyResult<Self> {
+    let random = globals.random.stream("collision_simulation");
+    let font = load_default_font(&globals)?;
+    let mut myself = Self {
+      globals,
+      random,
+      world: PhysicsWorld::new(Integrator::Verlet),
+      last_fixed_time: 0.0,
+      initial_kinetic_energy: 0.0,
+      font,
+    };
+    myself.reset_simulation();
+    Ok(myself)
+  }
+
+  fn early_update(&mut self) { self.world.bounds = Some(self.globals.window_size / 2.0); }
+
+  fn update(&mut self) -> SceneCommand {
+    let globals = self.globals.share();
+    let input = &globals.input_state;
+    if input.is_action_pressed(actions::RESET_SIMULATION) {
+      self.reset_simulation();
+    }
+    if input.is_action_pressed(actions::SWITCH_INTEGRATOR) {
+      self.world.integrator = match self.world.integrator {
+        Integrator::Verlet => Integrator::SemiImplicitEuler,
+        Integrator::SemiImplicitEuler => Integrator::Verlet,
+      };
+    }
+
+    if input.is_action_pressed(actions::PAUSE) {
+      SceneCommand::Push(SceneKind::Pause)
+    } else {
+      SceneCommand::None
+    }
+  }
+
+  fn fixed_update(&mut self) {
+    self.last_fixed_time = self.globals.fixed_time;
+    self.world.step(self.globals.fixed_delta_time as f32);
+  }
+
+  fn render(&mut self, renderer: &mut Renderer) {
+    renderer.prepare();
+    let alpha = if self.last_fixed_time == self.globals.fixed_time {
+      self.globals.fixed_update_alpha as f32
+    } else {
+      1.0
+    };
+
+    for (_, body) in self.world.iter() {
+      let pos = body.render_pos(alpha);
+      renderer.draw_shape(&mut Shape {
+        type_: ShapeType::Ellipse,
+        pos,
+        size: body.shape.size(),
+        rotation: 0.0,
+        fill: ShapeFill::Color(BODY_COLOR),
+        fill_clipping: None,
+      });
+
+      if !body.vel.is_zero() {
+        let line = body.vel.with_magnitude(VELOCITY_LINE_LENGTH);
+        renderer.draw_shape(&mut Shape {
+          type_: ShapeType::Rectangle,
+          pos: pos + line / 2.0,
+          size: vec2(VELOCITY_LINE_LENGTH, 2.0),
+          rotation: line.angle_from_x_axis(),
+          fill: ShapeFill::Color(VELOCITY_LINE_COLOR),
+          fill_clipping: None,
+        });
+      }
+    }
+
+    self.render_debug_info(renderer);
+    renderer.finish();
+  }
+}
+
+impl CollisionSimulation {
+  fn reset_simulation(&mut self) {
+    self.world.clear();
+    self.world.bounds = Some(self.globals.window_size / 2.0);
+    let random = &self.random;
+    let random_in = |min: f32, max: f32| min + random.next_f32() * (max - min);
+
+    for _ in 0..BODIES_COUNT {
+      let pos = vec2(
+        random_in(-BODY_SPAWN_AREA.x, BODY_SPAWN_AREA.x),
+        random_in(-BODY_SPAWN_AREA.y, BODY_SPAWN_AREA.y),
+      );
+      let vel = vec2(
+        random_in(-BODY_MAX_INITIAL_SPEED, BODY_MAX_INITIAL_SPEED),
+        random_in(-BODY_MAX_INITIAL_SPEED, BODY_MAX_INITIAL_SPEED),
+      );
+      let radius = random_in(BODY_MIN_RADIUS, BODY_MAX_RADIUS);
+      let mut body = RigidBody::new(CollisionShape::circle(radius), pos, BODY_MASS);
+      body.vel = vel;
+      self.world.add(body);
+    }
+
+    self.initial_kinetic_energy = self.world.kinetic_energy();
+  }
+
+  fn render_debug_info(&mut self, renderer: &mut Renderer) {
+    let mut text_block_offset = Vec2f::ZERO;
+    for &text in &[
+      format!("integrator: {:?}", self.world.integrator).as_str(),
+      format!("kinetic energy: {:>9.0}", self.world.kinetic_energy()).as_str(),
+      format!("       initial: {:>9.0}", self.initial_kinetic_energy).as_str(),
+    ] {
+      let text_block = &mut TextBlock {
+        text,
+        scale: vec2n(4.0),
+        character_spacing: vec2n(0.4),
+        horizontal_align: TextAlign::Start,
+        vertical_align: TextAlign::Start,
+      };
+      let (text_block_size, char_size) = self.font.measure_size(text_block);
+      let pos = (self.globals.window_size - char_size * 0.5) * vec2(-0.5, 0.5) - text_block_offset;
+      renderer.draw_text(&mut self.font, pos, text_block);
+      text_block_offset.y += text_block_size.y;
+    }
+  }
+}
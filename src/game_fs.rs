@@ -1,6 +1,7 @@
 use prelude_plus::*;
 
 const ASSETS_DIR_NAME: &str = "assets";
+const SETTINGS_FILE_NAME: &str = "settings.json";
 
 #[derive(Debug)]
 pub struct GameFs {
@@ -32,6 +33,10 @@ impl GameFs {
     bail!("Failed to find the installation directory")
   }
 
+  /// The settings live next to the assets instead of inside of them, since
+  /// they belong to the user and not to the game data.
+  pub fn settings_path(&self) -> PathBuf { self.installation_dir.join(SETTINGS_FILE_NAME) }
+
   pub fn open_file<P: AsRef<Path>>(&self, relative_path: P) -> AnyResult<File> {
     self._open_file(relative_path.as_ref())
   }
@@ -47,14 +52,29 @@ impl GameFs {
 
   fn _read_binary_file(&self, relative_path: &Path) -> AnyResult<Vec<u8>> {
     let file = self._open_file(relative_path)?;
-    let mut bytes = Vec::with_capacity(
-      // see the private function initial_buffer_size in std::fs
-      file.metadata().map_or(0, |m| m.len() as usize + 1),
-    );
-    let mut reader = BufReader::new(file);
-    reader
-      .read_to_end(&mut bytes)
-      .with_context(|| format!("Failed to read file '{}'", relative_path.display()))?;
-    Ok(bytes)
+    read_whole_file(file, relative_path)
+  }
+
+  /// Returns [`None`] if the settings file doesn't exist yet, unlike the
+  /// other files it is optional.
+  pub fn read_settings_file(&self) -> AnyResult<Option<Vec<u8>>> {
+    let path = self.settings_path();
+    match File::open(&path) {
+      Ok(file) => read_whole_file(file, &path).map(Some),
+      Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+      Err(e) => Err(e).with_context(|| format!("Failed to open file '{}'", path.display())),
+    }
   }
 }
+
+fn read_whole_file(file: File, path: &Path) -> AnyResult<Vec<u8>> {
+  let mut bytes = Vec::with_capacity(
+    // see the private function initial_buffer_size in std::fs
+    file.metadata().map_or(0, |m| m.len() as usize + 1),
+  );
+  let mut reader = BufReader::new(file);
+  reader
+    .read_to_end(&mut bytes)
+    .with_context(|| format!("Failed to read file '{}'", path.display()))?;
+  Ok(bytes)
+}
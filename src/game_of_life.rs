@@ -3,10 +3,11 @@ use cardboard_oogl as oogl;
 use cardboard_oogl::traits::*;
 use prelude_plus::*;
 
+use crate::bindings::{actions, axes};
 use crate::globals::SharedGlobals;
-use crate::input::Key;
 use crate::profiling::AverageTimeSampler;
 use crate::renderer;
+use crate::scene::{Scene, SceneCommand};
 
 const CHUNK_SIZE: Vec2u8 = vec2n(128);
 const CHUNKS_COUNT: Vec2u32 = vec2(8, 8);
@@ -44,6 +45,7 @@ pub struct GameOfLife {
   chunk_textures: Vec<oogl::Texture2D<u8>>,
   program: oogl::Program,
   program_reflection: ProgramReflection,
+  font: renderer::Font,
 
   prev_simulation_time: f64,
   camera_pos: Vec2f,
@@ -57,8 +59,8 @@ pub struct GameOfLife {
   texture_refill_times: AverageTimeSampler,
 }
 
-impl GameOfLife {
-  pub fn init(globals: SharedGlobals) -> AnyResult<Self> {
+impl Scene for GameOfLife {
+  fn init(globals: SharedGlobals) -> AnyResult<Self> {
     use oogl::ShaderType as ShTy;
     let vertex_shader =
       renderer::load_shader_asset(&globals, "shaders/game_of_life.vert.glsl", ShTy::Vertex)?;
@@ -108,12 +110,15 @@ impl GameOfLife {
     let next_generation = vec![0; GRID_SIZE.x as usize * GRID_SIZE.y as usize];
     let current_generation = next_generation.clone();
 
+    let font = renderer::load_default_font(&globals)?;
+
     let mut myself = Self {
       globals,
       vertex_buf,
       chunk_textures,
       program,
       program_reflection,
+      font,
 
       prev_simulation_time: 0.0,
       camera_pos: Vec2f::ZERO,
@@ -132,6 +137,80 @@ impl GameOfLife {
     Ok(myself)
   }
 
+  fn update(&mut self) -> SceneCommand {
+    if self.globals.input_state.is_action_pressed(actions::RESET_SIMULATION) {
+      self.reset_simulation();
+    }
+
+    self.camera_zoom *=
+      1.0 + self.globals.input_state.action_axis(axes::ZOOM) as f32 * CAMERA_ZOOM_SPEED;
+
+    let camera_movement = if self.globals.input_state.is_action_down(actions::CAMERA_DRAG) {
+      -self.globals.input_state.delta_mouse_pos
+    } else {
+      let input = &self.globals.input_state;
+      vec2(input.action_axis_value(axes::CAMERA_X), input.action_axis_value(axes::CAMERA_Y))
+        * CAMERA_MOVEMENT_SPEED_FROM_KEYBOARD
+        * self.globals.delta_time as f32
+    };
+    self.camera_pos += camera_movement / self.camera_zoom;
+
+    if self.globals.input_state.is_action_pressed(actions::RESET_VIEW) {
+      self.reset_view();
+    }
+
+    if self.globals.time >= self.prev_simulation_time + SIMULATION_INTERVAL {
+      self.prev_simulation_time = self.globals.time;
+      self.run_simulation();
+    }
+
+    SceneCommand::None
+  }
+
+  fn render(&mut self, renderer: &mut renderer::Renderer) {
+    self.render_cells();
+    renderer.prepare();
+    self.render_debug_info(renderer);
+    renderer.finish();
+  }
+}
+
+impl GameOfLife {
+  fn render_cells(&mut self) {
+    self.refill_textures();
+
+    let bound_program = self.program.bind();
+    let reflection = &self.program_reflection;
+
+    reflection.u_camera_pos.set(&bound_program, &self.camera_pos);
+    reflection.u_camera_zoom.set(&bound_program, &self.camera_zoom);
+    if self.globals.window_was_resized {
+      reflection.u_window_size.set(&bound_program, &self.globals.window_size);
+    }
+
+    let bound_vertex_buf = self.vertex_buf.bind();
+    bound_vertex_buf.enable_attribs();
+    bound_vertex_buf.configure_attribs();
+
+    let mut texture_idx = 0;
+    for chunk_y in 0..CHUNKS_COUNT.y {
+      for chunk_x in 0..CHUNKS_COUNT.x {
+        let chunk_contents_offset: Vec2u32 =
+          vec2(chunk_x, chunk_y) * Vec2u32::cast_from(CHUNK_SIZE);
+        reflection.u_chunk_offset.set(&bound_program, &Vec2f::cast_from(chunk_contents_offset));
+
+        let bound_chunk_texture = self.chunk_textures[texture_idx].bind(None);
+        reflection.u_chunk_texture.set(&bound_program, &bound_chunk_texture.unit());
+
+        bound_vertex_buf.draw(&bound_program, oogl::DrawPrimitive::TriangleFan);
+
+        texture_idx += 1;
+      }
+    }
+
+    bound_vertex_buf.disable_attribs();
+  }
+
   fn reset_view(&mut self) {
     self.camera_pos = Vec2f::cast_from(GRID_SIZE) * CELL_SIZE * 0.5;
     self.camera_zoom = 1.0;
@@ -234,82 +313,7 @@ impl GameOfLife {
     texture.bind(None).set(0, &self.texture_data);
   }
 
-  pub fn update(&mut self) {
-    if self.globals.input_state.is_key_pressed(Key::Space) {
-      self.reset_simulation();
-    }
-
-    self.camera_zoom *=
-      1.0 + self.globals.input_state.axis(Key::Minus, Key::Equals) as f32 * CAMERA_ZOOM_SPEED;
-
-    let camera_movement = if self.globals.input_state.is_key_down(Key::MouseLeft) {
-      -self.globals.input_state.delta_mouse_pos
-    } else {
-      let mut dir = Vec2f::ZERO;
-      for &(key, movement) in &[
-        (Key::W, Vec2f::UP),
-        (Key::D, Vec2f::RIGHT),
-        (Key::S, Vec2f::DOWN),
-        (Key::A, Vec2f::LEFT),
-      ] {
-        if self.globals.input_state.is_key_down(key) {
-          dir += movement;
-        }
-      }
-      dir * CAMERA_MOVEMENT_SPEED_FROM_KEYBOARD * self.globals.delta_time as f32
-    };
-    self.camera_pos += camera_movement / self.camera_zoom;
-
-    if self.globals.input_state.is_key_pressed(Key::R) {
-      self.reset_view();
-    }
-
-    if self.globals.time >= self.prev_simulation_time + SIMULATION_INTERVAL {
-      self.prev_simulation_time = self.globals.time;
-      self.run_simulation();
-    }
-  }
-
-  pub fn render(&mut self) {
-    self.refill_textures();
-
-    let bound_program = self.program.bind();
-    let reflection = &self.program_reflection;
-
-    reflection.u_camera_pos.set(&bound_program, &self.camera_pos);
-    reflection.u_camera_zoom.set(&bound_program, &self.camera_zoom);
-    if self.globals.window_was_resized {
-      reflection.u_window_size.set(&bound_program, &self.globals.window_size);
-    }
-
-    let bound_vertex_buf = self.vertex_buf.bind();
-    bound_vertex_buf.enable_attribs();
-    bound_vertex_buf.configure_attribs();
-
-    let mut texture_idx = 0;
-    for chunk_y in 0..CHUNKS_COUNT.y {
-      for chunk_x in 0..CHUNKS_COUNT.x {
-        let chunk_contents_offset: Vec2u32 =
-          vec2(chunk_x, chunk_y) * Vec2u32::cast_from(CHUNK_SIZE);
-        reflection.u_chunk_offset.set(&bound_program, &Vec2f::cast_from(chunk_contents_offset));
-
-        let bound_chunk_texture = self.chunk_textures[texture_idx].bind(None);
-        reflection.u_chunk_texture.set(&bound_program, &bound_chunk_texture.unit());
-
-        bound_vertex_buf.draw(&bound_program, oogl::DrawPrimitive::TriangleFan);
-
-        texture_idx += 1;
-      }
-    }
-
-    bound_vertex_buf.disable_attribs();
-  }
-
-  pub fn render_debug_info(
-    &mut self,
-    renderer: &mut renderer::Renderer,
-    font: &mut renderer::Font,
-  ) {
+  fn render_debug_info(&mut self, renderer: &mut renderer::Renderer) {
     let avg_texture_refill_time = self.texture_refill_times.average_micros() as f64 / 1000.0;
     let avg_simulation_time = self.simulation_times.average_micros() as f64 / 1000.0;
 
@@ -325,9 +329,9 @@ impl GameOfLife {
         horizontal_align: renderer::TextAlign::Start,
         vertical_align: renderer::TextAlign::Start,
       };
-      let (text_block_size, char_size) = font.measure_size(text_block);
+      let (text_block_size, char_size) = self.font.measure_size(text_block);
       let pos = (self.globals.window_size - char_size * 0.5) * vec2(-0.5, 0.5) - text_block_offset;
-      renderer.draw_text(font, pos, text_block);
+      renderer.draw_text(&mut self.font, pos, text_block);
       text_block_offset.y += text_block_size.y;
     }
   }
@@ -5,54 +5,320 @@
 // <http://bitsquid.blogspot.com/2014/08/building-data-oriented-entity-system.html>
 // <https://gist.github.com/jaburns/ca72487198832f6203e831133ffdfff4>
 
-// impl Index {
-//   pub fn from_raw_parts(index: usize, generation: Generation) -> Self {
-//     Self { index, generation }
-//   }
-
-//   pub fn index(self) -> usize { self.index }
-//   pub fn generation(self) -> Generation { self.generation }
-// }
-
-// #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
-// pub struct GenIdx {
-//   index: usize,
-//   generation: Generation,
-// }
-
-// impl GenIdx {
-//   pub fn index(&self) -> usize { self.index }
-//   pub fn generation(&self) -> Generation { self.generation }
-// }
-
-// struct GenIdxAllocatorEntry {
-//   is_alive: bool,
-//   generation: Generation,
-// }
-
-// pub struct GenIdxAllocator {
-//   entries: Vec<GenIdxAllocatorEntry>,
-//   free: Vec<usize>,
-// }
-
-// impl GenIdxAllocator {
-//   pub fn alloc(&mut self) -> GenIdx { todo!() }
-//   pub fn free(&mut self, gen_idx: GenIdx) -> bool { todo!() }
-//   pub fn is_alive(&self, gen_idx: GenIdx) -> bool { todo!() }
-// }
-
-// struct GenIdxVecEntry<T> {
-//   value: T,
-//   generation: Generation,
-// }
-
-// pub struct GenIdxVec<T>(Vec<Option<GenIdxVecEntry<T>>>);
-
-// impl<T> GenIdxVec<T> {
-//   // can update a value with an older generation
-//   pub fn set(&mut self, gen_idx: GenIdx, value: T) { todo!() }
-
-//   // generation must match
-//   pub fn get(&self, gen_idx: GenIdx) -> Option<&T> { todo!() }
-//   pub fn get_mut(&mut self, gen_idx: GenIdx) -> Option<&T> { todo!() }
-// }
+use prelude_plus::*;
+use std::ops;
+
+pub type Generation = u32;
+
+/// A handle which stays unique even after its slot gets reused: every time an
+/// index is freed its generation is bumped, so the old handles simply stop
+/// matching anything instead of pointing at whatever got allocated there next.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct GenIdx {
+  index: usize,
+  generation: Generation,
+}
+
+impl GenIdx {
+  #[inline(always)]
+  pub fn from_raw_parts(index: usize, generation: Generation) -> Self {
+    Self { index, generation }
+  }
+
+  #[inline(always)]
+  pub fn index(&self) -> usize { self.index }
+  #[inline(always)]
+  pub fn generation(&self) -> Generation { self.generation }
+}
+
+#[derive(Debug, Clone)]
+struct GenIdxAllocatorEntry {
+  is_alive: bool,
+  generation: Generation,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct GenIdxAllocator {
+  entries: Vec<GenIdxAllocatorEntry>,
+  free: Vec<usize>,
+}
+
+impl GenIdxAllocator {
+  pub fn new() -> Self { Self::default() }
+
+  /// Reuses the most recently freed index if there is one.
+  pub fn alloc(&mut self) -> GenIdx {
+    match self.free.pop() {
+      Some(index) => {
+        let entry = &mut self.entries[index];
+        debug_assert!(!entry.is_alive);
+        entry.is_alive = true;
+        GenIdx { index, generation: entry.generation }
+      }
+      None => {
+        let index = self.entries.len();
+        self.entries.push(GenIdxAllocatorEntry { is_alive: true, generation: 0 });
+        GenIdx { index, generation: 0 }
+      }
+    }
+  }
+
+  /// Returns `false` if the index has already been freed.
+  pub fn free(&mut self, gen_idx: GenIdx) -> bool {
+    if !self.is_alive(gen_idx) {
+      return false;
+    }
+    let entry = &mut self.entries[gen_idx.index];
+    entry.is_alive = false;
+    entry.generation = entry.generation.wrapping_add(1);
+    self.free.push(gen_idx.index);
+    true
+  }
+
+  pub fn is_alive(&self, gen_idx: GenIdx) -> bool {
+    self
+      .entries
+      .get(gen_idx.index)
+      .map_or(false, |entry| entry.is_alive && entry.generation == gen_idx.generation)
+  }
+
+  /// The number of alive indexes.
+  #[inline]
+  pub fn len(&self) -> usize { self.entries.len() - self.free.len() }
+  #[inline]
+  pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+  pub fn iter(&self) -> impl Iterator<Item = GenIdx> + '_ {
+    self
+      .entries
+      .iter()
+      .enumerate()
+      .filter(|(_, entry)| entry.is_alive)
+      .map(|(index, entry)| GenIdx { index, generation: entry.generation })
+  }
+}
+
+#[derive(Debug, Clone)]
+struct GenIdxVecEntry<T> {
+  value: T,
+  generation: Generation,
+}
+
+/// Storage of values associated with the indexes of a [`GenIdxAllocator`].
+/// All accessors check the generation, so a stale [`GenIdx`] can never observe
+/// a value which was stored for a newer one.
+#[derive(Debug, Clone)]
+pub struct GenIdxVec<T>(Vec<Option<GenIdxVecEntry<T>>>);
+
+impl<T> Default for GenIdxVec<T> {
+  fn default() -> Self { Self(Vec::new()) }
+}
+
+impl<T> GenIdxVec<T> {
+  pub fn new() -> Self { Self::default() }
+
+  /// Can overwrite a value with an older generation, but not a newer one, in
+  /// which case `false` is returned and the value is dropped.
+  pub fn set(&mut self, gen_idx: GenIdx, value: T) -> bool {
+    if gen_idx.index >= self.0.len() {
+      self.0.resize_with(gen_idx.index + 1, || None);
+    }
+    let slot = &mut self.0[gen_idx.index];
+    if let Some(entry) = slot {
+      if entry.generation > gen_idx.generation {
+        return false;
+      }
+    }
+    *slot = Some(GenIdxVecEntry { value, generation: gen_idx.generation });
+    true
+  }
+
+  pub fn remove(&mut self, gen_idx: GenIdx) -> Option<T> {
+    let slot = self.0.get_mut(gen_idx.index)?;
+    match slot {
+      Some(entry) if entry.generation == gen_idx.generation => {
+        slot.take().map(|entry| entry.value)
+      }
+      _ => None,
+    }
+  }
+
+  /// The generation must match.
+  pub fn get(&self, gen_idx: GenIdx) -> Option<&T> {
+    match self.0.get(gen_idx.index) {
+      Some(Some(entry)) if entry.generation == gen_idx.generation => Some(&entry.value),
+      _ => None,
+    }
+  }
+
+  /// The generation must match.
+  pub fn get_mut(&mut self, gen_idx: GenIdx) -> Option<&mut T> {
+    match self.0.get_mut(gen_idx.index) {
+      Some(Some(entry)) if entry.generation == gen_idx.generation => Some(&mut entry.value),
+      _ => None,
+    }
+  }
+
+  /// Borrows two different values at once. Panics if both handles point to the
+  /// same slot.
+  pub fn get2_mut(&mut self, a: GenIdx, b: GenIdx) -> (Option<&mut T>, Option<&mut T>) {
+    assert_ne!(a.index, b.index, "tried to borrow the same slot twice");
+    let split_at = a.index.max(b.index).min(self.0.len());
+    let (head, tail) = self.0.split_at_mut(split_at);
+    let (low, high) = (head.get_mut(a.index.min(b.index)), tail.first_mut());
+
+    fn check<T>(slot: Option<&mut Option<GenIdxVecEntry<T>>>, gen_idx: GenIdx) -> Option<&mut T> {
+      match slot {
+        Some(Some(entry)) if entry.generation == gen_idx.generation => Some(&mut entry.value),
+        _ => None,
+      }
+    }
+
+    if a.index < b.index {
+      (check(low, a), check(high, b))
+    } else {
+      (check(high, a), check(low, b))
+    }
+  }
+
+  #[inline]
+  pub fn contains(&self, gen_idx: GenIdx) -> bool { self.get(gen_idx).is_some() }
+
+  pub fn iter(&self) -> impl Iterator<Item = (GenIdx, &T)> {
+    self.0.iter().enumerate().filter_map(|(index, slot)| {
+      slot.as_ref().map(|entry| (GenIdx { index, generation: entry.generation }, &entry.value))
+    })
+  }
+
+  pub fn iter_mut(&mut self) -> impl Iterator<Item = (GenIdx, &mut T)> {
+    self.0.iter_mut().enumerate().filter_map(|(index, slot)| {
+      slot.as_mut().map(|entry| (GenIdx { index, generation: entry.generation }, &mut entry.value))
+    })
+  }
+}
+
+impl<T> ops::Index<GenIdx> for GenIdxVec<T> {
+  type Output = T;
+
+  #[track_caller]
+  fn index(&self, gen_idx: GenIdx) -> &Self::Output {
+    self.get(gen_idx).unwrap_or_else(|| stale_gen_idx_fail(gen_idx))
+  }
+}
+
+impl<T> ops::IndexMut<GenIdx> for GenIdxVec<T> {
+  #[track_caller]
+  fn index_mut(&mut self, gen_idx: GenIdx) -> &mut Self::Output {
+    self.get_mut(gen_idx).unwrap_or_else(|| stale_gen_idx_fail(gen_idx))
+  }
+}
+
+#[inline(never)]
+#[cold]
+#[track_caller]
+fn stale_gen_idx_fail(gen_idx: GenIdx) -> ! {
+  panic!("no value for {:?}, it is either stale or has never been set", gen_idx)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn alloc_reuses_freed_slots_with_a_new_generation() {
+    let mut allocator = GenIdxAllocator::new();
+    let a = allocator.alloc();
+    let b = allocator.alloc();
+    assert_eq!((a.index(), a.generation()), (0, 0));
+    assert_eq!((b.index(), b.generation()), (1, 0));
+    assert_eq!(allocator.len(), 2);
+
+    assert!(allocator.free(a));
+    assert!(!allocator.free(a));
+    assert!(!allocator.is_alive(a));
+    assert_eq!(allocator.len(), 1);
+
+    let c = allocator.alloc();
+    assert_eq!((c.index(), c.generation()), (0, 1));
+    assert!(allocator.is_alive(c));
+    assert!(!allocator.is_alive(a));
+    assert!(allocator.is_alive(b));
+    assert_eq!(allocator.iter().collect::<Vec<_>>(), [c, b]);
+
+    assert!(allocator.free(b));
+    assert!(allocator.free(c));
+    assert!(allocator.is_empty());
+    // The most recently freed slot comes first.
+    assert_eq!(allocator.alloc(), GenIdx::from_raw_parts(0, 2));
+    assert_eq!(allocator.alloc(), GenIdx::from_raw_parts(1, 1));
+    assert_eq!(allocator.alloc(), GenIdx::from_raw_parts(2, 0));
+    assert!(!allocator.is_alive(GenIdx::from_raw_parts(3, 0)));
+  }
+
+  #[test]
+  fn stale_handles_do_not_see_new_values() {
+    let mut allocator = GenIdxAllocator::new();
+    let mut values = GenIdxVec::new();
+    let old = allocator.alloc();
+    assert!(values.set(old, "old"));
+
+    allocator.free(old);
+    let new = allocator.alloc();
+    assert_eq!(new.index(), old.index());
+    assert!(values.set(new, "new"));
+    assert!(!values.set(old, "stale"));
+
+    assert_eq!(values.get(old), None);
+    assert_eq!(values.get_mut(old), None);
+    assert!(!values.contains(old));
+    assert_eq!(values.remove(old), None);
+    assert_eq!(values[new], "new");
+
+    *values.get_mut(new).unwrap() = "newer";
+    assert_eq!(values.iter().collect::<Vec<_>>(), [(new, &"newer")]);
+    assert_eq!(values.remove(new), Some("newer"));
+    assert_eq!(values.get(new), None);
+    assert_eq!(values.remove(new), None);
+  }
+
+  #[test]
+  #[should_panic(expected = "it is either stale or has never been set")]
+  fn indexing_with_a_stale_handle_panics() {
+    let mut values = GenIdxVec::new();
+    values.set(GenIdx::from_raw_parts(0, 1), ());
+    let _ = values[GenIdx::from_raw_parts(0, 0)];
+  }
+
+  #[test]
+  fn get2_mut_borrows_two_slots() {
+    let mut values = GenIdxVec::new();
+    let a = GenIdx::from_raw_parts(0, 0);
+    let b = GenIdx::from_raw_parts(2, 3);
+    values.set(a, 1);
+    values.set(b, 2);
+
+    let (value_a, value_b) = values.get2_mut(a, b);
+    mem::swap(value_a.unwrap(), value_b.unwrap());
+    assert_eq!((values[a], values[b]), (2, 1));
+    let (value_b, value_a) = values.get2_mut(b, a);
+    assert_eq!((value_a, value_b), (Some(&mut 2), Some(&mut 1)));
+
+    let stale_b = GenIdx::from_raw_parts(2, 2);
+    assert_eq!(values.get2_mut(a, stale_b), (Some(&mut 2), None));
+    assert_eq!(values.get2_mut(stale_b, a), (None, Some(&mut 2)));
+    let missing = GenIdx::from_raw_parts(1, 0);
+    assert_eq!(values.get2_mut(missing, b), (None, Some(&mut 1)));
+    let out_of_bounds = GenIdx::from_raw_parts(10, 0);
+    assert_eq!(values.get2_mut(out_of_bounds, a), (None, Some(&mut 2)));
+    assert_eq!(values.get2_mut(b, out_of_bounds), (Some(&mut 1), None));
+  }
+
+  #[test]
+  #[should_panic(expected = "tried to borrow the same slot twice")]
+  fn get2_mut_rejects_the_same_slot() {
+    let mut values = GenIdxVec::new();
+    let a = GenIdx::from_raw_parts(0, 0);
+    values.set(a, ());
+    let _ = values.get2_mut(a, GenIdx::from_raw_parts(0, 1));
+  }
+}
@@ -1,16 +1,63 @@
 use crate::game_fs::GameFs;
 use crate::input::InputState;
-use crate::oogl;
+use crate::{coal, oogl};
 use cardboard_math::*;
 use prelude_plus::*;
 
 pub type SharedGlobals = Rc<Globals>;
 
+pub const DEFAULT_FIXED_UPDATES_PER_SECOND: f64 = 120.0;
+/// The fixed updates are not allowed to catch up with more time than this in
+/// a single tick, otherwise after a long stall (e.g. the process having been
+/// suspended) the game would freeze while simulating all of it, or never catch
+/// up at all if the updates themselves are too slow.
+pub const MAX_FIXED_UPDATES_CATCH_UP_TIME: f64 = 0.25;
+
+/// The most fixed updates which may be run on a single game loop tick, see
+/// [`MAX_FIXED_UPDATES_CATCH_UP_TIME`].
+pub fn max_fixed_updates_per_tick(fixed_delta_time: f64) -> u32 {
+  (MAX_FIXED_UPDATES_CATCH_UP_TIME / fixed_delta_time).ceil().max(1.0) as u32
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FixedUpdates {
+  pub count: u32,
+  /// The updates over [`max_fixed_updates_per_tick`], which are dropped.
+  pub skipped: u32,
+  /// See [`Globals::fixed_update_alpha`], always in the range `[0, 1)`.
+  pub alpha: f64,
+}
+
+/// Adds the time of a game loop tick to the accumulator and takes as many
+/// whole fixed updates out of it as will fit, the remainder is carried over to
+/// the next tick.
+pub fn take_fixed_updates(
+  accumulator: &mut f64,
+  delta_time: f64,
+  fixed_delta_time: f64,
+) -> FixedUpdates {
+  *accumulator += delta_time;
+  let mut count = (*accumulator / fixed_delta_time).floor();
+  *accumulator = (*accumulator - count * fixed_delta_time).max(0.0);
+  let mut alpha = *accumulator / fixed_delta_time;
+  if alpha >= 1.0 {
+    // A rounding error, the remainder is actually one more whole update.
+    count += 1.0;
+    *accumulator = 0.0;
+    alpha = 0.0;
+  }
+
+  let max_count = max_fixed_updates_per_tick(fixed_delta_time) as f64;
+  let skipped = (count - max_count).max(0.0);
+  FixedUpdates { count: count.min(max_count) as u32, skipped: skipped as u32, alpha }
+}
+
 #[derive(Debug)]
 pub struct Globals {
   pub gl: oogl::SharedContext,
   pub game_fs: GameFs,
   pub random: GlobalRandom,
+  pub audio: RefCell<Box<dyn coal::OutputSink>>,
 
   pub should_stop_game_loop: Cell<bool>,
   pub first_game_loop_tick: bool,
@@ -18,6 +65,11 @@ pub struct Globals {
   pub delta_time: f64,
   pub fixed_time: f64,
   pub fixed_delta_time: f64,
+  /// How far the time has advanced past the last fixed update, as a fraction
+  /// of `fixed_delta_time`. The scenes with the physics in `fixed_update` can
+  /// use it to blend between the previous and the current state when
+  /// rendering, which keeps the movement smooth at any tick rate.
+  pub fixed_update_alpha: f64,
 
   pub window_size_i: Vec2u32,
   pub window_size: Vec2f,
@@ -28,30 +80,117 @@ pub struct Globals {
 }
 
 impl Globals {
+  pub fn new(
+    gl: oogl::SharedContext,
+    game_fs: GameFs,
+    random: GlobalRandom,
+    audio: Box<dyn coal::OutputSink>,
+    window_size_i: Vec2u32,
+  ) -> Self {
+    Self {
+      gl,
+      game_fs,
+      random,
+      audio: RefCell::new(audio),
+
+      should_stop_game_loop: Cell::new(false),
+      first_game_loop_tick: true,
+      time: 0.0,
+      delta_time: 0.0,
+      fixed_time: 0.0,
+      fixed_delta_time: 1.0 / DEFAULT_FIXED_UPDATES_PER_SECOND,
+      fixed_update_alpha: 1.0,
+
+      window_size_i,
+      window_size: window_size_i.cast_into(),
+      window_was_resized: true,
+      window_is_focused: true,
+
+      input_state: InputState::new(),
+    }
+  }
+
   #[inline(always)]
   pub fn share(self: &SharedGlobals) -> SharedGlobals { Rc::clone(self) }
 }
 
+/// The root of all randomness in the game. It is a [`Random`] stream itself
+/// (see the [`Deref`] impl), and the independent named sub-streams are derived
+/// from the same seed, so a single seed reproduces everything.
 #[derive(Debug)]
-pub struct GlobalRandom(UnsafeCell<Rand64>);
+pub struct GlobalRandom {
+  seed: u128,
+  main: Random,
+}
 
 impl GlobalRandom {
   pub fn init() -> Result<Self, getrandom::Error> {
     let mut seed_bytes = [0u8; mem::size_of::<u128>()];
     getrandom(&mut seed_bytes)?;
+    Ok(Self::with_seed(u128::from_le_bytes(seed_bytes)))
+  }
 
-    let mut seed_hex_str = String::with_capacity(seed_bytes.len() * 2);
-    // taken from <https://github.com/KokaKiwi/rust-hex/blob/76a83021a1d38cd0e11416c57e50579d1e567054/src/lib.rs#L68-L77>
-    static CHARS: &[u8] = b"0123456789abcdef";
-    for byte in &seed_bytes {
-      seed_hex_str.push(CHARS[(byte >> 4) as usize] as char);
-      seed_hex_str.push(CHARS[(byte & 0xF) as usize] as char);
-    }
+  pub fn with_seed(seed: u128) -> Self { Self { seed, main: Random::new(seed, 0) } }
+
+  #[inline(always)]
+  pub fn seed(&self) -> u128 { self.seed }
+
+  /// Creates a generator whose sequence depends only on the seed and the name,
+  /// so e.g. the cosmetic effects can't shift the gameplay randomness by
+  /// consuming the numbers from the same stream.
+  pub fn stream(&self, name: &str) -> Random {
+    Random::new(self.seed, fnv1a_hash(name.as_bytes()))
+  }
+}
+
+impl Deref for GlobalRandom {
+  type Target = Random;
+  #[inline(always)]
+  fn deref(&self) -> &Self::Target { &self.main }
+}
+
+/// A stable hash function for the stream names, unlike the std `Hasher`s which
+/// are allowed to change between Rust versions.
+fn fnv1a_hash(bytes: &[u8]) -> u128 {
+  const OFFSET_BASIS: u128 = 0x6c62272e07bb014262b821756295c58d;
+  const PRIME: u128 = 0x0000000001000000000000000000013b;
+  bytes.iter().fold(OFFSET_BASIS, |hash, &byte| (hash ^ byte as u128).wrapping_mul(PRIME))
+}
+
+/// Parses the seeds in the same format as they are logged, i.e. hexadecimal
+/// with the `0x` prefix, or plain decimal ones.
+pub fn parse_seed(s: &str) -> AnyResult<u128> {
+  let result = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+    Some(hex) => u128::from_str_radix(hex, 16),
+    None => s.parse(),
+  };
+  result.with_context(|| format!("Invalid seed: '{}'", s))
+}
+
+/// The complete state of a [`Random`], restoring it makes the generator repeat
+/// the numbers it produced after the snapshot was taken.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct RandomSnapshot {
+  pub state: u128,
+  pub inc: u128,
+}
+
+#[derive(Debug)]
+pub struct Random(UnsafeCell<Rand64>);
+
+impl Random {
+  pub fn new(seed: u128, stream: u128) -> Self {
+    // The increment selects one of the 2^127 distinct sequences of PCG.
+    Self(UnsafeCell::new(Rand64::new_inc(seed, Rand64::DEFAULT_INC ^ stream)))
+  }
 
-    debug!("RNG Seed: 0x{}", seed_hex_str);
+  pub fn snapshot(&self) -> RandomSnapshot {
+    let (state, inc) = unsafe { &*self.0.get() }.state();
+    RandomSnapshot { state, inc }
+  }
 
-    let inner = Rand64::new(u128::from_le_bytes(seed_bytes));
-    Ok(Self(UnsafeCell::new(inner)))
+  pub fn restore(&self, snapshot: RandomSnapshot) {
+    unsafe { *self.0.get() = Rand64::from_state((snapshot.state, snapshot.inc)) };
   }
 
   #[inline]
@@ -101,7 +240,7 @@ impl GlobalRandom {
 }
 
 #[rustfmt::skip]
-impl GlobalRandom {
+impl Random {
   #[inline(always)] pub fn next_u8 (&self) -> u8  { self.next_u64() as _ }
   #[inline(always)] pub fn next_i8 (&self) -> i8  { self.next_i64() as _ }
   #[inline(always)] pub fn next_u16(&self) -> u16 { self.next_u64() as _ }
@@ -116,7 +255,112 @@ impl GlobalRandom {
 // No 128-bit ISA support, as you can see
 #[cfg(any(target_pointer_width = "32", target_pointer_width = "64"))]
 #[rustfmt::skip]
-impl GlobalRandom {
+impl Random {
   #[inline(always)] pub fn next_usize(&self) -> usize { self.next_u64() as _ }
   #[inline(always)] pub fn next_isize(&self) -> isize { self.next_i64() as _ }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn sequence(random: &Random) -> Vec<u64> { (0..16).map(|_| random.next_u64()).collect() }
+
+  #[test]
+  fn fixed_updates_accumulate_over_the_ticks() {
+    let fixed_delta_time = 1.0 / 120.0;
+    let mut accumulator = 0.0;
+    let mut take = |delta_time: f64| {
+      let updates = take_fixed_updates(&mut accumulator, delta_time, fixed_delta_time);
+      assert!((0.0..1.0).contains(&updates.alpha), "{:?}", updates);
+      (updates.count, updates.skipped)
+    };
+
+    // At 60 FPS every tick runs two updates, at 240 FPS every other one.
+    let ticks: Vec<_> = (0..1200).map(|_| take(1.0 / 60.0)).collect();
+    assert_eq!(ticks.iter().map(|&(count, _)| count).sum::<u32>(), 2400);
+    assert!(ticks.iter().all(|&(count, skipped)| count <= 3 && skipped == 0));
+    let ticks: Vec<_> = (0..1200).map(|_| take(1.0 / 240.0)).collect();
+    assert_eq!(ticks.iter().map(|&(count, _)| count).sum::<u32>(), 600);
+
+    let mut accumulator = 0.0;
+    let updates = take_fixed_updates(&mut accumulator, 0.5 / 120.0, fixed_delta_time);
+    assert_eq!((updates.count, updates.skipped), (0, 0));
+    assert!((updates.alpha - 0.5).abs() < 1e-9);
+  }
+
+  #[test]
+  fn fixed_updates_are_capped_after_a_stall() {
+    let fixed_delta_time = 1.0 / 120.0;
+    let max_count = max_fixed_updates_per_tick(fixed_delta_time);
+    assert_eq!(max_count, 30);
+
+    let mut accumulator = 0.25 / 120.0;
+    let updates = take_fixed_updates(&mut accumulator, 10.0, fixed_delta_time);
+    assert_eq!((updates.count, updates.skipped), (max_count, 1200 - max_count));
+    assert!((updates.alpha - 0.25).abs() < 1e-6);
+    // The skipped time is gone for good, only the remainder is carried over.
+    let updates = take_fixed_updates(&mut accumulator, 1.0 / 120.0, fixed_delta_time);
+    assert_eq!((updates.count, updates.skipped), (1, 0));
+
+    // Even the absurdly long stalls.
+    let updates = take_fixed_updates(&mut accumulator, 1e30, fixed_delta_time);
+    assert_eq!(updates.count, max_count);
+    assert!((0.0..1.0).contains(&updates.alpha));
+
+    // The updates longer than the cap still run one at a time.
+    let updates = take_fixed_updates(&mut 0.0, 1.0, 1.0);
+    assert_eq!((updates.count, updates.skipped, updates.alpha), (1, 0, 0.0));
+  }
+
+  #[test]
+  fn parses_seeds() {
+    assert_eq!(parse_seed("0x0123456789abcdef").unwrap(), 0x0123456789abcdef);
+    assert_eq!(parse_seed("0XFF").unwrap(), 0xff);
+    assert_eq!(parse_seed("1234").unwrap(), 1234);
+    assert_eq!(parse_seed(&u128::MAX.to_string()).unwrap(), u128::MAX);
+    assert_eq!(parse_seed(&format!("{:#x}", u128::MAX)).unwrap(), u128::MAX);
+
+    for garbage in
+      &["", "0x", "abc", "12a", "-1", "0xfg", " 1", "0x1ffffffffffffffffffffffffffffffff"]
+    {
+      let error = parse_seed(garbage).unwrap_err();
+      assert_eq!(error.to_string(), format!("Invalid seed: '{}'", garbage));
+    }
+  }
+
+  #[test]
+  fn named_streams_are_independent() {
+    let random = GlobalRandom::with_seed(0x5eed);
+    let a = sequence(&random.stream("a"));
+    let b = sequence(&random.stream("b"));
+    let main = sequence(&random);
+    assert_ne!(a, b);
+    assert_ne!(a, main);
+    assert_ne!(b, main);
+
+    // The streams depend neither on each other nor on the main one.
+    let other_random = GlobalRandom::with_seed(0x5eed);
+    assert_eq!(sequence(&other_random.stream("b")), b);
+    assert_eq!(sequence(&other_random.stream("a")), a);
+    assert_eq!(sequence(&other_random), main);
+
+    assert_ne!(sequence(&GlobalRandom::with_seed(0x5eee).stream("a")), a);
+  }
+
+  #[test]
+  fn restoring_a_snapshot_repeats_the_sequence() {
+    let random = GlobalRandom::with_seed(1).stream("snapshot");
+    sequence(&random);
+    let snapshot = random.snapshot();
+    let expected = sequence(&random);
+    random.restore(snapshot);
+    assert_eq!(random.snapshot(), snapshot);
+    assert_eq!(sequence(&random), expected);
+
+    // Including into a generator of a different stream.
+    let other = Random::new(2, 0);
+    other.restore(snapshot);
+    assert_eq!(sequence(&other), expected);
+  }
+}
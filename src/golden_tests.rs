@@ -0,0 +1,586 @@
+//! Rendering regression tests: every scene is rendered offscreen, read back and
+//! compared with a reference image from `tests/golden` within a per-channel
+//! tolerance. On a mismatch the actual image and a diff are written into
+//! `target/golden`. Run with:
+//!
+//! ```text
+//! cargo run --features headless -- golden-tests [--bless] [--tolerance N] [NAME...]
+//! ```
+//!
+//! `--bless` overwrites the reference images with the current output. Like the
+//! rest of this module, its unit tests are compiled only with the feature:
+//! `cargo test --features headless`.
+
+use cardboard_coal as coal;
+use cardboard_math::*;
+use cardboard_oogl as oogl;
+use prelude_plus::*;
+
+use crate::collision;
+use crate::collision_simulation::CollisionSimulation;
+use crate::game_fs::GameFs;
+use crate::globals::{GlobalRandom, Globals, SharedGlobals, DEFAULT_FIXED_UPDATES_PER_SECOND};
+use crate::image_utils;
+use crate::input::{GamepadAxis, GamepadInputs, Key};
+use crate::map::Map;
+use crate::pong::{Pong, PongMode, PongState};
+use crate::pong_netplay::{self, NetplayPong};
+use crate::renderer::*;
+use crate::replay::ReplayFrame;
+use crate::scene::{Scene as _, SceneKind, SceneStack};
+use crate::tilemap::{Camera, Tilemap};
+
+const IMAGE_SIZE: Vec2u32 = vec2(568, 320);
+const DEFAULT_TOLERANCE: u8 = 2;
+const RANDOM_SEED: u128 = 0x6f70656e6b726f73736b6f64;
+
+const REFERENCES_DIR: &str = "tests/golden";
+const OUTPUT_DIR: &str = "target/golden";
+
+const DIFF_MISMATCH_COLOR: [u8; 4] = [0xFF, 0x00, 0xFF, 0xFF];
+
+#[derive(Debug)]
+struct Options {
+  bless: bool,
+  tolerance: u8,
+  filter: Vec<String>,
+}
+
+fn parse_args(args: impl Iterator<Item = String>) -> AnyResult<Options> {
+  let mut options = Options { bless: false, tolerance: DEFAULT_TOLERANCE, filter: Vec::new() };
+  let mut args = args;
+  while let Some(arg) = args.next() {
+    match arg.as_str() {
+      "--bless" => options.bless = true,
+      "--tolerance" => {
+        let value = args.next().ok_or_else(|| format_err!("--tolerance requires a value"))?;
+        options.tolerance =
+          value.parse().with_context(|| format!("Invalid tolerance: {}", value))?;
+      }
+      _ if arg.starts_with('-') => bail!("Unknown option: {}", arg),
+      _ => options.filter.push(arg),
+    }
+  }
+  Ok(options)
+}
+
+struct Scene {
+  name: &'static str,
+  render: fn(&mut SceneContext) -> AnyResult<()>,
+}
+
+struct SceneContext {
+  globals: SharedGlobals,
+  renderer: Renderer,
+  pong: Pong,
+}
+
+const SCENES: &[Scene] = &[
+  Scene { name: "shapes", render: render_shapes },
+  Scene { name: "text", render: render_text },
+  Scene { name: "pong", render: render_pong },
+  Scene { name: "tilemap", render: render_tilemap },
+  Scene { name: "pause_overlay", render: render_pause_overlay },
+  Scene { name: "pong_replay", render: render_pong_replay },
+  Scene { name: "collision", render: render_collision },
+  Scene { name: "collision_simulation", render: render_collision_simulation },
+  Scene { name: "netplay_pong", render: render_netplay_pong },
+];
+
+fn render_shapes(ctx: &mut SceneContext) -> AnyResult<()> {
+  let mut ball_texture =
+    load_texture_asset(&ctx.globals, "ball.png", oogl::TextureFilter::Linear)?;
+
+  let renderer = &mut ctx.renderer;
+  renderer.prepare();
+  renderer.draw_shape(&mut Shape {
+    type_: ShapeType::Rectangle,
+    pos: vec2(-180.0, 60.0),
+    size: vec2(120.0, 80.0),
+    rotation: 0.0,
+    fill: ShapeFill::Color(color(0.9, 0.2, 0.2, 1.0)),
+    fill_clipping: None,
+  });
+  renderer.draw_shape(&mut Shape {
+    type_: ShapeType::Rectangle,
+    pos: vec2(-180.0, -70.0),
+    size: vec2(120.0, 40.0),
+    rotation: f32::consts::FRAC_PI_6,
+    fill: ShapeFill::Color(color(0.2, 0.9, 0.2, 0.5)),
+    fill_clipping: None,
+  });
+  renderer.draw_shape(&mut Shape {
+    type_: ShapeType::Ellipse,
+    pos: vec2(0.0, 0.0),
+    size: vec2(160.0, 100.0),
+    rotation: f32::consts::FRAC_PI_4,
+    fill: ShapeFill::Color(color(0.2, 0.4, 1.0, 1.0)),
+    fill_clipping: None,
+  });
+  renderer.draw_shape(&mut Shape {
+    type_: ShapeType::Ellipse,
+    pos: vec2(180.0, 0.0),
+    size: vec2n(120.0),
+    rotation: 1.0,
+    fill: ShapeFill::Texture(&mut ball_texture),
+    fill_clipping: None,
+  });
+  renderer.finish();
+  Ok(())
+}
+
+fn render_text(ctx: &mut SceneContext) -> AnyResult<()> {
+  let renderer = &mut ctx.renderer;
+  let font = &mut ctx.pong.font;
+  renderer.prepare();
+  for (i, &align) in [TextAlign::Start, TextAlign::Center, TextAlign::End].iter().enumerate() {
+    renderer.draw_text(
+      font,
+      vec2(0.0, 100.0 - i as f32 * 100.0),
+      &mut TextBlock {
+        text: "Hello, World! 0123456789",
+        scale: vec2n(4.0),
+        character_spacing: vec2n(1.0 / 3.0),
+        horizontal_align: align,
+        vertical_align: align,
+      },
+    );
+  }
+  renderer.finish();
+  Ok(())
+}
+
+fn render_pong(ctx: &mut SceneContext) -> AnyResult<()> {
+  ctx.pong.start_match(PongMode::PlayerVsBot);
+  ctx.pong.early_update();
+  while ctx.pong.state() != PongState::Playing {
+    ctx.pong.fixed_update();
+  }
+  for _ in 0..60 {
+    ctx.pong.fixed_update();
+  }
+  ctx.pong.render(&mut ctx.renderer);
+  Ok(())
+}
+
+fn render_tilemap(ctx: &mut SceneContext) -> AnyResult<()> {
+  let map_path = ctx.globals.game_fs.installation_dir.join("tests/maps/tilemap.json");
+  let map_bytes =
+    fs::read(&map_path).with_context(|| format!("Failed to read '{}'", map_path.display()))?;
+  let map = Map::from_slice(&map_bytes)?;
+
+  // The right-most chunk column is outside of the view and must be culled.
+  let camera = Camera { pos: vec2(300.0, 176.0), zoom: 1.0 };
+  let mut tilemap = Tilemap::new(ctx.globals.share(), &map)?;
+  tilemap.render(&camera);
+  let stats = tilemap.take_stats();
+  ensure!(
+    (stats.visible_chunks as usize) < tilemap.chunks_count(),
+    "Expected some of the {} chunks to be culled, got {:?}",
+    tilemap.chunks_count(),
+    stats,
+  );
+
+  // Only the edited chunk should be rebuilt on the next frame.
+  for x in 20..28 {
+    tilemap.set_tile(1, vec2(x, 4), 16);
+  }
+  ctx.globals.gl.clear(oogl::ClearFlags::COLOR);
+  tilemap.render(&camera);
+  let stats = tilemap.take_stats();
+  ensure!(
+    stats.rebuilt_chunks == 1,
+    "Expected only the edited chunk to be rebuilt, got {:?}",
+    stats
+  );
+  Ok(())
+}
+
+/// A short match against the bot over the simulated network, which must end
+/// with both of the peers agreeing on the result.
+fn render_netplay_pong(ctx: &mut SceneContext) -> AnyResult<()> {
+  let args = ["--play", "--duration", "2"].iter().map(|&arg| arg.to_owned());
+  let mut scene = NetplayPong::new(ctx.globals.share(), pong_netplay::parse_args(args)?)?;
+  // The match is followed by a short period of only exchanging the packets.
+  for _ in 0..(3.0 * DEFAULT_FIXED_UPDATES_PER_SECOND) as u32 {
+    scene.fixed_update();
+  }
+  scene.render(&mut ctx.renderer);
+  Ok(())
+}
+
+fn render_pause_overlay(ctx: &mut SceneContext) -> AnyResult<()> {
+  let mut scenes = SceneStack::new(ctx.globals.share());
+  scenes.push(SceneKind::Pong)?;
+  scenes.push(SceneKind::Pause)?;
+  // Pong is covered by the overlay, so the ball must stay where it was thrown from.
+  scenes.early_update();
+  for _ in 0..60 {
+    scenes.fixed_update();
+  }
+  scenes.render(&mut ctx.renderer);
+  Ok(())
+}
+
+/// Plays a synthetic recording of a match against the bot being picked on the
+/// title screen and then, once the serve countdown is over, of the right racket
+/// moving around, with the keyboard and then with a gamepad which gets plugged
+/// in midway. The window loses the focus on the last frame, which must pause
+/// the match.
+fn render_pong_replay(ctx: &mut SceneContext) -> AnyResult<()> {
+  let mut frames = Vec::new();
+  // The countdown takes 1.5 seconds, and the first fixed updates after the
+  // mode is picked are on the next frame.
+  const SERVE_FRAMES: i32 = 91;
+  for i in -SERVE_FRAMES..180 {
+    let mut frame = ReplayFrame {
+      delta_time: 1.0 / 60.0,
+      fixed_updates: 2,
+      window_size: IMAGE_SIZE,
+      ..Default::default()
+    };
+    frame.keyboard_state_table[Key::Num1 as usize] = i == -SERVE_FRAMES;
+    frame.keyboard_state_table[Key::Up as usize] = (0..40).contains(&i);
+    frame.keyboard_state_table[Key::Down as usize] = (90..100).contains(&i);
+    if (120..150).contains(&i) {
+      let mut gamepad = GamepadInputs::default();
+      gamepad.axes[GamepadAxis::LeftY as usize] = 0.6;
+      frame.gamepads.push(Some(gamepad));
+    }
+    frame.window_is_focused = i != 179;
+    frames.push(frame);
+  }
+
+  let mut scenes = SceneStack::new(ctx.globals.share());
+  scenes.push(SceneKind::Pong)?;
+
+  fn mut_globals(ctx: &mut SceneContext) -> &mut Globals {
+    unsafe { Rc::get_mut_unchecked(&mut ctx.globals) }
+  }
+
+  for frame in &frames {
+    {
+      let globals = mut_globals(ctx);
+      frame.apply(globals);
+      globals.delta_time = frame.delta_time;
+      globals.time += frame.delta_time;
+      globals.fixed_update_alpha = frame.fixed_update_alpha;
+    }
+    scenes.early_update();
+    for _ in 0..frame.fixed_updates {
+      mut_globals(ctx).fixed_time += ctx.globals.fixed_delta_time;
+      scenes.fixed_update();
+    }
+    scenes.update()?;
+    ctx.globals.gl.clear(oogl::ClearFlags::COLOR);
+    scenes.render(&mut ctx.renderer);
+    mut_globals(ctx).first_game_loop_tick = false;
+  }
+  Ok(())
+}
+
+pub fn main(args: impl Iterator<Item = String>) -> AnyResult<()> {
+  let options = parse_args(args)?;
+
+  let headless_ctx =
+    oogl::HeadlessContext::new(oogl::HeadlessContextConfig { size: IMAGE_SIZE, debug: true })
+      .context("Failed to create a headless GL context")?;
+  let gl = headless_ctx.load_gl();
+  debug!("{:?}", gl.capabilities());
+
+  let game_fs = GameFs::init().context("Failed to initialize GameFs")?;
+  let references_dir = game_fs.installation_dir.join(REFERENCES_DIR);
+  let output_dir = game_fs.installation_dir.join(OUTPUT_DIR);
+
+  let mut failed_scenes = Vec::new();
+  let mut game_fs = Some(game_fs);
+  for scene in SCENES {
+    if !options.filter.is_empty() && !options.filter.iter().any(|name| name == scene.name) {
+      continue;
+    }
+
+    // Every scene gets fresh globals so that the results don't depend on the
+    // order or the set of the scenes which were run.
+    let game_fs = match game_fs.take() {
+      Some(game_fs) => game_fs,
+      None => GameFs::init().context("Failed to initialize GameFs")?,
+    };
+    let audio =
+      Box::new(coal::OfflineSink::new(coal::OutputFormat { sample_rate: 48000, channels: 2 }));
+    let globals = Rc::new(Globals::new(
+      gl.share(),
+      game_fs,
+      GlobalRandom::with_seed(RANDOM_SEED),
+      audio,
+      IMAGE_SIZE,
+    ));
+
+    crate::setup_gl_state(&gl);
+    gl.set_viewport(vec2n(0), IMAGE_SIZE.cast_into());
+    gl.clear(oogl::ClearFlags::COLOR);
+
+    let mut ctx = SceneContext {
+      renderer: Renderer::init(globals.share()).context("Failed to initialize the renderer")?,
+      pong: Pong::init(globals.share()).context("Failed to initialize Pong")?,
+      globals,
+    };
+    (scene.render)(&mut ctx)
+      .with_context(|| format!("Failed to render scene '{}'", scene.name))?;
+    debug!("{}: {:?}", scene.name, ctx.renderer.stats());
+    drop(ctx);
+
+    let mut pixels = vec![0; IMAGE_SIZE.x as usize * IMAGE_SIZE.y as usize * 4];
+    gl.read_pixels_rgba(IMAGE_SIZE, &mut pixels);
+    image_utils::flip_rgba_image_data_vertically(IMAGE_SIZE, &mut pixels);
+
+    let reference_path = references_dir.join(format!("{}.png", scene.name));
+    if options.bless {
+      fs::create_dir_all(&references_dir)
+        .with_context(|| format!("Failed to create directory '{}'", references_dir.display()))?;
+      image_utils::write_rgba_png(&reference_path, IMAGE_SIZE, &pixels)?;
+      info!("{}: reference image updated", scene.name);
+      continue;
+    }
+
+    let mismatch = match compare_with_reference(&reference_path, &pixels, options.tolerance)? {
+      None => {
+        info!("{}: ok", scene.name);
+        continue;
+      }
+      Some(mismatch) => mismatch,
+    };
+
+    error!("{}: {}", scene.name, mismatch.description);
+    fs::create_dir_all(&output_dir)
+      .with_context(|| format!("Failed to create directory '{}'", output_dir.display()))?;
+    let actual_path = output_dir.join(format!("{}.actual.png", scene.name));
+    image_utils::write_rgba_png(&actual_path, IMAGE_SIZE, &pixels)?;
+    if let Some(diff) = mismatch.diff {
+      let diff_path = output_dir.join(format!("{}.diff.png", scene.name));
+      image_utils::write_rgba_png(&diff_path, IMAGE_SIZE, &diff)?;
+      error!("{}: see '{}'", scene.name, diff_path.display());
+    }
+    failed_scenes.push(scene.name);
+  }
+
+  drop(gl);
+  drop(headless_ctx);
+
+  if !failed_scenes.is_empty() {
+    bail!("Golden image mismatch in scene(s): {}", failed_scenes.join(", "));
+  }
+  Ok(())
+}
+
+#[derive(Debug)]
+struct Mismatch {
+  description: String,
+  diff: Option<Vec<u8>>,
+}
+
+fn compare_with_reference(
+  reference_path: &Path,
+  actual: &[u8],
+  tolerance: u8,
+) -> AnyResult<Option<Mismatch>> {
+  let file = match File::open(reference_path) {
+    Ok(file) => file,
+    Err(e) if e.kind() == io::ErrorKind::NotFound => {
+      return Ok(Some(Mismatch {
+        description: format!(
+          "reference image '{}' doesn't exist, run with --bless to create it",
+          reference_path.display(),
+        ),
+        diff: None,
+      }));
+    }
+    Err(e) => {
+      return Err(e).with_context(|| format!("Failed to open '{}'", reference_path.display()))
+    }
+  };
+
+  let (reference_size, reference) = image_utils::read_rgba_png(BufReader::new(file))
+    .with_context(|| format!("Failed to decode '{}'", reference_path.display()))?;
+  Ok(compare_images(reference_size, &reference, IMAGE_SIZE, actual, tolerance))
+}
+
+/// Both images are RGBA, the pixels match if none of their channels differ by
+/// more than `tolerance`.
+fn compare_images(
+  reference_size: Vec2u32,
+  reference: &[u8],
+  actual_size: Vec2u32,
+  actual: &[u8],
+  tolerance: u8,
+) -> Option<Mismatch> {
+  if reference_size != actual_size {
+    return Some(Mismatch {
+      description: format!(
+        "size mismatch: expected {}x{}, got {}x{}",
+        reference_size.x, reference_size.y, actual_size.x, actual_size.y,
+      ),
+      diff: None,
+    });
+  }
+
+  let mut diff = Vec::with_capacity(actual.len());
+  let mut mismatched_pixels = 0;
+  let mut max_difference = 0;
+  for (expected_pixel, actual_pixel) in reference.chunks_exact(4).zip(actual.chunks_exact(4)) {
+    let difference =
+      expected_pixel.iter().zip(actual_pixel).map(|(&a, &b)| a.abs_diff(b)).max().unwrap();
+    max_difference = max_difference.max(difference);
+    if difference > tolerance {
+      mismatched_pixels += 1;
+      diff.extend_from_slice(&DIFF_MISMATCH_COLOR);
+    } else {
+      // Matching pixels are drawn as a faded out grayscale version of the
+      // reference, so that the differences stand out.
+      let (r, g, b) = (expected_pixel[0], expected_pixel[1], expected_pixel[2]);
+      let luma = (r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000 / 4;
+      diff.extend_from_slice(&[luma as u8, luma as u8, luma as u8, 0xFF]);
+    }
+  }
+
+  if mismatched_pixels == 0 {
+    return None;
+  }
+  Some(Mismatch {
+    description: format!(
+      "{} pixel(s) differ by more than {} (max difference is {})",
+      mismatched_pixels, tolerance, max_difference,
+    ),
+    diff: Some(diff),
+  })
+}
+
+/// Draws the swept collision tests against a box: the path of the circle,
+/// where it stops and the normal of the hit.
+fn render_collision(ctx: &mut SceneContext) -> AnyResult<()> {
+  let half_extents = vec2(10.0, 50.0);
+  let radius = 20.0;
+
+  let sweeps: &[(Vec2f, Vec2f)] = &[
+    // Way too fast for the box, would have jumped over it without a sweep.
+    (vec2(-200.0, 0.0), vec2(400.0, 0.0)),
+    // Hits the rounded corner.
+    (vec2(-60.0, 100.0), vec2(100.0, -100.0)),
+    // Misses the corner, even though it goes through the corner of the
+    // bounding box of the grown box.
+    (vec2(-49.0, 49.0), vec2(40.0, 40.0)),
+    // Starts overlapping, so stays in place.
+    (vec2(25.0, 0.0), vec2(100.0, 0.0)),
+    // Doesn't move.
+    (vec2(-100.0, 0.0), Vec2f::ZERO),
+  ];
+  let hits: Vec<_> = sweeps
+    .iter()
+    .map(|&(start, motion)| {
+      collision::sweep_circle_vs_aabb(start, radius, motion, Vec2f::ZERO, half_extents)
+    })
+    .collect();
+
+  let renderer = &mut ctx.renderer;
+  renderer.prepare();
+  let scale = 1.5;
+  let line = |renderer: &mut Renderer, start: Vec2f, vector: Vec2f, color: Colorf| {
+    renderer.draw_shape(&mut Shape {
+      type_: ShapeType::Rectangle,
+      pos: (start + vector / 2.0) * scale,
+      size: vec2(vector.magnitude() * scale, 2.0),
+      rotation: vector.angle_from_x_axis(),
+      fill: ShapeFill::Color(color),
+      fill_clipping: None,
+    });
+  };
+  renderer.draw_shape(&mut Shape {
+    type_: ShapeType::Rectangle,
+    pos: Vec2f::ZERO,
+    size: half_extents * 2.0 * scale,
+    rotation: 0.0,
+    fill: ShapeFill::Color(colorn(0.9, 1.0)),
+    fill_clipping: None,
+  });
+  for (&(start, motion), hit) in sweeps.iter().zip(&hits) {
+    let time = hit.map_or(1.0, |hit| hit.time);
+    let end = start + motion * time;
+    line(renderer, start, motion, color(0.4, 0.4, 0.4, 1.0));
+    renderer.draw_shape(&mut Shape {
+      type_: ShapeType::Ellipse,
+      pos: end * scale,
+      size: vec2n(radius * 2.0 * scale),
+      rotation: 0.0,
+      fill: ShapeFill::Color(if hit.is_some() {
+        color(0.9, 0.3, 0.2, 0.6)
+      } else {
+        color(0.2, 0.6, 0.9, 0.6)
+      }),
+      fill_clipping: None,
+    });
+    if let Some(hit) = hit {
+      line(renderer, end, hit.normal * radius * 1.5, color(0.2, 0.9, 0.2, 1.0));
+    }
+  }
+  renderer.finish();
+  Ok(())
+}
+
+fn render_collision_simulation(ctx: &mut SceneContext) -> AnyResult<()> {
+  let mut scene = CollisionSimulation::init(ctx.globals.share())?;
+  scene.early_update();
+  for _ in 0..240 {
+    scene.fixed_update();
+  }
+  scene.render(&mut ctx.renderer);
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const SIZE: Vec2u32 = vec2(2, 1);
+  const REFERENCE: [u8; 8] = [200, 100, 40, 0xFF, 0, 0, 0, 0xFF];
+
+  fn compare(actual: &[u8; 8], tolerance: u8) -> Option<Mismatch> {
+    compare_images(SIZE, &REFERENCE, SIZE, actual, tolerance)
+  }
+
+  #[test]
+  fn identical_images_match() {
+    assert!(compare(&REFERENCE, 0).is_none());
+  }
+
+  #[test]
+  fn small_differences_are_tolerated() {
+    let actual = [201, 99, 40, 0xFF, 0, 2, 0, 0xFE];
+    assert!(compare(&actual, 2).is_none());
+
+    let mismatch = compare(&actual, 1).unwrap();
+    assert_eq!(mismatch.description, "1 pixel(s) differ by more than 1 (max difference is 2)");
+    // The matching pixels are darkened grayscale.
+    assert_eq!(mismatch.diff.unwrap(), [30, 30, 30, 0xFF, 0xFF, 0x00, 0xFF, 0xFF]);
+  }
+
+  #[test]
+  fn large_differences_are_reported() {
+    let actual = [200, 100, 40, 0xFF, 0, 0, 0, 0];
+    let mismatch = compare(&actual, DEFAULT_TOLERANCE).unwrap();
+    assert_eq!(mismatch.description, "1 pixel(s) differ by more than 2 (max difference is 255)");
+    assert_eq!(mismatch.diff.unwrap(), [30, 30, 30, 0xFF, 0xFF, 0x00, 0xFF, 0xFF]);
+  }
+
+  #[test]
+  fn sizes_must_match() {
+    let mismatch = compare_images(SIZE, &REFERENCE, vec2(1, 2), &REFERENCE, 255).unwrap();
+    assert_eq!(mismatch.description, "size mismatch: expected 2x1, got 1x2");
+    assert!(mismatch.diff.is_none());
+  }
+
+  #[test]
+  fn missing_references_have_to_be_blessed() {
+    let path = Path::new(REFERENCES_DIR).join("does_not_exist.png");
+    let mismatch = compare_with_reference(&path, &[], 0).unwrap().unwrap();
+    assert!(mismatch.description.ends_with("run with --bless to create it"));
+  }
+}
@@ -0,0 +1,52 @@
+use cardboard_math::*;
+use prelude_plus::*;
+
+/// OpenGL reads the framebuffer rows from bottom to top, but pretty much all
+/// image formats store them from top to bottom.
+pub fn flip_rgba_image_data_vertically(size: Vec2u32, pixels: &mut [u8]) {
+  assert!(pixels.len() == size.x as usize * size.y as usize * 4);
+
+  let row_len = size.x as usize * 4;
+  let (mut half1, mut half2) = pixels.split_at_mut(row_len * (size.y / 2) as usize);
+  for _ in 0..size.y / 2 {
+    // len needs to be saved because using the length in slicing expressions
+    // makes the borrow checker unhappy
+    let half2_len = half2.len();
+    let row1 = &mut half1[..row_len];
+    let row2 = &mut half2[half2_len - row_len..];
+    row1.swap_with_slice(row2);
+    half1 = &mut half1[row_len..];
+    half2 = &mut half2[..half2_len - row_len];
+  }
+}
+
+pub fn write_rgba_png(path: &Path, size: Vec2u32, pixels: &[u8]) -> AnyResult<()> {
+  let file =
+    File::create(path).with_context(|| format!("Failed to create file '{}'", path.display()))?;
+  let writer = BufWriter::new(file);
+
+  let mut encoder = png::Encoder::new(writer, size.x, size.y);
+  encoder.set_color(png::ColorType::Rgba);
+  encoder.set_depth(png::BitDepth::Eight);
+  let mut writer = encoder.write_header().context("Failed to write the PNG header")?;
+  writer.write_image_data(pixels).context("Failed to write the encoded PNG data")?;
+
+  Ok(())
+}
+
+/// Only 8-bit RGBA images are supported, which is what [`write_rgba_png`]
+/// produces.
+pub fn read_rgba_png(reader: impl Read) -> AnyResult<(Vec2u32, Vec<u8>)> {
+  let decoder = png::Decoder::new(reader);
+  let mut reader = decoder.read_info()?;
+  let mut buf = vec![0; reader.output_buffer_size()];
+  let info = reader.next_frame(&mut buf)?;
+  ensure!(
+    info.color_type == png::ColorType::Rgba && info.bit_depth == png::BitDepth::Eight,
+    "Unsupported PNG format: {:?} {:?}",
+    info.color_type,
+    info.bit_depth,
+  );
+  buf.truncate(info.buffer_size());
+  Ok((vec2(info.width, info.height), buf))
+}
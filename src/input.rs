@@ -3,6 +3,9 @@ use prelude_plus::*;
 use sdl2::keyboard::Scancode;
 use sdl2::mouse::MouseButton;
 
+use crate::bindings::{Binding, InputBindings};
+use crate::text_input::TextInput;
+
 #[derive(Debug)]
 pub struct InputState {
   pub mouse_pos: Vec2f,
@@ -11,6 +14,17 @@ pub struct InputState {
 
   pub prev_keyboard_state_table: [bool; Key::VARIANTS.len()],
   pub keyboard_state_table: [bool; Key::VARIANTS.len()],
+
+  /// Is behind a `RefCell` so that the bindings can be changed at runtime
+  /// through the shared globals.
+  pub bindings: RefCell<InputBindings>,
+
+  /// Every connected gamepad keeps its slot until it is disconnected, so that
+  /// the player numbers don't shift when another one gets unplugged.
+  pub gamepads: Vec<Option<GamepadState>>,
+  pub gamepad_dead_zone: f32,
+
+  pub text_input: TextInput,
 }
 
 impl InputState {
@@ -22,6 +36,13 @@ impl InputState {
 
       prev_keyboard_state_table: [false; Key::VARIANTS.len()],
       keyboard_state_table: [false; Key::VARIANTS.len()],
+
+      bindings: RefCell::new(InputBindings::new()),
+
+      gamepads: Vec::new(),
+      gamepad_dead_zone: DEFAULT_GAMEPAD_DEAD_ZONE,
+
+      text_input: TextInput::default(),
     }
   }
 
@@ -57,35 +78,156 @@ impl InputState {
     }
     dir
   }
+
+  #[inline]
+  pub fn gamepad(&self, index: usize) -> Option<&GamepadState> {
+    self.gamepads.get(index).and_then(Option::as_ref)
+  }
+
+  pub fn connected_gamepads(&self) -> impl Iterator<Item = (usize, &GamepadState)> {
+    self.gamepads.iter().enumerate().filter_map(|(i, gamepad)| Some((i, gamepad.as_ref()?)))
+  }
+
+  pub fn gamepad_by_instance_id_mut(&mut self, instance_id: u32) -> Option<&mut GamepadState> {
+    self.gamepads.iter_mut().flatten().find(|gamepad| gamepad.instance_id == instance_id)
+  }
+
+  /// Takes the first free slot and returns its index.
+  pub fn connect_gamepad(&mut self, gamepad: GamepadState) -> usize {
+    match self.gamepads.iter().position(Option::is_none) {
+      Some(index) => {
+        self.gamepads[index] = Some(gamepad);
+        index
+      }
+      None => {
+        self.gamepads.push(Some(gamepad));
+        self.gamepads.len() - 1
+      }
+    }
+  }
+
+  pub fn disconnect_gamepad(&mut self, instance_id: u32) -> Option<(usize, GamepadState)> {
+    let index = self.gamepads.iter().position(|gamepad| {
+      gamepad.as_ref().map_or(false, |gamepad| gamepad.instance_id == instance_id)
+    })?;
+    let gamepad = self.gamepads[index].take()?;
+    while let Some(None) = self.gamepads.last() {
+      self.gamepads.pop();
+    }
+    Some((index, gamepad))
+  }
+
+  /// Returns whether any of the bindings of the action were down on the
+  /// previous tick and on this one.
+  fn action_state(&self, action: &str) -> (bool, bool) {
+    let bindings = self.bindings.borrow();
+    let bindings = bindings.action(action);
+    debug_assert!(bindings.is_some(), "unknown action '{}'", action);
+    let any_down = |previous| {
+      bindings.map_or(false, |bindings| bindings.iter().any(|b| b.is_down(self, previous)))
+    };
+    (any_down(true), any_down(false))
+  }
+
+  #[inline]
+  pub fn is_action_down(&self, action: &str) -> bool { self.action_state(action).1 }
+
+  #[inline]
+  pub fn is_action_up(&self, action: &str) -> bool { !self.is_action_down(action) }
+
+  /// Holding down a second binding of an action which is already down
+  /// doesn't press it again.
+  #[inline]
+  pub fn is_action_pressed(&self, action: &str) -> bool {
+    let (prev, now) = self.action_state(action);
+    !prev && now
+  }
+
+  #[inline]
+  pub fn is_action_unpressed(&self, action: &str) -> bool {
+    let (prev, now) = self.action_state(action);
+    prev && !now
+  }
+
+  /// The counterpart of [`Self::axis`] for the named axes, the analog inputs
+  /// are treated as pressed past [`crate::bindings::ANALOG_PRESS_THRESHOLD`].
+  pub fn action_axis(&self, axis: &str) -> i8 {
+    let bindings = self.bindings.borrow();
+    let bindings = match bindings.axis(axis) {
+      Some(bindings) => bindings,
+      None => {
+        debug_assert!(false, "unknown axis '{}'", axis);
+        return 0;
+      }
+    };
+    let any_down = |bindings: &[Binding]| bindings.iter().any(|b| b.is_down(self, false));
+    let mut dir = 0;
+    if any_down(&bindings.negative) {
+      dir -= 1;
+    }
+    if any_down(&bindings.positive) {
+      dir += 1;
+    }
+    dir
+  }
+
+  /// Same as [`Self::action_axis`], but keeps the analog values of the
+  /// gamepad sticks, in the `-1.0..=1.0` range.
+  pub fn action_axis_value(&self, axis: &str) -> f32 {
+    let bindings = self.bindings.borrow();
+    let bindings = match bindings.axis(axis) {
+      Some(bindings) => bindings,
+      None => {
+        debug_assert!(false, "unknown axis '{}'", axis);
+        return 0.0;
+      }
+    };
+    let strength =
+      |bindings: &[Binding]| bindings.iter().map(|b| b.value(self, false)).fold(0.0, f32::max);
+    strength(&bindings.positive) - strength(&bindings.negative)
+  }
 }
 
-macro_rules! generate_key_enum {
-  ($($key:ident),+ $(,)?) => {
+macro_rules! generate_input_enum {
+  ($name:ident, [$($key:ident),+ $(,)?]) => {
     #[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
     #[repr(u8)]
-    pub enum Key {
+    pub enum $name {
       $($key),+
     }
-    impl Key {
+    impl $name {
       pub const VARIANTS: &'static [Self] = &[$(Self::$key),+];
+
+      /// The name used in the config files, same as the variant.
+      pub fn name(self) -> &'static str {
+        match self {
+          $(Self::$key => stringify!($key)),+
+        }
+      }
+
+      pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+          $(stringify!($key) => Some(Self::$key),)+
+          _ => None,
+        }
+      }
     }
   };
 }
 
 #[rustfmt::skip]
-generate_key_enum! [
+generate_input_enum!(Key, [
   Backspace, Tab, Return, Pause, CapsLock, Escape, Space, PageUp, PageDown,
   End, Left, Up, Right, Down, Home, Insert, Delete, Num0, Num1, Num2, Num3,
   Num4, Num5, Num6, Num7, Num8, Num9, A, B, C, D, E, F, G, H, I, J, K, L, M, N,
   O, P, Q, R, S, T, U, V, W, X, Y, Z, Kp0, Kp1, Kp2, Kp3, Kp4, Kp5, Kp6, Kp7,
   Kp8, Kp9, KpMultiply, KpPlus, KpMinus, KpDecimal, KpDivide, F1, F2, F3, F4,
   F5, F6, F7, F8, F9, F10, F11, F12,
-  // TODO: handle left/right modifiers as the same single key?
   LShift, RShift, LCtrl, RCtrl, LAlt, RAlt, Equals, Comma, Minus, Period,
   Semicolon, Grave, Slash, LeftBracket, Backslash, RightBracket, Apostrophe,
 
   MouseLeft, MouseMiddle, MouseRight, MouseX1, MouseX2,
-];
+]);
 
 impl Key {
   #[rustfmt::skip]
@@ -122,3 +264,137 @@ impl Key {
     ]
   }
 }
+
+#[rustfmt::skip]
+generate_input_enum!(GamepadButton, [
+  A, B, X, Y, Back, Guide, Start, LeftStick, RightStick, LeftShoulder,
+  RightShoulder, DPadUp, DPadDown, DPadLeft, DPadRight,
+]);
+
+#[rustfmt::skip]
+generate_input_enum!(GamepadAxis, [
+  LeftX, LeftY, RightX, RightY, TriggerLeft, TriggerRight,
+]);
+
+impl GamepadButton {
+  #[rustfmt::skip]
+  pub fn from_sdl2(value: sdl2::controller::Button) -> Option<Self> {
+    use sdl2::controller::Button;
+    macro_rules! helper {
+      ($($button:ident),+ $(,)?) => {
+        Some(match value {
+          $(Button::$button => Self::$button,)+
+          _ => return None,
+        })
+      };
+    }
+    helper![
+      A, B, X, Y, Back, Guide, Start, LeftStick, RightStick, LeftShoulder,
+      RightShoulder, DPadUp, DPadDown, DPadLeft, DPadRight,
+    ]
+  }
+}
+
+impl GamepadAxis {
+  #[rustfmt::skip]
+  pub fn from_sdl2(value: sdl2::controller::Axis) -> Self {
+    use sdl2::controller::Axis;
+    match value {
+      Axis::LeftX        => Self::LeftX,
+      Axis::LeftY        => Self::LeftY,
+      Axis::RightX       => Self::RightX,
+      Axis::RightY       => Self::RightY,
+      Axis::TriggerLeft  => Self::TriggerLeft,
+      Axis::TriggerRight => Self::TriggerRight,
+    }
+  }
+
+  /// Converts the raw SDL value into the `-1.0..=1.0` range (`0.0..=1.0` for
+  /// the triggers). The Y axes are flipped to point up, same as everything
+  /// else in the game. Values within the dead zone are snapped to zero and the
+  /// rest are rescaled, so that the sticks still start moving from zero.
+  pub fn normalize(self, raw: i16, dead_zone: f32) -> f32 {
+    let mut value = (raw as f32 / i16::MAX as f32).clamp(-1.0, 1.0);
+    if let Self::LeftY | Self::RightY = self {
+      value = -value;
+    }
+    let magnitude = value.abs();
+    if magnitude <= dead_zone {
+      0.0
+    } else {
+      value.signum() * (magnitude - dead_zone) / (1.0 - dead_zone)
+    }
+  }
+}
+
+pub const DEFAULT_GAMEPAD_DEAD_ZONE: f32 = 0.2;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GamepadInputs {
+  pub buttons: [bool; GamepadButton::VARIANTS.len()],
+  pub axes: [f32; GamepadAxis::VARIANTS.len()],
+}
+
+impl Default for GamepadInputs {
+  fn default() -> Self {
+    Self {
+      buttons: [false; GamepadButton::VARIANTS.len()],
+      axes: [0.0; GamepadAxis::VARIANTS.len()],
+    }
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct GamepadState {
+  /// The SDL joystick instance ID, which the controller events refer to.
+  pub instance_id: u32,
+  pub name: String,
+  pub prev: GamepadInputs,
+  pub current: GamepadInputs,
+}
+
+impl GamepadState {
+  pub fn new(instance_id: u32, name: String) -> Self {
+    Self { instance_id, name, prev: GamepadInputs::default(), current: GamepadInputs::default() }
+  }
+
+  #[inline(always)]
+  fn inputs(&self, previous: bool) -> &GamepadInputs {
+    if previous {
+      &self.prev
+    } else {
+      &self.current
+    }
+  }
+
+  #[inline(always)]
+  pub fn button_state(&self, button: GamepadButton, previous: bool) -> bool {
+    self.inputs(previous).buttons[button as usize]
+  }
+
+  #[inline(always)]
+  pub fn axis_state(&self, axis: GamepadAxis, previous: bool) -> f32 {
+    self.inputs(previous).axes[axis as usize]
+  }
+
+  #[inline(always)]
+  pub fn is_button_down(&self, button: GamepadButton) -> bool { self.button_state(button, false) }
+
+  #[inline]
+  pub fn is_button_pressed(&self, button: GamepadButton) -> bool {
+    !self.button_state(button, true) && self.button_state(button, false)
+  }
+
+  #[inline]
+  pub fn is_button_unpressed(&self, button: GamepadButton) -> bool {
+    self.button_state(button, true) && !self.button_state(button, false)
+  }
+
+  #[inline(always)]
+  pub fn axis(&self, axis: GamepadAxis) -> f32 { self.axis_state(axis, false) }
+
+  /// Moves the inputs of the last tick into [`Self::prev`] and replaces them.
+  pub fn update(&mut self, inputs: GamepadInputs) {
+    self.prev = mem::replace(&mut self.current, inputs);
+  }
+}
@@ -2,41 +2,63 @@
 #![allow(clippy::new_without_default, clippy::missing_safety_doc)]
 #![feature(bench_black_box, get_mut_unchecked)]
 
-pub mod gen_idx; // TODO
+pub mod gen_idx;
 
+pub mod audio;
+pub mod bindings;
+pub mod collision;
 pub mod game_fs;
 pub mod globals;
+#[cfg(feature = "headless")]
+pub mod golden_tests;
+pub mod image_utils;
 pub mod input;
+pub mod map;
+pub mod netplay;
+pub mod physics;
 pub mod profiling;
 pub mod renderer;
-
+pub mod replay;
+pub mod scene;
+pub mod settings;
+#[cfg(test)]
+pub mod test_utils;
+pub mod text_input;
+pub mod tilemap;
+pub mod tournament;
+
+pub mod collision_simulation;
 pub mod game_of_life;
 pub mod image_decoding_speedrun;
 pub mod mandelbrot;
 pub mod marching_squares;
 pub mod pong;
+pub mod pong_ai;
+pub mod pong_netplay;
+pub mod pong_sim;
 
 use prelude_plus::*;
+use sdl2::controller::GameController;
 use sdl2::event::{Event, WindowEvent};
-use sdl2::video::{GLProfile, Window};
+use sdl2::keyboard::Mod;
+use sdl2::video::{GLProfile, SwapInterval, Window};
 use sdl2::EventPump;
 
+use cardboard_coal as coal;
 use cardboard_math::*;
 use cardboard_oogl as oogl;
 
+use crate::bindings::actions;
 use crate::game_fs::*;
 use crate::globals::*;
-use crate::input::Key;
+use crate::input::{GamepadAxis, GamepadButton, GamepadState, Key};
+use crate::pong_netplay::NetplayPong;
 use crate::renderer::*;
+use crate::replay::{ReplayFrame, ReplayHeader, ReplayReader, ReplayWriter};
 
-use crate::pong::Pong;
-
-#[cfg(feature = "game_of_life")]
-use crate::game_of_life::GameOfLife;
-#[cfg(feature = "mandelbrot")]
-use crate::mandelbrot::Mandelbrot;
-#[cfg(feature = "marching_squares")]
-use crate::marching_squares::MarchingSquares;
+use crate::scene::{SceneKind, SceneStack};
+use crate::settings::Settings;
+use crate::text_input::TextEdit;
 
 const GAME_NAME: &str = "openKrossKod";
 // const GAME_NAME: &str = env!("CARGO_PKG_NAME");
@@ -44,9 +66,7 @@ const GAME_VERSION: &str = env!("CARGO_PKG_VERSION");
 const GAME_ENGINE_NAME: &str = "Cardboard Engine, \"The Third Impact\" revision";
 
 const GL_CONTEXT_PROFILE: GLProfile = GLProfile::GLES;
-const GL_CONTEXT_VERSION: (u8, u8) = (2, 0);
 
-const DEFAULT_WINDOW_SIZE: Vec2u32 = vec2(568 * 2, 320 * 2);
 const BACKGROUND_COLOR: Colorf = colorn(0.1, 1.0);
 
 const GAME_LOOP_IDLING_WAIT_INTERVAL: f64 = 1.0 / 20.0;
@@ -58,20 +78,81 @@ fn main() {
     } else {
       eprintln!("ERROR: {:?}", err);
     }
+    std::process::exit(1);
   }
 }
 
+fn init_logger(filter: &str) {
+  env_logger::init_from_env(env_logger::Env::default().default_filter_or(filter));
+}
+
 fn try_main() -> AnyResult<()> {
-  env_logger::init_from_env(env_logger::Env::default().default_filter_or("debug"));
   // return image_decoding_speedrun::main();
 
+  #[cfg(feature = "headless")]
+  if env::args().nth(1).as_deref() == Some("golden-tests") {
+    init_logger(&Settings::default().log_filter);
+    return golden_tests::main(env::args().skip(2));
+  }
+
+  if env::args().nth(1).as_deref() == Some("tournament") {
+    init_logger(&Settings::default().log_filter);
+    return tournament::main(env::args().skip(2));
+  }
+
+  let mut netplay_options = None;
+  if env::args().nth(1).as_deref() == Some("netplay") {
+    let options = pong_netplay::parse_args(env::args().skip(2))?;
+    if options.help || !options.play {
+      init_logger(&Settings::default().log_filter);
+      return pong_netplay::main(options);
+    }
+    netplay_options = Some(options);
+  }
+
+  let args = match netplay_options {
+    Some(_) => settings::Args::default(),
+    None => settings::parse_args(env::args().skip(1))?,
+  };
+  if args.help {
+    print!("{}", settings::USAGE);
+    return Ok(());
+  }
+
+  // NOTE: The logger filter comes from the settings file, so the logger can't
+  // be set up before GameFs, and GameFs can't log anything while initializing.
+  let game_fs = GameFs::init().context("Failed to initialize GameFs")?;
+  let mut settings = Settings::load(&game_fs, &args)?;
+  init_logger(&settings.log_filter);
+
   info!("{} v{} ({})", GAME_NAME, GAME_VERSION, GAME_ENGINE_NAME);
   info!("Initiating the boot sequence...");
+  info!("Installation dir: '{}'", game_fs.installation_dir.display());
 
-  let game_fs = GameFs::init().context("Failed to initialize GameFs")?;
+  let input_bindings = settings.input_bindings()?;
+  let replay_reader = match &args.replay_path {
+    Some(path) => {
+      info!("Playing the replay '{}'", path.display());
+      Some(replay::open_replay_file(path, &input_bindings)?)
+    }
+    None => None,
+  };
+  if let Some(header) = replay_reader.as_ref().map(ReplayReader::header) {
+    settings.seed = Some(header.seed);
+    settings.scene = header.scene.clone();
+  }
+  if let Some(seed) = netplay_options.as_ref().and_then(|options| options.seed) {
+    settings.seed = Some(seed);
+  }
+  debug!("{:?}", settings);
 
-  let random =
-    GlobalRandom::init().context("Failed to initialize a random number generator (RNG)")?;
+  let random = match settings.seed {
+    Some(seed) => GlobalRandom::with_seed(seed),
+    None => {
+      GlobalRandom::init().context("Failed to initialize a random number generator (RNG)")?
+    }
+  };
+  info!("RNG seed: {:#034x}", random.seed());
 
   let sdl_context =
     sdl2::init().map_err(AnyError::msg).context("Failed to create an SDL context")?;
@@ -79,6 +160,17 @@ fn try_main() -> AnyResult<()> {
     .video()
     .map_err(AnyError::msg)
     .context("Failed to initialize SDL's video subsystem")?;
+  let audio_subsystem = sdl_context
+    .audio()
+    .map_err(AnyError::msg)
+    .context("Failed to initialize SDL's audio subsystem")?;
+  let controller_subsystem = match sdl_context.game_controller() {
+    Ok(subsystem) => Some(subsystem),
+    Err(e) => {
+      warn!("Failed to initialize SDL's game controller subsystem, gamepads won't work: {}", e);
+      None
+    }
+  };
   let event_pump = sdl_context
     .event_pump()
     .map_err(AnyError::msg)
@@ -89,23 +181,25 @@ fn try_main() -> AnyResult<()> {
   // order on macOS causes ANGLE to not work.
   let gl_attr = video_subsystem.gl_attr();
   gl_attr.set_context_profile(GL_CONTEXT_PROFILE);
-  gl_attr.set_context_version(GL_CONTEXT_VERSION.0, GL_CONTEXT_VERSION.1);
-  // TODO: A environment variable or a command-line flag for disabling the GL debug mode
-  gl_attr.set_context_flags().debug().set();
-  // gl_attr.set_multisample_buffers(1);
-  // gl_attr.set_multisample_samples(4);
-
-  let window = video_subsystem
-    .window(
-      &format!("{} v{}", GAME_NAME, GAME_VERSION),
-      DEFAULT_WINDOW_SIZE.x,
-      DEFAULT_WINDOW_SIZE.y,
-    )
-    .resizable()
-    .opengl()
-    .allow_highdpi()
-    .build()
-    .context("Failed to create the game window")?;
+  gl_attr.set_context_version(settings.gl_version.0, settings.gl_version.1);
+  if settings.gl_debug {
+    gl_attr.set_context_flags().debug().set();
+  }
+  if settings.msaa_samples > 0 {
+    gl_attr.set_multisample_buffers(1);
+    gl_attr.set_multisample_samples(settings.msaa_samples);
+  }
+
+  let mut window_builder = video_subsystem.window(
+    &format!("{} v{}", GAME_NAME, GAME_VERSION),
+    settings.window_size.x,
+    settings.window_size.y,
+  );
+  window_builder.resizable().opengl().allow_highdpi();
+  if settings.fullscreen {
+    window_builder.fullscreen_desktop();
+  }
+  let window = window_builder.build().context("Failed to create the game window")?;
 
   let sdl_gl_ctx = window
     .gl_create_context()
@@ -113,57 +207,68 @@ fn try_main() -> AnyResult<()> {
     .context("Failed to create an OpenGL context for the game window")?;
   assert_eq!(
     (gl_attr.context_profile(), gl_attr.context_version()),
-    (GL_CONTEXT_PROFILE, GL_CONTEXT_VERSION)
+    (GL_CONTEXT_PROFILE, settings.gl_version)
   );
 
+  let swap_interval = if settings.vsync { SwapInterval::VSync } else { SwapInterval::Immediate };
+  if let Err(e) = video_subsystem.gl_set_swap_interval(swap_interval) {
+    warn!("Failed to set the swap interval to {:?}: {}", swap_interval, e);
+  }
+
   let gl = Rc::new(oogl::Context::load_with(|name| {
     video_subsystem.gl_get_proc_address(name) as *const c_void
   }));
   debug!("{:?}", gl.capabilities());
 
-  gl.set_clear_color(BACKGROUND_COLOR);
+  setup_gl_state(&gl);
   gl.clear(oogl::ClearFlags::COLOR);
   window.gl_swap_window();
 
-  gl.set_blending_enabled(true);
-  gl.set_blending_factors(oogl::BlendingFactor::SrcAlpha, oogl::BlendingFactor::OneMinusSrcAlpha);
-  gl.set_blending_equation(oogl::BlendingEquation::Add);
-
-  let globals = Rc::new({
-    let window_size_i = Vec2::from(window.drawable_size());
-    Globals {
-      gl,
-      game_fs,
-      random,
-
-      should_stop_game_loop: Cell::new(false),
-      first_game_loop_tick: true,
-      time: 0.0,
-      delta_time: 0.0,
-      fixed_time: 0.0,
-      // TODO: reduce the timestep from 120 UPS to 60 UPS
-      fixed_delta_time: 1.0 / 60.0 / 2.0,
-
-      window_size_i,
-      window_size: window_size_i.cast_into(),
-      window_was_resized: true,
-      window_is_focused: true,
-
-      input_state: input::InputState::new(),
-    }
-  });
+  let audio: Box<dyn coal::OutputSink> =
+    match coal::SdlSink::open(&audio_subsystem, coal::SdlSinkConfig::default()) {
+      Ok(sink) => Box::new(sink),
+      Err(e) => {
+        warn!("Failed to open an audio device, the game will be silent: {}", e);
+        Box::new(coal::OfflineSink::new(coal::OutputFormat { sample_rate: 48000, channels: 2 }))
+      }
+    };
+  debug!("Audio output: {:?}", audio);
+
+  let mut globals = Globals::new(gl, game_fs, random, audio, Vec2::from(window.drawable_size()));
+  globals.fixed_delta_time = match &replay_reader {
+    // Recomputing it from the updates per second may round it differently.
+    Some(reader) => reader.header().fixed_delta_time,
+    None => settings.fixed_delta_time(),
+  };
+  *globals.input_state.bindings.get_mut() = input_bindings;
+  globals.input_state.gamepad_dead_zone = settings.gamepad_dead_zone;
+  let globals = Rc::new(globals);
 
   let renderer = Renderer::init(globals.share()).context("Failed to initialize the renderer")?;
-  let pong = Pong::init(globals.share()).context("Failed to initialize Pong")?;
+  let initial_scene = settings.scene_kind()?;
+  let mut scenes = SceneStack::new(globals.share());
+  match netplay_options {
+    Some(options) => {
+      let scene = NetplayPong::new(globals.share(), options)
+        .with_context(|| format!("Failed to initialize {:?}", SceneKind::NetplayPong))?;
+      scenes.push_initialized(SceneKind::NetplayPong, Box::new(scene));
+    }
+    None => scenes.push(initial_scene)?,
+  }
 
-  #[cfg(feature = "game_of_life")]
-  let game_of_life =
-    GameOfLife::init(globals.share()).context("Failed to initialize GameOfLife")?;
-  #[cfg(feature = "mandelbrot")]
-  let mandelbrot = Mandelbrot::init(globals.share()).context("Failed to initialize Mandelbrot")?;
-  #[cfg(feature = "marching_squares")]
-  let marching_squares =
-    MarchingSquares::init(globals.share()).context("Failed to initialize MarchingSquares")?;
+  let replay_writer = match &args.record_path {
+    Some(path) => {
+      info!("Recording a replay to '{}'", path.display());
+      let header = ReplayHeader {
+        seed: globals.random.seed(),
+        fixed_delta_time: globals.fixed_delta_time,
+        scene: initial_scene.name().to_owned(),
+        bindings_checksum: globals.input_state.bindings.borrow().checksum(),
+      };
+      Some(replay::create_replay_file(path, &header)?)
+    }
+    None => None,
+  };
 
   globals.gl.release_shader_compiler();
 
@@ -172,18 +277,16 @@ fn try_main() -> AnyResult<()> {
 
     sdl_context,
     video_subsystem,
+    audio_subsystem,
+    controller_subsystem,
+    controllers: HashMap::new(),
     sdl_gl_ctx,
     window,
     event_pump,
     renderer,
-
-    pong,
-    #[cfg(feature = "game_of_life")]
-    game_of_life,
-    #[cfg(feature = "mandelbrot")]
-    mandelbrot,
-    #[cfg(feature = "marching_squares")]
-    marching_squares,
+    scenes,
+    replay_writer,
+    replay_reader,
   };
 
   info!("Core subsystems have been initialized, starting the game loop...");
@@ -193,26 +296,33 @@ fn try_main() -> AnyResult<()> {
   result
 }
 
+pub fn setup_gl_state(gl: &oogl::Context) {
+  gl.set_clear_color(BACKGROUND_COLOR);
+  gl.set_blending_enabled(true);
+  gl.set_blending_factors(oogl::BlendingFactor::SrcAlpha, oogl::BlendingFactor::OneMinusSrcAlpha);
+  gl.set_blending_equation(oogl::BlendingEquation::Add);
+}
+
 struct Game {
   pub globals: SharedGlobals,
 
   #[allow(dead_code)]
   pub sdl_context: sdl2::Sdl,
-  #[allow(dead_code)]
   pub video_subsystem: sdl2::VideoSubsystem,
   #[allow(dead_code)]
+  pub audio_subsystem: sdl2::AudioSubsystem,
+  pub controller_subsystem: Option<sdl2::GameControllerSubsystem>,
+  /// Keyed by the joystick instance IDs. The controllers are closed when
+  /// dropped, so they must be kept around while connected.
+  pub controllers: HashMap<u32, GameController>,
+  #[allow(dead_code)]
   pub sdl_gl_ctx: sdl2::video::GLContext,
   pub window: Window,
   pub event_pump: EventPump,
   pub renderer: Renderer,
-
-  pub pong: Pong,
-  #[cfg(feature = "game_of_life")]
-  pub game_of_life: GameOfLife,
-  #[cfg(feature = "mandelbrot")]
-  pub mandelbrot: Mandelbrot,
-  #[cfg(feature = "marching_squares")]
-  pub marching_squares: MarchingSquares,
+  pub scenes: SceneStack,
+  pub replay_writer: Option<ReplayWriter<BufWriter<File>>>,
+  pub replay_reader: Option<ReplayReader<BufReader<File>>>,
 }
 
 impl Game {
@@ -227,17 +337,62 @@ impl Game {
 
     while !self.globals.should_stop_game_loop.get() {
       let current_time = Instant::now();
-      let delta_time = (current_time - prev_time).as_secs_f64();
+      let mut delta_time = (current_time - prev_time).as_secs_f64();
+
+      let replay_frame = match &mut self.replay_reader {
+        Some(reader) => match reader.read_frame().context("Failed to read the replay")? {
+          Some(frame) => Some(frame),
+          None => {
+            info!("The replay has ended");
+            break;
+          }
+        },
+        None => None,
+      };
+
+      let window_is_focused;
+      if let Some(frame) = &replay_frame {
+        self.process_replay_input(frame);
+        delta_time = frame.delta_time;
+        idling = frame.idle;
+        // The recorded idle flag is used instead.
+        window_is_focused = true;
+      } else {
+        self.process_input();
+        window_is_focused = self.globals.window_is_focused;
+        if window_is_focused {
+          idling = false;
+        }
+      }
+
       {
         let globals = mut_globals(self);
         globals.delta_time = delta_time;
         globals.time += delta_time;
       }
 
-      self.process_input();
-      let window_is_focused = self.globals.window_is_focused;
-      if window_is_focused {
-        idling = false;
+      let (fixed_updates, fixed_update_alpha) = match &replay_frame {
+        Some(frame) => (frame.fixed_updates, frame.fixed_update_alpha),
+        None if idling => (0, self.globals.fixed_update_alpha),
+        None => {
+          let fixed_delta_time = self.globals.fixed_delta_time;
+          let updates =
+            take_fixed_updates(&mut fixed_update_time_accumulator, delta_time, fixed_delta_time);
+          if updates.skipped > 0 {
+            warn!(
+              "The game loop is running behind, skipping {} fixed update(s) ({:.3}s)",
+              updates.skipped,
+              updates.skipped as f64 * fixed_delta_time,
+            );
+          }
+          (updates.count, updates.alpha)
+        }
+      };
+      mut_globals(self).fixed_update_alpha = fixed_update_alpha;
+
+      if let Some(writer) = &mut self.replay_writer {
+        let frame = ReplayFrame::capture(&self.globals, delta_time, fixed_updates, idling);
+        writer.write_frame(&frame).context("Failed to write the replay")?;
       }
 
       if !idling {
@@ -246,21 +401,17 @@ impl Game {
 
         self.early_update().context("Error in early_update")?;
 
-        fixed_update_time_accumulator += delta_time;
         let fixed_delta_time = self.globals.fixed_delta_time;
-        // FIXME: What if a lot of time has passed between frames, e.g. due to the
-        // game being suspended or paused with SIGSTOP (and resumed with SIGCONT)?
-        while fixed_update_time_accumulator >= fixed_delta_time {
+        for _ in 0..fixed_updates {
           mut_globals(self).fixed_time += fixed_delta_time;
           self.fixed_update().context("Error in fixed_update")?;
-          fixed_update_time_accumulator -= fixed_delta_time;
         }
 
         self.update().context("Error in update")?;
 
         self.render().context("Error in render")?;
         self.window.gl_swap_window();
-      } else {
+      } else if replay_frame.is_none() {
         thread::sleep(Duration::from_secs_f64(GAME_LOOP_IDLING_WAIT_INTERVAL));
       }
 
@@ -272,9 +423,38 @@ impl Game {
       prev_time = current_time;
     }
 
+    if let Some(writer) = self.replay_writer.take() {
+      writer.finish().context("Failed to write the replay")?;
+    }
+    self.scenes.clear();
     Ok(())
   }
 
+  /// The real input is ignored while a replay is playing, only the requests to
+  /// close the game are still handled.
+  pub fn process_replay_input(&mut self, frame: &ReplayFrame) {
+    let globals = unsafe { Rc::get_mut_unchecked(&mut self.globals) };
+    let main_window_id = self.window.id();
+
+    for event in self.event_pump.poll_iter() {
+      match event {
+        Event::Quit { .. } | Event::AppTerminating { .. } | Event::AppLowMemory { .. } => {
+          globals.should_stop_game_loop.set(true);
+        }
+
+        Event::Window { window_id, win_event: WindowEvent::Close, .. }
+          if window_id == main_window_id =>
+        {
+          globals.should_stop_game_loop.set(true);
+        }
+
+        _ => {}
+      }
+    }
+
+    frame.apply(globals);
+  }
+
   pub fn process_input(&mut self) {
     let globals = unsafe { Rc::get_mut_unchecked(&mut self.globals) };
     let main_window = &mut self.window;
@@ -293,6 +473,10 @@ impl Game {
 
     globals.input_state.prev_mouse_pos = globals.input_state.mouse_pos;
     globals.input_state.prev_keyboard_state_table = globals.input_state.keyboard_state_table;
+    for gamepad in globals.input_state.gamepads.iter_mut().flatten() {
+      gamepad.prev = gamepad.current.clone();
+    }
+    globals.input_state.text_input.clear_tick();
 
     for event in self.event_pump.poll_iter() {
       match event {
@@ -354,14 +538,38 @@ impl Game {
           }
         }
 
-        Event::KeyDown { window_id, scancode: Some(scancode), .. }
+        Event::KeyDown { window_id, scancode: Some(scancode), keymod, .. }
           if window_id == main_window_id =>
         {
           if let Some(key) = Key::from_sdl2_scancode(scancode) {
             globals.input_state.set_key_down(key, true);
+
+            let text_input = &mut globals.input_state.text_input;
+            // The IME handles the editing keys itself while composing.
+            if text_input.is_active() && !text_input.is_composing() {
+              let ctrl = keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD);
+              if let Some(edit) = TextEdit::from_key(key, ctrl) {
+                text_input.push_edit(edit);
+              }
+            }
           }
         }
 
+        Event::TextInput { window_id, text, .. } if window_id == main_window_id => {
+          let text_input = &mut globals.input_state.text_input;
+          text_input.text.push_str(&text);
+          text_input.composition.clear();
+        }
+
+        Event::TextEditing { window_id, text, start, length, .. }
+          if window_id == main_window_id =>
+        {
+          let text_input = &mut globals.input_state.text_input;
+          text_input.composition = text;
+          text_input.composition_cursor = start.max(0) as usize;
+          text_input.composition_selection_len = length.max(0) as usize;
+        }
+
         Event::KeyUp { window_id, scancode: Some(scancode), .. }
           if window_id == main_window_id =>
         {
@@ -370,6 +578,55 @@ impl Game {
           }
         }
 
+        // NOTE: SDL also sends this event for the controllers which were
+        // already connected when the game started.
+        Event::ControllerDeviceAdded { which: joystick_index, .. } => {
+          let subsystem = match &self.controller_subsystem {
+            Some(subsystem) => subsystem,
+            None => continue,
+          };
+          match subsystem.open(joystick_index) {
+            Ok(controller) => {
+              let instance_id = controller.instance_id();
+              if self.controllers.contains_key(&instance_id) {
+                continue;
+              }
+              let name = controller.name();
+              let slot =
+                globals.input_state.connect_gamepad(GamepadState::new(instance_id, name.clone()));
+              info!("Gamepad {} connected: {}", slot + 1, name);
+              self.controllers.insert(instance_id, controller);
+            }
+            Err(e) => warn!("Failed to open the game controller #{}: {}", joystick_index, e),
+          }
+        }
+
+        Event::ControllerDeviceRemoved { which: instance_id, .. } => {
+          self.controllers.remove(&instance_id);
+          if let Some((slot, gamepad)) = globals.input_state.disconnect_gamepad(instance_id) {
+            info!("Gamepad {} disconnected: {}", slot + 1, gamepad.name);
+          }
+        }
+
+        Event::ControllerButtonDown { which, button, .. }
+        | Event::ControllerButtonUp { which, button, .. } => {
+          let down = matches!(event, Event::ControllerButtonDown { .. });
+          if let (Some(gamepad), Some(button)) = (
+            globals.input_state.gamepad_by_instance_id_mut(which),
+            GamepadButton::from_sdl2(button),
+          ) {
+            gamepad.current.buttons[button as usize] = down;
+          }
+        }
+
+        Event::ControllerAxisMotion { which, axis, value, .. } => {
+          let dead_zone = globals.input_state.gamepad_dead_zone;
+          if let Some(gamepad) = globals.input_state.gamepad_by_instance_id_mut(which) {
+            let axis = GamepadAxis::from_sdl2(axis);
+            gamepad.current.axes[axis as usize] = axis.normalize(value, dead_zone);
+          }
+        }
+
         _ => {}
       }
     }
@@ -379,36 +636,41 @@ impl Game {
   }
 
   pub fn early_update(&mut self) -> AnyResult<()> {
-    if self.globals.input_state.is_key_pressed(Key::B) {
+    if self.globals.input_state.is_action_pressed(actions::DEBUG_BREAKPOINT) {
       breakpoint();
     }
 
-    if self.globals.input_state.is_key_pressed(Key::Q) {
+    if self.globals.input_state.is_action_pressed(actions::QUIT) {
       self.globals.should_stop_game_loop.set(true);
     }
 
-    #[cfg(not(feature = "disable_pong"))]
-    self.pong.early_update();
+    self.scenes.early_update();
 
     Ok(())
   }
 
   pub fn update(&mut self) -> AnyResult<()> {
-    #[cfg(feature = "game_of_life")]
-    self.game_of_life.update();
-
-    #[cfg(feature = "mandelbrot")]
-    self.mandelbrot.update();
+    self.scenes.update()?;
+    if self.scenes.is_empty() {
+      self.globals.should_stop_game_loop.set(true);
+    }
 
-    #[cfg(feature = "marching_squares")]
-    self.marching_squares.update();
+    // NOTE: SDL turns the text input on by default, this also takes care of
+    // switching it off on the first tick.
+    let text_input = self.video_subsystem.text_input();
+    if self.globals.input_state.text_input.is_active() != text_input.is_active() {
+      if self.globals.input_state.text_input.is_active() {
+        text_input.start();
+      } else {
+        text_input.stop();
+      }
+    }
 
     Ok(())
   }
 
   pub fn fixed_update(&mut self) -> AnyResult<()> {
-    #[cfg(not(feature = "disable_pong"))]
-    self.pong.fixed_update();
+    self.scenes.fixed_update();
 
     Ok(())
   }
@@ -420,35 +682,18 @@ impl Game {
     }
     gl.clear(oogl::ClearFlags::COLOR);
 
-    #[cfg(feature = "game_of_life")]
-    {
-      self.game_of_life.render();
-      self.renderer.prepare();
-      self.game_of_life.render_debug_info(&mut self.renderer, &mut self.pong.font);
-      self.renderer.finish();
-    }
-
-    #[cfg(feature = "mandelbrot")]
-    {
-      self.mandelbrot.render();
-      self.renderer.prepare();
-      self.mandelbrot.render_debug_info(&mut self.renderer, &mut self.pong.font);
-      self.renderer.finish();
-    }
+    self.scenes.render(&mut self.renderer);
 
-    #[cfg(feature = "marching_squares")]
-    {
-      self.marching_squares.render();
-      self.renderer.prepare();
-      self.marching_squares.render_debug_info(&mut self.renderer, &mut self.pong.font);
-      self.renderer.finish();
+    let renderer_stats = self.renderer.take_stats();
+    if self.globals.input_state.is_action_pressed(actions::RENDERER_STATS) {
+      info!(
+        "Renderer: {} draw call(s), {} quad(s) in the last frame",
+        renderer_stats.draw_calls, renderer_stats.quads,
+      );
     }
 
-    #[cfg(not(feature = "disable_pong"))]
-    self.pong.render(&mut self.renderer);
-
     #[cfg(feature = "screenshot")]
-    if self.globals.input_state.is_key_pressed(Key::F8) {
+    if self.globals.input_state.is_action_pressed(actions::SCREENSHOT) {
       self.screenshot().context("Failed to take a screenshot")?;
     }
 
@@ -468,36 +713,7 @@ impl Game {
       pixels.len()
     );
     self.globals.gl.read_pixels_rgba(size, &mut pixels);
-    flip_rgba_image_data_vertically(size, &mut pixels);
-
-    let file =
-      File::create(path).with_context(|| format!("Failed to create file '{}'", path.display()))?;
-    let writer = BufWriter::new(file);
-
-    let mut encoder = png::Encoder::new(writer, size.x, size.y);
-    encoder.set_color(png::ColorType::Rgba);
-    encoder.set_depth(png::BitDepth::Eight);
-    let mut writer = encoder.write_header().context("Failed to write the PNG header")?;
-    writer.write_image_data(&pixels).context("Failed to write the encoded PNG data")?;
-
-    Ok(())
-  }
-}
-
-#[cfg(feature = "screenshot")]
-fn flip_rgba_image_data_vertically(size: Vec2u32, pixels: &mut [u8]) {
-  assert!(pixels.len() == size.x as usize * size.y as usize * 4);
-
-  let row_len = size.x as usize * 4;
-  let (mut half1, mut half2) = pixels.split_at_mut(row_len * (size.y / 2) as usize);
-  for _ in 0..size.y / 2 {
-    // len needs to be saved because using the length in slicing expressions
-    // makes the borrow checker unhappy
-    let half2_len = half2.len();
-    let row1 = &mut half1[..row_len];
-    let row2 = &mut half2[half2_len - row_len..];
-    row1.swap_with_slice(row2);
-    half1 = &mut half1[row_len..];
-    half2 = &mut half2[..half2_len - row_len];
+    image_utils::flip_rgba_image_data_vertically(size, &mut pixels);
+    image_utils::write_rgba_png(path, size, &pixels)
   }
 }
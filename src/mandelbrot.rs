@@ -7,9 +7,10 @@ use cardboard_oogl as oogl;
 use cardboard_oogl::traits::*;
 use prelude_plus::*;
 
+use crate::bindings::{actions, axes};
 use crate::globals::SharedGlobals;
-use crate::input::Key;
 use crate::renderer;
+use crate::scene::{Scene, SceneCommand};
 
 const CAMERA_ZOOM_SPEED: f64 = 2.4;
 const CAMERA_UPDATE_COOLDOWN: f64 = 1.0;
@@ -40,6 +41,7 @@ pub struct Mandelbrot {
   texture: oogl::Texture2D,
   program: oogl::Program,
   program_reflection: ProgramReflection,
+  font: renderer::Font,
 
   camera_pos: Vec2f64,
   camera_zoom: f64,
@@ -53,8 +55,8 @@ pub struct Mandelbrot {
   workers: threadpool::ThreadPool,
 }
 
-impl Mandelbrot {
-  pub fn init(globals: SharedGlobals) -> AnyResult<Self> {
+impl Scene for Mandelbrot {
+  fn init(globals: SharedGlobals) -> AnyResult<Self> {
     use oogl::ShaderType as ShTy;
     let vertex_shader =
       renderer::load_shader_asset(&globals, "shaders/mandelbrot.vert.glsl", ShTy::Vertex)?;
@@ -97,12 +99,15 @@ impl Mandelbrot {
 
     let workers = threadpool::Builder::new().thread_name("Mandelbrot.workers".to_owned()).build();
 
+    let font = renderer::load_default_font(&globals)?;
+
     let mut myself = Self {
       globals,
       vertex_buf,
       texture: tex,
       program,
       program_reflection,
+      font,
 
       camera_pos: vec2n(0.0),
       camera_zoom: 0.0,
@@ -120,25 +125,13 @@ impl Mandelbrot {
     Ok(myself)
   }
 
-  fn reset_view(&mut self) {
-    self.camera_pos = vec2n(0.0);
-    self.camera_zoom = 1.0;
-    self.starting_point = vec2n(0.0);
-    self.mark_dirty();
-  }
-
-  fn mark_dirty(&mut self) {
-    self.is_camera_dirty = true;
-    self.camera_update_timer = CAMERA_UPDATE_COOLDOWN;
-  }
-
-  pub fn update(&mut self) {
+  fn update(&mut self) -> SceneCommand {
     self.camera_update_timer = (self.camera_update_timer - self.globals.delta_time).max(0.0);
 
     let mouse_pos = Vec2f64::cast_from(self.globals.input_state.mouse_pos);
     let delta_mouse_pos = Vec2f64::cast_from(self.globals.input_state.delta_mouse_pos);
 
-    let zoom_axis = self.globals.input_state.axis(Key::Minus, Key::Equals);
+    let zoom_axis = self.globals.input_state.action_axis(axes::ZOOM);
     if zoom_axis != 0 {
       let zoom_factor = 1.0 + zoom_axis.abs() as f64 * self.globals.delta_time * CAMERA_ZOOM_SPEED;
       let mut new_camera_zoom = self.camera_zoom;
@@ -154,17 +147,17 @@ impl Mandelbrot {
       self.mark_dirty();
     }
 
-    if self.globals.input_state.is_key_down(Key::MouseLeft) {
+    if self.globals.input_state.is_action_down(actions::CAMERA_DRAG) {
       self.camera_pos -= delta_mouse_pos / self.camera_zoom;
       self.mark_dirty();
     }
 
-    if self.globals.input_state.is_key_down(Key::MouseRight) {
+    if self.globals.input_state.is_action_down(actions::PICK_STARTING_POINT) {
       self.starting_point = mouse_pos / self.camera_zoom + self.camera_pos;
       self.mark_dirty();
     }
 
-    if self.globals.input_state.is_key_pressed(Key::J) {
+    if self.globals.input_state.is_action_pressed(actions::TOGGLE_JULIA_MODE) {
       self.is_julia_mode = !self.is_julia_mode;
       self.mark_dirty();
     }
@@ -173,12 +166,28 @@ impl Mandelbrot {
       self.mark_dirty();
     }
 
-    if self.globals.input_state.is_key_pressed(Key::R) {
+    if self.globals.input_state.is_action_pressed(actions::RESET_VIEW) {
       self.reset_view();
     }
+
+    SceneCommand::None
+  }
+
+  fn render(&mut self, renderer: &mut renderer::Renderer) {
+    self.render_fractal();
+    renderer.prepare();
+    self.render_debug_info(renderer);
+    renderer.finish();
   }
 
-  pub fn render(&mut self) {
+  fn shutdown(&mut self) {
+    // The pool doesn't wait for the queued jobs when it is dropped.
+    self.workers.join();
+  }
+}
+
+impl Mandelbrot {
+  fn render_fractal(&mut self) {
     let bound_program = self.program.bind();
     let reflection = &self.program_reflection;
     reflection.u_camera_pos.set(&bound_program, &Vec2f::cast_from(self.camera_pos));
@@ -291,6 +300,18 @@ impl Mandelbrot {
     bound_vertex_buf.draw(&bound_program, oogl::DrawPrimitive::TriangleStrip);
   }
 
+  fn reset_view(&mut self) {
+    self.camera_pos = vec2n(0.0);
+    self.camera_zoom = 1.0;
+    self.starting_point = vec2n(0.0);
+    self.mark_dirty();
+  }
+
+  fn mark_dirty(&mut self) {
+    self.is_camera_dirty = true;
+    self.camera_update_timer = CAMERA_UPDATE_COOLDOWN;
+  }
+
   fn calculate_chunks(tex_size: Vec2u32, count: usize) -> Vec<usize> {
     let tex_pixels = tex_size.x as usize * tex_size.y as usize;
     let mut chunks = Vec::with_capacity(count);
@@ -306,11 +327,7 @@ impl Mandelbrot {
     chunks
   }
 
-  pub fn render_debug_info(
-    &mut self,
-    renderer: &mut renderer::Renderer,
-    font: &mut renderer::Font,
-  ) {
+  fn render_debug_info(&mut self, renderer: &mut renderer::Renderer) {
     let mut text_block_offset = Vec2f::ZERO;
     for &text in &[
       format!("   pos: {:?} {:?}", self.camera_pos.x, self.camera_pos.y).as_str(),
@@ -324,9 +341,9 @@ impl Mandelbrot {
         horizontal_align: renderer::TextAlign::Start,
         vertical_align: renderer::TextAlign::Start,
       };
-      let (text_block_size, char_size) = font.measure_size(text_block);
+      let (text_block_size, char_size) = self.font.measure_size(text_block);
       let pos = (self.globals.window_size - char_size * 0.5) * vec2(-0.5, 0.5) - text_block_offset;
-      renderer.draw_text(font, pos, text_block);
+      renderer.draw_text(&mut self.font, pos, text_block);
       text_block_offset.y += text_block_size.y;
     }
   }
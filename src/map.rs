@@ -0,0 +1,452 @@
+//! Typed models of the CrossCode map files (`assets/data/maps/**/*.json`).
+//! Only the parts which are understood by the engine are modeled, everything
+//! else ends up in the `unknown_fields` maps and is reported by
+//! [`Map::warnings`], so that the new fields don't get silently lost.
+
+use cardboard_math::*;
+use prelude_plus::*;
+use serde::Deserialize;
+use serde_json as json;
+
+use crate::globals::Globals;
+
+pub type UnknownFields = BTreeMap<String, json::Value>;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Map {
+  pub name: String,
+  pub levels: Vec<MapLevel>,
+  /// In tiles.
+  pub map_width: u32,
+  /// In tiles.
+  pub map_height: u32,
+  #[serde(default)]
+  pub master_level: u32,
+  /// Map-wide settings (music, weather, area and such), they are specific to
+  /// the game logic, so they are kept untyped.
+  #[serde(default)]
+  pub attributes: json::Map<String, json::Value>,
+  #[serde(default)]
+  pub screen: Vec2f32,
+  #[serde(default)]
+  pub entities: Vec<MapEntity>,
+  #[serde(rename = "layer")]
+  pub layers: Vec<MapLayer>,
+  #[serde(flatten)]
+  pub unknown_fields: UnknownFields,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MapLevel {
+  /// In pixels, relative to the ground.
+  pub height: f32,
+  #[serde(flatten)]
+  pub unknown_fields: UnknownFields,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MapEntity {
+  #[serde(rename = "type")]
+  pub type_: String,
+  /// In pixels.
+  pub x: f32,
+  /// In pixels.
+  pub y: f32,
+  pub level: MapEntityLevel,
+  /// Depend on the entity type, so are kept untyped.
+  #[serde(default)]
+  pub settings: json::Map<String, json::Value>,
+  #[serde(flatten)]
+  pub unknown_fields: UnknownFields,
+}
+
+/// The level is written either as a plain number or as an object with an
+/// additional vertical offset.
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+#[serde(from = "RawMapEntityLevel")]
+pub struct MapEntityLevel {
+  pub level: u32,
+  /// In pixels.
+  pub offset: f32,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawMapEntityLevel {
+  Index(u32),
+  WithOffset {
+    level: u32,
+    #[serde(default)]
+    offset: f32,
+  },
+}
+
+impl From<RawMapEntityLevel> for MapEntityLevel {
+  fn from(raw: RawMapEntityLevel) -> Self {
+    match raw {
+      RawMapEntityLevel::Index(level) => Self { level, offset: 0.0 },
+      RawMapEntityLevel::WithOffset { level, offset } => Self { level, offset },
+    }
+  }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MapLayer {
+  pub id: u32,
+  #[serde(rename = "type")]
+  pub type_: MapLayerType,
+  pub name: String,
+  pub level: MapLayerLevel,
+  /// In tiles.
+  pub width: u32,
+  /// In tiles.
+  pub height: u32,
+  /// Is empty for the layers which don't have a tileset, e.g. collision ones.
+  #[serde(default)]
+  pub tileset_name: String,
+  /// In pixels.
+  pub tilesize: u32,
+  #[serde(default = "default_true", deserialize_with = "deserialize_loose_bool")]
+  pub visible: bool,
+  #[serde(default, deserialize_with = "deserialize_loose_bool")]
+  pub repeat: bool,
+  /// Parallax factor, 1 means that the layer moves together with the camera.
+  #[serde(default = "default_distance")]
+  pub distance: f32,
+  #[serde(default)]
+  pub y_distance: f32,
+  #[serde(default)]
+  pub move_speed: Vec2f32,
+  #[serde(default, deserialize_with = "deserialize_loose_bool")]
+  pub lighter: bool,
+  /// Rows of tile indexes, `data[y][x]`. For the tile layers 0 means an empty
+  /// tile and the rest are 1-based indexes into the tileset, for the other
+  /// layer types the meaning depends on the type.
+  pub data: Vec<Vec<u16>>,
+  #[serde(flatten)]
+  pub unknown_fields: UnknownFields,
+}
+
+impl MapLayer {
+  pub fn tileset(&self) -> Option<&str> {
+    if self.tileset_name.is_empty() {
+      None
+    } else {
+      Some(&self.tileset_name)
+    }
+  }
+
+  #[inline]
+  pub fn tile(&self, pos: Vec2u32) -> u16 { self.data[pos.y as usize][pos.x as usize] }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
+#[serde(from = "String")]
+pub enum MapLayerType {
+  Background,
+  Collision,
+  Navigation,
+  HeightMap,
+  Other(String),
+}
+
+impl From<String> for MapLayerType {
+  fn from(s: String) -> Self {
+    match s.as_str() {
+      "Background" => Self::Background,
+      "Collision" => Self::Collision,
+      "Navigation" => Self::Navigation,
+      "HeightMap" => Self::HeightMap,
+      _ => Self::Other(s),
+    }
+  }
+}
+
+/// The tile layers may be placed either on one of the height levels or on one
+/// of the special named ones (`first`, `last`, `light`, `postlight`,
+/// `object1`...) which are drawn over or under everything else.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
+#[serde(untagged)]
+pub enum MapLayerLevel {
+  Index(u32),
+  Named(String),
+}
+
+fn default_true() -> bool { true }
+fn default_distance() -> f32 { 1.0 }
+
+/// The game writes some of the flags as 0 and 1 instead of the proper booleans.
+fn deserialize_loose_bool<'de, D: serde::Deserializer<'de>>(
+  deserializer: D,
+) -> Result<bool, D::Error> {
+  #[derive(Deserialize)]
+  #[serde(untagged)]
+  enum LooseBool {
+    Bool(bool),
+    Int(i64),
+  }
+
+  Ok(match LooseBool::deserialize(deserializer)? {
+    LooseBool::Bool(b) => b,
+    LooseBool::Int(i) => i != 0,
+  })
+}
+
+impl Map {
+  pub fn from_slice(bytes: &[u8]) -> AnyResult<Self> {
+    let map: Self = json::from_slice(bytes).context("Failed to parse the map")?;
+    map.validate()?;
+    Ok(map)
+  }
+
+  /// Checks the invariants which are assumed by the rest of the engine and
+  /// can't be expressed with the types alone.
+  pub fn validate(&self) -> AnyResult<()> {
+    for (i, layer) in self.layers.iter().enumerate() {
+      let context = || format!("Invalid layer #{} '{}'", i, layer.name);
+
+      if layer.tilesize == 0 {
+        return Err(format_err!("tile size must not be zero")).with_context(context);
+      }
+      if layer.distance.is_nan() || layer.distance <= 0.0 {
+        return Err(format_err!("distance must be positive, got {}", layer.distance))
+          .with_context(context);
+      }
+
+      if layer.data.len() != layer.height as usize {
+        return Err(format_err!(
+          "expected {} rows of tiles, got {}",
+          layer.height,
+          layer.data.len(),
+        ))
+        .with_context(context);
+      }
+      for (y, row) in layer.data.iter().enumerate() {
+        if row.len() != layer.width as usize {
+          return Err(format_err!(
+            "expected {} tiles in row {}, got {}",
+            layer.width,
+            y,
+            row.len()
+          ))
+          .with_context(context);
+        }
+      }
+
+      if let MapLayerLevel::Index(level) = layer.level {
+        if level as usize >= self.levels.len() {
+          return Err(format_err!(
+            "level {} is out of bounds, the map has {} level(s)",
+            level,
+            self.levels.len(),
+          ))
+          .with_context(context);
+        }
+      }
+    }
+
+    for (i, entity) in self.entities.iter().enumerate() {
+      if entity.level.level as usize >= self.levels.len() {
+        bail!(
+          "Invalid entity #{} '{}': level {} is out of bounds, the map has {} level(s)",
+          i,
+          entity.type_,
+          entity.level.level,
+          self.levels.len(),
+        );
+      }
+    }
+
+    Ok(())
+  }
+
+  /// Lists the fields and values which were not recognized while parsing, as
+  /// human-readable messages with JSON paths.
+  pub fn warnings(&self) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    fn push_unknown_fields(warnings: &mut Vec<String>, prefix: &str, fields: &UnknownFields) {
+      for name in fields.keys() {
+        warnings.push(format!("unknown field '{}{}'", prefix, name));
+      }
+    }
+
+    push_unknown_fields(&mut warnings, "", &self.unknown_fields);
+    for (i, level) in self.levels.iter().enumerate() {
+      push_unknown_fields(&mut warnings, &format!("levels[{}].", i), &level.unknown_fields);
+    }
+    for (i, entity) in self.entities.iter().enumerate() {
+      push_unknown_fields(&mut warnings, &format!("entities[{}].", i), &entity.unknown_fields);
+    }
+    for (i, layer) in self.layers.iter().enumerate() {
+      push_unknown_fields(&mut warnings, &format!("layer[{}].", i), &layer.unknown_fields);
+      if let MapLayerType::Other(type_) = &layer.type_ {
+        warnings.push(format!("unknown layer type '{}' in 'layer[{}].type'", type_, i));
+      }
+    }
+
+    warnings
+  }
+
+  #[inline]
+  pub fn size(&self) -> Vec2u32 { vec2(self.map_width, self.map_height) }
+
+  /// Returns the unique tileset paths referenced by the layers, in the order of
+  /// their first appearance.
+  pub fn tilesets(&self) -> Vec<&str> {
+    let mut tilesets = Vec::new();
+    for tileset in self.layers.iter().filter_map(|layer| layer.tileset()) {
+      if !tilesets.contains(&tileset) {
+        tilesets.push(tileset);
+      }
+    }
+    tilesets
+  }
+}
+
+pub fn load_map_asset(globals: &Globals, path: &str) -> AnyResult<Map> {
+  let bytes = globals.game_fs.read_binary_file(&path)?;
+  let map = Map::from_slice(&bytes).with_context(|| format!("Failed to load map '{}'", path))?;
+  for warning in map.warnings() {
+    warn!("{}: {}", path, warning);
+  }
+  Ok(map)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn parse_fixture(bytes: &[u8]) -> Map { json::from_slice(bytes).unwrap() }
+
+  fn validation_error(map: &Map) -> String { format!("{:#}", map.validate().unwrap_err()) }
+
+  #[test]
+  fn parses_a_minimal_map() {
+    let map = Map::from_slice(include_bytes!("../tests/maps/minimal.json")).unwrap();
+    assert_eq!(map.name, "test/minimal");
+    assert_eq!(map.size(), vec2(4, 3));
+    assert_eq!(map.levels.len(), 1);
+    assert!(map.entities.is_empty());
+    assert_eq!(map.tilesets(), ["media/map/test.png"]);
+    assert!(map.warnings().is_empty());
+
+    let layer = &map.layers[0];
+    assert_eq!(layer.type_, MapLayerType::Background);
+    assert_eq!(layer.level, MapLayerLevel::Index(0));
+    assert!(layer.visible);
+    assert!(!layer.repeat);
+    assert_eq!(layer.distance, 1.0);
+    assert_eq!(layer.tile(vec2(0, 0)), 1);
+    assert_eq!(layer.tile(vec2(3, 2)), 9);
+  }
+
+  #[test]
+  fn parses_levels_entities_and_layer_flags() {
+    let map = Map::from_slice(include_bytes!("../tests/maps/levels.json")).unwrap();
+    assert_eq!(
+      map.levels.iter().map(|level| level.height).collect::<Vec<_>>(),
+      [-32.0, 0.0, 48.0]
+    );
+    assert_eq!(map.master_level, 1);
+    assert_eq!(map.screen, vec2(16.0, 8.0));
+    assert_eq!(map.attributes["mapStyle"], "test");
+    assert!(map.warnings().is_empty());
+
+    assert_eq!(map.entities[0].type_, "Chest");
+    assert_eq!(map.entities[0].level, MapEntityLevel { level: 1, offset: 0.0 });
+    assert_eq!(map.entities[1].x, 32.5);
+    assert_eq!(map.entities[1].level, MapEntityLevel { level: 2, offset: 8.0 });
+
+    let types = map.layers.iter().map(|layer| layer.type_.clone()).collect::<Vec<_>>();
+    assert_eq!(
+      types,
+      [
+        MapLayerType::Background,
+        MapLayerType::Background,
+        MapLayerType::Collision,
+        MapLayerType::Navigation,
+      ]
+    );
+    let sky = &map.layers[1];
+    assert_eq!(sky.level, MapLayerLevel::Named("first".to_owned()));
+    assert!(!sky.visible);
+    assert!(sky.repeat);
+    assert!(sky.lighter);
+    assert_eq!(sky.distance, 2.0);
+    assert_eq!(sky.y_distance, 0.5);
+    assert_eq!(sky.move_speed, vec2(4.0, 0.0));
+    assert_eq!(map.layers[2].tileset(), None);
+    assert_eq!(map.tilesets(), ["media/map/test.png", "media/map/sky.png"]);
+  }
+
+  #[test]
+  fn reports_unknown_fields() {
+    let map = Map::from_slice(include_bytes!("../tests/maps/unknown-fields.json")).unwrap();
+    assert_eq!(
+      map.warnings(),
+      [
+        "unknown field 'editorVersion'",
+        "unknown field 'levels[0].color'",
+        "unknown field 'entities[0].locked'",
+        "unknown field 'layer[0].holeTile'",
+        "unknown layer type 'Lighting' in 'layer[0].type'",
+      ]
+    );
+  }
+
+  #[test]
+  fn rejects_layers_with_invalid_sizes() {
+    let bytes = include_bytes!("../tests/maps/invalid-size.json");
+    assert!(Map::from_slice(bytes).is_err());
+    let map = parse_fixture(bytes);
+    assert_eq!(
+      validation_error(&map),
+      "Invalid layer #0 'ground': expected 2 tiles in row 1, got 1",
+    );
+
+    let mut map = parse_fixture(include_bytes!("../tests/maps/minimal.json"));
+    map.layers[0].data.pop();
+    assert_eq!(
+      validation_error(&map),
+      "Invalid layer #0 'ground': expected 3 rows of tiles, got 2",
+    );
+  }
+
+  #[test]
+  fn rejects_out_of_range_levels() {
+    let mut map = parse_fixture(include_bytes!("../tests/maps/levels.json"));
+    map.layers[2].level = MapLayerLevel::Index(3);
+    assert_eq!(
+      validation_error(&map),
+      "Invalid layer #2 'collision': level 3 is out of bounds, the map has 3 level(s)",
+    );
+
+    let mut map = parse_fixture(include_bytes!("../tests/maps/levels.json"));
+    map.entities[1].level.level = 5;
+    assert_eq!(
+      validation_error(&map),
+      "Invalid entity #1 'Prop': level 5 is out of bounds, the map has 3 level(s)",
+    );
+  }
+
+  #[test]
+  fn rejects_zero_tile_sizes_and_non_positive_distances() {
+    let mut map = parse_fixture(include_bytes!("../tests/maps/minimal.json"));
+    map.layers[0].tilesize = 0;
+    assert_eq!(validation_error(&map), "Invalid layer #0 'ground': tile size must not be zero");
+
+    for distance in [0.0, -1.0, f32::NAN] {
+      let mut map = parse_fixture(include_bytes!("../tests/maps/minimal.json"));
+      map.layers[0].distance = distance;
+      assert_eq!(
+        validation_error(&map),
+        format!("Invalid layer #0 'ground': distance must be positive, got {}", distance),
+      );
+    }
+  }
+}
@@ -5,10 +5,11 @@ use cardboard_oogl as oogl;
 use cardboard_oogl::traits::*;
 use prelude_plus::*;
 
+use crate::bindings::axes;
 use crate::globals::SharedGlobals;
-use crate::input::Key;
 use crate::profiling::AverageTimeSampler;
 use crate::renderer::*;
+use crate::scene::{Scene, SceneCommand};
 
 const GRID_SIZE: Vec2u32 = vec2(128, 64);
 // const GRID_SIZE: Vec2u32 = vec2(64, 32);
@@ -30,6 +31,7 @@ pub struct MarchingSquares {
   ebo: oogl::ElementBuffer<u16>,
   program: oogl::Program,
   program_reflection: ProgramReflection,
+  font: Font,
 
   prev_grid_size: Vec2u32,
   grid_size: Vec2u32,
@@ -44,8 +46,8 @@ pub struct MarchingSquares {
   field_function: Box<dyn FieldFunction>,
 }
 
-impl MarchingSquares {
-  pub fn init(globals: SharedGlobals) -> AnyResult<Self> {
+impl Scene for MarchingSquares {
+  fn init(globals: SharedGlobals) -> AnyResult<Self> {
     use oogl::ShaderType;
     let vertex_shader =
       load_shader_asset(&globals, "shaders/marching_squares.vert.glsl", ShaderType::Vertex)?;
@@ -73,12 +75,15 @@ impl MarchingSquares {
 
     let field_function = Box::new(TestFieldFunction::new(globals.share()));
 
+    let font = load_default_font(&globals)?;
+
     Ok(Self {
       globals,
       vbo,
       ebo,
       program,
       program_reflection,
+      font,
 
       prev_grid_size: vec2n(0),
       grid_size,
@@ -94,6 +99,48 @@ impl MarchingSquares {
     })
   }
 
+  fn update(&mut self) -> SceneCommand {
+    self.isovalue += 0.25
+      * self.globals.input_state.action_axis(axes::ISOVALUE) as f32
+      * self.globals.delta_time as f32;
+
+    SceneCommand::None
+  }
+
+  fn render(&mut self, renderer: &mut Renderer) {
+    self.render_mesh();
+    renderer.prepare();
+    self.render_debug_info(renderer);
+    renderer.finish();
+  }
+}
+
+impl MarchingSquares {
+  fn render_mesh(&mut self) {
+    self.rebuild_mesh();
+
+    let bound_program = self.program.bind();
+    let reflection = &self.program_reflection;
+    reflection.u_grid_size.set(&bound_program, &Vec2f::cast_from(self.grid_size));
+    reflection.u_global_color.set(&bound_program, &colorn(1.0, OPACITY));
+
+    {
+      let bound_vbo = self.vbo.bind();
+      bound_vbo.enable_attribs();
+      bound_vbo.configure_attribs();
+      let bound_ebo = self.ebo.bind();
+
+      copy_data_from_vec_into_buffer(&bound_vbo, &self.mesh_vertices);
+      copy_data_from_vec_into_buffer(&bound_ebo, &self.mesh_indices);
+
+      let indices_count = self.mesh_indices.len();
+      bound_ebo.draw_slice(&bound_program, oogl::DrawPrimitive::Points, ..indices_count);
+      bound_ebo.draw_slice(&bound_program, oogl::DrawPrimitive::Triangles, ..indices_count);
+
+      bound_vbo.disable_attribs();
+    }
+  }
+
   fn rebuild_mesh(&mut self) {
     let start_time = Instant::now();
     let grid_size = self.grid_size;
@@ -219,38 +266,7 @@ impl MarchingSquares {
     }
   }
 
-  pub fn update(&mut self) {
-    self.isovalue += 0.25
-      * self.globals.input_state.axis(Key::Minus, Key::Equals) as f32
-      * self.globals.delta_time as f32;
-  }
-
-  pub fn render(&mut self) {
-    self.rebuild_mesh();
-
-    let bound_program = self.program.bind();
-    let reflection = &self.program_reflection;
-    reflection.u_grid_size.set(&bound_program, &Vec2f::cast_from(self.grid_size));
-    reflection.u_global_color.set(&bound_program, &colorn(1.0, OPACITY));
-
-    {
-      let bound_vbo = self.vbo.bind();
-      bound_vbo.enable_attribs();
-      bound_vbo.configure_attribs();
-      let bound_ebo = self.ebo.bind();
-
-      copy_data_from_vec_into_buffer(&bound_vbo, &self.mesh_vertices);
-      copy_data_from_vec_into_buffer(&bound_ebo, &self.mesh_indices);
-
-      let indices_count = self.mesh_indices.len();
-      bound_ebo.draw_slice(&bound_program, oogl::DrawPrimitive::Points, ..indices_count);
-      bound_ebo.draw_slice(&bound_program, oogl::DrawPrimitive::Triangles, ..indices_count);
-
-      bound_vbo.disable_attribs();
-    }
-  }
-
-  pub fn render_debug_info(&mut self, renderer: &mut Renderer, font: &mut Font) {
+  fn render_debug_info(&mut self, renderer: &mut Renderer) {
     let vertices = &self.mesh_vertices;
     let indices = &self.mesh_indices;
 
@@ -270,9 +286,9 @@ impl MarchingSquares {
         horizontal_align: TextAlign::Start,
         vertical_align: TextAlign::Start,
       };
-      let (text_block_size, char_size) = font.measure_size(text_block);
+      let (text_block_size, char_size) = self.font.measure_size(text_block);
       let pos = (self.globals.window_size - char_size * 0.5) * vec2(-0.5, 0.5) - text_block_offset;
-      renderer.draw_text(font, pos, text_block);
+      renderer.draw_text(&mut self.font, pos, text_block);
       text_block_offset.y += text_block_size.y;
     }
   }
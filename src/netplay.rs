@@ -0,0 +1,737 @@
+//! Peer-to-peer rollback netcode for two players. Each peer runs the whole
+//! deterministic simulation on its own and only the inputs are exchanged. The
+//! local input is applied after a small delay, and the remote one is predicted
+//! (by repeating the last confirmed one) until it arrives. When the prediction
+//! turns out to be wrong the game is rewound to the first mispredicted frame
+//! and simulated again up to the current one. The peers also exchange the
+//! checksums of the states which can no longer change, so that a desync gets
+//! noticed right away instead of showing up as a different score later.
+//!
+//! The packets may be lost, duplicated or reordered. Every packet carries all
+//! of the local inputs which the peer hasn't acknowledged yet, so a lost one is
+//! simply covered by the next ones.
+
+use cardboard_math::*;
+use cardboard_utils::io::read_array;
+use prelude_plus::*;
+use std::net::{SocketAddr, UdpSocket};
+
+use crate::globals::Random;
+
+/// The input of a single player on a single frame.
+pub type NetInput = i8;
+
+/// The remote peer can't fall behind by more than this many frames, the local
+/// simulation is stalled until it catches up.
+pub const MAX_PREDICTION_FRAMES: u32 = 30;
+/// How often the checksums of the confirmed states are compared, in frames.
+pub const CHECKSUM_INTERVAL: u32 = 60;
+const MAX_KEPT_CHECKSUMS: usize = 64;
+
+const PACKET_MAGIC: &[u8; 4] = b"OKNP";
+const MAX_INPUTS_PER_PACKET: usize = u8::MAX as usize;
+pub const MAX_PACKET_SIZE: usize = 4 + 4 + 4 + 1 + MAX_INPUTS_PER_PACKET + 1 + 4 + 8;
+
+/// A deterministic simulation which can be rewound.
+pub trait RollbackGame {
+  type State: fmt::Debug;
+
+  fn save_state(&self) -> Self::State;
+  fn load_state(&mut self, state: &Self::State);
+  fn state_checksum(state: &Self::State) -> u64;
+  /// Simulates a single frame, the inputs are ordered by the player index.
+  fn advance(&mut self, inputs: [NetInput; 2]);
+}
+
+/// An unreliable channel to the other peer.
+pub trait Transport: fmt::Debug {
+  fn send(&mut self, packet: &[u8]) -> io::Result<()>;
+  /// Returns `None` when there are no more packets waiting.
+  fn recv(&mut self, buf: &mut [u8]) -> io::Result<Option<usize>>;
+}
+
+/// FNV-1a, unlike the std `Hasher`s its results are guaranteed to be the same
+/// on every platform and with every version of Rust.
+#[derive(Debug, Clone)]
+pub struct Checksum(u64);
+
+impl Default for Checksum {
+  fn default() -> Self { Self(0xcbf29ce484222325) }
+}
+
+impl Checksum {
+  pub fn new() -> Self { Self::default() }
+
+  pub fn write(&mut self, bytes: &[u8]) {
+    for &byte in bytes {
+      self.0 = (self.0 ^ byte as u64).wrapping_mul(0x00000100000001b3);
+    }
+  }
+
+  pub fn write_u64(&mut self, value: u64) { self.write(&value.to_le_bytes()); }
+  /// Prefixed with the length, so that the consecutive strings can't be
+  /// mistaken for each other when split differently.
+  pub fn write_str(&mut self, value: &str) {
+    self.write_u64(value.len() as u64);
+    self.write(value.as_bytes());
+  }
+  pub fn write_u128(&mut self, value: u128) { self.write(&value.to_le_bytes()); }
+  pub fn write_f32(&mut self, value: f32) { self.write(&value.to_bits().to_le_bytes()); }
+  pub fn write_vec2(&mut self, value: Vec2f) {
+    self.write_f32(value.x);
+    self.write_f32(value.y);
+  }
+
+  #[inline]
+  pub fn finish(&self) -> u64 { self.0 }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Packet {
+  /// How many of the receiver's inputs the sender has got, in a row from the
+  /// first frame.
+  ack: u32,
+  first_frame: u32,
+  inputs: Vec<NetInput>,
+  /// Of the state at the start of a frame which can no longer be rolled back.
+  checksum: Option<(u32, u64)>,
+}
+
+impl Packet {
+  fn encode(&self, out: &mut Vec<u8>) {
+    debug_assert!(self.inputs.len() <= MAX_INPUTS_PER_PACKET);
+    out.extend_from_slice(PACKET_MAGIC);
+    out.extend_from_slice(&self.ack.to_le_bytes());
+    out.extend_from_slice(&self.first_frame.to_le_bytes());
+    out.push(self.inputs.len() as u8);
+    out.extend(self.inputs.iter().map(|&input| input as u8));
+    match self.checksum {
+      Some((frame, checksum)) => {
+        out.push(1);
+        out.extend_from_slice(&frame.to_le_bytes());
+        out.extend_from_slice(&checksum.to_le_bytes());
+      }
+      None => out.push(0),
+    }
+  }
+
+  fn decode(mut bytes: &[u8]) -> AnyResult<Self> {
+    let r = &mut bytes;
+    ensure!(&read_array::<_, 4>(r)? == PACKET_MAGIC, "Not a netplay packet");
+    let ack = u32::from_le_bytes(read_array(r)?);
+    let first_frame = u32::from_le_bytes(read_array(r)?);
+    let [inputs_count] = read_array(r)?;
+    let mut inputs = vec![0; inputs_count as usize];
+    for input in &mut inputs {
+      let [byte] = read_array(r)?;
+      *input = byte as NetInput;
+    }
+    first_frame.checked_add(inputs_count as u32).context("The frame numbers overflow")?;
+    let checksum = match read_array(r)? {
+      [0] => None,
+      [1] => {
+        let frame = u32::from_le_bytes(read_array(r)?);
+        Some((frame, u64::from_le_bytes(read_array(r)?)))
+      }
+      [flag] => bail!("Invalid checksum flag {}", flag),
+    };
+    ensure!(r.is_empty(), "{} trailing byte(s)", r.len());
+    Ok(Self { ack, first_frame, inputs, checksum })
+  }
+}
+
+#[derive(Debug, Default, Copy, Clone)]
+pub struct SessionStats {
+  pub rollbacks: u32,
+  pub resimulated_frames: u32,
+  /// The ticks on which the session had to wait for the remote peer.
+  pub stalled_ticks: u32,
+  pub packets_sent: u32,
+  pub packets_received: u32,
+  pub checksums_compared: u32,
+}
+
+#[derive(Debug)]
+pub struct RollbackSession<G: RollbackGame, T: Transport> {
+  transport: T,
+  local_player: usize,
+  current_frame: u32,
+  /// The frame of the first input in each of `local_inputs`, `remote_inputs`
+  /// and `used_remote_inputs`, the older ones are no longer needed.
+  inputs_start: u32,
+  /// Already contains the inputs for `input_delay` frames past the current one.
+  local_inputs: Vec<NetInput>,
+  remote_inputs: Vec<Option<NetInput>>,
+  /// The number of the remote inputs received in a row from the first frame.
+  confirmed_frames: u32,
+  /// The remote inputs which the frames before the current one have been
+  /// simulated with, either the received or the predicted ones.
+  used_remote_inputs: Vec<NetInput>,
+  /// The states at the start of every frame from `saved_states_start` up to
+  /// the current one, i.e. from the first one which can still be rolled back.
+  saved_states: VecDeque<G::State>,
+  saved_states_start: u32,
+  /// The number of the local inputs the remote peer has acknowledged.
+  remote_ack: u32,
+  local_checksums: BTreeMap<u32, u64>,
+  remote_checksums: BTreeMap<u32, u64>,
+  /// The peer keeps sending its latest checksum until it has a newer one.
+  last_compared_frame: Option<u32>,
+  stats: SessionStats,
+  packet_buf: Vec<u8>,
+}
+
+impl<G: RollbackGame, T: Transport> RollbackSession<G, T> {
+  /// `local_player` is the index of the player controlled on this peer, 0 or
+  /// 1, the other peer must use the other one. The peers may use different
+  /// input delays.
+  pub fn new(transport: T, local_player: usize, input_delay: u32) -> Self {
+    assert!(local_player < 2, "there are only two players");
+    Self {
+      transport,
+      local_player,
+      current_frame: 0,
+      inputs_start: 0,
+      local_inputs: vec![0; input_delay as usize],
+      remote_inputs: Vec::new(),
+      confirmed_frames: 0,
+      used_remote_inputs: Vec::new(),
+      saved_states: VecDeque::new(),
+      saved_states_start: 0,
+      remote_ack: 0,
+      local_checksums: BTreeMap::new(),
+      remote_checksums: BTreeMap::new(),
+      last_compared_frame: None,
+      stats: SessionStats::default(),
+      packet_buf: Vec::with_capacity(MAX_PACKET_SIZE),
+    }
+  }
+
+  #[inline]
+  pub fn current_frame(&self) -> u32 { self.current_frame }
+  /// The frames before this one have been simulated with the inputs of both
+  /// of the players known for sure.
+  #[inline]
+  pub fn confirmed_frame(&self) -> u32 { self.confirmed_frames.min(self.current_frame) }
+  #[inline]
+  pub fn stats(&self) -> SessionStats { self.stats }
+  #[inline]
+  pub fn transport(&self) -> &T { &self.transport }
+
+  /// Exchanges the packets and corrects the mispredictions without advancing
+  /// the game, e.g. to let the other peer catch up at the end.
+  pub fn poll(&mut self, game: &mut G) -> AnyResult<()> {
+    self.receive(game)?;
+    self.finish_tick()
+  }
+
+  /// Runs a single frame with `local_input` scheduled for the frame after the
+  /// input delay, unless the remote peer has fallen behind too far. Returns
+  /// whether the frame has been run.
+  pub fn advance_frame(&mut self, game: &mut G, local_input: NetInput) -> AnyResult<bool> {
+    self.receive(game)?;
+    let can_advance = self.current_frame < self.confirmed_frames + MAX_PREDICTION_FRAMES;
+    if can_advance {
+      self.local_inputs.push(local_input);
+      self.simulate_frame(game);
+    } else {
+      self.stats.stalled_ticks += 1;
+    }
+    self.finish_tick()?;
+    Ok(can_advance)
+  }
+
+  fn receive(&mut self, game: &mut G) -> AnyResult<()> {
+    let mut buf = [0; MAX_PACKET_SIZE];
+    let mut rollback_frame: Option<u32> = None;
+    while let Some(len) = self.transport.recv(&mut buf).context("Failed to receive a packet")? {
+      let packet = match Packet::decode(&buf[..len]) {
+        Ok(packet) => packet,
+        Err(e) => {
+          warn!("Dropping an invalid packet: {}", e);
+          continue;
+        }
+      };
+      // The peer can't get further ahead than this (unless the input delays
+      // are absurdly long), and the inputs are stored for every frame up to
+      // the last one, so such a packet is dropped instead.
+      let end_frame = packet.first_frame + packet.inputs.len() as u32;
+      let max_end_frame =
+        self.current_frame + MAX_PREDICTION_FRAMES + MAX_INPUTS_PER_PACKET as u32;
+      if end_frame > max_end_frame {
+        warn!(
+          "Dropping a packet with the inputs up to frame {}, too far ahead of frame {}",
+          end_frame, self.current_frame,
+        );
+        continue;
+      }
+      self.stats.packets_received += 1;
+      self.remote_ack = self.remote_ack.max(packet.ack.min(self.local_inputs_end()));
+
+      for (frame, &input) in (packet.first_frame..).zip(&packet.inputs) {
+        // These have been confirmed long ago.
+        if frame < self.inputs_start {
+          continue;
+        }
+        let index = (frame - self.inputs_start) as usize;
+        if index >= self.remote_inputs.len() {
+          self.remote_inputs.resize(index + 1, None);
+        }
+        if self.remote_inputs[index].is_some() {
+          continue;
+        }
+        self.remote_inputs[index] = Some(input);
+        if frame < self.current_frame && self.used_remote_inputs[index] != input {
+          rollback_frame = Some(rollback_frame.map_or(frame, |f| f.min(frame)));
+        }
+      }
+      while let Some(Some(_)) =
+        self.remote_inputs.get((self.confirmed_frames - self.inputs_start) as usize)
+      {
+        self.confirmed_frames += 1;
+      }
+
+      if let Some((frame, checksum)) = packet.checksum {
+        if self.last_compared_frame.map_or(true, |last_frame| frame > last_frame) {
+          self.remote_checksums.insert(frame, checksum);
+        }
+      }
+    }
+
+    if let Some(frame) = rollback_frame {
+      self.rollback(game, frame);
+    }
+    Ok(())
+  }
+
+  #[inline]
+  fn local_inputs_end(&self) -> u32 { self.inputs_start + self.local_inputs.len() as u32 }
+
+  fn remote_input(&self, frame: u32) -> NetInput {
+    match self.remote_inputs.get((frame - self.inputs_start) as usize) {
+      Some(&Some(input)) => input,
+      // NOTE: The last input is usually still being held on the next frames.
+      _ => match self.confirmed_frames.checked_sub(1) {
+        Some(last_frame) => self.remote_inputs[(last_frame - self.inputs_start) as usize].unwrap(),
+        None => 0,
+      },
+    }
+  }
+
+  fn simulate_frame(&mut self, game: &mut G) {
+    let frame = self.current_frame;
+    let index = (frame - self.inputs_start) as usize;
+    self.saved_states.push_back(game.save_state());
+
+    let remote_input = self.remote_input(frame);
+    self.used_remote_inputs.truncate(index);
+    self.used_remote_inputs.push(remote_input);
+
+    let mut inputs = [0; 2];
+    inputs[self.local_player] = self.local_inputs[index];
+    inputs[1 - self.local_player] = remote_input;
+    game.advance(inputs);
+    self.current_frame += 1;
+  }
+
+  fn rollback(&mut self, game: &mut G, frame: u32) {
+    let index = (frame - self.saved_states_start) as usize;
+    game.load_state(&self.saved_states[index]);
+    self.saved_states.truncate(index);
+
+    let end_frame = self.current_frame;
+    self.current_frame = frame;
+    while self.current_frame < end_frame {
+      self.simulate_frame(game);
+    }
+    self.stats.rollbacks += 1;
+    self.stats.resimulated_frames += end_frame - frame;
+  }
+
+  fn finish_tick(&mut self) -> AnyResult<()> {
+    // The states up to the confirmed frame can't change anymore, so they are
+    // the ones to compare with the other peer.
+    let confirmed_frame = self.confirmed_frame();
+    for (frame, state) in (self.saved_states_start..=confirmed_frame).zip(&self.saved_states) {
+      if frame % CHECKSUM_INTERVAL == 0 && !self.local_checksums.contains_key(&frame) {
+        self.local_checksums.insert(frame, G::state_checksum(state));
+      }
+    }
+    self.check_desync()?;
+
+    // Only the confirmed frame itself is needed to roll back to.
+    let pruned_count = confirmed_frame - self.saved_states_start;
+    self.saved_states.drain(..pruned_count as usize);
+    self.saved_states_start = confirmed_frame;
+
+    // The local inputs are kept until the other peer acknowledges them, and
+    // the last confirmed remote one is still needed for the predictions.
+    let inputs_start = confirmed_frame.min(self.remote_ack).saturating_sub(1);
+    if inputs_start > self.inputs_start {
+      let pruned_count = (inputs_start - self.inputs_start) as usize;
+      self.local_inputs.drain(..pruned_count);
+      self.remote_inputs.drain(..pruned_count);
+      self.used_remote_inputs.drain(..pruned_count);
+      self.inputs_start = inputs_start;
+    }
+
+    self.send()
+  }
+
+  fn check_desync(&mut self) -> AnyResult<()> {
+    let mut compared_frames = Vec::new();
+    for (&frame, &remote_checksum) in &self.remote_checksums {
+      if let Some(&local_checksum) = self.local_checksums.get(&frame) {
+        ensure!(
+          local_checksum == remote_checksum,
+          "Desync detected at frame {}: the local checksum is {:#018x}, the remote one is {:#018x}",
+          frame,
+          local_checksum,
+          remote_checksum,
+        );
+        compared_frames.push(frame);
+      }
+    }
+    for frame in &compared_frames {
+      self.remote_checksums.remove(frame);
+      self.last_compared_frame = self.last_compared_frame.max(Some(*frame));
+    }
+    self.stats.checksums_compared += compared_frames.len() as u32;
+
+    // The old ones are kept around for a while, since the other peer may be
+    // behind by a few frames.
+    for checksums in &mut [&mut self.local_checksums, &mut self.remote_checksums] {
+      while checksums.len() > MAX_KEPT_CHECKSUMS {
+        let oldest_frame = *checksums.keys().next().unwrap();
+        checksums.remove(&oldest_frame);
+      }
+    }
+    Ok(())
+  }
+
+  fn send(&mut self) -> AnyResult<()> {
+    let first_index = (self.remote_ack - self.inputs_start) as usize;
+    let end_index = self.local_inputs.len().min(first_index + MAX_INPUTS_PER_PACKET);
+    let packet = Packet {
+      ack: self.confirmed_frames,
+      first_frame: self.remote_ack,
+      inputs: self.local_inputs[first_index..end_index].to_vec(),
+      checksum: self
+        .local_checksums
+        .iter()
+        .next_back()
+        .map(|(&frame, &checksum)| (frame, checksum)),
+    };
+    self.packet_buf.clear();
+    packet.encode(&mut self.packet_buf);
+    self.transport.send(&self.packet_buf).context("Failed to send a packet")?;
+    self.stats.packets_sent += 1;
+    Ok(())
+  }
+}
+
+impl<T: Transport + ?Sized> Transport for Box<T> {
+  fn send(&mut self, packet: &[u8]) -> io::Result<()> { (**self).send(packet) }
+  fn recv(&mut self, buf: &mut [u8]) -> io::Result<Option<usize>> { (**self).recv(buf) }
+}
+
+/// A UDP socket which talks only to the given peer.
+#[derive(Debug)]
+pub struct UdpTransport {
+  socket: UdpSocket,
+}
+
+impl UdpTransport {
+  pub fn new(local_addr: SocketAddr, peer_addr: SocketAddr) -> AnyResult<Self> {
+    let socket = UdpSocket::bind(local_addr)
+      .with_context(|| format!("Failed to bind a UDP socket to {}", local_addr))?;
+    socket.connect(peer_addr).with_context(|| format!("Failed to connect to {}", peer_addr))?;
+    socket.set_nonblocking(true).context("Failed to make the socket non-blocking")?;
+    Ok(Self { socket })
+  }
+}
+
+/// NOTE: Until the other peer has started its port is closed, which the OS may
+/// report (after receiving an ICMP message) on any of the following calls.
+/// These are just lost packets as far as the netcode is concerned.
+fn is_connection_refused(error: &io::Error) -> bool {
+  error.kind() == io::ErrorKind::ConnectionRefused
+}
+
+impl Transport for UdpTransport {
+  fn send(&mut self, packet: &[u8]) -> io::Result<()> {
+    match self.socket.send(packet) {
+      Ok(_) => Ok(()),
+      Err(e) if e.kind() == io::ErrorKind::WouldBlock || is_connection_refused(&e) => Ok(()),
+      Err(e) => Err(e),
+    }
+  }
+
+  fn recv(&mut self, buf: &mut [u8]) -> io::Result<Option<usize>> {
+    loop {
+      match self.socket.recv(buf) {
+        Ok(len) => return Ok(Some(len)),
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+        Err(e) if is_connection_refused(&e) => continue,
+        Err(e) => return Err(e),
+      }
+    }
+  }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct LinkConditions {
+  /// In seconds.
+  pub latency: f64,
+  /// A random delay from zero to this is added to every packet, so that they
+  /// also arrive out of order.
+  pub jitter: f64,
+  /// The fraction of the packets which get lost.
+  pub packet_loss: f32,
+}
+
+#[derive(Debug)]
+struct InFlightPacket {
+  delivery_time: f64,
+  data: Vec<u8>,
+}
+
+#[derive(Debug)]
+struct SimulatedNetworkState {
+  time: f64,
+  conditions: LinkConditions,
+  random: Random,
+  /// Indexed by the receiving endpoint.
+  queues: [Vec<InFlightPacket>; 2],
+  packets_lost: u32,
+}
+
+/// An in-memory network between two endpoints which loses, delays and
+/// reorders the packets, for testing the netcode within a single process.
+/// Its time is advanced explicitly, so the results depend only on the seed.
+#[derive(Debug, Clone)]
+pub struct SimulatedNetwork(Rc<RefCell<SimulatedNetworkState>>);
+
+impl SimulatedNetwork {
+  pub fn new(conditions: LinkConditions, random: Random) -> Self {
+    Self(Rc::new(RefCell::new(SimulatedNetworkState {
+      time: 0.0,
+      conditions,
+      random,
+      queues: [Vec::new(), Vec::new()],
+      packets_lost: 0,
+    })))
+  }
+
+  /// There are two of the endpoints, 0 and 1.
+  pub fn endpoint(&self, index: usize) -> SimulatedEndpoint {
+    assert!(index < 2);
+    SimulatedEndpoint { network: self.clone(), index }
+  }
+
+  pub fn advance(&self, delta_time: f64) { RefCell::borrow_mut(&self.0).time += delta_time; }
+
+  pub fn packets_lost(&self) -> u32 { RefCell::borrow(&self.0).packets_lost }
+}
+
+#[derive(Debug)]
+pub struct SimulatedEndpoint {
+  network: SimulatedNetwork,
+  index: usize,
+}
+
+impl Transport for SimulatedEndpoint {
+  fn send(&mut self, packet: &[u8]) -> io::Result<()> {
+    let network = &mut *RefCell::borrow_mut(&self.network.0);
+    let conditions = network.conditions;
+    if network.random.next_f32() < conditions.packet_loss {
+      network.packets_lost += 1;
+      return Ok(());
+    }
+    let delay = conditions.latency + conditions.jitter * network.random.next_f64();
+    network.queues[1 - self.index]
+      .push(InFlightPacket { delivery_time: network.time + delay, data: packet.to_vec() });
+    Ok(())
+  }
+
+  fn recv(&mut self, buf: &mut [u8]) -> io::Result<Option<usize>> {
+    let network = &mut *RefCell::borrow_mut(&self.network.0);
+    let time = network.time;
+    let queue = &mut network.queues[self.index];
+    let next_packet = (queue.iter().enumerate())
+      .filter(|(_, packet)| packet.delivery_time <= time)
+      .min_by(|(_, a), (_, b)| a.delivery_time.partial_cmp(&b.delivery_time).unwrap())
+      .map(|(index, _)| index);
+    Ok(next_packet.map(|index| {
+      let packet = queue.remove(index);
+      let len = packet.data.len().min(buf.len());
+      buf[..len].copy_from_slice(&packet.data[..len]);
+      len
+    }))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::globals::GlobalRandom;
+
+  const FRAMES: u32 = 600;
+  const INPUT_DELAYS: [u32; 2] = [2, 3];
+
+  /// Mixes the inputs of every frame into a single number, so that any
+  /// difference in the inputs or their order changes the final state.
+  #[derive(Debug, Default)]
+  struct TestGame {
+    frame: u32,
+    value: u64,
+    /// Makes the game of one of the peers diverge starting with this frame.
+    corrupt_from_frame: Option<u32>,
+  }
+
+  impl RollbackGame for TestGame {
+    type State = (u32, u64);
+
+    fn save_state(&self) -> Self::State { (self.frame, self.value) }
+    fn load_state(&mut self, state: &Self::State) { (self.frame, self.value) = *state; }
+    fn state_checksum(state: &Self::State) -> u64 {
+      let mut checksum = Checksum::new();
+      checksum.write(&state.0.to_le_bytes());
+      checksum.write_u64(state.1);
+      checksum.finish()
+    }
+
+    fn advance(&mut self, inputs: [NetInput; 2]) {
+      let mut checksum = Checksum(self.value);
+      checksum.write(&[inputs[0] as u8, inputs[1] as u8]);
+      self.value = checksum.finish();
+      if self.corrupt_from_frame.map_or(false, |frame| self.frame >= frame) {
+        self.value ^= 1;
+      }
+      self.frame += 1;
+    }
+  }
+
+  /// Changes every few frames, so that the predictions are often wrong.
+  fn scripted_input(player: usize, frame: u32) -> NetInput {
+    if frame < INPUT_DELAYS[player] {
+      return 0;
+    }
+    ((frame / (5 + player as u32 * 2) + player as u32) % 5) as NetInput - 2
+  }
+
+  fn simulate_locally() -> TestGame {
+    let mut game = TestGame::default();
+    for frame in 0..FRAMES {
+      game.advance([scripted_input(0, frame), scripted_input(1, frame)]);
+    }
+    game
+  }
+
+  struct TestPeer {
+    game: TestGame,
+    session: RollbackSession<TestGame, SimulatedEndpoint>,
+  }
+
+  fn run_match(
+    conditions: LinkConditions,
+    corrupt_from_frame: Option<u32>,
+  ) -> (SimulatedNetwork, AnyResult<[TestPeer; 2]>) {
+    let random = GlobalRandom::with_seed(0x5eed).stream("netplay/tests");
+    let network = SimulatedNetwork::new(conditions, random);
+    let mut peers = [0, 1].map(|player| TestPeer {
+      game: TestGame {
+        corrupt_from_frame: corrupt_from_frame.filter(|_| player == 1),
+        ..Default::default()
+      },
+      session: RollbackSession::new(network.endpoint(player), player, INPUT_DELAYS[player]),
+    });
+
+    let result = (|| {
+      for _ in 0..FRAMES * 10 {
+        network.advance(1.0 / 60.0);
+        for (player, peer) in peers.iter_mut().enumerate() {
+          let session = &mut peer.session;
+          if session.current_frame() < FRAMES {
+            let frame = session.current_frame() + INPUT_DELAYS[player];
+            session.advance_frame(&mut peer.game, scripted_input(player, frame))?;
+          } else {
+            session.poll(&mut peer.game)?;
+          }
+        }
+        if peers.iter().all(|peer| peer.session.confirmed_frame() == FRAMES) {
+          return Ok(());
+        }
+      }
+      bail!("The peers haven't finished the match");
+    })();
+    (network, result.map(|()| peers))
+  }
+
+  #[test]
+  fn peers_agree_over_a_lossy_network() {
+    let conditions = LinkConditions { latency: 0.05, jitter: 0.05, packet_loss: 0.2 };
+    let (network, result) = run_match(conditions, None);
+    let peers = result.unwrap();
+    assert!(network.packets_lost() > 0);
+
+    let expected = simulate_locally();
+    for peer in &peers {
+      assert_eq!(peer.game.save_state(), expected.save_state());
+      let stats = peer.session.stats();
+      assert!(stats.rollbacks > 0, "{:?}", stats);
+      assert!(stats.checksums_compared > 0, "{:?}", stats);
+    }
+  }
+
+  #[test]
+  fn old_inputs_are_pruned() {
+    let conditions = LinkConditions { latency: 0.05, jitter: 0.05, packet_loss: 0.2 };
+    let (_, result) = run_match(conditions, None);
+    for peer in &result.unwrap() {
+      let session = &peer.session;
+      assert!(session.inputs_start > FRAMES - MAX_PREDICTION_FRAMES, "{}", session.inputs_start);
+      let max_len = (FRAMES - session.inputs_start) as usize + INPUT_DELAYS[1] as usize;
+      assert!(session.local_inputs.len() <= max_len, "{}", session.local_inputs.len());
+      assert!(session.remote_inputs.len() <= max_len, "{}", session.remote_inputs.len());
+      assert!(session.used_remote_inputs.len() <= max_len, "{}", session.used_remote_inputs.len());
+    }
+  }
+
+  #[test]
+  fn desync_is_detected() {
+    let conditions = LinkConditions { latency: 0.03, jitter: 0.02, packet_loss: 0.1 };
+    let (_, result) = run_match(conditions, Some(200));
+    let error = result.err().expect("the desync went unnoticed").to_string();
+    // The first checksum taken after the divergence is the one to differ.
+    assert!(error.starts_with("Desync detected at frame 240:"), "{}", error);
+  }
+
+  #[test]
+  fn drops_packets_too_far_ahead() {
+    let conditions = LinkConditions { latency: 0.0, jitter: 0.0, packet_loss: 0.0 };
+    let network = SimulatedNetwork::new(conditions, GlobalRandom::with_seed(1).stream("test"));
+    let mut session = RollbackSession::new(network.endpoint(0), 0, 0);
+    let mut game = TestGame::default();
+
+    let mut remote = network.endpoint(1);
+    let mut send_inputs = |first_frame: u32| {
+      let packet = Packet { ack: 0, first_frame, inputs: vec![1; 10], checksum: None };
+      let mut bytes = Vec::new();
+      packet.encode(&mut bytes);
+      remote.send(&bytes).unwrap();
+    };
+
+    send_inputs(u32::MAX - 10);
+    send_inputs(MAX_PREDICTION_FRAMES + MAX_INPUTS_PER_PACKET as u32);
+    session.poll(&mut game).unwrap();
+    assert_eq!(session.stats().packets_received, 0);
+    assert!(session.remote_inputs.is_empty());
+
+    send_inputs(0);
+    session.poll(&mut game).unwrap();
+    assert_eq!(session.stats().packets_received, 1);
+    assert_eq!(session.remote_inputs.len(), 10);
+    assert_eq!(session.confirmed_frames, 10);
+  }
+}
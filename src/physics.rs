@@ -0,0 +1,350 @@
+//! A small rigid-body world: bodies with mass, forces, an integrator and the
+//! contact resolution on top of [`collision`](crate::collision). Only circles
+//! and axis-aligned boxes are supported, without any rotation. The bodies are
+//! always processed in the order of their handles, so that the results depend
+//! on nothing but the sequence of steps with the same `delta_time`.
+
+use cardboard_math::*;
+use prelude_plus::*;
+
+use crate::collision::{self, Shape};
+use crate::gen_idx::{GenIdx, GenIdxAllocator, GenIdxVec};
+
+pub type BodyId = GenIdx;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Integrator {
+  /// <https://en.wikipedia.org/wiki/Verlet_integration#Basic_St%C3%B6rmer%E2%80%93Verlet>,
+  /// with the difference of the last two positions replaced by the velocity,
+  /// which is the same thing unless the velocity was changed in between, e.g.
+  /// by an impulse or a contact.
+  Verlet,
+  /// <https://en.wikipedia.org/wiki/Semi-implicit_Euler_method>
+  SemiImplicitEuler,
+}
+
+#[derive(Debug, Clone)]
+pub struct RigidBody {
+  pub shape: Shape,
+  pub pos: Vec2f,
+  /// The position before the last step.
+  pub prev_pos: Vec2f,
+  pub vel: Vec2f,
+  /// Zero makes the body static: it is not moved by the world and pushes the
+  /// other bodies away with infinite mass.
+  pub inv_mass: f32,
+  /// How much of the velocity along the normal is kept after a bounce, from 0
+  /// to 1. The restitution of a contact is the maximum of the two bodies.
+  pub restitution: f32,
+  force: Vec2f,
+}
+
+impl RigidBody {
+  pub fn new(shape: Shape, pos: Vec2f, mass: f32) -> Self {
+    assert!(mass > 0.0, "the mass must be positive, use RigidBody::new_static instead");
+    Self {
+      shape,
+      pos,
+      prev_pos: pos,
+      vel: Vec2f::ZERO,
+      inv_mass: 1.0 / mass,
+      restitution: 1.0,
+      force: Vec2f::ZERO,
+    }
+  }
+
+  pub fn new_static(shape: Shape, pos: Vec2f) -> Self {
+    Self { inv_mass: 0.0, ..Self::new(shape, pos, 1.0) }
+  }
+
+  #[inline]
+  pub fn is_static(&self) -> bool { self.inv_mass == 0.0 }
+
+  #[inline]
+  pub fn mass(&self) -> f32 {
+    if self.is_static() {
+      f32::INFINITY
+    } else {
+      1.0 / self.inv_mass
+    }
+  }
+
+  /// Accumulated until the next step.
+  pub fn add_force(&mut self, force: Vec2f) { self.force += force; }
+
+  /// Changes the velocity instantly.
+  pub fn add_impulse(&mut self, impulse: Vec2f) { self.vel += impulse * self.inv_mass; }
+
+  /// Moves the body without giving it any velocity.
+  pub fn teleport(&mut self, pos: Vec2f) {
+    self.pos = pos;
+    self.prev_pos = pos;
+  }
+
+  pub fn kinetic_energy(&self) -> f32 {
+    if self.is_static() {
+      0.0
+    } else {
+      0.5 * self.mass() * self.vel.sqr_magnitude()
+    }
+  }
+
+  #[inline]
+  pub fn render_pos(&self, alpha: f32) -> Vec2f { self.prev_pos.lerp(self.pos, alpha) }
+
+  fn as_collision_body(&self) -> collision::Body {
+    collision::Body { pos: self.pos, shape: self.shape }
+  }
+}
+
+#[derive(Debug)]
+pub struct PhysicsWorld {
+  entities: GenIdxAllocator,
+  bodies: GenIdxVec<RigidBody>,
+  pub integrator: Integrator,
+  /// Applied to all of the non-static bodies regardless of their mass.
+  pub gravity: Vec2f,
+  /// The fraction of the velocity lost per second.
+  pub linear_damping: f32,
+  /// The half extents of a box centered at the origin, the dynamic bodies
+  /// bounce off its inner walls.
+  pub bounds: Option<Vec2f>,
+  /// The number of the contact resolution passes per step, more of them help
+  /// with the bodies stacked on top of each other.
+  pub solver_iterations: u32,
+}
+
+impl Default for PhysicsWorld {
+  fn default() -> Self {
+    Self {
+      entities: GenIdxAllocator::new(),
+      bodies: GenIdxVec::new(),
+      integrator: Integrator::SemiImplicitEuler,
+      gravity: Vec2f::ZERO,
+      linear_damping: 0.0,
+      bounds: None,
+      solver_iterations: 4,
+    }
+  }
+}
+
+impl PhysicsWorld {
+  pub fn new(integrator: Integrator) -> Self { Self { integrator, ..Default::default() } }
+
+  pub fn add(&mut self, body: RigidBody) -> BodyId {
+    let id = self.entities.alloc();
+    self.bodies.set(id, body);
+    id
+  }
+
+  pub fn remove(&mut self, id: BodyId) -> Option<RigidBody> {
+    if self.entities.free(id) {
+      self.bodies.remove(id)
+    } else {
+      None
+    }
+  }
+
+  pub fn clear(&mut self) {
+    let ids: Vec<BodyId> = self.bodies.iter().map(|(id, _)| id).collect();
+    for id in ids {
+      self.remove(id);
+    }
+  }
+
+  #[inline]
+  pub fn get(&self, id: BodyId) -> Option<&RigidBody> { self.bodies.get(id) }
+  #[inline]
+  pub fn get_mut(&mut self, id: BodyId) -> Option<&mut RigidBody> { self.bodies.get_mut(id) }
+
+  pub fn iter(&self) -> impl Iterator<Item = (BodyId, &RigidBody)> { self.bodies.iter() }
+  pub fn iter_mut(&mut self) -> impl Iterator<Item = (BodyId, &mut RigidBody)> {
+    self.bodies.iter_mut()
+  }
+
+  pub fn kinetic_energy(&self) -> f32 {
+    self.bodies.iter().map(|(_, body)| body.kinetic_energy()).sum()
+  }
+
+  pub fn step(&mut self, delta_time: f32) {
+    if delta_time <= 0.0 {
+      return;
+    }
+    self.integrate(delta_time);
+    for _ in 0..self.solver_iterations {
+      self.resolve_bounds();
+      self.resolve_contacts();
+    }
+  }
+
+  fn integrate(&mut self, dt: f32) {
+    let damping = (1.0 - self.linear_damping * dt).max(0.0);
+    for (_, body) in self.bodies.iter_mut() {
+      let force = mem::take(&mut body.force);
+      if body.is_static() {
+        body.prev_pos = body.pos;
+        continue;
+      }
+      let accel = force * body.inv_mass + self.gravity;
+
+      match self.integrator {
+        Integrator::Verlet => {
+          let velocity_term = body.vel * (dt * damping);
+          let new_pos = body.pos + velocity_term + accel * (dt * dt);
+          body.prev_pos = body.pos;
+          body.pos = new_pos;
+          body.vel = (body.pos - body.prev_pos) / dt;
+        }
+        Integrator::SemiImplicitEuler => {
+          body.vel = (body.vel + accel * dt) * damping;
+          body.prev_pos = body.pos;
+          body.pos += body.vel * dt;
+        }
+      }
+    }
+  }
+
+  fn resolve_bounds(&mut self) {
+    let bounds = match self.bounds {
+      Some(bounds) => bounds,
+      None => return,
+    };
+    for (_, body) in self.bodies.iter_mut() {
+      if body.is_static() {
+        continue;
+      }
+      let max_pos = (bounds - body.shape.size() / 2.0).max_components(Vec2f::ZERO);
+      for axis in 0..2 {
+        if body.pos[axis].abs() >= max_pos[axis] {
+          let side = body.pos[axis].signum();
+          body.pos[axis] = side * max_pos[axis];
+          if body.vel[axis] * side > 0.0 {
+            body.vel[axis] *= -body.restitution;
+          }
+        }
+      }
+    }
+  }
+
+  fn resolve_contacts(&mut self) {
+    let ids: Vec<BodyId> = self.bodies.iter().map(|(id, _)| id).collect();
+    for (i, &id_a) in ids.iter().enumerate() {
+      for &id_b in &ids[i + 1..] {
+        let (a, b) = self.bodies.get2_mut(id_a, id_b);
+        let (a, b) = (a.unwrap(), b.unwrap());
+        let inv_mass_sum = a.inv_mass + b.inv_mass;
+        if inv_mass_sum == 0.0 {
+          continue;
+        }
+        let contact = match collision::test(&a.as_collision_body(), &b.as_collision_body()) {
+          Some(contact) => contact,
+          None => continue,
+        };
+
+        // The bodies are pushed apart in proportion to their inverse masses,
+        // so that a static one doesn't move at all.
+        let correction = contact.normal * (contact.depth / inv_mass_sum);
+        a.pos += correction * a.inv_mass;
+        b.pos -= correction * b.inv_mass;
+
+        let approach_speed = (a.vel - b.vel).dot(contact.normal);
+        if approach_speed < 0.0 {
+          let restitution = a.restitution.max(b.restitution);
+          let impulse = contact.normal * (-(1.0 + restitution) * approach_speed / inv_mass_sum);
+          a.add_impulse(impulse);
+          b.add_impulse(-impulse);
+        }
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const DT: f32 = 1.0 / 60.0;
+
+  fn bouncing_world(integrator: Integrator) -> (PhysicsWorld, BodyId) {
+    let mut world = PhysicsWorld::new(integrator);
+    world.gravity = vec2(0.0, -500.0);
+    world.bounds = Some(vec2(200.0, 150.0));
+    let mut body = RigidBody::new(Shape::circle(10.0), vec2(-50.0, 100.0), 2.0);
+    body.vel = vec2(120.0, 0.0);
+    let id = world.add(body);
+    world.add(RigidBody::new_static(Shape::aabb(vec2(40.0, 40.0)), vec2(60.0, -60.0)));
+    (world, id)
+  }
+
+  fn positions(world: &PhysicsWorld) -> Vec<Vec2f> {
+    world.iter().map(|(_, body)| body.pos).collect()
+  }
+
+  #[test]
+  fn steps_are_deterministic() {
+    for &integrator in &[Integrator::Verlet, Integrator::SemiImplicitEuler] {
+      let (mut world_a, _) = bouncing_world(integrator);
+      let (mut world_b, _) = bouncing_world(integrator);
+      for _ in 0..600 {
+        world_a.step(DT);
+        world_b.step(DT);
+        assert_eq!(positions(&world_a), positions(&world_b));
+      }
+    }
+  }
+
+  #[test]
+  fn energy_of_a_bouncing_body_stays_bounded() {
+    for &integrator in &[Integrator::Verlet, Integrator::SemiImplicitEuler] {
+      let (mut world, id) = bouncing_world(integrator);
+      let total_energy = |world: &PhysicsWorld| {
+        let body = world.get(id).unwrap();
+        body.kinetic_energy() - body.mass() * world.gravity.dot(body.pos)
+      };
+      let initial_energy = total_energy(&world);
+      for _ in 0..1200 {
+        world.step(DT);
+        let energy = total_energy(&world);
+        assert!(
+          energy <= initial_energy * 1.1,
+          "{:?}: the energy grew from {} to {}",
+          integrator,
+          initial_energy,
+          energy,
+        );
+      }
+    }
+  }
+
+  #[test]
+  fn static_bodies_never_move() {
+    let mut world = PhysicsWorld::new(Integrator::Verlet);
+    world.gravity = vec2(0.0, -500.0);
+    let wall_pos = vec2(0.0, -100.0);
+    let mut wall = RigidBody::new_static(Shape::aabb(vec2(400.0, 20.0)), wall_pos);
+    wall.add_force(vec2(0.0, 1e6));
+    wall.add_impulse(vec2(1e6, 0.0));
+    let wall = world.add(wall);
+    for i in 0..5 {
+      world.add(RigidBody::new(Shape::circle(10.0), vec2(i as f32 * 15.0 - 30.0, 0.0), 5.0));
+    }
+
+    for _ in 0..600 {
+      world.step(DT);
+      let wall = world.get(wall).unwrap();
+      assert_eq!(wall.pos, wall_pos);
+      assert_eq!(wall.prev_pos, wall_pos);
+    }
+  }
+
+  #[test]
+  fn verlet_picks_up_impulses() {
+    let mut world = PhysicsWorld::new(Integrator::Verlet);
+    let id = world.add(RigidBody::new(Shape::circle(1.0), Vec2f::ZERO, 2.0));
+    world.get_mut(id).unwrap().add_impulse(vec2(120.0, 0.0));
+    world.step(0.5);
+    let body = world.get(id).unwrap();
+    assert_eq!(body.pos, vec2(30.0, 0.0));
+    assert_eq!(body.vel, vec2(60.0, 0.0));
+  }
+}
@@ -1,330 +1,379 @@
+use cardboard_coal as coal;
 use cardboard_math::*;
 use cardboard_oogl as oogl;
 use prelude_plus::*;
 
+use crate::bindings::{actions, axes};
 use crate::globals::{Globals, SharedGlobals};
 use crate::input::Key;
+use crate::pong_ai::BotDifficulty;
+use crate::pong_sim::{ControllerContext, PongEvent, PongSim, RacketController};
 use crate::renderer;
+use crate::scene::{self, Scene, SceneCommand, SceneKind};
 
-const FONT_CHAR_GRID_SIZE: Vec2u32 = vec2(4, 6);
-const FONT_CHAR_SIZE: Vec2u32 = vec2(3, 5);
 const SCORE_LABEL_CHAR_SPACING: Vec2f = vec2n(1.0 / 3.0);
 const SCORE_LABEL_TEXT_SCALE: Vec2f = vec2n(16.0);
 
-const RACKET_SIZE: Vec2f = vec2(20.0, 200.0);
-const RACKET_OFFSET: f32 = 2.0 * RACKET_SIZE.x + BALL_RADIUS;
 const RACKET_COLOR: Colorf = colorn(0.9, 1.0);
 
-const RACKET_MAX_SPEED: f32 = 1000.0;
-const RACKET_ACCELERATION: f32 = 8.0;
-const RACKET_SLOWDOWN: f32 = 12.0;
-const RACKET_SPEED_EPSILON: f32 = 1.0;
-const BOT_RACKET_VISION_DISTANCE: f32 = 1.0 / 2.0;
+const DIFFICULTY_LABEL_TEXT_SCALE: Vec2f = vec2n(4.0);
+/// For how long the difficulty is shown after switching it, in seconds.
+const DIFFICULTY_LABEL_DURATION: f64 = 2.0;
 
-const BALL_RADIUS: f32 = 40.0;
-const BALL_ROTATION_SPEED: f32 = 1.0;
-const BALL_MAX_SPEED: f32 = 1400.0;
-const BALL_MAX_VEL_DEVIATION_ANGLE: f32 = (/* 90 deg */f32::consts::FRAC_PI_2) * (2.0 / 3.0);
-const BALL_THROW_DISTANCE_FROM_RACKET: f32 = RACKET_SIZE.y;
+/// The first one to score this many points wins the match.
+const WINNING_SCORE: u32 = 5;
+/// The ball is held for this many seconds before every serve, the remaining
+/// time is shown rounded up to whole steps.
+const SERVE_COUNTDOWN_STEPS: u32 = 3;
+const SERVE_COUNTDOWN_STEP_DURATION: f64 = 0.5;
 
-#[derive(Debug)]
-struct GameState {
-  left_racket: Racket,
-  left_racket_controller: RacketController,
-  right_racket: Racket,
-  right_racket_controller: RacketController,
-  ball: Ball,
-}
+const MODE_KEYS: [Key; 3] = [Key::Num1, Key::Num2, Key::Num3];
 
-type EntityId = u32;
-
-#[derive(Debug, Default)]
-struct CollEntry {
-  id: EntityId,
-  size: Vec2f,
-  pos: Vec2f,
-  vel: Vec2f,
-  accel: Vec2f,
-  slowdown: f32,
-  max_speed: f32,
-}
+const HIT_SOUND_FREQUENCY: f64 = 440.0;
+const BOUNCE_SOUND_FREQUENCY: f64 = 220.0;
+const SOUND_DURATION: f64 = 0.08;
+const SOUND_VOLUME: f32 = 0.4;
 
-impl CollEntry {
-  fn extents(&self) -> Vec2f { self.size / 2.0 }
-}
+const_assert!(MODE_KEYS.len() == PongMode::ALL.len());
 
-#[derive(Debug)]
-struct Racket {
-  coll: CollEntry,
-  side: f32,
-  score: u32,
-}
-
-impl Racket {
-  fn new(id: EntityId, side: f32) -> Self {
-    Self {
-      coll: CollEntry {
-        id,
-        size: RACKET_SIZE,
-        max_speed: RACKET_MAX_SPEED,
-        slowdown: RACKET_SLOWDOWN,
-        ..Default::default()
-      },
-      side,
-      score: 0,
-    }
-  }
-
-  fn update_pos(&mut self, globals: &Globals) {
-    self.coll.pos.x =
-      self.side * (globals.window_size.x / 2.0 - self.coll.size.x / 2.0 - RACKET_OFFSET);
-  }
-}
+type BoxedController = Box<dyn RacketController>;
 
+/// Moves the racket up on the positive direction of the axis.
 #[derive(Debug)]
-struct Ball {
-  coll: CollEntry,
-  rotation: f32,
-  rotation_speed: f32,
-  currently_colliding_with: Option<EntityId>,
+struct PlayerController {
+  globals: SharedGlobals,
+  axis: &'static str,
 }
 
-impl Ball {
-  fn new(id: EntityId) -> Self {
-    Ball {
-      coll: CollEntry {
-        id,
-        size: vec2n(BALL_RADIUS * 2.0),
-        vel: vec2n(0.0),
-        max_speed: BALL_MAX_SPEED,
-        ..Default::default()
-      },
-      rotation: 0.0,
-      rotation_speed: BALL_ROTATION_SPEED,
-      currently_colliding_with: None,
-    }
-  }
-
-  fn throw_at(&mut self, globals: &Globals, racket: &Racket) {
-    let dist = BALL_THROW_DISTANCE_FROM_RACKET;
-    self.coll.pos = racket.coll.pos - vec2(racket.side * (dist + racket.coll.size.x / 2.0), 0.0);
-
-    let max_angle = ((racket.coll.size.y / 2.0) / dist).atan();
-    let angle = (globals.random.next_f32() * 2.0 - 1.0) * max_angle;
-    self.coll.vel = vec2(racket.side, 0.0).rotated(angle) * BALL_MAX_SPEED;
+impl RacketController for PlayerController {
+  fn movement_direction(&mut self, _ctx: &ControllerContext<'_>) -> f32 {
+    self.globals.input_state.action_axis_value(self.axis)
   }
 }
 
-#[derive(Debug)]
-enum RacketController {
-  Player { key_up: Key, key_down: Key },
-  Bot,
+/// Who controls the rackets, chosen on the title screen.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PongMode {
+  /// The player is on the right.
+  PlayerVsBot,
+  PlayerVsPlayer,
+  BotVsBot,
 }
 
-impl RacketController {
-  fn get_movement_direction(&mut self, globals: &Globals, racket: &Racket, ball: &Ball) -> f32 {
+impl PongMode {
+  pub const ALL: [Self; 3] = [Self::PlayerVsBot, Self::PlayerVsPlayer, Self::BotVsBot];
+
+  pub fn name(self) -> &'static str {
     match self {
-      Self::Player { key_up, key_down } => globals.input_state.axis(*key_down, *key_up) as f32,
-
-      Self::Bot => {
-        let vision_dist = BOT_RACKET_VISION_DISTANCE * globals.window_size.x;
-        if ball.coll.vel.x * racket.side > 0.0
-          && (racket.coll.pos.x - ball.coll.pos.x).abs() <= vision_dist
-        {
-          (ball.coll.pos.y - racket.coll.pos.y).signum()
-        } else {
-          0.0
-        }
-      }
+      Self::PlayerVsBot => "vs bot",
+      Self::PlayerVsPlayer => "2 players",
+      Self::BotVsBot => "bot vs bot",
     }
   }
 }
 
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PongState {
+  /// The mode selection, the bots play a demo match behind it.
+  Title,
+  /// Everything is frozen until the countdown (in seconds) runs out.
+  Serve {
+    countdown: f64,
+  },
+  Playing,
+  GameOver {
+    winner_is_left: bool,
+  },
+}
+
 #[derive(Debug)]
 pub struct Pong {
   globals: SharedGlobals,
-  state: GameState,
-  debug_vectors: Vec<(Vec2f, Vec2f, Colorf)>,
+  state: PongState,
+  mode: PongMode,
+  sim: PongSim,
+  /// The number of the simulations started so far, every one of them gets its
+  /// own random streams.
+  sims_count: u32,
+  bot_difficulty: BotDifficulty,
+  /// The value of `time` when the difficulty was last switched.
+  bot_difficulty_switch_time: Option<f64>,
+  /// The value of `fixed_time` on the last fixed update which has advanced the
+  /// simulation.
+  last_fixed_time: f64,
   pub font: renderer::Font,
   ball_texture: oogl::Texture2D,
+  hit_sound: coal::SoundBuffer,
+  bounce_sound: coal::SoundBuffer,
 }
 
-impl Pong {
-  pub fn init(globals: SharedGlobals) -> AnyResult<Self> {
-    let state = {
-      let (mut left_racket, mut right_racket) = (Racket::new(1, -1.0), Racket::new(2, 1.0));
-      left_racket.update_pos(&globals);
-      right_racket.update_pos(&globals);
-
-      let (left_racket_controller, right_racket_controller) = (
-        // RacketController::Player { key_up: Key::W, key_down: Key::S },
-        RacketController::Bot,
-        RacketController::Player { key_up: Key::Up, key_down: Key::Down },
-      );
+fn generate_blip_sound(sample_rate: u32, frequency: f64) -> coal::SoundBuffer {
+  coal::SoundBuffer::from_fn(sample_rate, SOUND_DURATION, |t| {
+    let envelope = 1.0 - t / SOUND_DURATION;
+    let square_wave = if (t * frequency).fract() < 0.5 { 1.0 } else { -1.0 };
+    (square_wave * envelope * envelope) as f32
+  })
+}
 
-      let mut ball = Ball::new(3);
-      ball
-        .throw_at(&globals, if globals.random.next_bool() { &left_racket } else { &right_racket });
+fn play_sound_at(globals: &Globals, sound: &coal::SoundBuffer, pos: Vec2f) {
+  let pan = (pos.x / (globals.window_size.x / 2.0)).clamp(-1.0, 1.0);
+  let params = coal::VoiceParams { volume: SOUND_VOLUME, pan, ..Default::default() };
+  globals.audio.borrow_mut().lock_mixer().play(sound.play(), params);
+}
 
-      GameState {
-        left_racket,
-        left_racket_controller,
-        right_racket,
-        right_racket_controller,
-        ball,
-      }
-    };
+impl Scene for Pong {
+  fn init(globals: SharedGlobals) -> AnyResult<Self> {
+    let bot_difficulty = BotDifficulty::default();
+    let sim = new_sim(&globals, 0, PongMode::BotVsBot, bot_difficulty);
 
-    let font_texture =
-      renderer::load_texture_asset(&globals, "font.png", oogl::TextureFilter::Nearest)?;
+    let font = renderer::load_default_font(&globals)?;
     let ball_texture =
       renderer::load_texture_asset(&globals, "ball.png", oogl::TextureFilter::Linear)?;
 
+    let sample_rate = globals.audio.borrow().format().sample_rate;
+    let hit_sound = generate_blip_sound(sample_rate, HIT_SOUND_FREQUENCY);
+    let bounce_sound = generate_blip_sound(sample_rate, BOUNCE_SOUND_FREQUENCY);
+
     Ok(Self {
       globals,
-      state,
-      debug_vectors: Vec::new(),
-      font: renderer::Font {
-        texture: font_texture,
-        grid_size: vec2(16, 8),
-        grid_cell_size: FONT_CHAR_GRID_SIZE,
-        character_size: FONT_CHAR_SIZE,
-      },
+      state: PongState::Title,
+      mode: PongMode::BotVsBot,
+      sim,
+      sims_count: 1,
+      bot_difficulty,
+      bot_difficulty_switch_time: None,
+      last_fixed_time: 0.0,
+      font,
       ball_texture,
+      hit_sound,
+      bounce_sound,
     })
   }
 
-  pub fn early_update(&mut self) {
+  fn early_update(&mut self) {
     if self.globals.window_was_resized {
-      for racket in &mut [&mut self.state.left_racket, &mut self.state.right_racket] {
-        racket.update_pos(&self.globals);
-      }
+      self.sim.set_arena_size(self.globals.window_size);
     }
   }
 
-  pub fn fixed_update(&mut self) {
-    self.debug_vectors.clear();
+  fn update(&mut self) -> SceneCommand {
+    let globals = self.globals.share();
+    let input = &globals.input_state;
 
-    let fixed_delta_time = self.globals.fixed_delta_time as f32;
-    let window_size = self.globals.window_size;
-
-    let GameState {
-      ball,
-      left_racket,
-      left_racket_controller,
-      right_racket,
-      right_racket_controller,
-      ..
-    } = &mut self.state;
-
-    for (racket, controller) in &mut [
-      (&mut *left_racket, &mut *left_racket_controller),
-      (&mut *right_racket, &mut *right_racket_controller),
-    ] {
-      let dir = controller.get_movement_direction(&self.globals, racket, ball);
-      racket.coll.accel.y = dir * RACKET_MAX_SPEED * RACKET_ACCELERATION;
+    if input.is_action_pressed(actions::SWITCH_BOT_DIFFICULTY) {
+      self.switch_bot_difficulty();
     }
 
-    {
-      let window_bouncing_bounds: Vec2f = window_size / 2.0 - ball.coll.size / 2.0;
-
-      if ball.coll.pos.x.abs() >= window_bouncing_bounds.x + ball.coll.size.x * 2.0 {
-        let winner_racket =
-          if ball.coll.pos.x >= 0.0 { &mut *left_racket } else { &mut *right_racket };
-        winner_racket.score += 1;
-        ball.throw_at(&self.globals, winner_racket);
+    match self.state {
+      PongState::Title => {
+        for (&key, &mode) in MODE_KEYS.iter().zip(&PongMode::ALL) {
+          if input.is_key_pressed(key) {
+            self.start_match(mode);
+          }
+        }
       }
 
-      if ball.coll.pos.y.abs() >= window_bouncing_bounds.y {
-        ball.coll.vel.y = -ball.coll.vel.y;
+      PongState::Serve { .. } | PongState::Playing => {
+        // NOTE: The game loop stops running the updates as soon as the window
+        // loses the focus, so this is the last chance to pause the match.
+        if input.is_action_pressed(actions::PAUSE) || !globals.window_is_focused {
+          return SceneCommand::Push(SceneKind::Pause);
+        }
       }
 
-      ball.rotation += ball.rotation_speed * fixed_delta_time * f32::consts::TAU;
-      ball.rotation %= f32::consts::TAU;
-
-      let vel = &mut ball.coll.vel;
-      let vel_magnitude = vel.magnitude();
-      if vel_magnitude != 0.0 {
-        let vel_guide = vec2(vel.x.signum(), 0.0);
-        let vel_angle = vel_guide.angle_normalized(*vel / vel_magnitude);
-        if vel_angle >= BALL_MAX_VEL_DEVIATION_ANGLE {
-          let sign = vel_guide.angle_sign(*vel);
-          *vel = vel_guide.rotated(BALL_MAX_VEL_DEVIATION_ANGLE * sign) * vel_magnitude;
+      PongState::GameOver { .. } => {
+        if input.is_action_pressed(actions::CONFIRM) {
+          self.start_match(self.mode);
+        } else if input.is_action_pressed(actions::BACK) {
+          self.show_title();
         }
       }
     }
 
-    for coll in &mut [&mut left_racket.coll, &mut right_racket.coll, &mut ball.coll] {
-      coll.vel += if !coll.accel.is_zero() { coll.accel } else { -coll.vel * coll.slowdown }
-        * fixed_delta_time;
+    SceneCommand::None
+  }
 
-      coll.vel = coll.vel.clamp_magnitude(coll.max_speed);
-      if coll.vel.sqr_magnitude() < RACKET_SPEED_EPSILON * RACKET_SPEED_EPSILON {
-        coll.vel = vec2n(0.0);
+  fn fixed_update(&mut self) {
+    match &mut self.state {
+      PongState::Title | PongState::Playing => {}
+      PongState::Serve { countdown } => {
+        *countdown -= self.globals.fixed_delta_time;
+        if *countdown <= 0.0 {
+          self.state = PongState::Playing;
+        }
+        return;
       }
-
-      coll.pos += coll.vel * fixed_delta_time;
-      coll.pos.y = coll.pos.y.clamp2_abs((window_size.y / 2.0 - coll.size.y / 2.0).abs())
+      PongState::GameOver { .. } => return,
     }
 
-    // TODO: Rewrite the collision handling system. The current one leaves a lot
-    // to be desired.
-    for racket in &mut [&mut *left_racket, &mut *right_racket] {
-      // <https://gamedev.stackexchange.com/q/136073/145058>
-      let racket_extents = racket.coll.extents();
-
-      let mut coll_point = (ball.coll.pos - racket.coll.pos).clamp2_abs(racket_extents);
-      // self.debug_vectors.push((racket.coll.pos, coll_point, color(0.0, 1.0, 0.0, 0.5)));
+    self.last_fixed_time = self.globals.fixed_time;
+    let is_playing = self.state == PongState::Playing;
+    let mut goal_winner_is_left = None;
+    for event in self.sim.step(self.globals.fixed_delta_time as f32) {
+      // The demo match behind the title screen is played silently.
+      if !is_playing {
+        continue;
+      }
+      match *event {
+        PongEvent::Hit { pos } => play_sound_at(&self.globals, &self.hit_sound, pos),
+        PongEvent::Bounce { pos } => play_sound_at(&self.globals, &self.bounce_sound, pos),
+        PongEvent::Goal { winner_is_left } => goal_winner_is_left = Some(winner_is_left),
+      }
+    }
 
-      // <https://stackoverflow.com/a/10657968/12005228>
-      if (coll_point.x / racket_extents.x).abs() >= (coll_point.y / racket_extents.y).abs() {
-        coll_point.x = coll_point.x.signum() * racket_extents.x; // vertical edge
+    if let Some(winner_is_left) = goal_winner_is_left {
+      let (left_score, right_score) = self.sim.scores();
+      self.state = if left_score >= WINNING_SCORE || right_score >= WINNING_SCORE {
+        PongState::GameOver { winner_is_left }
       } else {
-        coll_point.y = coll_point.y.signum() * racket_extents.y; // horizontal edge
-      }
-      // self.debug_vectors.push((racket.coll.pos, coll_point, color(1.0, 0.0, 0.0, 0.5)));
+        serve_state()
+      };
+    }
+  }
 
-      coll_point += racket.coll.pos;
+  fn render(&mut self, renderer: &mut renderer::Renderer) {
+    use renderer::{TextAlign, TextBlock};
+    renderer.prepare();
+    let window_size = self.globals.window_size;
 
-      let coll_dir = ball.coll.pos - coll_point;
-      // self.debug_vectors.push((coll_point, coll_dir, color(0.0, 0.0, 1.0, 0.5)));
+    // While the simulation is frozen, or covered by another scene (then the
+    // fixed updates don't reach this one), there is nothing to interpolate
+    // towards.
+    let alpha = if self.last_fixed_time == self.globals.fixed_time {
+      self.globals.fixed_update_alpha as f32
+    } else {
+      1.0
+    };
 
-      let coll_dir_magnitude = coll_dir.magnitude();
+    draw_field(
+      &self.globals,
+      renderer,
+      &mut self.font,
+      &mut self.ball_texture,
+      &self.sim,
+      alpha,
+      self.state != PongState::Title,
+    );
+
+    if let Some(switch_time) = self.bot_difficulty_switch_time {
+      if self.globals.time - switch_time < DIFFICULTY_LABEL_DURATION {
+        let text = format!("bot: {}", self.bot_difficulty.name());
+        let text_block = &mut TextBlock {
+          text: &text,
+          scale: DIFFICULTY_LABEL_TEXT_SCALE,
+          character_spacing: SCORE_LABEL_CHAR_SPACING,
+          horizontal_align: TextAlign::Center,
+          vertical_align: TextAlign::End,
+        };
+        renderer.draw_text(&mut self.font, vec2(0.0, -window_size.y / 2.0), text_block);
+      }
+    }
 
-      let current_collider = &mut ball.currently_colliding_with;
-      if current_collider.map_or(true, |id| id == racket.coll.id) {
-        // TODO: handle situations when the ball clips inside of the racket
-        if coll_dir_magnitude <= BALL_RADIUS {
-          if current_collider.is_none() {
-            *current_collider = Some(racket.coll.id);
+    if let PongState::Serve { countdown } = self.state {
+      let step = (countdown / SERVE_COUNTDOWN_STEP_DURATION).ceil().max(1.0);
+      renderer.draw_text(
+        &mut self.font,
+        Vec2f::ZERO,
+        &mut TextBlock {
+          text: &format!("{}", step),
+          scale: SCORE_LABEL_TEXT_SCALE,
+          character_spacing: SCORE_LABEL_CHAR_SPACING,
+          horizontal_align: TextAlign::Center,
+          vertical_align: TextAlign::Center,
+        },
+      );
+    }
 
-            if coll_dir_magnitude > 0.0 {
-              ball.coll.vel = ball.coll.vel.reflected_normal(coll_dir / coll_dir_magnitude);
-            } else {
-              todo!();
-            }
+    renderer.finish();
 
-            ball.coll.vel =
-              (ball.coll.vel + racket.coll.vel).with_magnitude(ball.coll.vel.magnitude());
-          }
-        } else {
-          *current_collider = None;
+    match self.state {
+      PongState::Title => {
+        let mut lines = vec!["pong".to_owned(), String::new()];
+        for (i, mode) in PongMode::ALL.iter().enumerate() {
+          lines.push(format!("{} - {}", i + 1, mode.name()));
         }
+        lines.push(String::new());
+        let bot = format!("bot: {}", self.bot_difficulty.name());
+        lines.push(scene::action_hint(&self.globals, actions::SWITCH_BOT_DIFFICULTY, &bot));
+        let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+        scene::render_overlay(&self.globals, renderer, &mut self.font, &lines);
       }
+
+      PongState::GameOver { winner_is_left } => {
+        let winner = if winner_is_left { "left" } else { "right" };
+        let rematch = scene::action_hint(&self.globals, actions::CONFIRM, "rematch");
+        let title = scene::action_hint(&self.globals, actions::BACK, "title");
+        scene::render_overlay(
+          &self.globals,
+          renderer,
+          &mut self.font,
+          &[&format!("{} wins", winner), "", &rematch, &title],
+        );
+      }
+
+      PongState::Serve { .. } | PongState::Playing => {}
     }
   }
+}
 
-  pub fn render(&mut self, renderer: &mut renderer::Renderer) {
-    use renderer::{Shape, ShapeFill, ShapeType, TextAlign, TextBlock};
-    renderer.prepare();
-    let window_size = self.globals.window_size;
+impl Pong {
+  #[inline]
+  pub fn state(&self) -> PongState { self.state }
+
+  /// Starts a new match from zero.
+  pub fn start_match(&mut self, mode: PongMode) {
+    self.mode = mode;
+    self.sim = new_sim(&self.globals, self.sims_count, mode, self.bot_difficulty);
+    self.sims_count += 1;
+    self.state = serve_state();
+  }
 
-    let GameState { ball, left_racket, right_racket, .. } = &self.state;
+  fn show_title(&mut self) {
+    self.sim = new_sim(&self.globals, self.sims_count, PongMode::BotVsBot, self.bot_difficulty);
+    self.sims_count += 1;
+    self.state = PongState::Title;
+  }
+
+  fn switch_bot_difficulty(&mut self) {
+    self.bot_difficulty = self.bot_difficulty.next();
+    self.bot_difficulty_switch_time = Some(self.globals.time);
+    info!("Bot difficulty: {}", self.bot_difficulty.name());
+
+    let sim_index = self.sims_count - 1;
+    let mode = if self.state == PongState::Title { PongMode::BotVsBot } else { self.mode };
+    let (left_controller, right_controller) =
+      new_controllers(&self.globals, sim_index, mode, self.bot_difficulty);
+    if let Some(controller) = left_controller {
+      self.sim.set_left_controller(controller);
+    }
+    if let Some(controller) = right_controller {
+      self.sim.set_right_controller(controller);
+    }
+  }
+}
+
+/// Draws the rackets and the ball of the simulation, and the scores above them
+/// if `show_scores` is set. Must be called between [`renderer::Renderer::prepare`]
+/// and [`renderer::Renderer::finish`].
+pub fn draw_field(
+  globals: &Globals,
+  renderer: &mut renderer::Renderer,
+  font: &mut renderer::Font,
+  ball_texture: &mut oogl::Texture2D,
+  sim: &PongSim,
+  alpha: f32,
+  show_scores: bool,
+) {
+  use renderer::{Shape, ShapeFill, ShapeType, TextAlign, TextBlock};
+  let window_size = globals.window_size;
+
+  if show_scores {
+    let (left_score, right_score) = sim.scores();
 
     for (text, side, align) in &[
-      (format!("{}", left_racket.score).as_str(), -1.0, TextAlign::End),
+      (format!("{}", left_score).as_str(), -1.0, TextAlign::End),
       (":", 0.0, TextAlign::Center),
-      (format!("{}", right_racket.score).as_str(), 1.0, TextAlign::Start),
+      (format!("{}", right_score).as_str(), 1.0, TextAlign::Start),
     ] {
       let text_block = &mut TextBlock {
         text,
@@ -333,65 +382,69 @@ impl Pong {
         horizontal_align: *align,
         vertical_align: TextAlign::Start,
       };
-      let (_text_block_size, char_spacing) = self.font.measure_size(text_block);
+      let (_text_block_size, char_spacing) = font.measure_size(text_block);
       let pos = vec2(side * char_spacing.x / 2.0, window_size.y / 2.0);
-      renderer.draw_text(&mut self.font, pos, text_block);
-    }
-
-    for racket in &[left_racket, right_racket] {
-      renderer.draw_shape(&mut Shape {
-        type_: ShapeType::Rectangle,
-        pos: racket.coll.pos,
-        size: racket.coll.size,
-        rotation: 0.0,
-        fill: ShapeFill::Color(RACKET_COLOR),
-        fill_clipping: None,
-      });
+      renderer.draw_text(font, pos, text_block);
     }
+  }
 
+  for racket_coll in &[sim.left_racket(), sim.right_racket()] {
     renderer.draw_shape(&mut Shape {
-      type_: ShapeType::Ellipse,
-      pos: ball.coll.pos,
-      size: ball.coll.size,
-      rotation: ball.rotation,
-      fill: ShapeFill::Texture(&mut self.ball_texture),
+      type_: ShapeType::Rectangle,
+      pos: racket_coll.render_pos(alpha),
+      size: racket_coll.size(),
+      rotation: 0.0,
+      fill: ShapeFill::Color(RACKET_COLOR),
       fill_clipping: None,
     });
+  }
 
-    // TODO: remove
-    for &(start_point, vector, color) in &self.debug_vectors {
-      let angle = vector.angle_from_x_axis();
-
-      renderer.draw_shape(&mut Shape {
-        type_: ShapeType::Rectangle,
-        pos: start_point + vector / 2.0,
-        size: vec2(vector.magnitude(), 5.0),
-        rotation: angle,
-        fill: ShapeFill::Color(color),
-        fill_clipping: None,
-      });
-
-      renderer.draw_shape(&mut Shape {
-        type_: ShapeType::Rectangle,
-        pos: start_point + vector,
-        size: vec2n(32.0),
-        rotation: angle,
-        fill: ShapeFill::Color(color),
-        fill_clipping: None,
-      });
-    }
+  let ball_coll = sim.ball();
+  renderer.draw_shape(&mut Shape {
+    type_: ShapeType::Ellipse,
+    pos: ball_coll.render_pos(alpha),
+    size: ball_coll.size(),
+    rotation: sim.ball_rotation(),
+    fill: ShapeFill::Texture(ball_texture),
+    fill_clipping: None,
+  });
+}
 
-    if !self.globals.window_is_focused {
-      renderer.draw_shape(&mut Shape {
-        type_: ShapeType::Rectangle,
-        pos: vec2n(0.0),
-        size: window_size,
-        rotation: 0.0,
-        fill: ShapeFill::Color(color(0.0, 0.0, 0.0, 0.6)),
-        fill_clipping: None,
-      });
-    }
+fn serve_state() -> PongState {
+  PongState::Serve { countdown: SERVE_COUNTDOWN_STEPS as f64 * SERVE_COUNTDOWN_STEP_DURATION }
+}
 
-    renderer.finish();
+/// Returns only the bots, for the sides which the mode gives to them.
+fn new_controllers(
+  globals: &SharedGlobals,
+  sim_index: u32,
+  mode: PongMode,
+  difficulty: BotDifficulty,
+) -> (Option<BoxedController>, Option<BoxedController>) {
+  let bot = |side: &str| {
+    let random = globals.random.stream(&format!("pong/{}/bot/{}", sim_index, side));
+    Some(difficulty.controller(random))
+  };
+  match mode {
+    PongMode::PlayerVsBot => (bot("left"), None),
+    PongMode::PlayerVsPlayer => (None, None),
+    PongMode::BotVsBot => (bot("left"), bot("right")),
   }
 }
+
+fn new_sim(
+  globals: &SharedGlobals,
+  sim_index: u32,
+  mode: PongMode,
+  difficulty: BotDifficulty,
+) -> PongSim {
+  let (left_controller, right_controller) = new_controllers(globals, sim_index, mode, difficulty);
+  let player =
+    |axis| -> BoxedController { Box::new(PlayerController { globals: globals.share(), axis }) };
+  PongSim::new(
+    globals.random.stream(&format!("pong/{}", sim_index)),
+    globals.window_size,
+    left_controller.unwrap_or_else(|| player(axes::LEFT_RACKET)),
+    right_controller.unwrap_or_else(|| player(axes::RIGHT_RACKET)),
+  )
+}
@@ -0,0 +1,268 @@
+//! The [`RacketController`]s for the rackets driven by the computer, from the
+//! naive one which simply follows the ball to the ones which work out where
+//! the ball is going to cross the line of the racket, including the bounces off
+//! the walls. [`BotDifficulty`] bundles them into the levels which the player
+//! can choose from.
+
+use cardboard_math::*;
+use prelude_plus::*;
+
+use crate::globals::Random;
+use crate::pong_sim::{CollEntry, ControllerContext, RacketController, RACKET_SLOWDOWN};
+
+const CHASING_BOT_VISION_DISTANCE: f32 = 1.0 / 2.0;
+/// The racket is left alone once it is going to stop this close to the point
+/// it is aiming for, otherwise it would keep jittering around it.
+const BOT_AIM_TOLERANCE: f32 = 8.0;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BotDifficulty {
+  Easy,
+  Normal,
+  Hard,
+  /// Never misses, unless the racket is physically too slow. Mostly useful
+  /// for testing the rest of the game.
+  Perfect,
+}
+
+impl Default for BotDifficulty {
+  fn default() -> Self { Self::Easy }
+}
+
+impl BotDifficulty {
+  pub const ALL: [Self; 4] = [Self::Easy, Self::Normal, Self::Hard, Self::Perfect];
+
+  pub fn name(self) -> &'static str {
+    match self {
+      Self::Easy => "easy",
+      Self::Normal => "normal",
+      Self::Hard => "hard",
+      Self::Perfect => "perfect",
+    }
+  }
+
+  pub fn from_name(name: &str) -> Option<Self> {
+    Self::ALL.iter().copied().find(|difficulty| difficulty.name() == name)
+  }
+
+  /// The next harder level, wraps around to the easiest one.
+  pub fn next(self) -> Self {
+    let index = Self::ALL.iter().position(|&difficulty| difficulty == self).unwrap();
+    Self::ALL[(index + 1) % Self::ALL.len()]
+  }
+
+  /// `random` is used for the mistakes of the bot, keep it separate from the
+  /// gameplay randomness, otherwise changing the difficulty would also change
+  /// how the ball is thrown.
+  pub fn controller(self, random: Random) -> Box<dyn RacketController> {
+    match self {
+      Self::Easy => Box::new(ChasingBot::default()),
+      Self::Normal => Box::new(PredictiveBot::new(random, 0.3, 1.2)),
+      Self::Hard => Box::new(PredictiveBot::new(random, 0.12, 0.5)),
+      Self::Perfect => Box::new(PerfectBot),
+    }
+  }
+}
+
+/// Simply follows the ball once it comes close enough.
+#[derive(Debug, Clone)]
+pub struct ChasingBot {
+  /// As a fraction of the width of the arena.
+  pub vision_distance: f32,
+}
+
+impl Default for ChasingBot {
+  fn default() -> Self { Self { vision_distance: CHASING_BOT_VISION_DISTANCE } }
+}
+
+impl RacketController for ChasingBot {
+  fn movement_direction(&mut self, ctx: &ControllerContext<'_>) -> f32 {
+    let vision_dist = self.vision_distance * ctx.arena_size.x;
+    if ctx.ball.vel.x * ctx.side > 0.0 && (ctx.racket.pos.x - ctx.ball.pos.x).abs() <= vision_dist
+    {
+      (ctx.ball.pos.y - ctx.racket.pos.y).signum()
+    } else {
+      0.0
+    }
+  }
+}
+
+/// Predicts where the ball is going to meet the racket, but sees the ball with
+/// a delay and aims a bit off.
+#[derive(Debug)]
+pub struct PredictiveBot {
+  /// How old the state of the ball the bot reacts to is, in seconds.
+  pub reaction_time: f32,
+  /// The largest distance between the point the bot aims for and the real one,
+  /// as a fraction of the half of the height of the racket. Past 1 the bot can
+  /// miss the ball completely. A new error is picked every time the ball turns
+  /// towards the racket.
+  pub max_aim_error: f32,
+  random: Random,
+  clock: f32,
+  /// The observed positions and velocities of the ball along with the time
+  /// they were observed at, the bot reacts to the first one.
+  observations: VecDeque<(f32, Vec2f, Vec2f)>,
+  aim_error: f32,
+  ball_was_approaching: bool,
+}
+
+impl PredictiveBot {
+  pub fn new(random: Random, reaction_time: f32, max_aim_error: f32) -> Self {
+    Self {
+      reaction_time,
+      max_aim_error,
+      random,
+      clock: 0.0,
+      observations: VecDeque::new(),
+      aim_error: 0.0,
+      ball_was_approaching: false,
+    }
+  }
+}
+
+impl RacketController for PredictiveBot {
+  fn movement_direction(&mut self, ctx: &ControllerContext<'_>) -> f32 {
+    self.clock += ctx.delta_time;
+    self.observations.push_back((self.clock, ctx.ball.pos, ctx.ball.vel));
+    let seen_time = self.clock - self.reaction_time;
+    while self.observations.len() > 1 && self.observations[1].0 <= seen_time {
+      self.observations.pop_front();
+    }
+    let (_, ball_pos, ball_vel) = self.observations[0];
+
+    let ball_is_approaching = ball_vel.x * ctx.side > 0.0;
+    if ball_is_approaching && !self.ball_was_approaching {
+      let max_error = self.max_aim_error * ctx.racket.size().y / 2.0;
+      self.aim_error = (self.random.next_f32() * 2.0 - 1.0) * max_error;
+    }
+    self.ball_was_approaching = ball_is_approaching;
+
+    let target_y = match intercept_y(ctx, ball_pos, ball_vel) {
+      Some(y) => y + self.aim_error,
+      None => 0.0,
+    };
+    steer_towards(ctx.racket, target_y)
+  }
+}
+
+/// Predicts the ball perfectly and reacts instantly.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct PerfectBot;
+
+impl RacketController for PerfectBot {
+  fn movement_direction(&mut self, ctx: &ControllerContext<'_>) -> f32 {
+    let target_y = intercept_y(ctx, ctx.ball.pos, ctx.ball.vel).unwrap_or(0.0);
+    steer_towards(ctx.racket, target_y)
+  }
+}
+
+/// The y coordinate at which the center of the ball will reach the line `x`
+/// while bouncing off the top and the bottom walls, or `None` if it moves away
+/// from the line. The ball is assumed to travel in a straight line otherwise,
+/// with no other rackets in the way.
+pub fn predict_ball_y(
+  ball_pos: Vec2f,
+  ball_vel: Vec2f,
+  ball_radius: f32,
+  x: f32,
+  arena_size: Vec2f,
+) -> Option<f32> {
+  if ball_vel.x == 0.0 {
+    return None;
+  }
+  let time = (x - ball_pos.x) / ball_vel.x;
+  if time < 0.0 {
+    return None;
+  }
+  let y = ball_pos.y + ball_vel.y * time;
+
+  let max_y = arena_size.y / 2.0 - ball_radius;
+  if max_y <= 0.0 {
+    return Some(0.0);
+  }
+  // The reflections are unfolded: with the arena mirrored at every wall the
+  // path becomes a straight line, and every other copy of it is flipped.
+  let period = 4.0 * max_y;
+  let unfolded_y = (y + max_y).rem_euclid(period);
+  let folded_y = if unfolded_y <= 2.0 * max_y { unfolded_y } else { period - unfolded_y };
+  Some(folded_y - max_y)
+}
+
+/// Where the ball is going to touch the front side of the racket.
+fn intercept_y(ctx: &ControllerContext<'_>, ball_pos: Vec2f, ball_vel: Vec2f) -> Option<f32> {
+  let ball_radius = ctx.ball.size().y / 2.0;
+  let x = ctx.racket.pos.x - ctx.side * (ctx.racket.size().x / 2.0 + ball_radius);
+  predict_ball_y(ball_pos, ball_vel, ball_radius, x, ctx.arena_size)
+}
+
+/// Accelerates the racket towards `target_y` and lets it go early enough to
+/// stop there on its own.
+fn steer_towards(racket: &CollEntry, target_y: f32) -> f32 {
+  // Without the acceleration the speed decays exponentially, so the racket
+  // still travels `vel / slowdown` before stopping.
+  let stopping_y = racket.pos.y + racket.vel.y / RACKET_SLOWDOWN;
+  let distance = target_y - stopping_y;
+  if distance.abs() <= BOT_AIM_TOLERANCE {
+    0.0
+  } else {
+    distance.signum()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const ARENA_SIZE: Vec2f = vec2(800.0, 600.0);
+  const BALL_RADIUS: f32 = 10.0;
+
+  fn predict(ball_pos: Vec2f, ball_vel: Vec2f, x: f32) -> Option<f32> {
+    predict_ball_y(ball_pos, ball_vel, BALL_RADIUS, x, ARENA_SIZE)
+  }
+
+  #[track_caller]
+  fn assert_prediction(prediction: Option<f32>, expected: f32) {
+    let y = prediction.unwrap();
+    assert!((y - expected).abs() < 1e-3, "{} != {}", y, expected);
+  }
+
+  #[test]
+  fn straight_shot() {
+    assert_prediction(predict(vec2(0.0, 0.0), vec2(100.0, 50.0), 200.0), 100.0);
+    assert_prediction(predict(vec2(300.0, -20.0), vec2(-100.0, 0.0), -100.0), -20.0);
+    // Already on the line.
+    assert_prediction(predict(vec2(200.0, 30.0), vec2(100.0, 50.0), 200.0), 30.0);
+  }
+
+  #[test]
+  fn one_bounce() {
+    // Off the top wall at 290, 110 pixels past it.
+    assert_prediction(predict(vec2(0.0, 200.0), vec2(100.0, 100.0), 200.0), 180.0);
+    assert_prediction(predict(vec2(0.0, -200.0), vec2(-100.0, -100.0), -200.0), -180.0);
+  }
+
+  #[test]
+  fn several_bounces() {
+    // Up to 290, down to -290, up to 290 and 550 pixels down again.
+    assert_prediction(predict(vec2(0.0, 0.0), vec2(100.0, 1000.0), 200.0), -260.0);
+    // Down to -290 and 510 pixels up again.
+    assert_prediction(predict(vec2(0.0, 0.0), vec2(100.0, -400.0), 200.0), 220.0);
+    // Exactly four wall-to-wall flights end up where it started.
+    assert_prediction(predict(vec2(0.0, 50.0), vec2(100.0, 1160.0), 200.0), 50.0);
+  }
+
+  #[test]
+  fn ball_moving_away_or_vertically() {
+    assert_eq!(predict(vec2(0.0, 0.0), vec2(-100.0, 50.0), 200.0), None);
+    assert_eq!(predict(vec2(0.0, 0.0), vec2(100.0, 50.0), -200.0), None);
+    assert_eq!(predict(vec2(0.0, 0.0), vec2(0.0, 50.0), 200.0), None);
+    assert_eq!(predict(vec2(200.0, 0.0), vec2(0.0, 50.0), 200.0), None);
+  }
+
+  #[test]
+  fn ball_as_tall_as_the_arena() {
+    let prediction = predict_ball_y(vec2(0.0, 0.0), vec2(100.0, 50.0), 300.0, 200.0, ARENA_SIZE);
+    assert_eq!(prediction, Some(0.0));
+  }
+}
@@ -0,0 +1,510 @@
+//! Two-player Pong over the network with [`crate::netplay`]. By default it is
+//! played by the bots headlessly, as a test bench for the netcode, and with
+//! `--play` the local racket is moved by the player in a window instead. Either
+//! two processes talk over UDP, e.g. on the same machine:
+//!
+//! ```text
+//! cargo run --release -- netplay --seed 1 --bind 127.0.0.1:7001 --peer 127.0.0.1:7002 --side left
+//! cargo run --release -- netplay --seed 1 --bind 127.0.0.1:7002 --peer 127.0.0.1:7001 --side right
+//! ```
+//!
+//! Or, without `--peer`, both of the peers run in a single process connected
+//! by a simulated network which loses and delays the packets. Each bot sees
+//! only its own peer's (possibly mispredicted) state of the game, just like a
+//! human player would.
+
+use cardboard_math::*;
+use cardboard_oogl as oogl;
+use prelude_plus::*;
+use std::net::SocketAddr;
+
+use crate::bindings::{actions, axes};
+use crate::globals::{parse_seed, GlobalRandom, SharedGlobals, DEFAULT_FIXED_UPDATES_PER_SECOND};
+use crate::netplay::{
+  LinkConditions, NetInput, RollbackGame, RollbackSession, SimulatedEndpoint, SimulatedNetwork,
+  Transport, UdpTransport,
+};
+use crate::pong;
+use crate::pong_ai::BotDifficulty;
+use crate::pong_sim::{ControllerContext, PongSim, PongSimState, RacketController};
+use crate::renderer::{self, Renderer};
+use crate::scene::{self, Scene, SceneCommand};
+use crate::settings::Settings;
+
+pub const USAGE: &str = "\
+Usage: openkrosskod netplay [OPTIONS]
+
+Options:
+  --bind ADDR             the local UDP address, e.g. 0.0.0.0:7001
+  --peer ADDR             the address of the other peer, without it both of the
+                          peers run in this process over a simulated network
+  --side left|right       the racket of this peer, the other one has to take
+                          the other side
+  --play                  open a window and move the racket of this peer with
+                          the keyboard or a gamepad instead of a bot
+  --latency SECONDS       of the simulated network
+  --jitter SECONDS        the largest extra random delay of the simulated network
+  --loss FRACTION         of the packets lost by the simulated network
+  --input-delay FRAMES    how long the local inputs are delayed for
+  --difficulty NAME       of the bots: easy, normal, hard or perfect
+  --duration SECONDS      of the game time to play
+  --seed SEED             the RNG seed, hexadecimal with 0x or decimal, has to
+                          be the same on both of the peers
+  --help                  print this message
+";
+
+const FIXED_DELTA_TIME: f64 = 1.0 / DEFAULT_FIXED_UPDATES_PER_SECOND;
+/// The session fails if nothing arrives from the other peer for this long.
+const PEER_TIMEOUT: f64 = 10.0;
+/// After everything has been confirmed the peer keeps sending for a while
+/// longer, in case its last packets with the acknowledgements get lost.
+const LINGER_TIME: f64 = 0.5;
+
+#[derive(Debug)]
+pub struct Options {
+  pub help: bool,
+  /// Starts the [`NetplayPong`] scene instead of running headlessly.
+  pub play: bool,
+  bind: Option<SocketAddr>,
+  peer: Option<SocketAddr>,
+  is_left: bool,
+  link_conditions: LinkConditions,
+  input_delay: u32,
+  difficulty: BotDifficulty,
+  duration: f64,
+  pub seed: Option<u128>,
+}
+
+pub fn parse_args(args: impl Iterator<Item = String>) -> AnyResult<Options> {
+  let mut options = Options {
+    help: false,
+    play: false,
+    bind: None,
+    peer: None,
+    is_left: true,
+    link_conditions: LinkConditions { latency: 0.05, jitter: 0.02, packet_loss: 0.1 },
+    input_delay: 2,
+    difficulty: BotDifficulty::Hard,
+    duration: 60.0,
+    seed: None,
+  };
+
+  fn parse_value<T: str::FromStr>(arg: &str, value: &str, expected: &str) -> AnyResult<T> {
+    value
+      .parse()
+      .map_err(|_| format_err!("Invalid value for {}: '{}', expected {}", arg, value, expected))
+  }
+
+  fn parse_seconds(arg: &str, value: &str) -> AnyResult<f64> {
+    let seconds: f64 = parse_value(arg, value, "a number of seconds")?;
+    ensure!(seconds.is_finite() && seconds >= 0.0, "{} must not be negative", arg);
+    Ok(seconds)
+  }
+
+  let mut args = args;
+  while let Some(arg) = args.next() {
+    let arg = arg.as_str();
+    let mut next_value = || args.next().ok_or_else(|| format_err!("{} requires a value", arg));
+    match arg {
+      "--help" | "-h" => options.help = true,
+      "--play" => options.play = true,
+      "--bind" => options.bind = Some(parse_value(arg, &next_value()?, "an address")?),
+      "--peer" => options.peer = Some(parse_value(arg, &next_value()?, "an address")?),
+      "--side" => {
+        options.is_left = match next_value()?.as_str() {
+          "left" => true,
+          "right" => false,
+          value => bail!("Invalid value for {}: '{}', expected left or right", arg, value),
+        };
+      }
+      "--latency" => options.link_conditions.latency = parse_seconds(arg, &next_value()?)?,
+      "--jitter" => options.link_conditions.jitter = parse_seconds(arg, &next_value()?)?,
+      "--loss" => {
+        let value = next_value()?;
+        let loss: f32 = parse_value(arg, &value, "a fraction")?;
+        ensure!((0.0..1.0).contains(&loss), "{} must be in the range [0, 1), got {}", arg, loss);
+        options.link_conditions.packet_loss = loss;
+      }
+      "--input-delay" => {
+        options.input_delay = parse_value(arg, &next_value()?, "a number of frames")?;
+      }
+      "--difficulty" => {
+        let value = next_value()?;
+        options.difficulty = BotDifficulty::from_name(&value).ok_or_else(|| {
+          let names: Vec<&str> = BotDifficulty::ALL.iter().map(|d| d.name()).collect();
+          format_err!("Invalid value for {}: '{}', expected {}", arg, value, names.join(", "))
+        })?;
+      }
+      "--duration" => options.duration = parse_seconds(arg, &next_value()?)?,
+      "--seed" => options.seed = Some(parse_seed(&next_value()?)?),
+      _ => bail!("Unknown option: {}", arg),
+    }
+  }
+
+  if options.peer.is_some() {
+    ensure!(options.bind.is_some(), "--peer requires --bind");
+    ensure!(options.seed.is_some(), "--peer requires --seed, the same one on both of the peers");
+  } else {
+    ensure!(options.bind.is_none(), "--bind requires --peer");
+  }
+  Ok(options)
+}
+
+/// The rackets are moved by the inputs of the session instead.
+#[derive(Debug)]
+struct NoController;
+
+impl RacketController for NoController {
+  fn movement_direction(&mut self, _ctx: &ControllerContext<'_>) -> f32 { 0.0 }
+}
+
+#[derive(Debug)]
+struct NetPong {
+  sim: PongSim,
+}
+
+impl RollbackGame for NetPong {
+  type State = PongSimState;
+
+  fn save_state(&self) -> Self::State { self.sim.save_state() }
+  fn load_state(&mut self, state: &Self::State) { self.sim.load_state(state); }
+  fn state_checksum(state: &Self::State) -> u64 { state.checksum() }
+
+  fn advance(&mut self, inputs: [NetInput; 2]) {
+    let directions = inputs.map(|input| input as f32 / NetInput::MAX as f32);
+    self.sim.step_with_inputs(FIXED_DELTA_TIME as f32, directions);
+  }
+}
+
+#[derive(Debug)]
+struct Peer<T: Transport> {
+  name: String,
+  is_left: bool,
+  game: NetPong,
+  session: RollbackSession<NetPong, T>,
+  bot: Box<dyn RacketController>,
+  last_packets_received: u32,
+  last_packet_time: f64,
+}
+
+impl<T: Transport> Peer<T> {
+  fn new(random: &GlobalRandom, options: &Options, is_left: bool, transport: T) -> Self {
+    let name = if is_left { "left" } else { "right" };
+    let arena_size = Vec2f::cast_from(Settings::default().window_size);
+    let sim = PongSim::new(
+      random.stream("netplay"),
+      arena_size,
+      Box::new(NoController),
+      Box::new(NoController),
+    );
+    let bot_random = random.stream(&format!("netplay/bot/{}", name));
+    Self {
+      name: name.to_owned(),
+      is_left,
+      game: NetPong { sim },
+      session: RollbackSession::new(transport, if is_left { 0 } else { 1 }, options.input_delay),
+      bot: options.difficulty.controller(bot_random),
+      last_packets_received: 0,
+      last_packet_time: 0.0,
+    }
+  }
+
+  /// What the bot would do on the next frame.
+  fn bot_direction(&mut self) -> f32 {
+    let ctx = self.game.sim.controller_context(self.is_left, FIXED_DELTA_TIME as f32);
+    self.bot.movement_direction(&ctx)
+  }
+
+  /// Runs the next frame with the racket moved in `direction` until
+  /// `total_frames` are done, then only exchanges the packets.
+  fn tick(&mut self, time: f64, total_frames: u32, direction: f32) -> AnyResult<()> {
+    if self.session.current_frame() < total_frames {
+      let input = (direction.clamp(-1.0, 1.0) * NetInput::MAX as f32).round() as NetInput;
+      self.session.advance_frame(&mut self.game, input)?;
+    } else {
+      self.session.poll(&mut self.game)?;
+    }
+
+    let packets_received = self.session.stats().packets_received;
+    if packets_received != self.last_packets_received {
+      self.last_packets_received = packets_received;
+      self.last_packet_time = time;
+    }
+    ensure!(
+      time - self.last_packet_time < PEER_TIMEOUT,
+      "The {} peer has received nothing from the other one for {} seconds",
+      self.name,
+      PEER_TIMEOUT,
+    );
+    Ok(())
+  }
+
+  fn is_finished(&self, total_frames: u32) -> bool {
+    self.session.current_frame() >= total_frames && self.session.confirmed_frame() >= total_frames
+  }
+
+  fn final_checksum(&self) -> u64 { self.game.sim.save_state().checksum() }
+
+  fn print_report(&self) {
+    let (left_score, right_score) = self.game.sim.scores();
+    let stats = self.session.stats();
+    println!(
+      "{:>5}: {} frames, score {}-{}, final checksum {:#018x}",
+      self.name,
+      self.session.current_frame(),
+      left_score,
+      right_score,
+      self.final_checksum(),
+    );
+    println!(
+      "       {} rollbacks, {} frames re-simulated, {} stalls, {} checksums compared",
+      stats.rollbacks, stats.resimulated_frames, stats.stalled_ticks, stats.checksums_compared,
+    );
+    println!("       {} packets sent, {} received", stats.packets_sent, stats.packets_received);
+  }
+}
+
+fn run_udp(random: &GlobalRandom, options: &Options, total_frames: u32) -> AnyResult<()> {
+  let (bind, peer_addr) = (options.bind.unwrap(), options.peer.unwrap());
+  let transport = UdpTransport::new(bind, peer_addr)?;
+  let mut peer = Peer::new(random, options, options.is_left, transport);
+  info!("Playing on the {} side against {}", peer.name, peer_addr);
+
+  let start_time = Instant::now();
+  let mut time = 0.0;
+  let mut finish_time = None;
+  loop {
+    let direction = peer.bot_direction();
+    peer.tick(time, total_frames, direction)?;
+    if finish_time.is_none() && peer.is_finished(total_frames) {
+      finish_time = Some(time);
+    }
+    if finish_time.map_or(false, |t| time - t >= LINGER_TIME) {
+      break;
+    }
+
+    time += FIXED_DELTA_TIME;
+    let elapsed = start_time.elapsed().as_secs_f64();
+    if time > elapsed {
+      thread::sleep(Duration::from_secs_f64(time - elapsed));
+    }
+  }
+
+  peer.print_report();
+  Ok(())
+}
+
+fn run_local(random: &GlobalRandom, options: &Options, total_frames: u32) -> AnyResult<()> {
+  let conditions = options.link_conditions;
+  info!(
+    "Simulating a network with {} ms of latency, {} ms of jitter and {}% packet loss",
+    conditions.latency * 1e3,
+    conditions.jitter * 1e3,
+    conditions.packet_loss * 100.0,
+  );
+  let network = SimulatedNetwork::new(conditions, random.stream("netplay/network"));
+  let mut peers = [
+    Peer::new(random, options, true, network.endpoint(0)),
+    Peer::new(random, options, false, network.endpoint(1)),
+  ];
+
+  let mut time = 0.0;
+  while !peers.iter().all(|peer| peer.is_finished(total_frames)) {
+    for peer in &mut peers {
+      let direction = peer.bot_direction();
+      peer.tick(time, total_frames, direction)?;
+    }
+    network.advance(FIXED_DELTA_TIME);
+    time += FIXED_DELTA_TIME;
+  }
+
+  for peer in &peers {
+    peer.print_report();
+  }
+  println!("{} packets lost", network.packets_lost());
+  ensure!(
+    peers[0].final_checksum() == peers[1].final_checksum(),
+    "The peers have ended up in different states"
+  );
+  Ok(())
+}
+
+/// Runs the match headlessly, see [`NetplayPong`] for the one with `--play`.
+pub fn main(options: Options) -> AnyResult<()> {
+  if options.help {
+    print!("{}", USAGE);
+    return Ok(());
+  }
+
+  let seed = match options.seed {
+    Some(seed) => seed,
+    None => GlobalRandom::init().context("Failed to generate a seed")?.seed(),
+  };
+  info!("RNG seed: {:#034x}", seed);
+  let random = GlobalRandom::with_seed(seed);
+  let total_frames = (options.duration / FIXED_DELTA_TIME).round() as u32;
+
+  let start_time = Instant::now();
+  if options.peer.is_some() {
+    run_udp(&random, &options, total_frames)?;
+  } else {
+    run_local(&random, &options, total_frames)?;
+  }
+  info!("Done in {:.2?}", start_time.elapsed());
+  Ok(())
+}
+
+/// The match with `--play`, the local racket is moved by the player and the
+/// other one by the remote peer, or by a bot on the other end of a simulated
+/// network without `--peer`. The game can't be paused, since the other peer
+/// would keep waiting for the inputs.
+///
+/// NOTE: The game loop stops running the updates while the window is out of
+/// focus, so the other peer will stall and eventually time out.
+#[derive(Debug)]
+pub struct NetplayPong {
+  globals: SharedGlobals,
+  local_peer: Peer<Box<dyn Transport>>,
+  remote_bot: Option<(SimulatedNetwork, Peer<SimulatedEndpoint>)>,
+  racket_axis: &'static str,
+  total_frames: u32,
+  time: f64,
+  finish_time: Option<f64>,
+  /// The session is over because of an error, e.g. the other peer has
+  /// disconnected.
+  failed: bool,
+  /// The value of `fixed_time` on the last fixed update which has advanced the
+  /// simulation.
+  last_fixed_time: f64,
+  font: renderer::Font,
+  ball_texture: oogl::Texture2D,
+}
+
+impl NetplayPong {
+  pub fn new(globals: SharedGlobals, options: Options) -> AnyResult<Self> {
+    let random = &globals.random;
+    let (local_peer, remote_bot) = match (options.bind, options.peer) {
+      (Some(bind), Some(peer_addr)) => {
+        let transport: Box<dyn Transport> = Box::new(UdpTransport::new(bind, peer_addr)?);
+        info!(
+          "Playing on the {} side against {}",
+          if options.is_left { "left" } else { "right" },
+          peer_addr
+        );
+        (Peer::new(random, &options, options.is_left, transport), None)
+      }
+      _ => {
+        let network =
+          SimulatedNetwork::new(options.link_conditions, random.stream("netplay/network"));
+        let transport: Box<dyn Transport> = Box::new(network.endpoint(0));
+        let local_peer = Peer::new(random, &options, options.is_left, transport);
+        let remote_peer = Peer::new(random, &options, !options.is_left, network.endpoint(1));
+        (local_peer, Some((network, remote_peer)))
+      }
+    };
+
+    let font = renderer::load_default_font(&globals)?;
+    let ball_texture =
+      renderer::load_texture_asset(&globals, "ball.png", oogl::TextureFilter::Linear)?;
+
+    Ok(Self {
+      racket_axis: if options.is_left { axes::LEFT_RACKET } else { axes::RIGHT_RACKET },
+      total_frames: (options.duration / FIXED_DELTA_TIME).round() as u32,
+      globals,
+      local_peer,
+      remote_bot,
+      time: 0.0,
+      finish_time: None,
+      failed: false,
+      last_fixed_time: 0.0,
+      font,
+      ball_texture,
+    })
+  }
+
+  fn is_over(&self) -> bool {
+    self.failed || self.finish_time.map_or(false, |t| self.time - t >= LINGER_TIME)
+  }
+
+  fn tick(&mut self) -> AnyResult<()> {
+    let direction = self.globals.input_state.action_axis_value(self.racket_axis);
+    self.local_peer.tick(self.time, self.total_frames, direction)?;
+    if let Some((network, remote_peer)) = &mut self.remote_bot {
+      let direction = remote_peer.bot_direction();
+      remote_peer.tick(self.time, self.total_frames, direction)?;
+      network.advance(FIXED_DELTA_TIME);
+    }
+    Ok(())
+  }
+}
+
+impl Scene for NetplayPong {
+  fn init(_globals: SharedGlobals) -> AnyResult<Self> {
+    bail!("The netplay match can only be started with `openkrosskod netplay --play`")
+  }
+
+  fn update(&mut self) -> SceneCommand {
+    if self.is_over() && self.globals.input_state.is_action_pressed(actions::BACK) {
+      self.globals.should_stop_game_loop.set(true);
+    }
+    SceneCommand::None
+  }
+
+  fn fixed_update(&mut self) {
+    if self.is_over() {
+      return;
+    }
+
+    let prev_frame = self.local_peer.session.current_frame();
+    if let Err(e) = self.tick() {
+      error!("{:?}", e.context("The netplay session has failed"));
+      self.failed = true;
+      return;
+    }
+    if self.local_peer.session.current_frame() != prev_frame {
+      self.last_fixed_time = self.globals.fixed_time;
+    }
+
+    self.time += FIXED_DELTA_TIME;
+    if self.finish_time.is_none() && self.local_peer.is_finished(self.total_frames) {
+      self.finish_time = Some(self.time);
+      info!("The match is over: {:?}", self.local_peer.session.stats());
+    }
+  }
+
+  fn render(&mut self, renderer: &mut Renderer) {
+    renderer.prepare();
+    // Same as in Pong, there is nothing to interpolate towards on the frames
+    // the simulation has stalled.
+    let alpha = if self.last_fixed_time == self.globals.fixed_time {
+      self.globals.fixed_update_alpha as f32
+    } else {
+      1.0
+    };
+    pong::draw_field(
+      &self.globals,
+      renderer,
+      &mut self.font,
+      &mut self.ball_texture,
+      &self.local_peer.game.sim,
+      alpha,
+      true,
+    );
+    renderer.finish();
+
+    let quit = scene::action_hint(&self.globals, actions::BACK, "quit");
+    if self.failed {
+      let lines = ["disconnected", "", &quit];
+      scene::render_overlay(&self.globals, renderer, &mut self.font, &lines);
+    } else if self.finish_time.is_some() {
+      let (left_score, right_score) = self.local_peer.game.sim.scores();
+      let result = match left_score.cmp(&right_score) {
+        cmp::Ordering::Greater => "left wins",
+        cmp::Ordering::Less => "right wins",
+        cmp::Ordering::Equal => "draw",
+      };
+      let lines = [result, "", &quit];
+      scene::render_overlay(&self.globals, renderer, &mut self.font, &lines);
+    }
+  }
+}
@@ -0,0 +1,539 @@
+//! The rules and the physics of Pong, without the window, the renderer or the
+//! audio, so that the matches can also be simulated headlessly at any speed.
+//! The rackets are driven by the [`RacketController`]s (see
+//! [`pong_ai`](crate::pong_ai) for the bots), and everything the
+//! presentation might want to react to is reported as [`PongEvent`]s.
+
+use cardboard_math::*;
+use prelude_plus::*;
+
+use crate::collision;
+use crate::gen_idx::{GenIdx, GenIdxAllocator, GenIdxVec};
+use crate::globals::{Random, RandomSnapshot};
+use crate::netplay::Checksum;
+
+pub const RACKET_SIZE: Vec2f = vec2(20.0, 200.0);
+const RACKET_OFFSET: f32 = 2.0 * RACKET_SIZE.x + BALL_RADIUS;
+
+const RACKET_MAX_SPEED: f32 = 1000.0;
+const RACKET_ACCELERATION: f32 = 8.0;
+/// The fraction of the speed lost per second while the racket isn't being
+/// accelerated.
+pub const RACKET_SLOWDOWN: f32 = 12.0;
+const RACKET_SPEED_EPSILON: f32 = 1.0;
+
+pub const BALL_RADIUS: f32 = 40.0;
+const BALL_ROTATION_SPEED: f32 = 1.0;
+const BALL_MAX_SPEED: f32 = 1400.0;
+const BALL_MAX_VEL_DEVIATION_ANGLE: f32 = (/* 90 deg */f32::consts::FRAC_PI_2) * (2.0 / 3.0);
+const BALL_THROW_DISTANCE_FROM_RACKET: f32 = RACKET_SIZE.y;
+
+type EntityId = GenIdx;
+
+#[derive(Debug, Default, Clone)]
+pub struct CollEntry {
+  shape: collision::Shape,
+  pub pos: Vec2f,
+  /// The position before the last fixed update, for the interpolation.
+  pub prev_pos: Vec2f,
+  pub vel: Vec2f,
+  accel: Vec2f,
+  slowdown: f32,
+  max_speed: f32,
+}
+
+impl CollEntry {
+  pub fn size(&self) -> Vec2f { self.shape.size() }
+
+  /// Moves the entry without interpolating from the old position.
+  fn teleport(&mut self, pos: Vec2f) {
+    self.pos = pos;
+    self.prev_pos = pos;
+  }
+
+  pub fn render_pos(&self, alpha: f32) -> Vec2f { self.prev_pos.lerp(self.pos, alpha) }
+}
+
+fn spawn_entity(
+  entities: &mut GenIdxAllocator,
+  colls: &mut GenIdxVec<CollEntry>,
+  coll: CollEntry,
+) -> EntityId {
+  let entity = entities.alloc();
+  colls.set(entity, coll);
+  entity
+}
+
+fn despawn_entity(
+  entities: &mut GenIdxAllocator,
+  colls: &mut GenIdxVec<CollEntry>,
+  entity: EntityId,
+) {
+  if entities.free(entity) {
+    colls.remove(entity);
+  }
+}
+
+#[derive(Debug, Clone)]
+struct Racket {
+  entity: EntityId,
+  side: f32,
+  score: u32,
+}
+
+impl Racket {
+  fn spawn(entities: &mut GenIdxAllocator, colls: &mut GenIdxVec<CollEntry>, side: f32) -> Self {
+    let coll = CollEntry {
+      shape: collision::Shape::aabb(RACKET_SIZE),
+      max_speed: RACKET_MAX_SPEED,
+      slowdown: RACKET_SLOWDOWN,
+      ..Default::default()
+    };
+    Self { entity: spawn_entity(entities, colls, coll), side, score: 0 }
+  }
+
+  fn update_pos(&self, arena_size: Vec2f, colls: &mut GenIdxVec<CollEntry>) {
+    let coll = &mut colls[self.entity];
+    let x = self.side * (arena_size.x / 2.0 - coll.size().x / 2.0 - RACKET_OFFSET);
+    coll.teleport(vec2(x, coll.pos.y));
+  }
+}
+
+#[derive(Debug, Clone)]
+struct Ball {
+  entity: EntityId,
+  rotation: f32,
+  rotation_speed: f32,
+}
+
+impl Ball {
+  fn new_coll() -> CollEntry {
+    CollEntry {
+      shape: collision::Shape::circle(BALL_RADIUS),
+      vel: vec2n(0.0),
+      max_speed: BALL_MAX_SPEED,
+      ..Default::default()
+    }
+  }
+
+  fn spawn(entities: &mut GenIdxAllocator, colls: &mut GenIdxVec<CollEntry>) -> Self {
+    Ball {
+      entity: spawn_entity(entities, colls, Self::new_coll()),
+      rotation: 0.0,
+      rotation_speed: BALL_ROTATION_SPEED,
+    }
+  }
+
+  fn throw_at(&self, random: &Random, colls: &mut GenIdxVec<CollEntry>, racket: &Racket) {
+    let dist = BALL_THROW_DISTANCE_FROM_RACKET;
+    let (coll, racket_coll) = colls.get2_mut(self.entity, racket.entity);
+    let (coll, racket_coll) = (coll.unwrap(), racket_coll.unwrap());
+    coll.teleport(racket_coll.pos - vec2(racket.side * (dist + racket_coll.size().x / 2.0), 0.0));
+
+    let max_angle = ((racket_coll.size().y / 2.0) / dist).atan();
+    let angle = (random.next_f32() * 2.0 - 1.0) * max_angle;
+    coll.vel = vec2(racket.side, 0.0).rotated(angle) * BALL_MAX_SPEED;
+  }
+}
+
+/// What a [`RacketController`] gets to see on every fixed update.
+#[derive(Debug, Copy, Clone)]
+pub struct ControllerContext<'a> {
+  /// -1 for the left racket, 1 for the right one.
+  pub side: f32,
+  pub racket: &'a CollEntry,
+  pub ball: &'a CollEntry,
+  pub arena_size: Vec2f,
+  pub delta_time: f32,
+}
+
+pub trait RacketController: fmt::Debug {
+  /// Returns the direction to accelerate the racket in, from -1 (down) to 1
+  /// (up).
+  fn movement_direction(&mut self, ctx: &ControllerContext<'_>) -> f32;
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PongEvent {
+  /// The ball has bounced off a racket.
+  Hit {
+    pos: Vec2f,
+  },
+  /// The ball has bounced off a wall.
+  Bounce {
+    pos: Vec2f,
+  },
+  Goal {
+    winner_is_left: bool,
+  },
+}
+
+/// Everything the simulation depends on besides the controllers, so that it
+/// can be rewound by the rollback netcode.
+#[derive(Debug, Clone)]
+pub struct PongSimState {
+  random: RandomSnapshot,
+  arena_size: Vec2f,
+  entities: GenIdxAllocator,
+  colls: GenIdxVec<CollEntry>,
+  left_racket: Racket,
+  right_racket: Racket,
+  ball: Ball,
+}
+
+impl PongSimState {
+  /// Covers the exact bits of every number, so it is equal on two machines
+  /// only if their simulations haven't diverged at all.
+  pub fn checksum(&self) -> u64 {
+    let mut checksum = Checksum::new();
+    checksum.write_u128(self.random.state);
+    checksum.write_u128(self.random.inc);
+    checksum.write_vec2(self.arena_size);
+    for (id, coll) in self.colls.iter() {
+      checksum.write_u64(id.index() as u64);
+      checksum.write_u64(id.generation() as u64);
+      for &v in &[coll.pos, coll.prev_pos, coll.vel, coll.accel] {
+        checksum.write_vec2(v);
+      }
+    }
+    checksum.write_u64(self.left_racket.score as u64);
+    checksum.write_u64(self.right_racket.score as u64);
+    checksum.write_u64(self.ball.entity.index() as u64);
+    checksum.write_f32(self.ball.rotation);
+    checksum.finish()
+  }
+}
+
+#[derive(Debug)]
+pub struct PongSim {
+  /// Gameplay randomness only, so that the rounds depend on nothing but the seed.
+  random: Random,
+  arena_size: Vec2f,
+  entities: GenIdxAllocator,
+  colls: GenIdxVec<CollEntry>,
+  left_racket: Racket,
+  left_controller: Box<dyn RacketController>,
+  right_racket: Racket,
+  right_controller: Box<dyn RacketController>,
+  ball: Ball,
+  events: Vec<PongEvent>,
+}
+
+impl PongSim {
+  pub fn new(
+    random: Random,
+    arena_size: Vec2f,
+    left_controller: Box<dyn RacketController>,
+    right_controller: Box<dyn RacketController>,
+  ) -> Self {
+    let mut entities = GenIdxAllocator::new();
+    let mut colls = GenIdxVec::new();
+
+    let left_racket = Racket::spawn(&mut entities, &mut colls, -1.0);
+    let right_racket = Racket::spawn(&mut entities, &mut colls, 1.0);
+    left_racket.update_pos(arena_size, &mut colls);
+    right_racket.update_pos(arena_size, &mut colls);
+
+    let ball = Ball::spawn(&mut entities, &mut colls);
+    let thrower = if random.next_bool() { &left_racket } else { &right_racket };
+    ball.throw_at(&random, &mut colls, thrower);
+
+    Self {
+      random,
+      arena_size,
+      entities,
+      colls,
+      left_racket,
+      left_controller,
+      right_racket,
+      right_controller,
+      ball,
+      events: Vec::new(),
+    }
+  }
+
+  #[inline]
+  pub fn arena_size(&self) -> Vec2f { self.arena_size }
+
+  /// Moves the rackets back to the sides of the arena.
+  pub fn set_arena_size(&mut self, arena_size: Vec2f) {
+    self.arena_size = arena_size;
+    for racket in &[&self.left_racket, &self.right_racket] {
+      racket.update_pos(arena_size, &mut self.colls);
+    }
+  }
+
+  pub fn set_left_controller(&mut self, controller: Box<dyn RacketController>) {
+    self.left_controller = controller;
+  }
+
+  pub fn set_right_controller(&mut self, controller: Box<dyn RacketController>) {
+    self.right_controller = controller;
+  }
+
+  pub fn save_state(&self) -> PongSimState {
+    PongSimState {
+      random: self.random.snapshot(),
+      arena_size: self.arena_size,
+      entities: self.entities.clone(),
+      colls: self.colls.clone(),
+      left_racket: self.left_racket.clone(),
+      right_racket: self.right_racket.clone(),
+      ball: self.ball.clone(),
+    }
+  }
+
+  pub fn load_state(&mut self, state: &PongSimState) {
+    self.random.restore(state.random);
+    self.arena_size = state.arena_size;
+    self.entities.clone_from(&state.entities);
+    self.colls.clone_from(&state.colls);
+    self.left_racket.clone_from(&state.left_racket);
+    self.right_racket.clone_from(&state.right_racket);
+    self.ball.clone_from(&state.ball);
+  }
+
+  /// What the controller of one of the rackets would get to see on the next
+  /// step.
+  pub fn controller_context(&self, is_left: bool, delta_time: f32) -> ControllerContext<'_> {
+    let racket = if is_left { &self.left_racket } else { &self.right_racket };
+    ControllerContext {
+      side: racket.side,
+      racket: &self.colls[racket.entity],
+      ball: &self.colls[self.ball.entity],
+      arena_size: self.arena_size,
+      delta_time,
+    }
+  }
+
+  #[inline]
+  pub fn scores(&self) -> (u32, u32) { (self.left_racket.score, self.right_racket.score) }
+  #[inline]
+  pub fn left_racket(&self) -> &CollEntry { &self.colls[self.left_racket.entity] }
+  #[inline]
+  pub fn right_racket(&self) -> &CollEntry { &self.colls[self.right_racket.entity] }
+  #[inline]
+  pub fn ball(&self) -> &CollEntry { &self.colls[self.ball.entity] }
+  #[inline]
+  pub fn ball_rotation(&self) -> f32 { self.ball.rotation }
+
+  /// The ball is replaced with a new entity after every goal, so anything which
+  /// still refers to the old one will simply stop finding it.
+  fn respawn_ball(&mut self, thrower_is_left: bool) {
+    despawn_entity(&mut self.entities, &mut self.colls, self.ball.entity);
+    self.ball.entity = spawn_entity(&mut self.entities, &mut self.colls, Ball::new_coll());
+    let racket = if thrower_is_left { &self.left_racket } else { &self.right_racket };
+    self.ball.throw_at(&self.random, &mut self.colls, racket);
+  }
+
+  /// Advances the simulation by a single fixed update, returns the events
+  /// which have happened during it.
+  pub fn step(&mut self, fixed_delta_time: f32) -> &[PongEvent] {
+    let Self {
+      arena_size,
+      colls,
+      ball,
+      left_racket,
+      left_controller,
+      right_racket,
+      right_controller,
+      ..
+    } = self;
+    let mut directions = [0.0; 2];
+    for (dir, (racket, controller)) in directions
+      .iter_mut()
+      .zip(&mut [(&*left_racket, &mut *left_controller), (&*right_racket, &mut *right_controller)])
+    {
+      *dir = controller.movement_direction(&ControllerContext {
+        side: racket.side,
+        racket: &colls[racket.entity],
+        ball: &colls[ball.entity],
+        arena_size: *arena_size,
+        delta_time: fixed_delta_time,
+      });
+    }
+    self.step_with_inputs(fixed_delta_time, directions)
+  }
+
+  /// Same as [`Self::step`], but the rackets are moved in the given directions
+  /// (the left one first) instead of asking the controllers.
+  pub fn step_with_inputs(&mut self, fixed_delta_time: f32, directions: [f32; 2]) -> &[PongEvent] {
+    self.events.clear();
+    for (_, coll) in self.colls.iter_mut() {
+      coll.prev_pos = coll.pos;
+    }
+
+    let arena_size = self.arena_size;
+
+    for (racket, dir) in [&self.left_racket, &self.right_racket].iter().zip(&directions) {
+      self.colls[racket.entity].accel.y = dir * RACKET_MAX_SPEED * RACKET_ACCELERATION;
+    }
+
+    {
+      let ball_coll = &self.colls[self.ball.entity];
+      let window_bouncing_bounds: Vec2f = arena_size / 2.0 - ball_coll.size() / 2.0;
+      if ball_coll.pos.x.abs() >= window_bouncing_bounds.x + ball_coll.size().x * 2.0 {
+        let winner_is_left = ball_coll.pos.x >= 0.0;
+        let winner_racket =
+          if winner_is_left { &mut self.left_racket } else { &mut self.right_racket };
+        winner_racket.score += 1;
+        self.events.push(PongEvent::Goal { winner_is_left });
+        self.respawn_ball(winner_is_left);
+      }
+    }
+
+    let Self { colls, ball, left_racket, right_racket, events, .. } = self;
+
+    {
+      let ball_coll = &mut colls[ball.entity];
+      let window_bouncing_bounds: Vec2f = arena_size / 2.0 - ball_coll.size() / 2.0;
+
+      if ball_coll.pos.y.abs() >= window_bouncing_bounds.y {
+        ball_coll.vel.y = -ball_coll.vel.y;
+        events.push(PongEvent::Bounce { pos: ball_coll.pos });
+      }
+
+      ball.rotation += ball.rotation_speed * fixed_delta_time * f32::consts::TAU;
+      ball.rotation %= f32::consts::TAU;
+
+      let vel = &mut ball_coll.vel;
+      let vel_magnitude = vel.magnitude();
+      if vel_magnitude != 0.0 {
+        let vel_guide = vec2(vel.x.signum(), 0.0);
+        let vel_angle = vel_guide.angle_normalized(*vel / vel_magnitude);
+        if vel_angle >= BALL_MAX_VEL_DEVIATION_ANGLE {
+          let sign = vel_guide.angle_sign(*vel);
+          *vel = vel_guide.rotated(BALL_MAX_VEL_DEVIATION_ANGLE * sign) * vel_magnitude;
+        }
+      }
+    }
+
+    for (id, coll) in colls.iter_mut() {
+      coll.vel += if !coll.accel.is_zero() { coll.accel } else { -coll.vel * coll.slowdown }
+        * fixed_delta_time;
+
+      coll.vel = coll.vel.clamp_magnitude(coll.max_speed);
+      if coll.vel.sqr_magnitude() < RACKET_SPEED_EPSILON * RACKET_SPEED_EPSILON {
+        coll.vel = vec2n(0.0);
+      }
+
+      // The ball is moved separately, after all of the rackets.
+      if id != ball.entity {
+        coll.pos += coll.vel * fixed_delta_time;
+        coll.pos.y = coll.pos.y.clamp2_abs((arena_size.y / 2.0 - coll.size().y / 2.0).abs());
+      }
+    }
+
+    // The ball is fast enough to jump over a racket within a single update, so
+    // its whole path is swept to find the first racket it runs into.
+    let mut remaining_time = fixed_delta_time;
+    let mut hit_racket: Option<EntityId> = None;
+    loop {
+      let ball_coll = &colls[ball.entity];
+      let mut first_hit: Option<(collision::SweepHit, EntityId)> = None;
+      for racket in &[&*left_racket, &*right_racket] {
+        // Don't hit the same racket twice within a single update, in case
+        // it is still moving towards the ball after the reflection.
+        if hit_racket == Some(racket.entity) {
+          continue;
+        }
+        let racket_coll = &colls[racket.entity];
+        let half_extents = racket_coll.size() / 2.0;
+        // The rackets have already been moved to the end of the update, so the
+        // sweep starts from where the racket was at the current moment and goes
+        // in its frame of reference.
+        let racket_pos = racket_coll.pos - racket_coll.vel * remaining_time;
+        let motion = (ball_coll.vel - racket_coll.vel) * remaining_time;
+        let hit = collision::sweep_circle_vs_aabb(
+          ball_coll.pos,
+          BALL_RADIUS,
+          motion,
+          racket_pos,
+          half_extents,
+        );
+        if let Some(hit) = hit {
+          if first_hit.map_or(true, |(first, _)| hit.time < first.time) {
+            first_hit = Some((hit, racket.entity));
+          }
+        }
+      }
+
+      let (hit, racket_entity) = match first_hit {
+        Some(v) => v,
+        None => break,
+      };
+      let (ball_coll, racket_coll) = colls.get2_mut(ball.entity, racket_entity);
+      let (ball_coll, racket_coll) = (ball_coll.unwrap(), racket_coll.unwrap());
+
+      let hit_time = remaining_time * hit.time;
+      ball_coll.pos += ball_coll.vel * hit_time + hit.normal * hit.depth;
+      remaining_time -= hit_time;
+      hit_racket = Some(racket_entity);
+
+      // The ball is let go when it overlaps the racket but already moves away
+      // from it, e.g. after the racket has been pushed into it.
+      let relative_vel = ball_coll.vel - racket_coll.vel;
+      if relative_vel.dot(hit.normal) < 0.0 {
+        let speed = ball_coll.vel.magnitude();
+        ball_coll.vel = ball_coll.vel.reflected_normal(hit.normal);
+        ball_coll.vel = (ball_coll.vel + racket_coll.vel).with_magnitude(speed);
+        events.push(PongEvent::Hit { pos: ball_coll.pos });
+      }
+    }
+
+    let ball_coll = &mut colls[ball.entity];
+    ball_coll.pos += ball_coll.vel * remaining_time;
+    ball_coll.pos.y =
+      ball_coll.pos.y.clamp2_abs((arena_size.y / 2.0 - ball_coll.size().y / 2.0).abs());
+
+    &self.events
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::pong_ai::ChasingBot;
+
+  const ARENA_SIZE: Vec2f = vec2(800.0, 600.0);
+  const FIXED_DELTA_TIME: f32 = 1.0 / 120.0;
+
+  fn new_sim(seed: u128) -> PongSim {
+    PongSim::new(
+      Random::new(seed, 0),
+      ARENA_SIZE,
+      Box::new(ChasingBot { vision_distance: 1.0 }),
+      Box::new(ChasingBot::default()),
+    )
+  }
+
+  /// The checksums of the state after every step.
+  fn run(sim: &mut PongSim, steps: usize) -> (Vec<u64>, Vec<PongEvent>) {
+    let mut checksums = Vec::new();
+    let mut events = Vec::new();
+    for _ in 0..steps {
+      events.extend_from_slice(sim.step(FIXED_DELTA_TIME));
+      checksums.push(sim.save_state().checksum());
+    }
+    (checksums, events)
+  }
+
+  #[test]
+  fn same_seed_same_simulation() {
+    let (checksums, events) = run(&mut new_sim(42), 3000);
+    assert!(events.iter().any(|event| matches!(event, PongEvent::Goal { .. })));
+    assert_eq!(run(&mut new_sim(42), 3000), (checksums.clone(), events));
+    assert_ne!(run(&mut new_sim(43), 3000).0, checksums);
+  }
+
+  #[test]
+  fn loading_a_state_rewinds_the_simulation() {
+    let mut sim = new_sim(42);
+    run(&mut sim, 500);
+    let state = sim.save_state();
+    let first_run = run(&mut sim, 1000);
+    sim.load_state(&state);
+    assert_eq!(sim.save_state().checksum(), state.checksum());
+    assert_eq!(run(&mut sim, 1000), first_run);
+  }
+}
@@ -2,20 +2,61 @@ use cardboard_math::*;
 use cardboard_oogl as oogl;
 use cardboard_oogl::traits::*;
 use prelude_plus::*;
+use serde::Deserialize;
+use serde_json as json;
 
 use crate::globals::{Globals, SharedGlobals};
 
+/// The maximum number of quads in a single draw call, limited by the `u16`
+/// indices: 4 vertices per quad must fit into 65536.
+const MAX_BATCH_QUADS: usize = 4096;
+const QUAD_CORNERS: [Vec2f; 4] =
+  [vec2(-1.0, -1.0), vec2(-1.0, 1.0), vec2(1.0, 1.0), vec2(1.0, -1.0)];
+const SPRITE_TEXTURE_UNIT: oogl::TextureUnit = 0;
+const ATTRIB_LOCATIONS: &[(&str, u32)] =
+  &[("a_pos", 0), ("a_local_pos", 1), ("a_texcoord", 2), ("a_color", 3)];
+
+const DEFAULT_FONT_PATH: &str = "font.json";
+
+#[repr(C, packed)]
+#[derive(Copy, Debug, Clone, Default)]
+struct SpriteVertex {
+  pos: Vec2f,
+  local_pos: Vec2f,
+  texcoord: Vec2f,
+  color: Colorf,
+}
+
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct RendererStats {
+  pub draw_calls: u32,
+  pub quads: u32,
+}
+
+/// Draws shapes in batches: the quads are accumulated into a streaming vertex
+/// buffer and are flushed in a single draw call only when the texture or the
+/// program changes, the batch fills up, or on [`Self::finish`].
+///
+/// NOTE: The texture of the current batch is bound when the batch is started,
+/// so textures must not be bound by anyone else between [`Self::prepare`] and
+/// [`Self::finish`].
 #[derive(Debug)]
 pub struct Renderer {
   globals: SharedGlobals,
 
-  vbo: oogl::VertexBuffer<[i8; 2]>,
+  vbo: oogl::VertexBuffer<SpriteVertex>,
+  ebo: oogl::ElementBuffer<u16>,
   white_texture: oogl::Texture2D,
 
   rectangle_program: oogl::Program,
   rectangle_program_reflection: RendererProgramReflection,
   ellipse_program: oogl::Program,
   ellipse_program_reflection: RendererProgramReflection,
+
+  batch_vertices: Vec<SpriteVertex>,
+  batch_shape_type: ShapeType,
+  batch_texture_addr: u32,
+  stats: RendererStats,
 }
 
 impl Renderer {
@@ -28,46 +69,46 @@ impl Renderer {
     let ellipse_fragment_shader =
       load_shader_asset(&globals, "shaders/ellipse.frag.glsl", ShaderType::Fragment)?;
 
-    let rectangle_program = load_program_asset(
+    let rectangle_program = load_program_asset_with_attrib_locations(
       &globals,
       "Renderer.rectangle",
       &[&common_vertex_shader, &rectangle_fragment_shader],
+      ATTRIB_LOCATIONS,
     )?;
     let rectangle_program_reflection = RendererProgramReflection::new(&rectangle_program);
 
-    let ellipse_program = load_program_asset(
+    let ellipse_program = load_program_asset_with_attrib_locations(
       &globals,
       "Renderer.ellipse",
       &[&common_vertex_shader, &ellipse_fragment_shader],
+      ATTRIB_LOCATIONS,
     )?;
     let ellipse_program_reflection = RendererProgramReflection::new(&ellipse_program);
 
-    assert_eq!(
-      rectangle_program_reflection.a_pos.location(),
-      ellipse_program_reflection.a_pos.location()
-    );
-    assert_eq!(
-      rectangle_program_reflection.a_pos.data_type(),
-      ellipse_program_reflection.a_pos.data_type()
-    );
-
-    let mut vbo = oogl::VertexBuffer::new(
-      globals.gl.share(),
-      oogl::BufferUsageHint::StaticDraw,
-      // this attribute pointer will be the same for both programs because both
-      // use the same vertex shader, as such the VBO can be shared
-      vec![
-        //
-        rectangle_program_reflection
-          .a_pos
-          .to_pointer_simple_with_cast(oogl::AttribPtrTypeName::I8),
-      ],
-    );
-
+    // The attribute locations are fixed, so the VBO can be shared between both
+    // programs. The ellipse program is used for reflection because in the
+    // rectangle one `a_local_pos` is optimized out.
+    let vbo_attribs = vec![
+      ellipse_program_reflection.a_pos.to_pointer_simple(),
+      ellipse_program_reflection.a_local_pos.to_pointer_simple(),
+      ellipse_program_reflection.a_texcoord.to_pointer_simple(),
+      ellipse_program_reflection.a_color.to_pointer_simple(),
+    ];
+
+    let mut vbo =
+      oogl::VertexBuffer::new(globals.gl.share(), oogl::BufferUsageHint::StreamDraw, vbo_attribs);
     vbo.set_debug_label(b"Renderer.vbo");
+    vbo.bind().alloc(MAX_BATCH_QUADS * QUAD_CORNERS.len());
+
+    let mut ebo = oogl::ElementBuffer::new(globals.gl.share(), oogl::BufferUsageHint::StaticDraw);
+    ebo.set_debug_label(b"Renderer.ebo");
     {
-      let bound_vbo = vbo.bind();
-      bound_vbo.alloc_and_set(&[[-1, -1], [-1, 1], [1, 1], [1, -1]]);
+      let mut indices = Vec::with_capacity(MAX_BATCH_QUADS * 6);
+      for i in 0..MAX_BATCH_QUADS as u16 {
+        let first = i * QUAD_CORNERS.len() as u16;
+        indices.extend_from_slice(&[first, first + 1, first + 2, first, first + 2, first + 3]);
+      }
+      ebo.bind().alloc_and_set(&indices);
     }
 
     let mut white_texture =
@@ -85,15 +126,27 @@ impl Renderer {
       globals,
 
       vbo,
+      ebo,
       white_texture,
 
       rectangle_program,
       rectangle_program_reflection,
       ellipse_program,
       ellipse_program_reflection,
+
+      batch_vertices: Vec::with_capacity(MAX_BATCH_QUADS * QUAD_CORNERS.len()),
+      batch_shape_type: ShapeType::Rectangle,
+      batch_texture_addr: 0,
+      stats: RendererStats::default(),
     })
   }
 
+  /// The counters accumulated since the last call to [`Self::take_stats`].
+  #[inline(always)]
+  pub fn stats(&self) -> RendererStats { self.stats }
+  #[inline]
+  pub fn take_stats(&mut self) -> RendererStats { mem::take(&mut self.stats) }
+
   pub fn prepare(&mut self) {
     if self.globals.window_was_resized {
       let window_size = self.globals.window_size;
@@ -112,60 +165,103 @@ impl Renderer {
   }
 
   pub fn finish(&mut self) {
+    self.flush();
     let bound_vbo = self.vbo.bind();
     bound_vbo.disable_attribs();
   }
 
   pub fn draw_shape(&mut self, shape: &mut Shape) {
-    let (color, bound_texture) = match &mut shape.fill {
-      ShapeFill::Color(color) => (*color, self.white_texture.bind(None)),
-      ShapeFill::Texture(bound_texture) => (colorn(1.0, 1.0), bound_texture.bind(None)),
+    let texture_addr = match &shape.fill {
+      ShapeFill::Color(_) => self.white_texture.addr(),
+      ShapeFill::Texture(texture) => texture.addr(),
     };
 
-    let (program, reflection) = match shape.type_ {
-      ShapeType::Rectangle => {
-        let program = self.rectangle_program.bind();
-        (program, &self.rectangle_program_reflection)
-      }
-      ShapeType::Ellipse => {
-        let program = self.ellipse_program.bind();
-        (program, &self.ellipse_program_reflection)
-      }
+    let batch_is_full = self.batch_vertices.len() >= MAX_BATCH_QUADS * QUAD_CORNERS.len();
+    if batch_is_full
+      || shape.type_ != self.batch_shape_type
+      || texture_addr != self.batch_texture_addr
+    {
+      self.flush();
+    }
+    if self.batch_vertices.is_empty() {
+      let texture = match &mut shape.fill {
+        ShapeFill::Color(_) => &mut self.white_texture,
+        ShapeFill::Texture(texture) => &mut **texture,
+      };
+      drop(texture.bind(Some(SPRITE_TEXTURE_UNIT)));
+      self.batch_shape_type = shape.type_;
+      self.batch_texture_addr = texture_addr;
+    }
+
+    let color = match shape.fill {
+      ShapeFill::Color(color) => color,
+      ShapeFill::Texture(_) => colorn(1.0, 1.0),
+    };
+    let (tex_clipping_offset, tex_clipping_size) = match &shape.fill_clipping {
+      Some(clipping) => (clipping.offset, clipping.size),
+      None => (vec2n(0.0), vec2n(1.0)),
     };
+    let (rotation_sin, rotation_cos) = shape.rotation.sin_cos();
+
+    for &corner in &QUAD_CORNERS {
+      let offset = corner * 0.5 * shape.size;
+      let rotated_offset = vec2(
+        offset.x * rotation_cos - offset.y * rotation_sin,
+        offset.x * rotation_sin + offset.y * rotation_cos,
+      );
+      let texcoord = vec2(0.5 + 0.5 * corner.x, 0.5 - 0.5 * corner.y);
+      self.batch_vertices.push(SpriteVertex {
+        pos: shape.pos + rotated_offset,
+        local_pos: corner,
+        texcoord: texcoord * tex_clipping_size + tex_clipping_offset,
+        color,
+      });
+    }
+  }
 
-    reflection.u_pos.set(&program, &shape.pos);
-    reflection.u_size.set(&program, &shape.size);
-    reflection.u_rotation.set(&program, &shape.rotation);
-    reflection.u_color.set(&program, &color);
-    reflection.u_tex.set(&program, &bound_texture.unit());
-    if let Some(clipping) = &shape.fill_clipping {
-      reflection.u_tex_clipping_offset.set(&program, &clipping.offset);
-      reflection.u_tex_clipping_size.set(&program, &clipping.size);
-    } else {
-      reflection.u_tex_clipping_offset.set(&program, &vec2n(0.0));
-      reflection.u_tex_clipping_size.set(&program, &vec2n(1.0));
+  fn flush(&mut self) {
+    if self.batch_vertices.is_empty() {
+      return;
     }
 
-    let bound_vbo = self.vbo.bind();
-    bound_vbo.draw(&program, oogl::DrawPrimitive::TriangleFan);
+    let (program, reflection) = match self.batch_shape_type {
+      ShapeType::Rectangle => (&mut self.rectangle_program, &self.rectangle_program_reflection),
+      ShapeType::Ellipse => (&mut self.ellipse_program, &self.ellipse_program_reflection),
+    };
+    let bound_program = program.bind();
+    reflection.u_tex.set(&bound_program, &SPRITE_TEXTURE_UNIT);
+
+    {
+      let bound_vbo = self.vbo.bind();
+      // Orphaning lets the driver give us a fresh block of memory instead of
+      // waiting until the previous draw call is done with the buffer.
+      bound_vbo.orphan_data();
+      bound_vbo.set_slice(..self.batch_vertices.len(), &self.batch_vertices);
+    }
+
+    let quads = self.batch_vertices.len() / QUAD_CORNERS.len();
+    let bound_ebo = self.ebo.bind();
+    bound_ebo.draw_slice(&bound_program, oogl::DrawPrimitive::Triangles, ..quads * 6);
+
+    self.stats.draw_calls += 1;
+    self.stats.quads += quads as u32;
+    self.batch_vertices.clear();
   }
 
   pub fn draw_text(&mut self, font: &mut Font, pos: Vec2f, text_block: &mut TextBlock<'_>) {
-    let font_char_grid_size_f = Vec2f::cast_from(font.grid_cell_size);
-    let font_char_size_f = Vec2f::cast_from(font.character_size);
     let font_texture_size_f = Vec2f::cast_from(font.texture.size());
-    let char_size = font_char_size_f * text_block.scale;
-    let (text_block_size, char_spacing) = font.measure_size(text_block);
+    let line_height = font.glyph_map.character_size.y as f32;
+    let extra_spacing = font.glyph_map.extra_spacing(text_block);
+    let (text_block_size, _) = font.measure_size(text_block);
 
-    let mut char_pos = pos;
-    char_pos.x += char_spacing.x / 2.0;
-    char_pos.x -= text_block_size.x
+    let mut pen_pos = pos;
+    pen_pos.x -= text_block_size.x
       * match text_block.horizontal_align {
         TextAlign::Start => 0.0,
         TextAlign::Center => 1.0 / 2.0,
         TextAlign::End => 1.0,
       };
-    char_pos.y -= text_block_size.y
+    pen_pos.y -= text_block_size.y
       * match text_block.vertical_align {
         TextAlign::Start => 0.5,
         TextAlign::Center => 0.0,
@@ -173,26 +269,37 @@ impl Renderer {
       };
 
     for chr in text_block.text.chars() {
-      let chr = chr as u32;
-      if chr >= font.grid_size.x * font.grid_size.y {
-        return;
+      let glyph = match font.glyph(chr) {
+        Some(glyph) => *glyph,
+        None => continue,
+      };
+      let glyph_pos_f = Vec2f::cast_from(glyph.pos);
+      let glyph_size_f = Vec2f::cast_from(glyph.size);
+      let glyph_offset_f = Vec2f::cast_from(glyph.offset);
+
+      // The glyph offsets point down from the top of the line, like the
+      // texture coordinates, and the extra spacing is split evenly between
+      // both sides of a glyph.
+      let glyph_center = vec2(
+        extra_spacing.x / 2.0 + glyph_offset_f.x + glyph_size_f.x / 2.0,
+        line_height / 2.0 - glyph_offset_f.y - glyph_size_f.y / 2.0,
+      );
+
+      if glyph.size.x > 0 && glyph.size.y > 0 {
+        self.draw_shape(&mut Shape {
+          type_: ShapeType::Rectangle,
+          pos: pen_pos + glyph_center * text_block.scale,
+          size: glyph_size_f * text_block.scale,
+          rotation: 0.0,
+          fill: ShapeFill::Texture(&mut font.texture),
+          fill_clipping: Some(ShapeClipping {
+            offset: glyph_pos_f / font_texture_size_f,
+            size: glyph_size_f / font_texture_size_f,
+          }),
+        });
       }
 
-      let char_tex_pos: Vec2f = vec2(chr % font.grid_size.x, chr / font.grid_size.x).cast_into();
-
-      self.draw_shape(&mut Shape {
-        type_: ShapeType::Rectangle,
-        pos: char_pos,
-        size: char_size,
-        rotation: 0.0,
-        fill: ShapeFill::Texture(&mut font.texture),
-        fill_clipping: Some(ShapeClipping {
-          offset: (font_char_grid_size_f / font_texture_size_f) * char_tex_pos,
-          size: font_char_size_f / font_texture_size_f,
-        }),
-      });
-
-      char_pos.x += char_spacing.x;
+      pen_pos.x += (glyph.advance as f32 + extra_spacing.x) * text_block.scale.x;
     }
   }
 }
@@ -201,14 +308,11 @@ oogl::program_reflection_block!({
   #[derive(Debug)]
   struct RendererProgramReflection {
     a_pos: oogl::Attrib<Vec2f>,
+    a_local_pos: oogl::Attrib<Vec2f>,
+    a_texcoord: oogl::Attrib<Vec2f>,
+    a_color: oogl::Attrib<Colorf>,
     u_window_size: oogl::Uniform<Vec2f>,
-    u_pos: oogl::Uniform<Vec2f>,
-    u_size: oogl::Uniform<Vec2f>,
-    u_rotation: oogl::Uniform<f32>,
-    u_color: oogl::Uniform<Colorf>,
     u_tex: oogl::Uniform<oogl::TextureUnit>,
-    u_tex_clipping_offset: oogl::Uniform<Vec2f>,
-    u_tex_clipping_size: oogl::Uniform<Vec2f>,
   }
 });
 
@@ -222,7 +326,7 @@ pub struct Shape<'a> {
   pub fill_clipping: Option<ShapeClipping>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum ShapeType {
   Rectangle,
   Ellipse,
@@ -240,22 +344,187 @@ pub struct ShapeClipping {
   pub size: Vec2f,
 }
 
+/// A sprite font, see [`FontDescriptor`] for how it is described.
 #[derive(Debug)]
 pub struct Font {
   pub texture: oogl::Texture2D,
-  pub grid_size: Vec2u32,
-  pub grid_cell_size: Vec2u32,
-  pub character_size: Vec2u32,
+  pub glyph_map: GlyphMap,
+}
+
+/// All of the coordinates are in the pixels of the font texture.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Glyph {
+  /// Of the top left corner of the glyph in the texture.
+  pub pos: Vec2u32,
+  pub size: Vec2u32,
+  /// From the current position of the pen, which is at the top of the line,
+  /// to the top left corner of the glyph.
+  #[serde(default)]
+  pub offset: Vec2i32,
+  /// How far the pen moves after the glyph.
+  pub advance: u32,
 }
 
 impl Font {
+  pub fn new(texture: oogl::Texture2D, descriptor: &FontDescriptor) -> AnyResult<Self> {
+    let glyph_map = GlyphMap::new(descriptor, texture.size())?;
+    Ok(Self { texture, glyph_map })
+  }
+
+  #[inline(always)]
+  pub fn glyph(&self, chr: char) -> Option<&Glyph> { self.glyph_map.glyph(chr) }
+
+  #[inline(always)]
+  pub fn measure_size(&self, text_block: &TextBlock<'_>) -> (Vec2f, Vec2f) {
+    self.glyph_map.measure_size(text_block)
+  }
+}
+
+/// The layout of a [`Font`], built from its descriptor and checked against the
+/// size of the texture.
+#[derive(Debug, Clone)]
+pub struct GlyphMap {
+  /// The nominal size of a character: the height of a line and the unit of
+  /// [`TextBlock::character_spacing`]. The glyphs themselves may be larger or
+  /// smaller.
+  pub character_size: Vec2u32,
+  pub glyphs: HashMap<char, Glyph>,
+  /// Drawn in place of the characters which have no glyphs, those are skipped
+  /// entirely if this is `None`.
+  pub fallback_glyph: Option<Glyph>,
+}
+
+impl GlyphMap {
+  pub fn new(descriptor: &FontDescriptor, texture_size: Vec2u32) -> AnyResult<Self> {
+    let character_size = descriptor.character_size;
+    ensure!(character_size.x > 0 && character_size.y > 0, "The character size must not be zero");
+
+    let checked_add =
+      |a: Vec2u32, b: Vec2u32| Some(vec2(a.x.checked_add(b.x)?, a.y.checked_add(b.y)?));
+    let checked_mul =
+      |a: Vec2u32, b: Vec2u32| Some(vec2(a.x.checked_mul(b.x)?, a.y.checked_mul(b.y)?));
+
+    let mut glyphs = HashMap::new();
+    for (i, grid) in descriptor.grids.iter().enumerate() {
+      ensure!(grid.columns > 0, "The grid #{} has no columns", i);
+      for index in 0..grid.count {
+        let chr = (grid.first_char as u32)
+          .checked_add(index)
+          .and_then(char::from_u32)
+          .ok_or_else(|| format_err!("The grid #{} covers invalid codepoints", i))?;
+        let cell = vec2(index % grid.columns, index / grid.columns);
+        let pos = checked_mul(cell, grid.cell_size)
+          .and_then(|offset| checked_add(grid.origin, offset))
+          .ok_or_else(|| format_err!("The grid #{} is too large", i))?;
+        let size = grid.glyph_size.unwrap_or(grid.cell_size);
+        glyphs.insert(
+          chr,
+          Glyph { pos, size, offset: grid.offset, advance: grid.advance.unwrap_or(size.x) },
+        );
+      }
+    }
+    for (&chr, &glyph) in &descriptor.glyphs {
+      glyphs.insert(chr, glyph);
+    }
+
+    for (&chr, glyph) in &glyphs {
+      let fits = match checked_add(glyph.pos, glyph.size) {
+        Some(end) => end.x <= texture_size.x && end.y <= texture_size.y,
+        None => false,
+      };
+      ensure!(
+        fits,
+        "The glyph of {:?} doesn't fit into the {}x{} texture",
+        chr,
+        texture_size.x,
+        texture_size.y,
+      );
+    }
+
+    let fallback_glyph = match descriptor.fallback {
+      Some(chr) => {
+        Some(*glyphs.get(&chr).ok_or_else(|| format_err!("No glyph for the fallback {:?}", chr))?)
+      }
+      None => None,
+    };
+
+    Ok(Self { character_size, glyphs, fallback_glyph })
+  }
+
+  #[inline]
+  pub fn glyph(&self, chr: char) -> Option<&Glyph> {
+    self.glyphs.get(&chr).or(self.fallback_glyph.as_ref())
+  }
+
+  /// In the unscaled pixels of the font.
+  fn extra_spacing(&self, text_block: &TextBlock<'_>) -> Vec2f {
+    Vec2f::cast_from(self.character_size) * text_block.character_spacing
+  }
+
+  /// Returns the size of the whole text block and the space taken up by a
+  /// nominal character (see [`Self::character_size`]), the latter is also the
+  /// height of a line.
   pub fn measure_size(&self, text_block: &TextBlock<'_>) -> (Vec2f, Vec2f) {
-    let char_size = Vec2f::cast_from(self.character_size) * text_block.scale;
-    let char_spacing = char_size * (vec2n(1.0) + text_block.character_spacing);
-    (char_spacing * vec2(text_block.text.len() as f32, 1.0), char_spacing)
+    let extra_spacing = self.extra_spacing(text_block);
+    let char_spacing = (Vec2f::cast_from(self.character_size) + extra_spacing) * text_block.scale;
+    let width: f32 = (text_block.text.chars())
+      .filter_map(|chr| self.glyph(chr))
+      .map(|glyph| glyph.advance as f32 + extra_spacing.x)
+      .sum();
+    (vec2(width * text_block.scale.x, char_spacing.y), char_spacing)
   }
 }
 
+/// The contents of a font descriptor file, a JSON object which maps the
+/// characters to the glyphs in the texture. The glyphs laid out in a regular
+/// grid can be described all at once, and then the individual glyphs from
+/// `glyphs` override the ones from the grids:
+///
+/// ```json
+/// {
+///   "texture": "font.png",
+///   "character_size": [3, 5],
+///   "fallback": "?",
+///   "grids": [{ "first_char": " ", "count": 95, "columns": 16, "cell_size": [4, 6] }],
+///   "glyphs": { "é": { "pos": [0, 48], "size": [3, 7], "offset": [0, -2], "advance": 3 } }
+/// }
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FontDescriptor {
+  /// Relative to the directory of the descriptor.
+  pub texture: String,
+  pub character_size: Vec2u32,
+  #[serde(default)]
+  pub fallback: Option<char>,
+  #[serde(default)]
+  pub grids: Vec<GlyphGrid>,
+  #[serde(default)]
+  pub glyphs: BTreeMap<char, Glyph>,
+}
+
+/// Consecutive characters in the cells of the same size, row by row.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GlyphGrid {
+  pub first_char: char,
+  pub count: u32,
+  /// Of the top left corner of the first cell.
+  #[serde(default)]
+  pub origin: Vec2u32,
+  pub columns: u32,
+  pub cell_size: Vec2u32,
+  /// The glyphs fill the whole cells if unset.
+  #[serde(default)]
+  pub glyph_size: Option<Vec2u32>,
+  #[serde(default)]
+  pub offset: Vec2i32,
+  /// The width of the glyphs if unset.
+  #[serde(default)]
+  pub advance: Option<u32>,
+}
+
 #[derive(Debug)]
 pub struct TextBlock<'a> {
   pub text: &'a str,
@@ -289,7 +558,16 @@ pub fn load_program_asset(
   name: &str,
   shaders: &[&oogl::Shader],
 ) -> AnyResult<oogl::Program> {
-  let program = link_program(globals.gl.share(), shaders)
+  load_program_asset_with_attrib_locations(globals, name, shaders, &[])
+}
+
+pub fn load_program_asset_with_attrib_locations(
+  globals: &Globals,
+  name: &str,
+  shaders: &[&oogl::Shader],
+  attrib_locations: &[(&str, u32)],
+) -> AnyResult<oogl::Program> {
+  let program = link_program_with_attrib_locations(globals.gl.share(), shaders, attrib_locations)
     .with_context(|| format!("Failed to link program '{}'", name))?;
   program.set_debug_label(name.as_bytes());
   Ok(program)
@@ -318,11 +596,22 @@ pub fn compile_shader(
 pub fn link_program(
   ctx: oogl::SharedContext,
   shaders: &[&oogl::Shader],
+) -> AnyResult<oogl::Program> {
+  link_program_with_attrib_locations(ctx, shaders, &[])
+}
+
+pub fn link_program_with_attrib_locations(
+  ctx: oogl::SharedContext,
+  shaders: &[&oogl::Shader],
+  attrib_locations: &[(&str, u32)],
 ) -> AnyResult<oogl::Program> {
   let program = oogl::Program::new(ctx);
   for shader in shaders {
     program.attach_shader(shader);
   }
+  for &(name, location) in attrib_locations {
+    program.request_attrib_location(name, location);
+  }
 
   let success = program.link();
   let log = program.get_info_log();
@@ -339,6 +628,24 @@ pub fn link_program(
   Ok(program)
 }
 
+/// Loads `font.json`, the bitmap font used for all of the UI and debug text.
+pub fn load_default_font(globals: &Globals) -> AnyResult<Font> {
+  load_font_asset(globals, DEFAULT_FONT_PATH)
+}
+
+/// Loads a font descriptor (see [`FontDescriptor`]) along with its texture.
+pub fn load_font_asset(globals: &Globals, path: &str) -> AnyResult<Font> {
+  let bytes = globals.game_fs.read_binary_file(path)?;
+  let descriptor: FontDescriptor = json::from_slice(&bytes)
+    .with_context(|| format!("Failed to parse the font descriptor '{}'", path))?;
+  let texture_path = match path.rsplit_once('/') {
+    Some((dir, _)) => format!("{}/{}", dir, descriptor.texture),
+    None => descriptor.texture.clone(),
+  };
+  let texture = load_texture_asset(globals, &texture_path, oogl::TextureFilter::Nearest)?;
+  Font::new(texture, &descriptor).with_context(|| format!("Invalid font descriptor '{}'", path))
+}
+
 pub fn load_texture_asset(
   globals: &Globals,
   path: &str,
@@ -395,3 +702,129 @@ pub fn load_texture_data_from_png(
 
   Ok(texture)
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn descriptor(json_str: &str) -> FontDescriptor { json::from_str(json_str).unwrap() }
+
+  fn glyph_map(json_str: &str) -> AnyResult<GlyphMap> {
+    GlyphMap::new(&descriptor(json_str), vec2(64, 64))
+  }
+
+  fn text_block(text: &str) -> TextBlock<'_> {
+    TextBlock {
+      text,
+      scale: vec2(2.0, 3.0),
+      character_spacing: vec2(0.5, 1.0),
+      horizontal_align: TextAlign::Start,
+      vertical_align: TextAlign::Start,
+    }
+  }
+
+  #[test]
+  fn builds_the_glyphs_from_grids_and_overrides() {
+    let map = glyph_map(
+      r#"{
+        "texture": "font.png",
+        "character_size": [4, 6],
+        "grids": [
+          { "first_char": "a", "count": 3, "columns": 2, "origin": [1, 2], "cell_size": [4, 6] },
+          { "first_char": "я", "count": 1, "columns": 1, "origin": [0, 32], "cell_size": [5, 7],
+            "glyph_size": [3, 7], "offset": [1, -1], "advance": 4 }
+        ],
+        "glyphs": { "c": { "pos": [40, 40], "size": [2, 2], "advance": 3 } }
+      }"#,
+    )
+    .unwrap();
+
+    let glyph = |pos: (u32, u32), size: (u32, u32), offset: (i32, i32), advance: u32| Glyph {
+      pos: vec2(pos.0, pos.1),
+      size: vec2(size.0, size.1),
+      offset: vec2(offset.0, offset.1),
+      advance,
+    };
+    assert_eq!(map.glyphs.len(), 4);
+    assert_eq!(map.glyphs[&'a'], glyph((1, 2), (4, 6), (0, 0), 4));
+    assert_eq!(map.glyphs[&'b'], glyph((5, 2), (4, 6), (0, 0), 4));
+    assert_eq!(map.glyphs[&'c'], glyph((40, 40), (2, 2), (0, 0), 3));
+    assert_eq!(map.glyphs[&'я'], glyph((0, 32), (3, 7), (1, -1), 4));
+    assert_eq!(map.fallback_glyph, None);
+    assert_eq!(map.glyph('d'), None);
+  }
+
+  #[test]
+  fn rejects_invalid_descriptors() {
+    let error = |json_str: &str| format!("{:#}", glyph_map(json_str).unwrap_err());
+    let grid = |grid: &str| {
+      error(&format!(r#"{{ "texture": "", "character_size": [4, 6], "grids": [{}] }}"#, grid))
+    };
+
+    assert_eq!(
+      error(r#"{ "texture": "", "character_size": [0, 6] }"#),
+      "The character size must not be zero",
+    );
+    assert_eq!(
+      grid(r#"{ "first_char": "a", "count": 1, "columns": 0, "cell_size": [4, 6] }"#),
+      "The grid #0 has no columns",
+    );
+    assert_eq!(
+      grid(r#"{ "first_char": "\udbff\udfff", "count": 2, "columns": 1, "cell_size": [1, 1] }"#),
+      "The grid #0 covers invalid codepoints",
+    );
+    assert_eq!(
+      grid(r#"{ "first_char": "\ud7ff", "count": 2, "columns": 2, "cell_size": [1, 1] }"#),
+      "The grid #0 covers invalid codepoints",
+    );
+    assert_eq!(
+      grid(r#"{ "first_char": "a", "count": 3, "columns": 1, "cell_size": [1, 4294967295] }"#),
+      "The grid #0 is too large",
+    );
+    assert_eq!(
+      grid(r#"{ "first_char": "a", "count": 17, "columns": 17, "cell_size": [4, 6] }"#),
+      "The glyph of 'q' doesn't fit into the 64x64 texture",
+    );
+    assert_eq!(
+      error(
+        r#"{ "texture": "", "character_size": [4, 6],
+          "glyphs": { "x": { "pos": [4294967295, 0], "size": [2, 2], "advance": 2 } } }"#
+      ),
+      "The glyph of 'x' doesn't fit into the 64x64 texture",
+    );
+    assert_eq!(
+      error(r#"{ "texture": "", "character_size": [4, 6], "fallback": "?" }"#),
+      "No glyph for the fallback '?'",
+    );
+  }
+
+  #[test]
+  fn measures_the_text_with_the_fallback_glyph() {
+    let json_str = r#"{
+      "texture": "font.png",
+      "character_size": [4, 6],
+      "grids": [{ "first_char": "?", "count": 3, "columns": 3, "cell_size": [4, 6], "advance": 5 }],
+      "glyphs": { "é": { "pos": [0, 8], "size": [3, 8], "advance": 3 } }
+    }"#;
+    let mut with_fallback = descriptor(json_str);
+    with_fallback.fallback = Some('?');
+    let with_fallback = GlyphMap::new(&with_fallback, vec2(64, 64)).unwrap();
+    let without_fallback = glyph_map(json_str).unwrap();
+
+    // The extra spacing is half of a character, i.e. 2 pixels, and the line
+    // height is 6 + 6 pixels.
+    let char_spacing = vec2((4.0 + 2.0) * 2.0, (6.0 + 6.0) * 3.0);
+    let (size, spacing) = without_fallback.measure_size(&text_block("@é"));
+    assert_eq!((size, spacing), (vec2((7.0 + 5.0) * 2.0, char_spacing.y), char_spacing));
+
+    // Every character, even the ones outside of the BMP, takes up one glyph.
+    let (size, _) = without_fallback.measure_size(&text_block("@ж😀é"));
+    assert_eq!(size.x, (7.0 + 5.0) * 2.0);
+    let (size, _) = with_fallback.measure_size(&text_block("@ж😀é"));
+    assert_eq!(size.x, (7.0 + 7.0 + 7.0 + 5.0) * 2.0);
+    assert_eq!(with_fallback.glyph('ж'), with_fallback.glyphs.get(&'?'));
+
+    let (size, _) = with_fallback.measure_size(&text_block(""));
+    assert_eq!(size, vec2(0.0, char_spacing.y));
+  }
+}
@@ -0,0 +1,726 @@
+//! Input recordings which make the game runs reproducible. A replay file
+//! consists of a header with everything needed to start the game in the same
+//! state (the RNG seed, the fixed timestep, the initial scene, the checksum of
+//! the input bindings), followed by one record per game loop tick with the
+//! input state, `delta_time` and the number of fixed updates which were run on
+//! that tick (along with the interpolation factor they left behind). The
+//! timing fields change on nearly every tick and are always written, but to
+//! keep the files small the input state is written only when it has changed
+//! since the previous tick. All numbers are little-endian. A recording cut off
+//! in the middle of the last tick, e.g. by a crash, ends before that tick.
+//!
+//! The gamepads are recorded by their slots, a frame stores the buttons and
+//! the (already normalized) axes of every slot in use. The typed text and the
+//! editing keys are stored on the ticks they occur on, but not the IME
+//! composition, which is only displayed and never affects the game state.
+
+use cardboard_math::*;
+use cardboard_utils::io::read_array;
+use prelude_plus::*;
+
+use crate::bindings::InputBindings;
+use crate::globals::{max_fixed_updates_per_tick, Globals};
+use crate::input::{GamepadAxis, GamepadButton, GamepadInputs, GamepadState, Key};
+use crate::text_input::{TextEdit, TextEditEvent};
+
+const MAGIC: &[u8; 8] = b"OKKREPLY";
+const VERSION: u16 = 6;
+
+const KEYBOARD_STATE_BYTES: usize = (Key::VARIANTS.len() + 7) / 8;
+const GAMEPAD_BUTTONS_BYTES: usize = (GamepadButton::VARIANTS.len() + 7) / 8;
+
+const FLAG_KEYBOARD_CHANGED: u8 = 1 << 0;
+const FLAG_MOUSE_CHANGED: u8 = 1 << 1;
+const FLAG_WINDOW_CHANGED: u8 = 1 << 2;
+const FLAG_IDLE: u8 = 1 << 3;
+const FLAG_GAMEPADS_CHANGED: u8 = 1 << 4;
+const FLAG_TEXT_INPUT: u8 = 1 << 5;
+const FLAG_UNFOCUSED: u8 = 1 << 6;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayHeader {
+  pub seed: u128,
+  pub fixed_delta_time: f64,
+  pub scene: String,
+  /// Of the bindings the replay was recorded with, see
+  /// [`InputBindings::checksum`].
+  pub bindings_checksum: u64,
+}
+
+impl ReplayHeader {
+  /// The physical inputs are recorded, so with different bindings the replay
+  /// would trigger different actions.
+  pub fn check_bindings(&self, bindings: &InputBindings) -> AnyResult<()> {
+    let checksum = bindings.checksum();
+    ensure!(
+      checksum == self.bindings_checksum,
+      "The replay was recorded with different input bindings (checksum {:#018x}, the current \
+       one is {:#018x})",
+      self.bindings_checksum,
+      checksum,
+    );
+    Ok(())
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayFrame {
+  pub delta_time: f64,
+  pub fixed_updates: u32,
+  pub fixed_update_alpha: f64,
+  /// The game loop doesn't run any updates while the window is unfocused, but
+  /// the input is still processed on those ticks.
+  pub idle: bool,
+  /// The scenes may react to losing the focus, e.g. by pausing themselves.
+  pub window_is_focused: bool,
+  pub keyboard_state_table: [bool; Key::VARIANTS.len()],
+  pub mouse_pos: Vec2f,
+  pub window_size: Vec2u32,
+  /// Mirrors [`crate::input::InputState::gamepads`].
+  pub gamepads: Vec<Option<GamepadInputs>>,
+  pub text: String,
+  pub text_edits: Vec<TextEditEvent>,
+}
+
+impl Default for ReplayFrame {
+  fn default() -> Self {
+    Self {
+      delta_time: 0.0,
+      fixed_updates: 0,
+      fixed_update_alpha: 1.0,
+      idle: false,
+      window_is_focused: true,
+      keyboard_state_table: [false; Key::VARIANTS.len()],
+      mouse_pos: Vec2f::ZERO,
+      window_size: Vec2u32::ZERO,
+      gamepads: Vec::new(),
+      text: String::new(),
+      text_edits: Vec::new(),
+    }
+  }
+}
+
+impl ReplayFrame {
+  pub fn capture(globals: &Globals, delta_time: f64, fixed_updates: u32, idle: bool) -> Self {
+    Self {
+      delta_time,
+      fixed_updates,
+      fixed_update_alpha: globals.fixed_update_alpha,
+      idle,
+      window_is_focused: globals.window_is_focused,
+      keyboard_state_table: globals.input_state.keyboard_state_table,
+      mouse_pos: globals.input_state.mouse_pos,
+      window_size: globals.window_size_i,
+      gamepads: (globals.input_state.gamepads.iter())
+        .map(|gamepad| gamepad.as_ref().map(|gamepad| gamepad.current.clone()))
+        .collect(),
+      text: globals.input_state.text_input.text.clone(),
+      text_edits: globals.input_state.text_input.edits.clone(),
+    }
+  }
+
+  /// Does the same to the globals as `Game::process_input` would've done with
+  /// the recorded events.
+  pub fn apply(&self, globals: &mut Globals) {
+    globals.window_was_resized = globals.first_game_loop_tick;
+    if self.window_size != globals.window_size_i {
+      globals.window_size_i = self.window_size;
+      globals.window_size = Vec2f::cast_from(self.window_size);
+      globals.window_was_resized = true;
+    }
+    globals.window_is_focused = self.window_is_focused;
+
+    let input = &mut globals.input_state;
+    input.prev_mouse_pos = input.mouse_pos;
+    input.prev_keyboard_state_table = input.keyboard_state_table;
+    input.mouse_pos = self.mouse_pos;
+    input.keyboard_state_table = self.keyboard_state_table;
+    input.delta_mouse_pos = input.mouse_pos - input.prev_mouse_pos;
+
+    input.gamepads.resize_with(self.gamepads.len(), || None);
+    for (slot, inputs) in input.gamepads.iter_mut().zip(&self.gamepads) {
+      match inputs {
+        Some(inputs) => {
+          let gamepad =
+            slot.get_or_insert_with(|| GamepadState::new(0, "Recorded gamepad".to_owned()));
+          gamepad.prev = mem::replace(&mut gamepad.current, inputs.clone());
+        }
+        None => *slot = None,
+      }
+    }
+
+    let text_input = &mut input.text_input;
+    text_input.text.clone_from(&self.text);
+    text_input.edits.clone_from(&self.text_edits);
+  }
+}
+
+#[derive(Debug)]
+pub struct ReplayWriter<W: Write> {
+  writer: W,
+  prev_frame: ReplayFrame,
+}
+
+impl<W: Write> ReplayWriter<W> {
+  pub fn new(mut writer: W, header: &ReplayHeader) -> AnyResult<Self> {
+    let scene_len = u8::try_from(header.scene.len())
+      .map_err(|_| format_err!("The scene name '{}' is too long", header.scene))?;
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&(Key::VARIANTS.len() as u16).to_le_bytes())?;
+    writer.write_all(&[GamepadButton::VARIANTS.len() as u8, GamepadAxis::VARIANTS.len() as u8])?;
+    writer.write_all(&header.seed.to_le_bytes())?;
+    writer.write_all(&header.fixed_delta_time.to_le_bytes())?;
+    writer.write_all(&header.bindings_checksum.to_le_bytes())?;
+    writer.write_all(&[scene_len])?;
+    writer.write_all(header.scene.as_bytes())?;
+    Ok(Self { writer, prev_frame: ReplayFrame::default() })
+  }
+
+  pub fn write_frame(&mut self, frame: &ReplayFrame) -> AnyResult<()> {
+    let prev = &self.prev_frame;
+    let mut flags = 0;
+    if frame.keyboard_state_table != prev.keyboard_state_table {
+      flags |= FLAG_KEYBOARD_CHANGED;
+    }
+    if frame.mouse_pos != prev.mouse_pos {
+      flags |= FLAG_MOUSE_CHANGED;
+    }
+    if frame.window_size != prev.window_size {
+      flags |= FLAG_WINDOW_CHANGED;
+    }
+    if frame.idle {
+      flags |= FLAG_IDLE;
+    }
+    if !frame.window_is_focused {
+      flags |= FLAG_UNFOCUSED;
+    }
+    if frame.gamepads != prev.gamepads {
+      flags |= FLAG_GAMEPADS_CHANGED;
+    }
+    if !frame.text.is_empty() || !frame.text_edits.is_empty() {
+      flags |= FLAG_TEXT_INPUT;
+    }
+
+    let w = &mut self.writer;
+    w.write_all(&[flags])?;
+    w.write_all(&frame.delta_time.to_le_bytes())?;
+    w.write_all(&frame.fixed_updates.to_le_bytes())?;
+    w.write_all(&frame.fixed_update_alpha.to_le_bytes())?;
+    if flags & FLAG_KEYBOARD_CHANGED != 0 {
+      let mut bits = [0u8; KEYBOARD_STATE_BYTES];
+      for (i, &down) in frame.keyboard_state_table.iter().enumerate() {
+        bits[i / 8] |= (down as u8) << (i % 8);
+      }
+      w.write_all(&bits)?;
+    }
+    if flags & FLAG_MOUSE_CHANGED != 0 {
+      w.write_all(&frame.mouse_pos.x.to_le_bytes())?;
+      w.write_all(&frame.mouse_pos.y.to_le_bytes())?;
+    }
+    if flags & FLAG_WINDOW_CHANGED != 0 {
+      w.write_all(&frame.window_size.x.to_le_bytes())?;
+      w.write_all(&frame.window_size.y.to_le_bytes())?;
+    }
+    if flags & FLAG_GAMEPADS_CHANGED != 0 {
+      let slots_count = u8::try_from(frame.gamepads.len())
+        .map_err(|_| format_err!("Too many gamepads: {}", frame.gamepads.len()))?;
+      w.write_all(&[slots_count])?;
+      for inputs in &frame.gamepads {
+        w.write_all(&[inputs.is_some() as u8])?;
+        if let Some(inputs) = inputs {
+          let mut bits = [0u8; GAMEPAD_BUTTONS_BYTES];
+          for (i, &down) in inputs.buttons.iter().enumerate() {
+            bits[i / 8] |= (down as u8) << (i % 8);
+          }
+          w.write_all(&bits)?;
+          for value in &inputs.axes {
+            w.write_all(&value.to_le_bytes())?;
+          }
+        }
+      }
+    }
+    if flags & FLAG_TEXT_INPUT != 0 {
+      let text_len = u16::try_from(frame.text.len())
+        .map_err(|_| format_err!("Too much text typed in a single tick"))?;
+      let edits_count = u8::try_from(frame.text_edits.len())
+        .map_err(|_| format_err!("Too many text edits in a single tick"))?;
+      w.write_all(&text_len.to_le_bytes())?;
+      w.write_all(frame.text.as_bytes())?;
+      w.write_all(&[edits_count])?;
+      for event in &frame.text_edits {
+        w.write_all(&[event.edit as u8])?;
+        w.write_all(&(event.text_offset as u16).to_le_bytes())?;
+      }
+    }
+
+    self.prev_frame = frame.clone();
+    Ok(())
+  }
+
+  pub fn finish(mut self) -> AnyResult<W> {
+    self.writer.flush()?;
+    Ok(self.writer)
+  }
+}
+
+#[derive(Debug)]
+pub struct ReplayReader<R: Read> {
+  reader: R,
+  header: ReplayHeader,
+  max_fixed_updates: u32,
+  prev_frame: ReplayFrame,
+}
+
+impl<R: Read> ReplayReader<R> {
+  pub fn new(mut reader: R) -> AnyResult<Self> {
+    let r = &mut reader;
+    if &read_array::<_, 8>(r)? != MAGIC {
+      bail!("Not a replay file");
+    }
+    let version = u16::from_le_bytes(read_array(r)?);
+    if version != VERSION {
+      bail!("Unsupported replay version {}, expected {}", version, VERSION);
+    }
+    let keys_count = u16::from_le_bytes(read_array(r)?);
+    if keys_count as usize != Key::VARIANTS.len() {
+      bail!(
+        "The replay was recorded with {} keys, but the game has {}",
+        keys_count,
+        Key::VARIANTS.len(),
+      );
+    }
+    let [buttons_count, axes_count] = read_array(r)?;
+    if buttons_count as usize != GamepadButton::VARIANTS.len()
+      || axes_count as usize != GamepadAxis::VARIANTS.len()
+    {
+      bail!(
+        "The replay was recorded with {} gamepad buttons and {} axes, but the game has {} and {}",
+        buttons_count,
+        axes_count,
+        GamepadButton::VARIANTS.len(),
+        GamepadAxis::VARIANTS.len(),
+      );
+    }
+    let seed = u128::from_le_bytes(read_array(r)?);
+    let fixed_delta_time = f64::from_le_bytes(read_array(r)?);
+    ensure!(
+      fixed_delta_time.is_finite() && fixed_delta_time > 0.0,
+      "Invalid fixed timestep: {}",
+      fixed_delta_time,
+    );
+    let bindings_checksum = u64::from_le_bytes(read_array(r)?);
+    let [scene_len] = read_array(r)?;
+    let mut scene = vec![0; scene_len as usize];
+    r.read_exact(&mut scene)?;
+    let scene = String::from_utf8(scene).context("The scene name is not valid UTF-8")?;
+
+    let header = ReplayHeader { seed, fixed_delta_time, scene, bindings_checksum };
+    Ok(Self {
+      reader,
+      header,
+      max_fixed_updates: max_fixed_updates_per_tick(fixed_delta_time),
+      prev_frame: ReplayFrame::default(),
+    })
+  }
+
+  #[inline(always)]
+  pub fn header(&self) -> &ReplayHeader { &self.header }
+
+  /// Returns `None` at the end of the recording.
+  pub fn read_frame(&mut self) -> AnyResult<Option<ReplayFrame>> {
+    let flags = match read_array::<_, 1>(&mut self.reader) {
+      Ok([flags]) => flags,
+      Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+      Err(e) => return Err(e.into()),
+    };
+
+    match self.read_frame_fields(flags) {
+      Ok(frame) => {
+        self.prev_frame = frame.clone();
+        Ok(Some(frame))
+      }
+      Err(e) if is_unexpected_eof(&e) => {
+        warn!("The last tick of the replay is cut off, ignoring it");
+        Ok(None)
+      }
+      Err(e) => Err(e),
+    }
+  }
+
+  fn read_frame_fields(&mut self, flags: u8) -> AnyResult<ReplayFrame> {
+    let r = &mut self.reader;
+    let mut frame = self.prev_frame.clone();
+    frame.delta_time = f64::from_le_bytes(read_array(r)?);
+    frame.fixed_updates = u32::from_le_bytes(read_array(r)?);
+    ensure!(
+      frame.fixed_updates <= self.max_fixed_updates,
+      "Too many fixed updates in a single tick: {}, at most {} can be run",
+      frame.fixed_updates,
+      self.max_fixed_updates,
+    );
+    frame.fixed_update_alpha = f64::from_le_bytes(read_array(r)?);
+    frame.idle = flags & FLAG_IDLE != 0;
+    frame.window_is_focused = flags & FLAG_UNFOCUSED == 0;
+    if flags & FLAG_KEYBOARD_CHANGED != 0 {
+      let bits: [u8; KEYBOARD_STATE_BYTES] = read_array(r)?;
+      for (i, down) in frame.keyboard_state_table.iter_mut().enumerate() {
+        *down = bits[i / 8] & (1 << (i % 8)) != 0;
+      }
+    }
+    if flags & FLAG_MOUSE_CHANGED != 0 {
+      frame.mouse_pos.x = f32::from_le_bytes(read_array(r)?);
+      frame.mouse_pos.y = f32::from_le_bytes(read_array(r)?);
+    }
+    if flags & FLAG_WINDOW_CHANGED != 0 {
+      frame.window_size.x = u32::from_le_bytes(read_array(r)?);
+      frame.window_size.y = u32::from_le_bytes(read_array(r)?);
+    }
+    if flags & FLAG_GAMEPADS_CHANGED != 0 {
+      let [slots_count] = read_array(r)?;
+      frame.gamepads.clear();
+      for _ in 0..slots_count {
+        let [connected] = read_array(r)?;
+        if connected == 0 {
+          frame.gamepads.push(None);
+          continue;
+        }
+        let mut inputs = GamepadInputs::default();
+        let bits: [u8; GAMEPAD_BUTTONS_BYTES] = read_array(r)?;
+        for (i, down) in inputs.buttons.iter_mut().enumerate() {
+          *down = bits[i / 8] & (1 << (i % 8)) != 0;
+        }
+        for value in &mut inputs.axes {
+          *value = f32::from_le_bytes(read_array(r)?);
+        }
+        frame.gamepads.push(Some(inputs));
+      }
+    }
+    frame.text.clear();
+    frame.text_edits.clear();
+    if flags & FLAG_TEXT_INPUT != 0 {
+      let text_len = u16::from_le_bytes(read_array(r)?);
+      let mut text = vec![0; text_len as usize];
+      r.read_exact(&mut text)?;
+      frame.text = String::from_utf8(text).context("The typed text is not valid UTF-8")?;
+      let [edits_count] = read_array(r)?;
+      let mut prev_offset = 0;
+      for _ in 0..edits_count {
+        let [code] = read_array(r)?;
+        let edit = *TextEdit::VARIANTS
+          .get(code as usize)
+          .ok_or_else(|| format_err!("Invalid text edit code: {}", code))?;
+        let text_offset = u16::from_le_bytes(read_array(r)?) as usize;
+        ensure!(
+          frame.text.is_char_boundary(text_offset),
+          "Invalid text edit offset: {}",
+          text_offset,
+        );
+        // The edits are recorded in order, and the text between the offsets is
+        // typed in between them.
+        ensure!(
+          text_offset >= prev_offset,
+          "Text edit offset {} goes back from {}",
+          text_offset,
+          prev_offset,
+        );
+        prev_offset = text_offset;
+        frame.text_edits.push(TextEditEvent { edit, text_offset });
+      }
+    }
+    Ok(frame)
+  }
+}
+
+fn is_unexpected_eof(error: &AnyError) -> bool {
+  error.downcast_ref::<io::Error>().map_or(false, |e| e.kind() == io::ErrorKind::UnexpectedEof)
+}
+
+pub fn create_replay_file(
+  path: &Path,
+  header: &ReplayHeader,
+) -> AnyResult<ReplayWriter<BufWriter<File>>> {
+  let file =
+    File::create(path).with_context(|| format!("Failed to create file '{}'", path.display()))?;
+  ReplayWriter::new(BufWriter::new(file), header)
+    .with_context(|| format!("Failed to write the replay header to '{}'", path.display()))
+}
+
+pub fn open_replay_file(
+  path: &Path,
+  bindings: &InputBindings,
+) -> AnyResult<ReplayReader<BufReader<File>>> {
+  let file =
+    File::open(path).with_context(|| format!("Failed to open file '{}'", path.display()))?;
+  let reader = ReplayReader::new(BufReader::new(file))
+    .with_context(|| format!("Failed to read the replay header from '{}'", path.display()))?;
+  reader.header().check_bindings(bindings).with_context(|| {
+    format!("The replay '{}' can't be played with the current settings", path.display())
+  })?;
+  Ok(reader)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Flags, `delta_time`, `fixed_updates` and `fixed_update_alpha`.
+  const FRAME_PREFIX_BYTES: usize = 1 + 8 + 4 + 8;
+
+  fn test_header() -> ReplayHeader {
+    ReplayHeader {
+      seed: 0x0123_4567_89ab_cdef,
+      fixed_delta_time: 1.0 / 120.0,
+      scene: "pong".into(),
+      bindings_checksum: 0x0bad_f00d,
+    }
+  }
+
+  fn test_frame() -> ReplayFrame {
+    ReplayFrame {
+      delta_time: 1.0 / 60.0,
+      fixed_updates: 2,
+      window_size: vec2(640, 480),
+      ..Default::default()
+    }
+  }
+
+  fn gamepad(button: GamepadButton, axis: GamepadAxis, value: f32) -> GamepadInputs {
+    let mut inputs = GamepadInputs::default();
+    inputs.buttons[button as usize] = true;
+    inputs.axes[axis as usize] = value;
+    inputs
+  }
+
+  fn write_replay(frames: &[ReplayFrame]) -> Vec<u8> {
+    let mut writer = ReplayWriter::new(Vec::new(), &test_header()).unwrap();
+    for frame in frames {
+      writer.write_frame(frame).unwrap();
+    }
+    writer.finish().unwrap()
+  }
+
+  /// Returns the size of every written frame record.
+  fn write_replay_frame_sizes(frames: &[ReplayFrame]) -> Vec<usize> {
+    let mut writer = ReplayWriter::new(Vec::new(), &test_header()).unwrap();
+    let mut sizes = Vec::new();
+    for frame in frames {
+      let prev_len = writer.writer.len();
+      writer.write_frame(frame).unwrap();
+      sizes.push(writer.writer.len() - prev_len);
+    }
+    sizes
+  }
+
+  fn read_replay(bytes: &[u8]) -> AnyResult<Vec<ReplayFrame>> {
+    let mut reader = ReplayReader::new(bytes)?;
+    assert_eq!(reader.header(), &test_header());
+    let mut frames = Vec::new();
+    while let Some(frame) = reader.read_frame()? {
+      frames.push(frame);
+    }
+    Ok(frames)
+  }
+
+  fn assert_round_trip(frames: &[ReplayFrame]) {
+    assert_eq!(read_replay(&write_replay(frames)).unwrap(), frames);
+  }
+
+  #[test]
+  fn round_trip() {
+    let mut frames = Vec::new();
+    for i in 0..60 {
+      let mut frame = test_frame();
+      frame.fixed_updates = i % 3;
+      frame.fixed_update_alpha = i as f64 / 60.0;
+      frame.idle = i == 10;
+      frame.window_is_focused = i != 59;
+      frame.keyboard_state_table[Key::Up as usize] = (5..20).contains(&i);
+      frame.keyboard_state_table[Key::Num1 as usize] = i == 0;
+      frame.mouse_pos = vec2(i as f32 / 2.0, 10.0);
+      if i >= 30 {
+        frame.window_size = vec2(800, 600);
+      }
+      if (20..40).contains(&i) {
+        frame.gamepads.push(Some(gamepad(GamepadButton::A, GamepadAxis::LeftY, 0.6)));
+      }
+      if i == 25 {
+        frame.text.push_str("gg wp");
+        frame.text_edits.push(TextEditEvent { edit: TextEdit::Backspace, text_offset: 2 });
+        frame.text_edits.push(TextEditEvent { edit: TextEdit::Submit, text_offset: 5 });
+      }
+      frames.push(frame);
+    }
+    assert_round_trip(&frames);
+    assert_round_trip(&[]);
+  }
+
+  #[test]
+  fn only_the_changed_fields_are_written() {
+    let first = test_frame();
+    let mut mouse_moved = first.clone();
+    mouse_moved.mouse_pos = vec2(1.0, 2.0);
+    let mut key_pressed = mouse_moved.clone();
+    key_pressed.keyboard_state_table[Key::Space as usize] = true;
+    let mut idle = key_pressed.clone();
+    idle.idle = true;
+    idle.window_is_focused = false;
+    let frames = [first, mouse_moved, key_pressed, idle.clone(), idle];
+
+    assert_eq!(
+      write_replay_frame_sizes(&frames),
+      [
+        FRAME_PREFIX_BYTES + 8,
+        FRAME_PREFIX_BYTES + 8,
+        FRAME_PREFIX_BYTES + KEYBOARD_STATE_BYTES,
+        FRAME_PREFIX_BYTES,
+        FRAME_PREFIX_BYTES,
+      ]
+    );
+    assert_round_trip(&frames);
+  }
+
+  #[test]
+  fn gamepad_slot_changes() {
+    let pad_a = gamepad(GamepadButton::A, GamepadAxis::LeftX, -1.0);
+    let pad_b = gamepad(GamepadButton::Start, GamepadAxis::TriggerRight, 0.25);
+    let slots = [
+      vec![Some(pad_a.clone())],
+      vec![Some(pad_a.clone())],
+      vec![Some(pad_a.clone()), Some(pad_b.clone())],
+      vec![None, Some(pad_b.clone())],
+      vec![None, Some(gamepad(GamepadButton::Start, GamepadAxis::TriggerRight, 0.5))],
+      vec![Some(pad_a), Some(pad_b)],
+      vec![],
+    ];
+    let frames = slots
+      .iter()
+      .map(|gamepads| ReplayFrame { gamepads: gamepads.clone(), ..test_frame() })
+      .collect::<Vec<_>>();
+    assert_round_trip(&frames);
+
+    let gamepad_bytes = 1 + GAMEPAD_BUTTONS_BYTES + GamepadAxis::VARIANTS.len() * 4;
+    let sizes = write_replay_frame_sizes(&frames);
+    // The first frame also records the window size.
+    assert_eq!(
+      sizes[1..],
+      [
+        FRAME_PREFIX_BYTES,
+        FRAME_PREFIX_BYTES + 1 + gamepad_bytes * 2,
+        FRAME_PREFIX_BYTES + 1 + 1 + gamepad_bytes,
+        FRAME_PREFIX_BYTES + 1 + 1 + gamepad_bytes,
+        FRAME_PREFIX_BYTES + 1 + gamepad_bytes * 2,
+        FRAME_PREFIX_BYTES + 1,
+      ]
+    );
+  }
+
+  #[test]
+  fn rejects_bad_headers() {
+    let bytes = write_replay(&[]);
+    let error = |bytes: &[u8]| format!("{:#}", ReplayReader::new(bytes).unwrap_err());
+
+    let mut bad_magic = bytes.clone();
+    bad_magic[0] = b'X';
+    assert_eq!(error(&bad_magic), "Not a replay file");
+
+    let mut bad_version = bytes.clone();
+    bad_version[MAGIC.len()..MAGIC.len() + 2].copy_from_slice(&(VERSION + 1).to_le_bytes());
+    assert_eq!(
+      error(&bad_version),
+      format!("Unsupported replay version {}, expected {}", VERSION + 1, VERSION),
+    );
+
+    let mut bad_keys_count = bytes.clone();
+    bad_keys_count[MAGIC.len() + 2] ^= 1;
+    assert!(error(&bad_keys_count).starts_with("The replay was recorded with"));
+
+    assert!(ReplayReader::new(&bytes[..bytes.len() - 1]).is_err());
+  }
+
+  #[test]
+  fn rejects_invalid_text_edits() {
+    let mut frame = test_frame();
+    frame.text.push('é');
+    frame.text_edits.push(TextEditEvent { edit: TextEdit::Left, text_offset: 2 });
+    let bytes = write_replay(&[frame.clone()]);
+    assert_round_trip(&[frame]);
+
+    // The offset is in the last two bytes, and points into the middle of 'é'.
+    let mut bad_offset = bytes.clone();
+    let offset_pos = bad_offset.len() - 2;
+    bad_offset[offset_pos..].copy_from_slice(&1u16.to_le_bytes());
+    assert_eq!(
+      format!("{:#}", read_replay(&bad_offset).unwrap_err()),
+      "Invalid text edit offset: 1",
+    );
+
+    let mut bad_offset = bytes.clone();
+    bad_offset[offset_pos..].copy_from_slice(&3u16.to_le_bytes());
+    assert!(read_replay(&bad_offset).is_err());
+
+    let mut bad_code = bytes;
+    bad_code[offset_pos - 1] = TextEdit::VARIANTS.len() as u8;
+    assert_eq!(
+      format!("{:#}", read_replay(&bad_code).unwrap_err()),
+      format!("Invalid text edit code: {}", TextEdit::VARIANTS.len()),
+    );
+
+    // Several edits may happen at the same offset, but never go back.
+    let mut frame = test_frame();
+    frame.text.push_str("ab");
+    frame.text_edits.push(TextEditEvent { edit: TextEdit::Left, text_offset: 1 });
+    frame.text_edits.push(TextEditEvent { edit: TextEdit::Left, text_offset: 1 });
+    frame.text_edits.push(TextEditEvent { edit: TextEdit::Backspace, text_offset: 2 });
+    assert_round_trip(&[frame.clone()]);
+    frame.text_edits[2].text_offset = 0;
+    assert_eq!(
+      format!("{:#}", read_replay(&write_replay(&[frame])).unwrap_err()),
+      "Text edit offset 0 goes back from 1",
+    );
+  }
+
+  #[test]
+  fn cut_off_last_frame_ends_the_replay() {
+    let mut frames = vec![test_frame(); 3];
+    frames[2].text.push_str("gg");
+    let bytes = write_replay(&frames);
+    let last_frame_len = FRAME_PREFIX_BYTES + 2 + 2 + 1;
+    for cut in 1..=last_frame_len {
+      let frames_read = read_replay(&bytes[..bytes.len() - cut]).unwrap();
+      assert_eq!(frames_read, frames[..2], "{} byte(s) cut off", cut);
+    }
+  }
+
+  #[test]
+  fn rejects_too_many_fixed_updates() {
+    let max_fixed_updates = max_fixed_updates_per_tick(test_header().fixed_delta_time);
+    let frame = ReplayFrame { fixed_updates: max_fixed_updates, ..test_frame() };
+    assert_round_trip(&[frame]);
+
+    let frame = ReplayFrame { fixed_updates: max_fixed_updates + 1, ..test_frame() };
+    assert_eq!(
+      format!("{:#}", read_replay(&write_replay(&[frame])).unwrap_err()),
+      format!(
+        "Too many fixed updates in a single tick: {}, at most {} can be run",
+        max_fixed_updates + 1,
+        max_fixed_updates,
+      ),
+    );
+  }
+
+  #[test]
+  fn checks_the_bindings() {
+    let mut bindings = InputBindings::new();
+    let header = ReplayHeader { bindings_checksum: bindings.checksum(), ..test_header() };
+    header.check_bindings(&bindings).unwrap();
+
+    bindings.set_action(crate::bindings::actions::PAUSE, vec![]);
+    let error = header.check_bindings(&bindings).unwrap_err().to_string();
+    assert!(
+      error.starts_with("The replay was recorded with different input bindings"),
+      "{}",
+      error
+    );
+  }
+}
@@ -0,0 +1,330 @@
+//! Everything driven by the game loop is a [`Scene`]. The scenes live on a
+//! stack: only the topmost one receives the updates, while the overlays (the
+//! menu, the pause screen) let the scenes under them show through.
+
+use cardboard_math::*;
+use prelude_plus::*;
+
+use crate::bindings::actions;
+use crate::collision_simulation::CollisionSimulation;
+use crate::game_of_life::GameOfLife;
+use crate::globals::{Globals, SharedGlobals};
+use crate::input::Key;
+use crate::mandelbrot::Mandelbrot;
+use crate::marching_squares::MarchingSquares;
+use crate::pong::Pong;
+use crate::pong_netplay::NetplayPong;
+use crate::renderer::{self, Renderer};
+
+const MENU_SCENE_KEYS: [Key; 5] = [Key::Num1, Key::Num2, Key::Num3, Key::Num4, Key::Num5];
+
+const OVERLAY_BACKGROUND_COLOR: Colorf = color(0.0, 0.0, 0.0, 0.6);
+const OVERLAY_TEXT_SCALE: Vec2f = vec2n(6.0);
+const OVERLAY_TEXT_CHAR_SPACING: Vec2f = vec2n(1.0 / 3.0);
+
+const_assert!(MENU_SCENE_KEYS.len() == SceneKind::MAIN.len());
+
+pub trait Scene: fmt::Debug {
+  fn init(globals: SharedGlobals) -> AnyResult<Self>
+  where
+    Self: Sized;
+
+  /// Unlike the rest of the updates is called for every scene on the stack, so
+  /// that the covered ones can keep up with e.g. the window resizes.
+  fn early_update(&mut self) {}
+  fn update(&mut self) -> SceneCommand { SceneCommand::None }
+  fn fixed_update(&mut self) {}
+  fn render(&mut self, renderer: &mut Renderer);
+  /// Is called right before the scene gets removed from the stack.
+  fn shutdown(&mut self) {}
+
+  /// Overlays are rendered over the scene below them instead of replacing it.
+  fn is_overlay(&self) -> bool { false }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum SceneKind {
+  Pong,
+  GameOfLife,
+  Mandelbrot,
+  MarchingSquares,
+  CollisionSimulation,
+  /// Is started only from the command line, see [`NetplayPong`].
+  NetplayPong,
+  Menu,
+  Pause,
+}
+
+impl SceneKind {
+  /// The scenes which can be selected from the menu or on the command line.
+  pub const MAIN: &'static [Self] = &[
+    Self::Pong,
+    Self::GameOfLife,
+    Self::Mandelbrot,
+    Self::MarchingSquares,
+    Self::CollisionSimulation,
+  ];
+
+  pub fn name(self) -> &'static str {
+    match self {
+      Self::Pong => "pong",
+      Self::GameOfLife => "game_of_life",
+      Self::Mandelbrot => "mandelbrot",
+      Self::MarchingSquares => "marching_squares",
+      Self::CollisionSimulation => "collision_simulation",
+      Self::NetplayPong => "netplay_pong",
+      Self::Menu => "menu",
+      Self::Pause => "pause",
+    }
+  }
+
+  /// Only the [main](Self::MAIN) scenes can be looked up.
+  pub fn from_name(name: &str) -> Option<Self> {
+    Self::MAIN.iter().copied().find(|kind| kind.name() == name)
+  }
+
+  pub fn init(self, globals: SharedGlobals) -> AnyResult<Box<dyn Scene>> {
+    fn boxed<T: Scene + 'static>(globals: SharedGlobals) -> AnyResult<Box<dyn Scene>> {
+      Ok(Box::new(T::init(globals)?))
+    }
+
+    match self {
+      Self::Pong => boxed::<Pong>(globals),
+      Self::GameOfLife => boxed::<GameOfLife>(globals),
+      Self::Mandelbrot => boxed::<Mandelbrot>(globals),
+      Self::MarchingSquares => boxed::<MarchingSquares>(globals),
+      Self::CollisionSimulation => boxed::<CollisionSimulation>(globals),
+      Self::NetplayPong => boxed::<NetplayPong>(globals),
+      Self::Menu => boxed::<Menu>(globals),
+      Self::Pause => boxed::<Pause>(globals),
+    }
+    .with_context(|| format!("Failed to initialize {:?}", self))
+  }
+}
+
+/// Requested by the scenes from [`Scene::update`], applied by the stack right
+/// after the update.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SceneCommand {
+  None,
+  Push(SceneKind),
+  Pop,
+  /// Replaces the whole stack.
+  SwitchTo(SceneKind),
+}
+
+#[derive(Debug)]
+pub struct SceneStack {
+  globals: SharedGlobals,
+  scenes: Vec<(SceneKind, Box<dyn Scene>)>,
+}
+
+impl SceneStack {
+  pub fn new(globals: SharedGlobals) -> Self { Self { globals, scenes: Vec::new() } }
+
+  #[inline]
+  pub fn is_empty(&self) -> bool { self.scenes.is_empty() }
+  pub fn top_kind(&self) -> Option<SceneKind> { self.scenes.last().map(|&(kind, _)| kind) }
+
+  pub fn push(&mut self, kind: SceneKind) -> AnyResult<()> {
+    let scene = kind.init(self.globals.share())?;
+    self.scenes.push((kind, scene));
+    Ok(())
+  }
+
+  /// For the scenes which need more than the globals to be initialized.
+  pub fn push_initialized(&mut self, kind: SceneKind, scene: Box<dyn Scene>) {
+    self.scenes.push((kind, scene));
+  }
+
+  pub fn pop(&mut self) -> Option<SceneKind> {
+    let (kind, mut scene) = self.scenes.pop()?;
+    scene.shutdown();
+    Some(kind)
+  }
+
+  pub fn clear(&mut self) { while self.pop().is_some() {} }
+
+  /// The new scene is initialized before the old ones are shut down, so the
+  /// stack is left untouched if that fails.
+  pub fn switch_to(&mut self, kind: SceneKind) -> AnyResult<()> {
+    let scene = kind.init(self.globals.share())?;
+    self.clear();
+    self.scenes.push((kind, scene));
+    Ok(())
+  }
+
+  pub fn apply(&mut self, command: SceneCommand) -> AnyResult<()> {
+    match command {
+      SceneCommand::None => {}
+      SceneCommand::Push(kind) => self.push(kind)?,
+      SceneCommand::Pop => {
+        self.pop();
+      }
+      SceneCommand::SwitchTo(kind) => self.switch_to(kind)?,
+    }
+    Ok(())
+  }
+
+  pub fn early_update(&mut self) {
+    for (_, scene) in &mut self.scenes {
+      scene.early_update();
+    }
+  }
+
+  pub fn update(&mut self) -> AnyResult<()> {
+    let command = if self.globals.input_state.is_action_pressed(actions::MENU)
+      && self.top_kind() != Some(SceneKind::Menu)
+    {
+      SceneCommand::Push(SceneKind::Menu)
+    } else if let Some((_, scene)) = self.scenes.last_mut() {
+      scene.update()
+    } else {
+      SceneCommand::None
+    };
+    self.apply(command)
+  }
+
+  pub fn fixed_update(&mut self) {
+    if let Some((_, scene)) = self.scenes.last_mut() {
+      scene.fixed_update();
+    }
+  }
+
+  pub fn render(&mut self, renderer: &mut Renderer) {
+    let first_visible =
+      self.scenes.iter().rposition(|(_, scene)| !scene.is_overlay()).unwrap_or(0);
+    for (_, scene) in &mut self.scenes[first_visible..] {
+      scene.render(renderer);
+    }
+  }
+}
+
+/// Darkens whatever has been rendered so far and writes the lines centered
+/// over it.
+/// A line like `escape - resume` for the overlays, which names the first key
+/// (or button) bound to the action, so that the hints follow the bindings from
+/// the settings.
+pub fn action_hint(globals: &Globals, action: &str, description: &str) -> String {
+  let bindings = globals.input_state.bindings.borrow();
+  match bindings.action(action).and_then(<[_]>::first) {
+    Some(binding) => format!("{} - {}", binding.to_string().to_lowercase(), description),
+    None => format!("unbound - {}", description),
+  }
+}
+
+pub fn render_overlay(
+  globals: &SharedGlobals,
+  renderer: &mut Renderer,
+  font: &mut renderer::Font,
+  lines: &[&str],
+) {
+  use renderer::{Shape, ShapeFill, ShapeType, TextAlign, TextBlock};
+  renderer.prepare();
+
+  renderer.draw_shape(&mut Shape {
+    type_: ShapeType::Rectangle,
+    pos: Vec2f::ZERO,
+    size: globals.window_size,
+    rotation: 0.0,
+    fill: ShapeFill::Color(OVERLAY_BACKGROUND_COLOR),
+    fill_clipping: None,
+  });
+
+  let line_height = font
+    .measure_size(&TextBlock {
+      text: "",
+      scale: OVERLAY_TEXT_SCALE,
+      character_spacing: OVERLAY_TEXT_CHAR_SPACING,
+      horizontal_align: TextAlign::Center,
+      vertical_align: TextAlign::Center,
+    })
+    .1
+    .y;
+  let mut pos = vec2(0.0, line_height * (lines.len() as f32 - 1.0) / 2.0);
+  for &text in lines {
+    renderer.draw_text(
+      font,
+      pos,
+      &mut TextBlock {
+        text,
+        scale: OVERLAY_TEXT_SCALE,
+        character_spacing: OVERLAY_TEXT_CHAR_SPACING,
+        horizontal_align: TextAlign::Center,
+        vertical_align: TextAlign::Center,
+      },
+    );
+    pos.y -= line_height;
+  }
+
+  renderer.finish();
+}
+
+/// Lists the main scenes and switches to the chosen one.
+#[derive(Debug)]
+pub struct Menu {
+  globals: SharedGlobals,
+  font: renderer::Font,
+}
+
+impl Scene for Menu {
+  fn init(globals: SharedGlobals) -> AnyResult<Self> {
+    let font = renderer::load_default_font(&globals)?;
+    Ok(Self { globals, font })
+  }
+
+  fn update(&mut self) -> SceneCommand {
+    let input = &self.globals.input_state;
+    if input.is_action_pressed(actions::MENU) || input.is_action_pressed(actions::BACK) {
+      return SceneCommand::Pop;
+    }
+    for (&key, &kind) in MENU_SCENE_KEYS.iter().zip(SceneKind::MAIN) {
+      if input.is_key_pressed(key) {
+        return SceneCommand::SwitchTo(kind);
+      }
+    }
+    SceneCommand::None
+  }
+
+  fn render(&mut self, renderer: &mut Renderer) {
+    let lines: Vec<String> = SceneKind::MAIN
+      .iter()
+      .enumerate()
+      .map(|(i, kind)| format!("{} - {}", i + 1, kind.name()))
+      .collect();
+    let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+    render_overlay(&self.globals, renderer, &mut self.font, &lines);
+  }
+
+  fn is_overlay(&self) -> bool { true }
+}
+
+/// Freezes the scene below it, since that one no longer receives the updates.
+#[derive(Debug)]
+pub struct Pause {
+  globals: SharedGlobals,
+  font: renderer::Font,
+}
+
+impl Scene for Pause {
+  fn init(globals: SharedGlobals) -> AnyResult<Self> {
+    let font = renderer::load_default_font(&globals)?;
+    Ok(Self { globals, font })
+  }
+
+  fn update(&mut self) -> SceneCommand {
+    if self.globals.input_state.is_action_pressed(actions::PAUSE) {
+      SceneCommand::Pop
+    } else {
+      SceneCommand::None
+    }
+  }
+
+  fn render(&mut self, renderer: &mut Renderer) {
+    let resume = action_hint(&self.globals, actions::PAUSE, "resume");
+    let menu = action_hint(&self.globals, actions::MENU, "menu");
+    render_overlay(&self.globals, renderer, &mut self.font, &["paused", "", &resume, &menu]);
+  }
+
+  fn is_overlay(&self) -> bool { true }
+}
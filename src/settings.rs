@@ -0,0 +1,417 @@
+//! Engine settings, read from `settings.json` in the installation directory
+//! (or the file given with `--settings`) and then overridden by the
+//! command-line flags. A missing default file is not an error, every field of
+//! it is optional.
+
+use cardboard_math::*;
+use prelude_plus::*;
+use serde::Deserialize;
+use serde_json as json;
+
+use crate::bindings::{BindingsConfig, InputBindings};
+use crate::game_fs::GameFs;
+use crate::globals::{parse_seed, DEFAULT_FIXED_UPDATES_PER_SECOND};
+use crate::input::DEFAULT_GAMEPAD_DEAD_ZONE;
+use crate::scene::SceneKind;
+
+pub const USAGE: &str = "\
+Usage: openkrosskod [OPTIONS]
+       openkrosskod tournament [OPTIONS]
+       openkrosskod netplay [OPTIONS]
+
+Options:
+  --settings PATH         read the settings from PATH instead of settings.json
+  --scene NAME            the scene to start with
+  --window-size WxH       the initial window size in pixels
+  --fullscreen, --windowed
+  --vsync, --no-vsync
+  --msaa SAMPLES          0 disables multisampling
+  --gl-version MAJOR.MINOR
+  --gl-debug, --no-gl-debug
+  --log FILTER            an env_logger filter, RUST_LOG takes precedence
+  --ups N                 fixed updates per second
+  --seed SEED             the RNG seed, hexadecimal with 0x or decimal, the
+                          environment variable OPENKROSSKOD_SEED works too
+  --record PATH           record the input into a replay file
+  --replay PATH           play a recorded replay instead of reading the input,
+                          its seed and initial scene take precedence
+  --help                  print this message
+";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+  pub window_size: Vec2u32,
+  pub fullscreen: bool,
+  pub vsync: bool,
+  pub msaa_samples: u8,
+  /// Only the GLES profile is supported.
+  pub gl_version: (u8, u8),
+  /// Creates a debug context, so that the driver reports the errors and the
+  /// performance warnings.
+  pub gl_debug: bool,
+  pub log_filter: String,
+  pub fixed_updates_per_second: f64,
+  pub scene: String,
+  /// A random one is generated if unset. Is written as a string since the JSON
+  /// numbers can't hold 128 bits.
+  #[serde(deserialize_with = "deserialize_seed")]
+  pub seed: Option<u128>,
+  /// Overrides for the built-in input bindings, see [`crate::bindings`].
+  pub bindings: BindingsConfig,
+  /// The fraction of the range of the gamepad sticks around the center which
+  /// is ignored, the worn out sticks don't return to exactly zero.
+  pub gamepad_dead_zone: f32,
+}
+
+pub const SEED_ENV_VAR: &str = "OPENKROSSKOD_SEED";
+
+fn deserialize_seed<'de, D: serde::Deserializer<'de>>(
+  deserializer: D,
+) -> Result<Option<u128>, D::Error> {
+  Option::<String>::deserialize(deserializer)?
+    .map(|s| parse_seed(&s))
+    .transpose()
+    .map_err(serde::de::Error::custom)
+}
+
+impl Default for Settings {
+  fn default() -> Self {
+    Self {
+      window_size: vec2(568 * 2, 320 * 2),
+      fullscreen: false,
+      vsync: true,
+      msaa_samples: 0,
+      gl_version: (2, 0),
+      gl_debug: true,
+      log_filter: "debug".to_owned(),
+      fixed_updates_per_second: DEFAULT_FIXED_UPDATES_PER_SECOND,
+      scene: SceneKind::Pong.name().to_owned(),
+      seed: None,
+      bindings: BindingsConfig::new(),
+      gamepad_dead_zone: DEFAULT_GAMEPAD_DEAD_ZONE,
+    }
+  }
+}
+
+impl Settings {
+  /// Reads the file given on the command line, or the default one if it
+  /// exists, and applies the overrides from the environment and then from the
+  /// command-line flags on top.
+  pub fn load(game_fs: &GameFs, args: &Args) -> AnyResult<Self> {
+    let (path, bytes) = match &args.settings_path {
+      Some(path) => {
+        let bytes = fs::read(path)
+          .with_context(|| format!("Failed to read the settings file '{}'", path.display()))?;
+        (path.clone(), Some(bytes))
+      }
+      None => (game_fs.settings_path(), game_fs.read_settings_file()?),
+    };
+
+    let mut settings = match bytes {
+      Some(bytes) => json::from_slice(&bytes)
+        .with_context(|| format!("Failed to parse the settings file '{}'", path.display()))?,
+      None => Self::default(),
+    };
+    if let Some(seed) = env::var_os(SEED_ENV_VAR) {
+      let seed =
+        seed.to_str().ok_or_else(|| format_err!("{} is not valid UTF-8", SEED_ENV_VAR))?;
+      settings.seed = Some(parse_seed(seed).with_context(|| format!("Invalid {}", SEED_ENV_VAR))?);
+    }
+    args.overrides.apply(&mut settings);
+    settings.validate()?;
+    Ok(settings)
+  }
+
+  pub fn validate(&self) -> AnyResult<()> {
+    ensure!(
+      self.window_size.x > 0 && self.window_size.y > 0,
+      "The window size must not be zero, got {}x{}",
+      self.window_size.x,
+      self.window_size.y,
+    );
+    ensure!(
+      self.fixed_updates_per_second.is_finite() && self.fixed_updates_per_second > 0.0,
+      "The number of fixed updates per second must be positive, got {}",
+      self.fixed_updates_per_second,
+    );
+    ensure!(
+      (0.0..1.0).contains(&self.gamepad_dead_zone),
+      "The gamepad dead zone must be in the range [0, 1), got {}",
+      self.gamepad_dead_zone,
+    );
+    self.scene_kind()?;
+    self.input_bindings()?;
+    Ok(())
+  }
+
+  pub fn scene_kind(&self) -> AnyResult<SceneKind> {
+    SceneKind::from_name(&self.scene).ok_or_else(|| {
+      let names: Vec<&str> = SceneKind::MAIN.iter().map(|kind| kind.name()).collect();
+      format_err!("Unknown scene '{}', expected one of: {}", self.scene, names.join(", "))
+    })
+  }
+
+  /// The built-in bindings with the ones from the file applied on top.
+  pub fn input_bindings(&self) -> AnyResult<InputBindings> {
+    let mut bindings = InputBindings::new();
+    bindings.apply_config(&self.bindings).context("Invalid input bindings")?;
+    Ok(bindings)
+  }
+
+  #[inline]
+  pub fn fixed_delta_time(&self) -> f64 { 1.0 / self.fixed_updates_per_second }
+}
+
+#[derive(Debug, Default)]
+pub struct Args {
+  pub help: bool,
+  pub settings_path: Option<PathBuf>,
+  pub record_path: Option<PathBuf>,
+  pub replay_path: Option<PathBuf>,
+  pub overrides: Overrides,
+}
+
+/// The settings given on the command line, these take precedence over the
+/// ones from the file.
+#[derive(Debug, Default)]
+pub struct Overrides {
+  pub window_size: Option<Vec2u32>,
+  pub fullscreen: Option<bool>,
+  pub vsync: Option<bool>,
+  pub msaa_samples: Option<u8>,
+  pub gl_version: Option<(u8, u8)>,
+  pub gl_debug: Option<bool>,
+  pub log_filter: Option<String>,
+  pub fixed_updates_per_second: Option<f64>,
+  pub scene: Option<String>,
+  pub seed: Option<u128>,
+}
+
+impl Overrides {
+  pub fn apply(&self, settings: &mut Settings) {
+    macro_rules! apply {
+      ($($field:ident),+ $(,)?) => {
+        $(if let Some(value) = &self.$field {
+          settings.$field = value.clone();
+        })+
+      };
+    }
+    apply![
+      window_size,
+      fullscreen,
+      vsync,
+      msaa_samples,
+      gl_version,
+      gl_debug,
+      log_filter,
+      fixed_updates_per_second,
+      scene,
+    ];
+    if let Some(seed) = self.seed {
+      settings.seed = Some(seed);
+    }
+  }
+}
+
+pub fn parse_args(args: impl Iterator<Item = String>) -> AnyResult<Args> {
+  let mut result = Args::default();
+  let overrides = &mut result.overrides;
+  let mut args = args;
+
+  fn next_value(args: &mut impl Iterator<Item = String>, arg: &str) -> AnyResult<String> {
+    args.next().ok_or_else(|| format_err!("{} requires a value", arg))
+  }
+
+  fn parse_value<T: str::FromStr>(arg: &str, value: &str, expected: &str) -> AnyResult<T> {
+    value
+      .parse()
+      .map_err(|_| format_err!("Invalid value for {}: '{}', expected {}", arg, value, expected))
+  }
+
+  fn parse_pair<T: str::FromStr>(
+    arg: &str,
+    value: &str,
+    separator: char,
+    expected: &str,
+  ) -> AnyResult<(T, T)> {
+    let (a, b) = value.split_once(separator).ok_or_else(|| {
+      format_err!("Invalid value for {}: '{}', expected {}", arg, value, expected)
+    })?;
+    Ok((parse_value(arg, a, expected)?, parse_value(arg, b, expected)?))
+  }
+
+  while let Some(arg) = args.next() {
+    let arg = arg.as_str();
+    match arg {
+      "--help" | "-h" => result.help = true,
+      "--settings" => result.settings_path = Some(PathBuf::from(next_value(&mut args, arg)?)),
+      "--record" => result.record_path = Some(PathBuf::from(next_value(&mut args, arg)?)),
+      "--replay" => result.replay_path = Some(PathBuf::from(next_value(&mut args, arg)?)),
+      "--scene" => overrides.scene = Some(next_value(&mut args, arg)?),
+      "--window-size" => {
+        let (w, h) = parse_pair(arg, &next_value(&mut args, arg)?, 'x', "WxH")?;
+        overrides.window_size = Some(vec2(w, h));
+      }
+      "--fullscreen" => overrides.fullscreen = Some(true),
+      "--windowed" => overrides.fullscreen = Some(false),
+      "--vsync" => overrides.vsync = Some(true),
+      "--no-vsync" => overrides.vsync = Some(false),
+      "--msaa" => {
+        overrides.msaa_samples =
+          Some(parse_value(arg, &next_value(&mut args, arg)?, "a number of samples")?);
+      }
+      "--gl-version" => {
+        overrides.gl_version =
+          Some(parse_pair(arg, &next_value(&mut args, arg)?, '.', "MAJOR.MINOR")?);
+      }
+      "--gl-debug" => overrides.gl_debug = Some(true),
+      "--no-gl-debug" => overrides.gl_debug = Some(false),
+      "--log" => overrides.log_filter = Some(next_value(&mut args, arg)?),
+      "--ups" => {
+        overrides.fixed_updates_per_second =
+          Some(parse_value(arg, &next_value(&mut args, arg)?, "a number")?);
+      }
+      "--seed" => overrides.seed = Some(parse_seed(&next_value(&mut args, arg)?)?),
+      _ => bail!("Unknown option: {}", arg),
+    }
+  }
+
+  Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_utils::TempGameFs;
+
+  fn args(list: &[&str]) -> AnyResult<Args> { parse_args(list.iter().map(|s| s.to_string())) }
+
+  #[test]
+  fn reads_the_default_settings_file_through_game_fs() {
+    let game_fs = TempGameFs::new("settings");
+    let args = Args::default();
+
+    let settings = Settings::load(&game_fs, &args).unwrap();
+    assert_eq!(settings.msaa_samples, Settings::default().msaa_samples);
+
+    fs::write(game_fs.settings_path(), r#"{ "msaa_samples": 4 }"#).unwrap();
+    let settings = Settings::load(&game_fs, &args).unwrap();
+    assert_eq!(settings.msaa_samples, 4);
+
+    fs::write(game_fs.settings_path(), "{").unwrap();
+    assert!(format!("{:#}", Settings::load(&game_fs, &args).unwrap_err())
+      .starts_with("Failed to parse the settings file"));
+  }
+
+  #[test]
+  fn requires_an_explicit_settings_file_to_exist() {
+    let game_fs = TempGameFs::new("settings-explicit");
+    let args = Args {
+      settings_path: Some(game_fs.installation_dir.join("missing.json")),
+      ..Args::default()
+    };
+    assert!(format!("{:#}", Settings::load(&game_fs, &args).unwrap_err())
+      .starts_with("Failed to read the settings file"));
+  }
+
+  #[test]
+  fn the_environment_overrides_the_file_and_the_flags_override_both() {
+    let game_fs = TempGameFs::new("settings-precedence");
+    let path = game_fs.installation_dir.join("custom.json");
+    fs::write(&path, r#"{ "seed": "0x10", "msaa_samples": 2, "vsync": false }"#).unwrap();
+    let path = path.to_str().unwrap();
+    let load = |list: &[&str]| Settings::load(&game_fs, &args(list).unwrap());
+
+    // NOTE: This is the only test which touches the variable, the others don't
+    // depend on the seed anyway.
+    env::remove_var(SEED_ENV_VAR);
+    let settings = load(&["--settings", path]).unwrap();
+    assert_eq!((settings.seed, settings.msaa_samples, settings.vsync), (Some(0x10), 2, false));
+
+    env::set_var(SEED_ENV_VAR, "0x20");
+    let settings = load(&["--settings", path]).unwrap();
+    assert_eq!((settings.seed, settings.msaa_samples, settings.vsync), (Some(0x20), 2, false));
+    let settings = load(&["--settings", path, "--seed", "48", "--msaa", "8"]).unwrap();
+    assert_eq!((settings.seed, settings.msaa_samples, settings.vsync), (Some(48), 8, false));
+
+    env::set_var(SEED_ENV_VAR, "nope");
+    let error = load(&["--settings", path]).unwrap_err();
+    assert!(format!("{:#}", error).starts_with("Invalid OPENKROSSKOD_SEED"), "{:#}", error);
+    env::remove_var(SEED_ENV_VAR);
+
+    // The flags are validated like the file.
+    let error = load(&["--settings", path, "--ups", "0"]).unwrap_err();
+    assert!(error.to_string().contains("fixed updates per second"), "{}", error);
+  }
+
+  #[test]
+  fn parses_the_flags() {
+    let args = args(&[
+      "--settings",
+      "a.json",
+      "--record",
+      "b.replay",
+      "--scene",
+      "tilemap",
+      "--window-size",
+      "800x600",
+      "--fullscreen",
+      "--no-vsync",
+      "--msaa",
+      "4",
+      "--gl-version",
+      "3.1",
+      "--no-gl-debug",
+      "--log",
+      "info",
+      "--ups",
+      "60",
+      "--seed",
+      "0xff",
+    ])
+    .unwrap();
+    assert!(!args.help);
+    assert_eq!(args.settings_path, Some(PathBuf::from("a.json")));
+    assert_eq!(args.record_path, Some(PathBuf::from("b.replay")));
+    assert_eq!(args.replay_path, None);
+
+    let mut settings = Settings::default();
+    args.overrides.apply(&mut settings);
+    assert_eq!(settings.scene, "tilemap");
+    assert_eq!(settings.window_size, vec2(800, 600));
+    assert_eq!((settings.fullscreen, settings.vsync), (true, false));
+    assert_eq!(
+      (settings.msaa_samples, settings.gl_version, settings.gl_debug),
+      (4, (3, 1), false)
+    );
+    assert_eq!(settings.log_filter, "info");
+    assert_eq!(settings.fixed_updates_per_second, 60.0);
+    assert_eq!(settings.seed, Some(0xff));
+
+    // The last one wins.
+    let args = self::args(&["--windowed", "--fullscreen", "-h"]).unwrap();
+    assert_eq!((args.overrides.fullscreen, args.help), (Some(true), true));
+    assert_eq!(self::args(&[]).unwrap().overrides.scene, None);
+  }
+
+  #[test]
+  fn rejects_invalid_flags() {
+    let error = |list: &[&str]| args(list).unwrap_err().to_string();
+    assert_eq!(error(&["--scene"]), "--scene requires a value");
+    assert_eq!(error(&["--frobnicate"]), "Unknown option: --frobnicate");
+    assert_eq!(
+      error(&["--msaa", "lots"]),
+      "Invalid value for --msaa: 'lots', expected a number of samples"
+    );
+    assert_eq!(
+      error(&["--window-size", "800"]),
+      "Invalid value for --window-size: '800', expected WxH"
+    );
+    assert_eq!(
+      error(&["--gl-version", "3.x"]),
+      "Invalid value for --gl-version: 'x', expected MAJOR.MINOR"
+    );
+    assert!(args(&["--seed", "0xzz"]).is_err());
+  }
+}
@@ -0,0 +1,40 @@
+//! Helpers shared by the unit tests.
+
+use prelude_plus::*;
+
+use crate::game_fs::GameFs;
+
+/// A [`GameFs`] rooted in a fresh temporary directory, which is deleted when
+/// this is dropped, so also when the test panics.
+#[derive(Debug)]
+pub struct TempGameFs {
+  game_fs: GameFs,
+}
+
+impl TempGameFs {
+  pub fn new(name: &str) -> Self {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let id = COUNTER.fetch_add(1, AtomicOrdering::Relaxed);
+    let installation_dir =
+      env::temp_dir().join(format!("openkrosskod-{}-{}-{}", name, std::process::id(), id));
+    // A leftover of a killed test run with a recycled PID.
+    let _ = fs::remove_dir_all(&installation_dir);
+    let assets_dir = installation_dir.join("assets");
+    fs::create_dir_all(&assets_dir).unwrap();
+    Self { game_fs: GameFs { installation_dir, assets_dir } }
+  }
+}
+
+impl Deref for TempGameFs {
+  type Target = GameFs;
+  #[inline(always)]
+  fn deref(&self) -> &Self::Target { &self.game_fs }
+}
+
+impl Drop for TempGameFs {
+  fn drop(&mut self) {
+    if let Err(e) = fs::remove_dir_all(&self.game_fs.installation_dir) {
+      warn!("Failed to delete '{}': {}", self.game_fs.installation_dir.display(), e);
+    }
+  }
+}
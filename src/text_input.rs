@@ -0,0 +1,288 @@
+//! Typed text, as opposed to the pressed keys: the text comes out of the
+//! keyboard layout and the IME, so it can't be reconstructed from the key
+//! states. SDL only sends it while the text input mode is on, which the scenes
+//! switch with [`TextInput::start`] and [`TextInput::stop`].
+
+use prelude_plus::*;
+
+use crate::input::Key;
+
+/// The editing keys, generated on every key press including the repeated
+/// ones, so that holding down e.g. backspace works as expected.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum TextEdit {
+  Backspace,
+  Delete,
+  Left,
+  Right,
+  WordLeft,
+  WordRight,
+  Home,
+  End,
+  Submit,
+}
+
+impl TextEdit {
+  #[rustfmt::skip]
+  pub const VARIANTS: &'static [Self] = &[
+    Self::Backspace, Self::Delete, Self::Left, Self::Right, Self::WordLeft,
+    Self::WordRight, Self::Home, Self::End, Self::Submit,
+  ];
+
+  pub fn from_key(key: Key, ctrl: bool) -> Option<Self> {
+    Some(match key {
+      Key::Backspace => Self::Backspace,
+      Key::Delete => Self::Delete,
+      Key::Left if ctrl => Self::WordLeft,
+      Key::Right if ctrl => Self::WordRight,
+      Key::Left => Self::Left,
+      Key::Right => Self::Right,
+      Key::Home => Self::Home,
+      Key::End => Self::End,
+      Key::Return => Self::Submit,
+      _ => return None,
+    })
+  }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TextEditEvent {
+  pub edit: TextEdit,
+  /// The length of [`TextInput::text`] at the moment of the edit, so that the
+  /// order of the typing and the editing within a single tick is preserved.
+  pub text_offset: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct TextInput {
+  requested: Cell<bool>,
+  /// The text typed on the current game loop tick.
+  pub text: String,
+  pub edits: Vec<TextEditEvent>,
+  /// The unfinished text in the IME, should be displayed at the cursor but is
+  /// not a part of the input yet.
+  pub composition: String,
+  /// In characters, within the composition.
+  pub composition_cursor: usize,
+  pub composition_selection_len: usize,
+}
+
+impl TextInput {
+  /// Turns on the text input mode, which shows the on-screen keyboard or the
+  /// IME if there are any. Takes effect on the next tick.
+  pub fn start(&self) { self.requested.set(true); }
+  pub fn stop(&self) { self.requested.set(false); }
+  #[inline]
+  pub fn is_active(&self) -> bool { self.requested.get() }
+
+  /// Called at the start of every tick.
+  pub fn clear_tick(&mut self) {
+    self.text.clear();
+    self.edits.clear();
+  }
+
+  pub fn push_edit(&mut self, edit: TextEdit) {
+    self.edits.push(TextEditEvent { edit, text_offset: self.text.len() });
+  }
+
+  #[inline]
+  pub fn is_composing(&self) -> bool { !self.composition.is_empty() }
+}
+
+/// A single-line text field, the cursor is a byte offset which always lies on
+/// a character boundary.
+#[derive(Debug, Clone, Default)]
+pub struct TextField {
+  text: String,
+  cursor: usize,
+  /// In characters.
+  pub max_len: Option<usize>,
+}
+
+impl TextField {
+  pub fn new(text: String) -> Self {
+    let cursor = text.len();
+    Self { text, cursor, max_len: None }
+  }
+
+  #[inline(always)]
+  pub fn text(&self) -> &str { &self.text }
+  #[inline(always)]
+  pub fn cursor(&self) -> usize { self.cursor }
+
+  pub fn set_text(&mut self, text: String) {
+    self.cursor = text.len();
+    self.text = text;
+  }
+
+  /// Applies the input of the current tick, returns `true` if it was
+  /// submitted.
+  pub fn update(&mut self, input: &TextInput) -> bool {
+    let mut submitted = false;
+    let mut inserted = 0;
+    for event in &input.edits {
+      self.insert(&input.text[inserted..event.text_offset]);
+      inserted = event.text_offset;
+      submitted |= self.apply_edit(event.edit);
+    }
+    self.insert(&input.text[inserted..]);
+    submitted
+  }
+
+  pub fn insert(&mut self, text: &str) {
+    let mut text = text;
+    if let Some(max_len) = self.max_len {
+      let free = max_len.saturating_sub(self.text.chars().count());
+      text = match text.char_indices().nth(free) {
+        Some((end, _)) => &text[..end],
+        None => text,
+      };
+    }
+    self.text.insert_str(self.cursor, text);
+    self.cursor += text.len();
+  }
+
+  /// Returns `true` on [`TextEdit::Submit`].
+  pub fn apply_edit(&mut self, edit: TextEdit) -> bool {
+    match edit {
+      TextEdit::Backspace => {
+        let start = self.prev_char_boundary();
+        self.text.replace_range(start..self.cursor, "");
+        self.cursor = start;
+      }
+      TextEdit::Delete => {
+        let end = self.next_char_boundary();
+        self.text.replace_range(self.cursor..end, "");
+      }
+      TextEdit::Left => self.cursor = self.prev_char_boundary(),
+      TextEdit::Right => self.cursor = self.next_char_boundary(),
+      TextEdit::WordLeft => {
+        let before = &self.text[..self.cursor];
+        let word_end = before.trim_end_matches(|c: char| !c.is_alphanumeric()).len();
+        self.cursor = before[..word_end].trim_end_matches(char::is_alphanumeric).len();
+      }
+      TextEdit::WordRight => {
+        let after = &self.text[self.cursor..];
+        let word_start =
+          after.len() - after.trim_start_matches(|c: char| !c.is_alphanumeric()).len();
+        let rest = &after[word_start..];
+        self.cursor +=
+          word_start + (rest.len() - rest.trim_start_matches(char::is_alphanumeric).len());
+      }
+      TextEdit::Home => self.cursor = 0,
+      TextEdit::End => self.cursor = self.text.len(),
+      TextEdit::Submit => return true,
+    }
+    false
+  }
+
+  fn prev_char_boundary(&self) -> usize {
+    self.text[..self.cursor].char_indices().next_back().map_or(0, |(i, _)| i)
+  }
+
+  fn next_char_boundary(&self) -> usize {
+    self.text[self.cursor..].chars().next().map_or(self.cursor, |c| self.cursor + c.len_utf8())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn edit(field: &mut TextField, edits: &[TextEdit]) -> usize {
+    for &edit in edits {
+      field.apply_edit(edit);
+    }
+    field.cursor()
+  }
+
+  #[test]
+  fn cursor_moves_over_whole_characters() {
+    let mut field = TextField::new("aé😀b".to_owned());
+    assert_eq!(field.cursor(), 8);
+    assert_eq!(edit(&mut field, &[TextEdit::Left]), 7);
+    assert_eq!(edit(&mut field, &[TextEdit::Left]), 3);
+    assert_eq!(edit(&mut field, &[TextEdit::Left]), 1);
+    assert_eq!(edit(&mut field, &[TextEdit::Left, TextEdit::Left]), 0);
+    assert_eq!(edit(&mut field, &[TextEdit::Right, TextEdit::Right]), 3);
+    assert_eq!(edit(&mut field, &[TextEdit::End, TextEdit::Right]), 8);
+
+    assert_eq!(edit(&mut field, &[TextEdit::Left, TextEdit::Backspace]), 3);
+    assert_eq!(field.text(), "aéb");
+    assert_eq!(edit(&mut field, &[TextEdit::Home, TextEdit::Delete, TextEdit::Delete]), 0);
+    assert_eq!(field.text(), "b");
+    assert_eq!(edit(&mut field, &[TextEdit::Backspace, TextEdit::End, TextEdit::Delete]), 1);
+    assert_eq!(field.text(), "b");
+
+    field.insert("ж");
+    assert_eq!((field.text(), field.cursor()), ("bж", 3));
+    edit(&mut field, &[TextEdit::Home]);
+    field.insert("ё");
+    assert_eq!((field.text(), field.cursor()), ("ёbж", 2));
+  }
+
+  #[test]
+  fn word_motion() {
+    let mut field = TextField::new("héllo, wörld  42".to_owned());
+    let text = field.text().to_owned();
+    let pos = |s: &str| text.find(s).unwrap();
+    assert_eq!(edit(&mut field, &[TextEdit::WordLeft]), pos("42"));
+    assert_eq!(edit(&mut field, &[TextEdit::WordLeft]), pos("wörld"));
+    assert_eq!(edit(&mut field, &[TextEdit::WordLeft]), 0);
+    assert_eq!(edit(&mut field, &[TextEdit::WordLeft]), 0);
+    assert_eq!(edit(&mut field, &[TextEdit::WordRight]), pos(", "));
+    assert_eq!(edit(&mut field, &[TextEdit::WordRight]), pos("  42"));
+    assert_eq!(edit(&mut field, &[TextEdit::WordRight]), text.len());
+    assert_eq!(edit(&mut field, &[TextEdit::WordRight]), text.len());
+    // From the middle of a word the cursor goes to its boundaries.
+    field.apply_edit(TextEdit::Home);
+    assert_eq!(edit(&mut field, &[TextEdit::Right, TextEdit::Right, TextEdit::WordLeft]), 0);
+    assert_eq!(
+      edit(&mut field, &[TextEdit::Right, TextEdit::Right, TextEdit::WordRight]),
+      pos(",")
+    );
+  }
+
+  #[test]
+  fn max_len_counts_characters() {
+    let mut field = TextField { max_len: Some(4), ..TextField::default() };
+    field.insert("ab");
+    field.insert("жжж");
+    assert_eq!(field.text(), "abжж");
+    field.insert("c");
+    assert_eq!(field.text(), "abжж");
+
+    // The cut never lands in the middle of a character.
+    let mut field = TextField { max_len: Some(3), ..TextField::new("a".to_owned()) };
+    field.apply_edit(TextEdit::Home);
+    field.insert("😀é😀");
+    assert_eq!((field.text(), field.cursor()), ("😀éa", 6));
+
+    // A text which is already too long is not cut, but doesn't grow either.
+    let mut field = TextField { max_len: Some(2), ..TextField::new("abc".to_owned()) };
+    field.insert("d");
+    assert_eq!(field.text(), "abc");
+    field.apply_edit(TextEdit::Backspace);
+    field.insert("éd");
+    assert_eq!(field.text(), "ab");
+  }
+
+  #[test]
+  fn applies_the_typing_and_the_edits_in_order() {
+    let mut input = TextInput::default();
+    input.text.push_str("ab");
+    input.push_edit(TextEdit::Backspace);
+    input.push_edit(TextEdit::Home);
+    input.text.push('é');
+    input.push_edit(TextEdit::End);
+    input.text.push('c');
+
+    let mut field = TextField::new("x".to_owned());
+    assert!(!field.update(&input));
+    assert_eq!((field.text(), field.cursor()), ("éxac", 5));
+
+    input.push_edit(TextEdit::Submit);
+    assert!(field.update(&input));
+  }
+}
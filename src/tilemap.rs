@@ -0,0 +1,488 @@
+use cardboard_math::*;
+use cardboard_oogl as oogl;
+use cardboard_oogl::traits::*;
+use prelude_plus::*;
+
+use crate::globals::SharedGlobals;
+use crate::map::{Map, MapLayerLevel, MapLayerType};
+use crate::renderer;
+
+/// In tiles.
+const CHUNK_SIZE: Vec2u32 = vec2n(32);
+const CHUNK_MAX_QUADS: usize = CHUNK_SIZE.x as usize * CHUNK_SIZE.y as usize;
+const QUAD_CORNERS: [Vec2f; 4] = [vec2(0.0, 0.0), vec2(1.0, 0.0), vec2(1.0, 1.0), vec2(0.0, 1.0)];
+
+const_assert!(CHUNK_MAX_QUADS * 4 <= u16::MAX as usize + 1);
+
+const TILESET_TEXTURE_UNIT: oogl::TextureUnit = 0;
+const ATTRIB_LOCATIONS: &[(&str, u32)] = &[("a_pos", 0), ("a_texcoord", 1)];
+
+#[repr(C, packed)]
+#[derive(Copy, Debug, Clone, Default)]
+struct TileVertex {
+  pos: Vec2f,
+  texcoord: Vec2f,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Camera {
+  /// The point of the map in the center of the window, in pixels.
+  pub pos: Vec2f,
+  pub zoom: f32,
+}
+
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct TilemapStats {
+  pub draw_calls: u32,
+  pub visible_chunks: u32,
+  pub rebuilt_chunks: u32,
+}
+
+/// Draws the tile layers of a [`Map`]. Every layer is split into chunks of
+/// [`CHUNK_SIZE`] tiles, each with its own static vertex buffer which is
+/// rebuilt only after the tiles inside it were changed with
+/// [`Self::set_tile`], and only the chunks which intersect the view of the
+/// camera are drawn.
+#[derive(Debug)]
+pub struct Tilemap {
+  globals: SharedGlobals,
+
+  program: oogl::Program,
+  program_reflection: ProgramReflection,
+  ebo: oogl::ElementBuffer<u16>,
+  tilesets: Vec<Tileset>,
+
+  /// In the same order as in the map file.
+  layers: Vec<TilemapLayer>,
+  /// Indexes into `layers`, sorted by the levels.
+  draw_order: Vec<usize>,
+  stats: TilemapStats,
+}
+
+#[derive(Debug)]
+struct Tileset {
+  path: String,
+  texture: oogl::Texture2D,
+}
+
+#[derive(Debug)]
+struct TilemapLayer {
+  /// [`None`] for the layers which are not drawn, e.g. collision ones.
+  tileset: Option<usize>,
+  visible: bool,
+  /// The layer is tiled infinitely in both directions.
+  repeat: bool,
+  /// In pixels.
+  tile_size: u32,
+  /// In tiles.
+  size: Vec2u32,
+  distance: f32,
+  tiles: Vec<u16>,
+
+  chunks_count: Vec2u32,
+  chunks: Vec<TilemapChunk>,
+}
+
+#[derive(Debug)]
+struct TilemapChunk {
+  vbo: oogl::VertexBuffer<TileVertex>,
+  quads: usize,
+  dirty: bool,
+}
+
+impl TilemapLayer {
+  fn tile_index(&self, pos: Vec2u32) -> usize {
+    assert!(pos.x < self.size.x && pos.y < self.size.y);
+    pos.x as usize + pos.y as usize * self.size.x as usize
+  }
+}
+
+/// Layers on the named levels are drawn under (`first`) or over (everything
+/// else) the numbered ones.
+fn level_draw_order(level: &MapLayerLevel) -> (u32, u32) {
+  match level {
+    MapLayerLevel::Named(name) if name == "first" => (0, 0),
+    MapLayerLevel::Index(index) => (1, *index),
+    MapLayerLevel::Named(name) => match name.as_str() {
+      "object1" => (2, 1),
+      "object2" => (2, 2),
+      "object3" => (2, 3),
+      "last" => (3, 0),
+      "light" => (4, 0),
+      "postlight" => (5, 0),
+      _ => {
+        warn!("Unknown layer level '{}', drawing it over the others", name);
+        (6, 0)
+      }
+    },
+  }
+}
+
+impl Tilemap {
+  pub fn new(globals: SharedGlobals, map: &Map) -> AnyResult<Self> {
+    // The chunks are built by dividing by the tile size and the layers are
+    // positioned by dividing by the distance, the validation ensures that
+    // neither is zero.
+    map.validate()?;
+
+    use oogl::ShaderType;
+    let vertex_shader =
+      renderer::load_shader_asset(&globals, "shaders/tilemap.vert.glsl", ShaderType::Vertex)?;
+    let fragment_shader =
+      renderer::load_shader_asset(&globals, "shaders/tilemap.frag.glsl", ShaderType::Fragment)?;
+    let mut program = renderer::load_program_asset_with_attrib_locations(
+      &globals,
+      "Tilemap",
+      &[&vertex_shader, &fragment_shader],
+      ATTRIB_LOCATIONS,
+    )?;
+    let program_reflection = ProgramReflection::new(&program);
+    {
+      let bound_program = program.bind();
+      let reflection = &program_reflection;
+      reflection.u_window_size.set(&bound_program, &globals.window_size);
+      reflection.u_tex.set(&bound_program, &TILESET_TEXTURE_UNIT);
+    }
+
+    let mut ebo = oogl::ElementBuffer::new(globals.gl.share(), oogl::BufferUsageHint::StaticDraw);
+    ebo.set_debug_label(b"Tilemap.ebo");
+    {
+      let mut indices = Vec::with_capacity(CHUNK_MAX_QUADS * 6);
+      for i in 0..CHUNK_MAX_QUADS as u16 {
+        let first = i * QUAD_CORNERS.len() as u16;
+        indices.extend_from_slice(&[first, first + 1, first + 2, first, first + 2, first + 3]);
+      }
+      ebo.bind().alloc_and_set(&indices);
+    }
+
+    let vbo_attribs = vec![
+      program_reflection.a_pos.to_pointer_simple(),
+      program_reflection.a_texcoord.to_pointer_simple(),
+    ];
+
+    let mut tilesets: Vec<Tileset> = Vec::new();
+    let mut layers = Vec::with_capacity(map.layers.len());
+    for (layer_idx, map_layer) in map.layers.iter().enumerate() {
+      let tileset = match (&map_layer.type_, map_layer.tileset()) {
+        (MapLayerType::Background, Some(path)) => {
+          Some(match tilesets.iter().position(|tileset| tileset.path == path) {
+            Some(idx) => idx,
+            None => {
+              let texture =
+                renderer::load_texture_asset(&globals, path, oogl::TextureFilter::Nearest)?;
+              tilesets.push(Tileset { path: path.to_owned(), texture });
+              tilesets.len() - 1
+            }
+          })
+        }
+        _ => None,
+      };
+      let size = vec2(map_layer.width, map_layer.height);
+      let chunks_count = (size + CHUNK_SIZE - vec2n(1)) / CHUNK_SIZE;
+      let mut chunks = Vec::new();
+      if tileset.is_some() {
+        chunks.reserve(chunks_count.x as usize * chunks_count.y as usize);
+        for chunk_idx in 0..chunks.capacity() {
+          let vbo = oogl::VertexBuffer::new(
+            globals.gl.share(),
+            oogl::BufferUsageHint::StaticDraw,
+            vbo_attribs.clone(),
+          );
+          vbo.set_debug_label(
+            format!("Tilemap.layers[{}].chunks[{}]", layer_idx, chunk_idx).as_bytes(),
+          );
+          chunks.push(TilemapChunk { vbo, quads: 0, dirty: true });
+        }
+      }
+
+      layers.push(TilemapLayer {
+        tileset,
+        visible: map_layer.visible,
+        repeat: map_layer.repeat,
+        tile_size: map_layer.tilesize,
+        size,
+        distance: map_layer.distance,
+        tiles: map_layer.data.iter().flatten().copied().collect(),
+        chunks_count,
+        chunks,
+      });
+    }
+
+    let mut draw_order: Vec<usize> = (0..layers.len()).collect();
+    // The sort is stable, so the layers on the same level are drawn in the
+    // order of the map file.
+    draw_order.sort_by_cached_key(|&idx| level_draw_order(&map.layers[idx].level));
+
+    Ok(Self {
+      globals,
+
+      program,
+      program_reflection,
+      ebo,
+      tilesets,
+
+      layers,
+      draw_order,
+      stats: TilemapStats::default(),
+    })
+  }
+
+  /// The counters accumulated since the last call to [`Self::take_stats`].
+  #[inline(always)]
+  pub fn stats(&self) -> TilemapStats { self.stats }
+  #[inline]
+  pub fn take_stats(&mut self) -> TilemapStats { mem::take(&mut self.stats) }
+
+  /// The number of chunks in all of the drawn layers.
+  pub fn chunks_count(&self) -> usize { self.layers.iter().map(|layer| layer.chunks.len()).sum() }
+
+  /// `layer` is an index into [`Map::layers`].
+  pub fn tile(&self, layer: usize, pos: Vec2u32) -> u16 {
+    let layer = &self.layers[layer];
+    layer.tiles[layer.tile_index(pos)]
+  }
+
+  pub fn set_tile(&mut self, layer: usize, pos: Vec2u32, tile: u16) {
+    let layer = &mut self.layers[layer];
+    let tile_index = layer.tile_index(pos);
+    if layer.tiles[tile_index] == tile {
+      return;
+    }
+    layer.tiles[tile_index] = tile;
+    if !layer.chunks.is_empty() {
+      let chunk_pos = pos / CHUNK_SIZE;
+      let chunk_idx = chunk_pos.x as usize + chunk_pos.y as usize * layer.chunks_count.x as usize;
+      layer.chunks[chunk_idx].dirty = true;
+    }
+  }
+
+  pub fn set_layer_visible(&mut self, layer: usize, visible: bool) {
+    self.layers[layer].visible = visible;
+  }
+
+  pub fn render(&mut self, camera: &Camera) {
+    let bound_program = self.program.bind();
+    let reflection = &self.program_reflection;
+    if self.globals.window_was_resized {
+      reflection.u_window_size.set(&bound_program, &self.globals.window_size);
+    }
+    reflection.u_camera_zoom.set(&bound_program, &camera.zoom);
+    let view_half_size = self.globals.window_size * 0.5 / camera.zoom;
+
+    let bound_ebo = self.ebo.bind();
+    let mut attribs_enabled = false;
+
+    for &layer_idx in &self.draw_order {
+      let layer = &mut self.layers[layer_idx];
+      let tileset = match layer.tileset {
+        Some(tileset) if layer.visible => &mut self.tilesets[tileset],
+        _ => continue,
+      };
+
+      // Layers which are further away scroll slower, producing the parallax
+      // effect.
+      let layer_camera_pos = camera.pos / layer.distance;
+      let ranges = visible_chunk_ranges(
+        layer_camera_pos - view_half_size,
+        layer_camera_pos + view_half_size,
+        layer.tile_size,
+        layer.size,
+        layer.repeat,
+      );
+
+      let bound_texture = tileset.texture.bind(Some(TILESET_TEXTURE_UNIT));
+      for range in ranges {
+        // The copies of a repeated layer are drawn by moving the camera instead
+        // of the chunks.
+        reflection.u_camera_pos.set(&bound_program, &(layer_camera_pos - range.offset));
+
+        for chunk_y in range.min.y..range.max.y {
+          for chunk_x in range.min.x..range.max.x {
+            let chunk_pos = vec2(chunk_x, chunk_y);
+            let chunk_idx = chunk_x as usize + chunk_y as usize * layer.chunks_count.x as usize;
+            self.stats.visible_chunks += 1;
+
+            if layer.chunks[chunk_idx].dirty {
+              rebuild_chunk(layer, bound_texture.size(), chunk_pos, chunk_idx);
+              self.stats.rebuilt_chunks += 1;
+            }
+            let chunk = &mut layer.chunks[chunk_idx];
+            if chunk.quads == 0 {
+              continue;
+            }
+
+            let bound_vbo = chunk.vbo.bind();
+            if !attribs_enabled {
+              bound_vbo.enable_attribs();
+              attribs_enabled = true;
+            }
+            bound_vbo.configure_attribs();
+            bound_ebo.draw_slice(
+              &bound_program,
+              oogl::DrawPrimitive::Triangles,
+              ..chunk.quads * 6,
+            );
+            self.stats.draw_calls += 1;
+          }
+        }
+      }
+      drop(bound_texture);
+    }
+
+    if attribs_enabled {
+      // The attribute locations are the same for every chunk, so any of the
+      // buffers will do for disabling them.
+      let chunk = self.layers.iter_mut().flat_map(|layer| &mut layer.chunks).next().unwrap();
+      chunk.vbo.bind().disable_attribs();
+    }
+  }
+}
+
+/// The chunks of a single copy of a layer which intersect the view, `offset`
+/// is the position of the copy in pixels. A layer which isn't repeated has
+/// only the copy at the origin.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct ChunkRange {
+  offset: Vec2f,
+  min: Vec2u32,
+  max: Vec2u32,
+}
+
+/// `view_min` and `view_max` are the corners of the view in the coordinates of
+/// the layer, in pixels.
+fn visible_chunk_ranges(
+  view_min: Vec2f,
+  view_max: Vec2f,
+  tile_size: u32,
+  layer_size: Vec2u32,
+  repeat: bool,
+) -> Vec<ChunkRange> {
+  let chunk_size_px = Vec2f::cast_from(CHUNK_SIZE * vec2n(tile_size));
+  let layer_size_px = Vec2f::cast_from(layer_size * vec2n(tile_size));
+  let chunks_count = (layer_size + CHUNK_SIZE - vec2n(1)) / CHUNK_SIZE;
+  let chunks_count_f = Vec2f::cast_from(chunks_count);
+
+  let (copies_min, copies_max) = if repeat && layer_size.x > 0 && layer_size.y > 0 {
+    (
+      Vec2i32::cast_from((view_min / layer_size_px).map(f32::floor)),
+      Vec2i32::cast_from((view_max / layer_size_px).map(f32::ceil)),
+    )
+  } else {
+    (Vec2i32::ZERO, vec2n(1))
+  };
+
+  let mut ranges = Vec::new();
+  for copy_y in copies_min.y..copies_max.y {
+    for copy_x in copies_min.x..copies_max.x {
+      let offset = Vec2f::cast_from(vec2(copy_x, copy_y)) * layer_size_px;
+      let local_min = (view_min - offset) / chunk_size_px;
+      let local_max = (view_max - offset) / chunk_size_px;
+      let min = Vec2u32::cast_from(local_min.map(f32::floor).clamp2(Vec2f::ZERO, chunks_count_f));
+      let max = Vec2u32::cast_from(local_max.map(f32::ceil).clamp2(Vec2f::ZERO, chunks_count_f));
+      if min.x < max.x && min.y < max.y {
+        ranges.push(ChunkRange { offset, min, max });
+      }
+    }
+  }
+  ranges
+}
+
+fn rebuild_chunk(
+  layer: &mut TilemapLayer,
+  tileset_size: Vec2u32,
+  chunk_pos: Vec2u32,
+  chunk_idx: usize,
+) {
+  let tile_size = layer.tile_size;
+  let tile_size_f = tile_size as f32;
+  let tileset_columns = (tileset_size.x / tile_size).max(1);
+  let tileset_tiles_count = tileset_columns * (tileset_size.y / tile_size);
+  let texcoord_tile_size = Vec2f::cast_from(vec2n(tile_size)) / Vec2f::cast_from(tileset_size);
+
+  let chunk_start = chunk_pos * CHUNK_SIZE;
+  let chunk_end = (chunk_start + CHUNK_SIZE).min_components(layer.size);
+
+  let mut vertices = Vec::with_capacity(CHUNK_MAX_QUADS * QUAD_CORNERS.len());
+  for y in chunk_start.y..chunk_end.y {
+    for x in chunk_start.x..chunk_end.x {
+      // Tile indexes are 1-based, 0 is an empty tile.
+      let tile = layer.tiles[layer.tile_index(vec2(x, y))] as u32;
+      if tile == 0 || tile > tileset_tiles_count {
+        continue;
+      }
+      let tile = tile - 1;
+      let tile_texcoord = Vec2f::cast_from(vec2(tile % tileset_columns, tile / tileset_columns))
+        * texcoord_tile_size;
+      let tile_pos = Vec2f::cast_from(vec2(x, y)) * tile_size_f;
+
+      for &corner in &QUAD_CORNERS {
+        vertices.push(TileVertex {
+          pos: tile_pos + corner * tile_size_f,
+          texcoord: tile_texcoord + corner * texcoord_tile_size,
+        });
+      }
+    }
+  }
+
+  let chunk = &mut layer.chunks[chunk_idx];
+  chunk.quads = vertices.len() / QUAD_CORNERS.len();
+  chunk.dirty = false;
+  if !vertices.is_empty() {
+    chunk.vbo.bind().alloc_and_set(&vertices);
+  }
+}
+
+oogl::program_reflection_block!({
+  #[derive(Debug)]
+  struct ProgramReflection {
+    a_pos: oogl::Attrib<Vec2f>,
+    a_texcoord: oogl::Attrib<Vec2f>,
+
+    u_window_size: oogl::Uniform<Vec2f>,
+    u_camera_pos: oogl::Uniform<Vec2f>,
+    u_camera_zoom: oogl::Uniform<f32>,
+    u_tex: oogl::Uniform<oogl::TextureUnit>,
+  }
+});
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn visible_chunk_ranges_are_clamped_to_the_layer() {
+    // 70x24 tiles of 16 pixels, 3x1 chunks of 512 pixels.
+    let ranges =
+      visible_chunk_ranges(vec2(-100.0, -100.0), vec2(600.0, 300.0), 16, vec2(70, 24), false);
+    assert_eq!(ranges, [ChunkRange { offset: Vec2f::ZERO, min: vec2(0, 0), max: vec2(2, 1) }]);
+
+    let ranges =
+      visible_chunk_ranges(vec2(2000.0, 0.0), vec2(2500.0, 300.0), 16, vec2(70, 24), false);
+    assert_eq!(ranges, []);
+  }
+
+  #[test]
+  fn repeated_layers_are_drawn_around_the_view() {
+    // 40x40 tiles of 16 pixels, the layer is 640 pixels and has 2x2 chunks.
+    let ranges =
+      visible_chunk_ranges(vec2(-100.0, 600.0), vec2(100.0, 700.0), 16, vec2(40, 40), true);
+    assert_eq!(
+      ranges,
+      [
+        ChunkRange { offset: vec2(-640.0, 0.0), min: vec2(1, 1), max: vec2(2, 2) },
+        ChunkRange { offset: vec2(0.0, 0.0), min: vec2(0, 1), max: vec2(1, 2) },
+        ChunkRange { offset: vec2(-640.0, 640.0), min: vec2(1, 0), max: vec2(2, 1) },
+        ChunkRange { offset: vec2(0.0, 640.0), min: vec2(0, 0), max: vec2(1, 1) },
+      ]
+    );
+  }
+
+  #[test]
+  fn repeated_layers_cover_views_larger_than_the_layer() {
+    let ranges = visible_chunk_ranges(vec2(0.0, 0.0), vec2(1000.0, 100.0), 16, vec2(20, 20), true);
+    assert_eq!(ranges.len(), 4);
+    assert!(ranges.iter().all(|range| range.min == vec2(0, 0) && range.max == vec2(1, 1)));
+    let offsets: Vec<f32> = ranges.iter().map(|range| range.offset.x).collect();
+    assert_eq!(offsets, [0.0, 320.0, 640.0, 960.0]);
+  }
+}
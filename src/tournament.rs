@@ -0,0 +1,285 @@
+//! A port of `extras/mega_tournament.py`: runs a lot of round-robin tournaments
+//! between the Pong bots, in which one of the players (the champion) is better
+//! than the rest, and measures how often the champion wins depending on the
+//! number of players. Unlike in the script the matches are actually played,
+//! headlessly. Run with:
+//!
+//! ```text
+//! cargo run --release -- tournament [OPTIONS]
+//! ```
+//!
+//! The results are written in the same CSV format as the script uses, so it
+//! can plot them: `extras/mega_tournament.py tournaments.csv`.
+
+use cardboard_math::*;
+use prelude_plus::*;
+
+use crate::globals::{parse_seed, GlobalRandom, DEFAULT_FIXED_UPDATES_PER_SECOND};
+use crate::pong_ai::ChasingBot;
+use crate::pong_sim::{PongEvent, PongSim};
+use crate::settings::Settings;
+
+pub const USAGE: &str = "\
+Usage: openkrosskod tournament [OPTIONS]
+
+Options:
+  --players N|MIN..MAX    the numbers of players to run the tournaments with
+  --iterations N          tournaments per number of players
+  --points N              the score a bot has to reach to win a match
+  --threads N             defaults to the number of CPUs
+  --seed SEED             the RNG seed, hexadecimal with 0x or decimal
+  --output PATH           where to write the CSV table
+  --help                  print this message
+";
+
+const CHAMPION_VISION_DISTANCE: f32 = 1.0;
+/// The matches which take longer than this (in seconds of the game time) end
+/// with the current score, possibly a draw.
+const MAX_MATCH_DURATION: f64 = 10.0 * 60.0;
+
+#[derive(Debug)]
+struct Options {
+  help: bool,
+  players: RangeInclusive<usize>,
+  iterations: u32,
+  points: u32,
+  threads: usize,
+  seed: Option<u128>,
+  output: PathBuf,
+}
+
+fn parse_args(args: impl Iterator<Item = String>) -> AnyResult<Options> {
+  let mut options = Options {
+    help: false,
+    players: 2..=8,
+    iterations: 100,
+    points: 5,
+    threads: num_cpus::get(),
+    seed: None,
+    output: PathBuf::from("tournaments.csv"),
+  };
+
+  fn parse_value<T: str::FromStr>(arg: &str, value: &str, expected: &str) -> AnyResult<T> {
+    value
+      .parse()
+      .map_err(|_| format_err!("Invalid value for {}: '{}', expected {}", arg, value, expected))
+  }
+
+  let mut args = args;
+  while let Some(arg) = args.next() {
+    let arg = arg.as_str();
+    let mut next_value = || args.next().ok_or_else(|| format_err!("{} requires a value", arg));
+    match arg {
+      "--help" | "-h" => options.help = true,
+      "--players" => {
+        let value = next_value()?;
+        let expected = "a number or MIN..MAX";
+        options.players = match value.split_once("..") {
+          Some((min, max)) => parse_value(arg, min, expected)?..=parse_value(arg, max, expected)?,
+          None => {
+            let n = parse_value(arg, &value, expected)?;
+            n..=n
+          }
+        };
+        ensure!(
+          *options.players.start() >= 2 && !options.players.is_empty(),
+          "Invalid value for {}: '{}', at least 2 players are required",
+          arg,
+          value,
+        );
+      }
+      "--iterations" => options.iterations = parse_value(arg, &next_value()?, "a number")?,
+      "--points" => {
+        options.points = parse_value(arg, &next_value()?, "a number")?;
+        ensure!(options.points > 0, "{} must be positive", arg);
+      }
+      "--threads" => {
+        options.threads = parse_value(arg, &next_value()?, "a number")?;
+        ensure!(options.threads > 0, "{} must be positive", arg);
+      }
+      "--seed" => options.seed = Some(parse_seed(&next_value()?)?),
+      "--output" => options.output = PathBuf::from(next_value()?),
+      _ => bail!("Unknown option: {}", arg),
+    }
+  }
+  Ok(options)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MatchResult {
+  pub left_score: u32,
+  pub right_score: u32,
+  pub fixed_updates: u64,
+  pub hits: u32,
+}
+
+impl MatchResult {
+  /// `None` on a draw.
+  pub fn winner_is_left(&self) -> Option<bool> {
+    match self.left_score.cmp(&self.right_score) {
+      cmp::Ordering::Greater => Some(true),
+      cmp::Ordering::Less => Some(false),
+      cmp::Ordering::Equal => None,
+    }
+  }
+}
+
+/// Plays a single match until one of the bots reaches `points`, in an arena of
+/// the default window size.
+pub fn play_match(sim: &mut PongSim, points: u32) -> MatchResult {
+  let fixed_delta_time = 1.0 / DEFAULT_FIXED_UPDATES_PER_SECOND;
+  let max_fixed_updates = (MAX_MATCH_DURATION / fixed_delta_time) as u64;
+
+  let mut fixed_updates = 0;
+  let mut hits = 0;
+  loop {
+    let (left_score, right_score) = sim.scores();
+    if left_score >= points || right_score >= points || fixed_updates >= max_fixed_updates {
+      return MatchResult { left_score, right_score, fixed_updates, hits };
+    }
+    for event in sim.step(fixed_delta_time as f32) {
+      if let PongEvent::Hit { .. } = event {
+        hits += 1;
+      }
+    }
+    fixed_updates += 1;
+  }
+}
+
+/// Returns the index of the player with the most wins, or `None` if there is
+/// more than one of them. The player 0 is the champion.
+fn run_tournament(seed: u128, total_players: usize, iteration: u32, points: u32) -> Option<usize> {
+  let random = GlobalRandom::with_seed(seed);
+  let arena_size = Vec2f::cast_from(Settings::default().window_size);
+  let bot = |player: usize| {
+    Box::new(if player == 0 {
+      ChasingBot { vision_distance: CHAMPION_VISION_DISTANCE }
+    } else {
+      ChasingBot::default()
+    })
+  };
+
+  let mut scores = vec![0; total_players];
+  for player2 in 0..total_players {
+    for player1 in 0..player2 {
+      // Every match gets its own stream, so that the results depend neither on
+      // the number of threads nor on the order they finish in.
+      let stream_name =
+        format!("tournament/{}/{}/{}-{}", total_players, iteration, player1, player2);
+      let stream = random.stream(&stream_name);
+      let mut sim = PongSim::new(stream, arena_size, bot(player1), bot(player2));
+      match play_match(&mut sim, points).winner_is_left() {
+        Some(true) => scores[player1] += 1,
+        Some(false) => scores[player2] += 1,
+        None => {}
+      }
+    }
+  }
+
+  let best_score = *scores.iter().max()?;
+  let mut best_players = scores.iter().enumerate().filter(|&(_, &score)| score == best_score);
+  let (best_player, _) = best_players.next()?;
+  if best_players.next().is_some() {
+    return None;
+  }
+  Some(best_player)
+}
+
+pub fn main(args: impl Iterator<Item = String>) -> AnyResult<()> {
+  let options = parse_args(args)?;
+  if options.help {
+    print!("{}", USAGE);
+    return Ok(());
+  }
+
+  let seed = match options.seed {
+    Some(seed) => seed,
+    None => GlobalRandom::init().context("Failed to generate a seed")?.seed(),
+  };
+  info!("RNG seed: {:#034x}", seed);
+
+  let file = fs::File::create(&options.output)
+    .with_context(|| format!("Failed to create '{}'", options.output.display()))?;
+  let mut output = io::BufWriter::new(file);
+
+  let tasks: Vec<(usize, u32)> = options
+    .players
+    .clone()
+    .flat_map(|total_players| (0..options.iterations).map(move |i| (total_players, i)))
+    .collect();
+  info!("Running {} tournaments on {} threads", tasks.len(), options.threads);
+  let start_time = Instant::now();
+
+  let workers = threadpool::Builder::new()
+    .num_threads(options.threads)
+    .thread_name("tournament.workers".to_owned())
+    .build();
+  let (results_tx, results_rx) = mpsc::channel();
+  for (task_index, &(total_players, iteration)) in tasks.iter().enumerate() {
+    let results_tx = results_tx.clone();
+    let points = options.points;
+    workers.execute(move || {
+      let best_player = run_tournament(seed, total_players, iteration, points);
+      let _ = results_tx.send((task_index, best_player));
+    });
+  }
+  drop(results_tx);
+
+  // The rows are written in the order of the tasks, the finished ones are held
+  // here until all of the ones before them are done.
+  let mut pending_results = BTreeMap::new();
+  let mut next_task_index = 0;
+  let mut champion_wins: BTreeMap<usize, u32> = BTreeMap::new();
+  for (task_index, best_player) in results_rx {
+    pending_results.insert(task_index, best_player);
+    while let Some(best_player) = pending_results.remove(&next_task_index) {
+      let (total_players, iteration) = tasks[next_task_index];
+      let best_player = best_player.map_or(-1, |player| player as i64);
+      writeln!(output, "{},{},{}", total_players, iteration, best_player)
+        .context("Failed to write the results")?;
+      *champion_wins.entry(total_players).or_default() += (best_player == 0) as u32;
+      next_task_index += 1;
+    }
+  }
+  ensure!(next_task_index == tasks.len(), "Some of the tournaments have failed");
+  output.flush().context("Failed to write the results")?;
+  info!("Done in {:.2?}, see '{}'", start_time.elapsed(), options.output.display());
+
+  println!("players  champion win probability");
+  for (total_players, wins) in champion_wins {
+    println!(" {:7}  {}", total_players, wins as f64 / options.iterations.max(1) as f64);
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn play(seed: u128) -> MatchResult {
+    let arena_size = Vec2f::cast_from(Settings::default().window_size);
+    let mut sim = PongSim::new(
+      GlobalRandom::with_seed(seed).stream("match"),
+      arena_size,
+      Box::new(ChasingBot { vision_distance: CHAMPION_VISION_DISTANCE }),
+      Box::new(ChasingBot::default()),
+    );
+    play_match(&mut sim, 2)
+  }
+
+  #[test]
+  fn matches_are_deterministic() {
+    let result = play(1);
+    assert!(result.left_score == 2 || result.right_score == 2, "{:?}", result);
+    assert_eq!(play(1), result);
+    assert!((2..10).map(play).any(|other| other != result));
+  }
+
+  #[test]
+  fn tournaments_are_deterministic() {
+    let winners: Vec<_> = (0..3).map(|iteration| run_tournament(7, 3, iteration, 1)).collect();
+    for (iteration, &winner) in winners.iter().enumerate() {
+      assert_eq!(run_tournament(7, 3, iteration as u32, 1), winner);
+    }
+  }
+}